@@ -0,0 +1,127 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, ensure_author, maintenance, metrics, Error, Memory, Quiz, StorablePrincipal,
+    MEMORY_MANAGER,
+};
+use candid::Principal;
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// ten years, nobody would pass a longer requirement
+const MAX_MIN_AGE_DAYS: u32 = 3650;
+
+thread_local! {
+    // the time of the first update call of every principal that got past the maintenance checks,
+    // before this existed the time of its oldest quiz or latest vote
+    static FIRST_SEEN: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(130)))
+    ));
+
+    // the days a principal has to be known for before it votes on the quiz, by quiz id
+    static MIN_AGES: RefCell<StableBTreeMap<u64, u32, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(131)))
+    ));
+}
+
+fn first_seen(principal: Principal) -> Option<u64> {
+    FIRST_SEEN.with(|seen| seen.borrow().get(&StorablePrincipal(principal)))
+}
+
+fn min_age_of(quiz_id: u64) -> Option<u32> {
+    MIN_AGES.with(|ages| ages.borrow().get(&quiz_id))
+}
+
+// Notes when the principal was first seen, every update call that gets past
+// `maintenance::ensure_available` goes through here.
+pub fn record(principal: Principal) {
+    backfill(principal, time());
+}
+
+// keeps the earlier of the known time and `at`
+pub fn backfill(principal: Principal, at: u64) {
+    if principal == Principal::anonymous() {
+        return;
+    }
+    let key = StorablePrincipal(principal);
+    FIRST_SEEN.with(|seen| {
+        let mut seen = seen.borrow_mut();
+        if seen.get(&key).is_none_or(|first_seen| at < first_seen) {
+            seen.insert(key, at);
+        }
+    });
+}
+
+pub fn remove_min_account_age(quiz_id: u64) {
+    MIN_AGES.with(|ages| ages.borrow_mut().remove(&quiz_id));
+}
+
+// Every way of voting goes through here, see `ensure_open`.
+pub fn ensure_old_enough(quiz: &Quiz) -> Result<(), Error> {
+    let Some(days) = min_age_of(quiz.id) else {
+        return Ok(());
+    };
+    let eligible_at = first_seen(caller())
+        .unwrap_or_else(time)
+        .saturating_add(days as u64 * DAY_NS);
+    if time() >= eligible_at {
+        return Ok(());
+    }
+    Err(Error::NotEligible {
+        msg: format!(
+            "voting on the quiz with id={} takes an account known for {} days, yours is in {} \
+             more",
+            quiz.id,
+            days,
+            (eligible_at - time()).div_ceil(DAY_NS)
+        ),
+    })
+}
+
+// Turns away principals that are newer than `days` from the quiz, None lets every principal
+// vote again. Fresh principals cost nothing, so this blunts sybil votes on open polls.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_min_account_age(quiz_id: u64, days: Option<u32>) -> Result<(), Error> {
+    metrics::count_call("set_min_account_age");
+    maintenance::ensure_available("set_min_account_age")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_author(&quiz)?;
+    let msg = match days {
+        None => {
+            remove_min_account_age(quiz_id);
+            return Ok(());
+        }
+        Some(0) => "an age of 0 days lets everybody in, None removes the requirement".to_string(),
+        Some(days) if days > MAX_MIN_AGE_DAYS => {
+            format!("the age can be at most {} days", MAX_MIN_AGE_DAYS)
+        }
+        Some(_) if quiz.allows_anonymous() => "anonymous voters have no account age".to_string(),
+        Some(days) => {
+            MIN_AGES.with(|ages| ages.borrow_mut().insert(quiz_id, days));
+            return Ok(());
+        }
+    };
+    Err(Error::ValidationFailed {
+        field: "days".to_string(),
+        msg,
+    })
+}
+
+#[ic_cdk::query]
+fn get_min_account_age(quiz_id: u64) -> Option<u32> {
+    min_age_of(quiz_id)
+}
+
+// when the principal was first seen by the canister, None if it never made an update call
+#[ic_cdk::query]
+fn get_first_seen(principal: Principal) -> Option<u64> {
+    first_seen(principal)
+}
@@ -0,0 +1,293 @@
+use crate::guards::only_admin;
+use crate::{
+    leaderboard, maintenance, metrics, streaks, Error, IdCell, Memory, Quiz, StorablePrincipal,
+    MEMORY_MANAGER, QUIZZES_BY_AUTHOR, VOTES_BY_VOTER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// every rule is checked on every vote
+const MAX_RULES: u64 = 50;
+const MAX_NAME_LEN: usize = 64;
+const MAX_DESCRIPTION_LEN: usize = 256;
+
+// what a rule counts for a principal
+#[derive(candid::CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum AchievementMetric {
+    QuizzesCreated,
+    VotesCast,
+    // votes others cast on the principal's quizzes, a ballot counts once
+    VotesReceived,
+    LongestStreak,
+    Points,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct AchievementRulePayload {
+    name: String,
+    description: String,
+    metric: AchievementMetric,
+    threshold: u64,
+}
+
+// earned as soon as the metric of a principal reaches the threshold
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct AchievementRule {
+    id: u64,
+    name: String,
+    description: String,
+    metric: AchievementMetric,
+    threshold: u64,
+}
+
+impl AchievementRule {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Storable for AchievementRule {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for AchievementRule {
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct Achievement {
+    rule: AchievementRule,
+    earned_at: u64,
+}
+
+thread_local! {
+    static RULES: RefCell<StableBTreeMap<u64, AchievementRule, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(48)))
+    ));
+
+    // the time every achievement was earned at, by (principal, rule id)
+    static EARNED: RefCell<StableBTreeMap<(StorablePrincipal, u64), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(49)))
+    ));
+
+    // rule ids aren't reused, an earned achievement must not turn into another one
+    static RULE_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(50))), 0)
+            .expect("Cannot create the achievement rule counter")
+    );
+
+    static VOTES_RECEIVED: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(51)))
+    ));
+}
+
+fn metric_of(principal: Principal, metric: AchievementMetric) -> u64 {
+    let key = StorablePrincipal(principal);
+    match metric {
+        AchievementMetric::QuizzesCreated => QUIZZES_BY_AUTHOR
+            .with(|index| index.borrow().range((key, 0)..=(key, u64::MAX)).count() as u64),
+        AchievementMetric::VotesCast => VOTES_BY_VOTER
+            .with(|index| index.borrow().range((key, 0)..=(key, u64::MAX)).count() as u64),
+        AchievementMetric::VotesReceived => VOTES_RECEIVED
+            .with(|received| received.borrow().get(&key))
+            .unwrap_or_default(),
+        AchievementMetric::LongestStreak => streaks::longest_streak(principal) as u64,
+        AchievementMetric::Points => leaderboard::points_of(principal),
+    }
+}
+
+// Awards the rules the principal now meets. Rules added later are earned on the next event of a
+// principal, nobody is awarded retroactively right away.
+pub fn evaluate(principal: Principal) {
+    if principal == Principal::anonymous() {
+        return;
+    }
+    let key = StorablePrincipal(principal);
+    let rules: Vec<AchievementRule> =
+        RULES.with(|rules| rules.borrow().iter().map(|(_, rule)| rule).collect());
+    for rule in rules {
+        let earned = EARNED.with(|earned| earned.borrow().contains_key(&(key, rule.id)));
+        if !earned && metric_of(principal, rule.metric) >= rule.threshold {
+            EARNED.with(|earned| earned.borrow_mut().insert((key, rule.id), time()));
+        }
+    }
+}
+
+// counts the vote for the author of the quiz and checks the achievements of both of them.
+pub fn record_vote(quiz: &Quiz, voter: Principal) {
+    evaluate(voter);
+    if let Some(author) = quiz.author.filter(|author| *author != voter) {
+        add_votes_received(author, 1);
+        evaluate(author);
+    }
+}
+
+pub fn add_votes_received(author: Principal, votes: u64) {
+    let key = StorablePrincipal(author);
+    VOTES_RECEIVED.with(|received| {
+        let mut received = received.borrow_mut();
+        let current = received.get(&key).unwrap_or_default();
+        received.insert(key, current + votes);
+    });
+}
+
+fn insert_rule(payload: AchievementRulePayload) -> Result<AchievementRule, Error> {
+    if payload.name.trim().is_empty() || payload.name.len() > MAX_NAME_LEN {
+        return Err(Error::ValidationFailed {
+            field: "name".to_string(),
+            msg: format!("the name must be 1 to {} bytes", MAX_NAME_LEN),
+        });
+    }
+    if payload.description.len() > MAX_DESCRIPTION_LEN {
+        return Err(Error::ValidationFailed {
+            field: "description".to_string(),
+            msg: format!(
+                "the description must not exceed {} bytes",
+                MAX_DESCRIPTION_LEN
+            ),
+        });
+    }
+    if payload.threshold == 0 {
+        return Err(Error::ValidationFailed {
+            field: "threshold".to_string(),
+            msg: "the threshold must be at least 1".to_string(),
+        });
+    }
+    if RULES.with(|rules| rules.borrow().len()) >= MAX_RULES {
+        return Err(Error::StorageFull {
+            msg: format!("at most {} achievement rules can be defined", MAX_RULES),
+        });
+    }
+    let id = RULE_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment achievement rule counter".to_string(),
+        })?;
+    let rule = AchievementRule {
+        id,
+        name: payload.name.trim().to_string(),
+        description: payload.description,
+        metric: payload.metric,
+        threshold: payload.threshold,
+    };
+    RULES.with(|rules| rules.borrow_mut().insert(id, rule.clone()));
+    Ok(rule)
+}
+
+// the rules a fresh canister starts with, admins may remove them like any other.
+pub fn seed_default_rules() {
+    let defaults = [
+        (
+            "First quiz",
+            "Created a quiz",
+            AchievementMetric::QuizzesCreated,
+            1,
+        ),
+        (
+            "First vote",
+            "Voted on a quiz",
+            AchievementMetric::VotesCast,
+            1,
+        ),
+        (
+            "Crowd pleaser",
+            "Received 100 votes",
+            AchievementMetric::VotesReceived,
+            100,
+        ),
+        (
+            "Regular",
+            "Voted 10 days in a row",
+            AchievementMetric::LongestStreak,
+            10,
+        ),
+    ];
+    for (name, description, metric, threshold) in defaults {
+        let inserted = insert_rule(AchievementRulePayload {
+            name: name.to_string(),
+            description: description.to_string(),
+            metric,
+            threshold,
+        });
+        if inserted.is_err() {
+            ic_cdk::trap("the default achievement rules don't fit");
+        }
+    }
+}
+
+// the rule and the time the principal earned it at, None if they didn't or the rule is gone.
+pub fn earned(principal: Principal, rule_id: u64) -> Option<(AchievementRule, u64)> {
+    let earned_at = EARNED.with(|earned| {
+        earned
+            .borrow()
+            .get(&(StorablePrincipal(principal), rule_id))
+    })?;
+    RULES
+        .with(|rules| rules.borrow().get(&rule_id))
+        .map(|rule| (rule, earned_at))
+}
+
+#[ic_cdk::query]
+fn get_achievement_rules() -> Vec<AchievementRule> {
+    RULES.with(|rules| rules.borrow().iter().map(|(_, rule)| rule).collect())
+}
+
+#[ic_cdk::query]
+fn get_my_achievements() -> Vec<Achievement> {
+    let key = StorablePrincipal(caller());
+    let earned: Vec<(u64, u64)> = EARNED.with(|earned| {
+        earned
+            .borrow()
+            .range((key, 0)..=(key, u64::MAX))
+            .map(|((_, rule_id), earned_at)| (rule_id, earned_at))
+            .collect()
+    });
+    // achievements of removed rules are left out
+    earned
+        .into_iter()
+        .filter_map(|(rule_id, earned_at)| {
+            RULES
+                .with(|rules| rules.borrow().get(&rule_id))
+                .map(|rule| Achievement { rule, earned_at })
+        })
+        .collect()
+}
+
+#[ic_cdk::update(guard = "only_admin")]
+fn add_achievement_rule(payload: AchievementRulePayload) -> Result<AchievementRule, Error> {
+    metrics::count_call("add_achievement_rule");
+    maintenance::ensure_available("add_achievement_rule")?;
+    insert_rule(payload)
+}
+
+// the achievements earned through the rule disappear with it.
+#[ic_cdk::update(guard = "only_admin")]
+fn remove_achievement_rule(id: u64) -> Result<AchievementRule, Error> {
+    metrics::count_call("remove_achievement_rule");
+    maintenance::ensure_available("remove_achievement_rule")?;
+    RULES
+        .with(|rules| rules.borrow_mut().remove(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("an achievement rule with id={} not found", id),
+        })
+}
@@ -0,0 +1,125 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, editors, ensure_editor, id_key_range, maintenance, metrics, organizations,
+    remove_quiz_entries, roles, Error, Memory, OrgRole, Quiz, Role, StorablePrincipal, Visibility,
+    MEMORY_MANAGER,
+};
+use candid::Principal;
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+thread_local! {
+    // principals allowed to see and answer a private or unlisted quiz, besides its author
+    static ALLOWED_VOTERS: RefCell<StableBTreeMap<(u64, StorablePrincipal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18)))
+    ));
+}
+
+pub fn can_view(quiz: &Quiz, principal: Principal) -> bool {
+    if quiz.deleted_at.is_some() {
+        return false;
+    }
+    let is_editor = quiz.author == Some(principal)
+        || editors::is_editor(quiz.id, principal)
+        || organizations::has_quiz_role(quiz, principal, OrgRole::Member);
+    if quiz.is_hidden() {
+        return is_editor;
+    }
+    match quiz.visibility() {
+        Visibility::Public => true,
+        Visibility::Private | Visibility::Unlisted => {
+            is_editor
+                || roles::has_role(principal, Role::Moderator)
+                || ALLOWED_VOTERS.with(|allowed| {
+                    allowed
+                        .borrow()
+                        .contains_key(&(quiz.id, StorablePrincipal(principal)))
+                })
+        }
+    }
+}
+
+// hidden quizzes of others are reported missing like deleted ones, nobody is to know they exist.
+pub fn ensure_not_hidden(quiz: &Quiz) -> Result<(), Error> {
+    if quiz.deleted_at.is_some() || quiz.is_hidden() && !can_view(quiz, caller()) {
+        return Err(Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz.id),
+        });
+    }
+    Ok(())
+}
+
+pub fn ensure_can_view(quiz: &Quiz) -> Result<(), Error> {
+    ensure_not_hidden(quiz)?;
+    if !can_view(quiz, caller()) {
+        return Err(Error::Unauthorized {
+            msg: format!("the quiz with id={} is not public", quiz.id),
+        });
+    }
+    Ok(())
+}
+
+pub fn allow(quiz_id: u64, principal: Principal) {
+    ALLOWED_VOTERS.with(|allowed| {
+        allowed
+            .borrow_mut()
+            .insert((quiz_id, StorablePrincipal(principal)), ())
+    });
+}
+
+pub fn remove_allowlist(quiz_id: u64) {
+    ALLOWED_VOTERS.with(|allowed| remove_quiz_entries(&mut allowed.borrow_mut(), quiz_id));
+}
+
+fn authored_quiz(quiz_id: u64) -> Result<Quiz, Error> {
+    let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", quiz_id),
+    })?;
+    ensure_editor(&quiz)?;
+    Ok(quiz)
+}
+
+// the allowlist can be prepared while the quiz is still public, it only applies once private.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn add_allowed_voter(quiz_id: u64, principal: Principal) -> Result<(), Error> {
+    metrics::count_call("add_allowed_voter");
+    maintenance::ensure_available("add_allowed_voter")?;
+    authored_quiz(quiz_id)?;
+    allow(quiz_id, principal);
+    Ok(())
+}
+
+// votes already cast by the principal are kept.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn remove_allowed_voter(quiz_id: u64, principal: Principal) -> Result<(), Error> {
+    metrics::count_call("remove_allowed_voter");
+    maintenance::ensure_available("remove_allowed_voter")?;
+    authored_quiz(quiz_id)?;
+    ALLOWED_VOTERS
+        .with(|allowed| {
+            allowed
+                .borrow_mut()
+                .remove(&(quiz_id, StorablePrincipal(principal)))
+        })
+        .ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "{} is not allowed to vote on the quiz with id={}",
+                principal, quiz_id
+            ),
+        })
+}
+
+#[ic_cdk::query]
+fn list_allowed_voters(quiz_id: u64) -> Result<Vec<Principal>, Error> {
+    authored_quiz(quiz_id)?;
+    Ok(ALLOWED_VOTERS.with(|allowed| {
+        allowed
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|((_, principal), _)| principal.0)
+            .collect()
+    }))
+}
@@ -0,0 +1,211 @@
+use crate::guards::only_admin;
+use crate::{
+    _get_quiz, maintenance, metrics, roles, tallies, Error, Memory, Quiz, Role, MAX_PAGE_SIZE,
+    MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{StableBTreeMap, StableLog, Storable};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, cell::RefCell, collections::HashMap};
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AuditAction {
+    Create,
+    Update,
+    // a rollback that kept the votes, one that resets them is logged as an update
+    Rollback,
+    // a draft was opened to the voters
+    Publish,
+    Delete,
+    // the quiz changed hands, the actor is its new author
+    Transfer,
+    Restore,
+    // the correct options behind the hash a scored quiz was created with
+    RevealAnswers,
+    // the retention period of a deleted quiz ran out, the actor is the canister itself
+    Purge,
+    // a hidden commit-reveal vote, the matching reveal is logged as a vote
+    Commit,
+    Vote,
+    // an editor graded the free-text answer of a voter
+    GradeAnswer,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    // position in the log
+    seq: u64,
+    action: AuditAction,
+    quiz_id: u64,
+    actor: Principal,
+    timestamp: u64,
+    // sha256 of the candid encoded arguments of the call
+    digest: Vec<u8>,
+    // what a vote added to the answers map, None for votes logged before tallies were
+    tally: Option<Vec<(String, u32)>>,
+    // token balance added to every option of the tally on token-weighted quizzes
+    weight: Option<u128>,
+}
+
+// log entries are written once and never resized, so they don't need a bound
+impl Storable for AuditEvent {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+thread_local! {
+    static AUDIT_LOG: RefCell<StableLog<AuditEvent, Memory, Memory>> =
+        RefCell::new(
+            StableLog::init(
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23))),
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24))),
+            ).expect("failed to initialize the audit log")
+    );
+
+    // positions of the events of every quiz, so its history is read without scanning the log
+    static EVENTS_BY_QUIZ: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25)))
+    ));
+}
+
+fn digest(args: &[u8]) -> Vec<u8> {
+    Sha256::digest(args).to_vec()
+}
+
+// appends an event for a mutation that already went through, `args` are its encoded arguments.
+pub fn record(action: AuditAction, quiz_id: u64, args: &[u8]) {
+    record_by(action, quiz_id, caller(), args);
+}
+
+pub fn record_by(action: AuditAction, quiz_id: u64, actor: Principal, args: &[u8]) {
+    append(AuditEvent {
+        seq: 0,
+        action,
+        quiz_id,
+        actor,
+        timestamp: time(),
+        digest: digest(args),
+        tally: None,
+        weight: None,
+    });
+}
+
+// votes carry what they added to the tallies, `rebuild_tallies` replays them from the log.
+pub fn record_vote(quiz_id: u64, args: &[u8], tally: Vec<(String, u32)>, weight: Option<u128>) {
+    append(AuditEvent {
+        seq: 0,
+        action: AuditAction::Vote,
+        quiz_id,
+        actor: caller(),
+        timestamp: time(),
+        digest: digest(args),
+        tally: Some(tally),
+        weight,
+    });
+}
+
+fn append(mut event: AuditEvent) {
+    let quiz_id = event.quiz_id;
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        event.seq = log.len();
+        let seq = log
+            .append(&event)
+            .expect("the audit log is out of stable memory");
+        EVENTS_BY_QUIZ.with(|index| index.borrow_mut().insert((quiz_id, seq), ()));
+    });
+}
+
+#[ic_cdk::query(guard = "only_admin")]
+fn get_audit_log(offset: u64, limit: u64) -> Result<Vec<AuditEvent>, Error> {
+    Ok(AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        (offset..log.len())
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .filter_map(|seq| log.get(seq))
+            .collect()
+    }))
+}
+
+fn quiz_events(id: u64) -> Vec<AuditEvent> {
+    let seqs: Vec<u64> = EVENTS_BY_QUIZ.with(|index| {
+        index
+            .borrow()
+            .range((id, 0)..=(id, u64::MAX))
+            .map(|((_, seq), _)| seq)
+            .collect()
+    });
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        seqs.into_iter().filter_map(|seq| log.get(seq)).collect()
+    })
+}
+
+// the history names the voters, so it is limited to the author and admins.
+#[ic_cdk::query]
+fn get_quiz_history(id: u64) -> Result<Vec<AuditEvent>, Error> {
+    let author = _get_quiz(&id).and_then(|quiz| quiz.author);
+    if author != Some(caller()) && !roles::has_role(caller(), Role::Admin) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only the author or an admin can read the history of the quiz with id={}",
+                id
+            ),
+        });
+    }
+    Ok(quiz_events(id))
+}
+
+// Recomputes the answers map (and the weighted tallies) from the votes logged since the quiz was
+// created or last updated, both of which start the tallies from zero.
+#[ic_cdk::update(guard = "only_admin")]
+fn rebuild_tallies(quiz_id: u64) -> Result<Quiz, Error> {
+    metrics::count_call("rebuild_tallies");
+    maintenance::ensure_available("rebuild_tallies")?;
+    let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", quiz_id),
+    })?;
+
+    let events = quiz_events(quiz_id);
+    let since_reset = events
+        .iter()
+        .rposition(|event| matches!(event.action, AuditAction::Create | AuditAction::Update))
+        .map_or(0, |position| position + 1);
+
+    let mut answers: HashMap<String, u64> = HashMap::new();
+    let mut weighted_answers: HashMap<String, u128> = HashMap::new();
+    for event in events[since_reset..]
+        .iter()
+        .filter(|event| event.action == AuditAction::Vote)
+    {
+        let tally = event
+            .tally
+            .as_ref()
+            .ok_or_else(|| Error::ValidationFailed {
+                field: "quiz_id".to_string(),
+                msg: format!(
+                    "the vote #{} was logged without its tally and can't be replayed",
+                    event.seq
+                ),
+            })?;
+        for (option, count) in tally {
+            let answer_count = answers.entry(option.clone()).or_insert(0);
+            *answer_count = answer_count.saturating_add(*count as u64);
+            if let Some(weight) = event.weight {
+                let weighted = weighted_answers.entry(option.clone()).or_insert(0);
+                *weighted = weighted.saturating_add(weight);
+            }
+        }
+    }
+
+    tallies::replace(&quiz, &answers, &weighted_answers);
+    Ok(tallies::attach(quiz))
+}
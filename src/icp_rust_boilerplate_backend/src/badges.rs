@@ -0,0 +1,255 @@
+use crate::achievements;
+use crate::guards::only_authenticated;
+use crate::icrc::Account;
+use crate::{maintenance, metrics, Error, IdCell, Memory, StorablePrincipal, MEMORY_MANAGER};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// The canister is a minimal ICRC-7 collection of its own: badges are minted to the default
+// account of whoever earned them and can't be transferred, they'd no longer say anything.
+const COLLECTION_NAME: &str = "Quiz badges";
+const COLLECTION_SYMBOL: &str = "BADGE";
+const COLLECTION_DESCRIPTION: &str = "Badges for the achievements earned on quizzes";
+// the most token ids or accounts a single ICRC-7 query answers for, the rest is left out
+const MAX_QUERY_BATCH: usize = 100;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Badge {
+    token_id: u128,
+    owner: Principal,
+    achievement_id: u64,
+    // copied from the rule, the badge stays the same if the rule is changed or removed
+    name: String,
+    description: String,
+    earned_at: u64,
+    minted_at: u64,
+}
+
+impl Storable for Badge {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Badge {
+    // the name and the description of an achievement rule and a principal
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// the ICRC-3 value type the metadata of a token is made of, the cases it uses
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub enum Value {
+    Nat(u128),
+    Text(String),
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct SupportedStandard {
+    name: String,
+    url: String,
+}
+
+thread_local! {
+    // by token id
+    static BADGES: RefCell<StableBTreeMap<u64, Badge, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(117)))
+    ));
+
+    // the token id of every badge, by (owner, achievement rule id)
+    static BADGES_BY_OWNER: RefCell<StableBTreeMap<(StorablePrincipal, u64), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(118)))
+    ));
+
+    static BADGE_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(119))), 0)
+            .expect("Cannot create the badge counter")
+    );
+}
+
+// badges are only ever held by default accounts
+fn holder_of(account: &Account) -> Option<Principal> {
+    match &account.subaccount {
+        Some(subaccount) if subaccount.iter().any(|byte| *byte != 0) => None,
+        _ => Some(account.owner),
+    }
+}
+
+fn token_ids_of(owner: Principal) -> Vec<u64> {
+    let key = StorablePrincipal(owner);
+    let mut token_ids: Vec<u64> = BADGES_BY_OWNER.with(|index| {
+        index
+            .borrow()
+            .range((key, 0)..=(key, u64::MAX))
+            .map(|(_, token_id)| token_id)
+            .collect()
+    });
+    token_ids.sort_unstable();
+    token_ids
+}
+
+fn badge_of(token_id: u128) -> Option<Badge> {
+    let token_id = u64::try_from(token_id).ok()?;
+    BADGES.with(|badges| badges.borrow().get(&token_id))
+}
+
+// Mints the badge of an achievement the caller earned, once per achievement.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn claim_badge(achievement_id: u64) -> Result<Badge, Error> {
+    metrics::count_call("claim_badge");
+    maintenance::ensure_available("claim_badge")?;
+    let owner = caller();
+    let key = (StorablePrincipal(owner), achievement_id);
+    if BADGES_BY_OWNER.with(|index| index.borrow().contains_key(&key)) {
+        return Err(Error::ValidationFailed {
+            field: "achievement_id".to_string(),
+            msg: format!(
+                "you claimed the badge of the achievement with id={} already",
+                achievement_id
+            ),
+        });
+    }
+    let (rule, earned_at) =
+        achievements::earned(owner, achievement_id).ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "you haven't earned the achievement with id={}",
+                achievement_id
+            ),
+        })?;
+
+    let token_id = BADGE_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment badge counter".to_string(),
+        })?;
+    let badge = Badge {
+        token_id: token_id as u128,
+        owner,
+        achievement_id,
+        name: rule.name().to_string(),
+        description: rule.description().to_string(),
+        earned_at,
+        minted_at: time(),
+    };
+    BADGES.with(|badges| badges.borrow_mut().insert(token_id, badge.clone()));
+    BADGES_BY_OWNER.with(|index| index.borrow_mut().insert(key, token_id));
+    Ok(badge)
+}
+
+#[ic_cdk::query]
+fn get_badges_of(owner: Principal) -> Vec<Badge> {
+    token_ids_of(owner)
+        .into_iter()
+        .filter_map(|token_id| badge_of(token_id as u128))
+        .collect()
+}
+
+#[ic_cdk::query]
+fn icrc7_name() -> String {
+    COLLECTION_NAME.to_string()
+}
+
+#[ic_cdk::query]
+fn icrc7_symbol() -> String {
+    COLLECTION_SYMBOL.to_string()
+}
+
+#[ic_cdk::query]
+fn icrc7_description() -> Option<String> {
+    Some(COLLECTION_DESCRIPTION.to_string())
+}
+
+#[ic_cdk::query]
+fn icrc7_total_supply() -> u128 {
+    BADGES.with(|badges| badges.borrow().len() as u128)
+}
+
+#[ic_cdk::query]
+fn icrc7_max_query_batch_size() -> Option<u128> {
+    Some(MAX_QUERY_BATCH as u128)
+}
+
+#[ic_cdk::query]
+fn icrc7_owner_of(token_ids: Vec<u128>) -> Vec<Option<Account>> {
+    token_ids
+        .into_iter()
+        .take(MAX_QUERY_BATCH)
+        .map(|token_id| badge_of(token_id).map(|badge| Account::of(badge.owner)))
+        .collect()
+}
+
+#[ic_cdk::query]
+fn icrc7_balance_of(accounts: Vec<Account>) -> Vec<u128> {
+    accounts
+        .iter()
+        .take(MAX_QUERY_BATCH)
+        .map(|account| holder_of(account).map_or(0, |owner| token_ids_of(owner).len() as u128))
+        .collect()
+}
+
+// the token ids of an account in ascending order, the page starts after `prev`.
+#[ic_cdk::query]
+fn icrc7_tokens_of(account: Account, prev: Option<u128>, take: Option<u128>) -> Vec<u128> {
+    let take = take.map_or(MAX_QUERY_BATCH, |take| {
+        take.min(MAX_QUERY_BATCH as u128) as usize
+    });
+    holder_of(&account)
+        .map(token_ids_of)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|token_id| token_id as u128)
+        .filter(|token_id| prev.is_none_or(|prev| *token_id > prev))
+        .take(take)
+        .collect()
+}
+
+#[ic_cdk::query]
+fn icrc7_token_metadata(token_ids: Vec<u128>) -> Vec<Option<Vec<(String, Value)>>> {
+    token_ids
+        .into_iter()
+        .take(MAX_QUERY_BATCH)
+        .map(|token_id| {
+            badge_of(token_id).map(|badge| {
+                vec![
+                    ("icrc7:name".to_string(), Value::Text(badge.name)),
+                    (
+                        "icrc7:description".to_string(),
+                        Value::Text(badge.description),
+                    ),
+                    (
+                        "achievement_id".to_string(),
+                        Value::Nat(badge.achievement_id as u128),
+                    ),
+                    ("earned_at".to_string(), Value::Nat(badge.earned_at as u128)),
+                    ("minted_at".to_string(), Value::Nat(badge.minted_at as u128)),
+                ]
+            })
+        })
+        .collect()
+}
+
+#[ic_cdk::query]
+fn icrc10_supported_standards() -> Vec<SupportedStandard> {
+    [
+        ("ICRC-7", "https://github.com/dfinity/ICRC/ICRCs/ICRC-7"),
+        ("ICRC-10", "https://github.com/dfinity/ICRC/ICRCs/ICRC-10"),
+    ]
+    .into_iter()
+    .map(|(name, url)| SupportedStandard {
+        name: name.to_string(),
+        url: url.to_string(),
+    })
+    .collect()
+}
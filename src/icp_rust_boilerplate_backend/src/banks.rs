@@ -0,0 +1,331 @@
+use crate::guards::only_authenticated;
+use crate::survey::{self, Survey, SurveyPayload, SurveyQuestionPayload};
+use crate::{
+    maintenance, metrics, moderation, validate_question, Error, IdCell, Memory, Quiz,
+    StorablePrincipal, MAX_PAGE_SIZE, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, cell::RefCell};
+
+const MIN_NAME_LEN: usize = 3;
+const MAX_NAME_LEN: usize = 64;
+const MAX_BANK_QUESTIONS: usize = 500;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct QuestionBank {
+    id: u64,
+    name: String,
+    author: Principal,
+    created_at: u64,
+}
+
+impl Storable for QuestionBank {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for QuestionBank {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct BankQuestion {
+    // counts up within the bank
+    id: u64,
+    question: String,
+    options: Vec<String>,
+    correct_option: Option<String>,
+    added_at: u64,
+}
+
+impl Storable for BankQuestion {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for BankQuestion {
+    // the question and the options of a quiz
+    const MAX_SIZE: u32 = Quiz::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct BankQuestionPage {
+    questions: Vec<BankQuestion>,
+    total: u64,
+}
+
+thread_local! {
+    static BANKS: RefCell<StableBTreeMap<u64, QuestionBank, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(80)))
+    ));
+
+    static BANK_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(81))), 0)
+            .expect("Cannot create the question bank counter")
+    );
+
+    // by (bank id, question id)
+    static BANK_QUESTIONS: RefCell<StableBTreeMap<(u64, u64), BankQuestion, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(82)))
+    ));
+
+    // (author, bank id)
+    static BANKS_BY_AUTHOR: RefCell<StableBTreeMap<(StorablePrincipal, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(83)))
+    ));
+}
+
+// only the author of a bank reads or changes its questions.
+fn authored_bank(bank_id: u64) -> Result<QuestionBank, Error> {
+    let bank = BANKS
+        .with(|banks| banks.borrow().get(&bank_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a question bank with id={} not found", bank_id),
+        })?;
+    if bank.author != caller() {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only the author can use the question bank with id={}",
+                bank_id
+            ),
+        });
+    }
+    Ok(bank)
+}
+
+fn question_ids(bank_id: u64) -> Vec<u64> {
+    BANK_QUESTIONS.with(|questions| {
+        questions
+            .borrow()
+            .range((bank_id, 0)..=(bank_id, u64::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+fn create_question_bank(name: String) -> Result<QuestionBank, Error> {
+    metrics::count_call("create_question_bank");
+    maintenance::ensure_available("create_question_bank")?;
+    let len = name.trim().len();
+    if !(MIN_NAME_LEN..=MAX_NAME_LEN).contains(&len) {
+        return Err(Error::ValidationFailed {
+            field: "name".to_string(),
+            msg: format!(
+                "the name must be {} to {} bytes",
+                MIN_NAME_LEN, MAX_NAME_LEN
+            ),
+        });
+    }
+    moderation::ensure_clean("name", &name)?;
+    let id = BANK_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment question bank counter".to_string(),
+        })?;
+    let bank = QuestionBank {
+        id,
+        name: name.trim().to_string(),
+        author: caller(),
+        created_at: time(),
+    };
+    BANKS.with(|banks| banks.borrow_mut().insert(id, bank.clone()));
+    BANKS_BY_AUTHOR.with(|index| {
+        index
+            .borrow_mut()
+            .insert((StorablePrincipal(caller()), id), ())
+    });
+    Ok(bank)
+}
+
+#[ic_cdk::query]
+fn get_my_question_banks() -> Vec<QuestionBank> {
+    let author = StorablePrincipal(caller());
+    BANKS_BY_AUTHOR.with(|index| {
+        index
+            .borrow()
+            .range((author, 0)..=(author, u64::MAX))
+            .filter_map(|((_, id), _)| BANKS.with(|banks| banks.borrow().get(&id)))
+            .collect()
+    })
+}
+
+// Adds the questions to the bank, they are checked like the questions of a quiz and either all
+// of them are added or none.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn add_bank_questions(
+    bank_id: u64,
+    questions: Vec<SurveyQuestionPayload>,
+) -> Result<Vec<BankQuestion>, Error> {
+    metrics::count_call("add_bank_questions");
+    maintenance::ensure_available("add_bank_questions")?;
+    authored_bank(bank_id)?;
+    if questions.len() > MAX_PAGE_SIZE as usize {
+        return Err(Error::ValidationFailed {
+            field: "questions".to_string(),
+            msg: format!("at most {} questions can be added per call", MAX_PAGE_SIZE),
+        });
+    }
+    let ids = question_ids(bank_id);
+    if ids.len() + questions.len() > MAX_BANK_QUESTIONS {
+        return Err(Error::StorageFull {
+            msg: format!(
+                "a question bank holds at most {} questions",
+                MAX_BANK_QUESTIONS
+            ),
+        });
+    }
+    let first_id = ids.last().map_or(0, |last| last + 1);
+    let mut added = Vec::with_capacity(questions.len());
+    for (id, question) in (first_id..).zip(questions) {
+        validate_question(&question.question, &question.options)?;
+        survey::validate_correct_option(&question.options, &question.correct_option)?;
+        let entry = BankQuestion {
+            id,
+            question: question.question,
+            options: question.options,
+            correct_option: question.correct_option,
+            added_at: time(),
+        };
+        if entry.to_bytes().len() > BankQuestion::MAX_SIZE as usize {
+            return Err(Error::TooLarge {
+                msg: format!(
+                    "a question must not exceed {} bytes once encoded",
+                    BankQuestion::MAX_SIZE
+                ),
+            });
+        }
+        added.push(entry);
+    }
+    BANK_QUESTIONS.with(|stored| {
+        let mut stored = stored.borrow_mut();
+        for entry in &added {
+            stored.insert((bank_id, entry.id), entry.clone());
+        }
+    });
+    Ok(added)
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+fn remove_bank_question(bank_id: u64, question_id: u64) -> Result<(), Error> {
+    metrics::count_call("remove_bank_question");
+    maintenance::ensure_available("remove_bank_question")?;
+    authored_bank(bank_id)?;
+    BANK_QUESTIONS
+        .with(|questions| questions.borrow_mut().remove(&(bank_id, question_id)))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "the question bank with id={} has no question {}",
+                bank_id, question_id
+            ),
+        })?;
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_bank_questions(bank_id: u64, offset: u64, limit: u64) -> Result<BankQuestionPage, Error> {
+    authored_bank(bank_id)?;
+    Ok(BANK_QUESTIONS.with(|questions| {
+        let questions = questions.borrow();
+        let range = (bank_id, 0)..=(bank_id, u64::MAX);
+        BankQuestionPage {
+            total: questions.range(range.clone()).count() as u64,
+            questions: questions
+                .range(range)
+                .skip(offset as usize)
+                .take(limit.min(MAX_PAGE_SIZE) as usize)
+                .map(|(_, question)| question)
+                .collect(),
+        }
+    }))
+}
+
+// The first `n` of the ids after a Fisher-Yates shuffle, every swap draws from a sha256 of the
+// seed and its position so 32 random bytes are enough for any bank.
+fn sample(mut ids: Vec<u64>, n: usize, seed: &[u8]) -> Vec<u64> {
+    for position in 0..n {
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        hasher.update((position as u64).to_be_bytes());
+        let digest = hasher.finalize();
+        let mut draw = [0u8; 8];
+        draw.copy_from_slice(&digest[..8]);
+        let remaining = (ids.len() - position) as u64;
+        let pick = position + (u64::from_le_bytes(draw) % remaining) as usize;
+        ids.swap(position, pick);
+    }
+    ids.truncate(n);
+    ids
+}
+
+// Creates a survey of the caller out of `n` questions drawn at random from their bank. An update
+// call, queries can't ask the management canister for randomness.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn generate_quiz_from_bank(bank_id: u64, n: u32, title: String) -> Result<Survey, Error> {
+    metrics::count_call("generate_quiz_from_bank");
+    maintenance::ensure_available("generate_quiz_from_bank")?;
+    authored_bank(bank_id)?;
+    let n = n as usize;
+    if n == 0 || n > survey::MAX_SURVEY_QUESTIONS {
+        return Err(Error::ValidationFailed {
+            field: "n".to_string(),
+            msg: format!(
+                "a survey needs between 1 and {} questions",
+                survey::MAX_SURVEY_QUESTIONS
+            ),
+        });
+    }
+    let (random,) = raw_rand().await.map_err(|(code, msg)| Error::CallFailed {
+        msg: format!("raw_rand failed ({:?}): {}", code, msg),
+    })?;
+
+    // drawn once the randomness is there, questions may have been removed in the meantime
+    authored_bank(bank_id)?;
+    let ids = question_ids(bank_id);
+    if ids.len() < n {
+        return Err(Error::ValidationFailed {
+            field: "n".to_string(),
+            msg: format!(
+                "the question bank with id={} holds only {} questions",
+                bank_id,
+                ids.len()
+            ),
+        });
+    }
+    let questions = BANK_QUESTIONS.with(|questions| {
+        let questions = questions.borrow();
+        sample(ids, n, &random)
+            .into_iter()
+            .filter_map(|id| questions.get(&(bank_id, id)))
+            .map(|question| SurveyQuestionPayload {
+                question: question.question,
+                options: question.options,
+                correct_option: question.correct_option,
+            })
+            .collect()
+    });
+    survey::insert_survey(SurveyPayload { title, questions })
+}
@@ -0,0 +1,281 @@
+use crate::{_get_quiz, allowlist, Error, Quiz, STORAGE};
+use candid::Encode;
+use sha2::{Digest, Sha256};
+use std::{cell::RefCell, collections::BTreeMap};
+
+// Every quiz is certified under the path ["quizzes", bucket, id] with bucket = id / BUCKET_SIZE,
+// both as 8 byte big endian labels. The leaf is the sha256 of the quiz as returned by
+// `get_certified_quiz`. The buckets keep an update from rehashing every quiz.
+const BUCKET_SIZE: u64 = 64;
+const QUIZZES_LABEL: &[u8] = b"quizzes";
+
+type Hash = [u8; 32];
+
+// the hash tree of the interface spec, the witness is sent cbor encoded
+enum HashTree {
+    Empty,
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(Vec<u8>, Box<HashTree>),
+    Leaf(Vec<u8>),
+    Pruned(Hash),
+}
+
+thread_local! {
+    // rebuilt from the stable quizzes after an upgrade, see `certify_all`
+    static LEAVES: RefCell<BTreeMap<u64, Hash>> = const { RefCell::new(BTreeMap::new()) };
+    static BUCKETS: RefCell<BTreeMap<u64, Hash>> = const { RefCell::new(BTreeMap::new()) };
+    // the migrations write quizzes before the tree exists, there's nothing to update then
+    static READY: RefCell<bool> = const { RefCell::new(false) };
+}
+
+fn domain_hash(separator: &str, parts: &[&[u8]]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([separator.len() as u8]);
+    hasher.update(separator.as_bytes());
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+impl HashTree {
+    fn labeled(label: &[u8], tree: HashTree) -> Self {
+        HashTree::Labeled(label.to_vec(), Box::new(tree))
+    }
+
+    fn digest(&self) -> Hash {
+        match self {
+            HashTree::Empty => domain_hash("ic-hashtree-empty", &[]),
+            HashTree::Fork(left, right) => {
+                domain_hash("ic-hashtree-fork", &[&left.digest(), &right.digest()])
+            }
+            HashTree::Labeled(label, tree) => {
+                domain_hash("ic-hashtree-labeled", &[label, &tree.digest()])
+            }
+            HashTree::Leaf(value) => domain_hash("ic-hashtree-leaf", &[value]),
+            HashTree::Pruned(hash) => *hash,
+        }
+    }
+
+    // forks of two pruned trees are pruned right away, so a witness stays logarithmic
+    fn fork(left: HashTree, right: HashTree) -> Self {
+        match (&left, &right) {
+            (HashTree::Pruned(left), HashTree::Pruned(right)) => {
+                HashTree::Pruned(domain_hash("ic-hashtree-fork", &[left, right]))
+            }
+            _ => HashTree::Fork(Box::new(left), Box::new(right)),
+        }
+    }
+
+    // joins the trees into a balanced one, keeping their order so the labels stay sorted
+    fn balanced(mut trees: Vec<HashTree>) -> Self {
+        if trees.is_empty() {
+            return HashTree::Empty;
+        }
+        while trees.len() > 1 {
+            let mut joined = Vec::with_capacity(trees.len().div_ceil(2));
+            let mut trees_iter = trees.into_iter();
+            while let Some(left) = trees_iter.next() {
+                joined.push(match trees_iter.next() {
+                    Some(right) => HashTree::fork(left, right),
+                    None => left,
+                });
+            }
+            trees = joined;
+        }
+        trees.pop().unwrap()
+    }
+
+    fn write_cbor(&self, out: &mut Vec<u8>) {
+        match self {
+            HashTree::Empty => {
+                cbor_head(out, 4, 1);
+                cbor_head(out, 0, 0);
+            }
+            HashTree::Fork(left, right) => {
+                cbor_head(out, 4, 3);
+                cbor_head(out, 0, 1);
+                left.write_cbor(out);
+                right.write_cbor(out);
+            }
+            HashTree::Labeled(label, tree) => {
+                cbor_head(out, 4, 3);
+                cbor_head(out, 0, 2);
+                cbor_bytes(out, label);
+                tree.write_cbor(out);
+            }
+            HashTree::Leaf(value) => {
+                cbor_head(out, 4, 2);
+                cbor_head(out, 0, 3);
+                cbor_bytes(out, value);
+            }
+            HashTree::Pruned(hash) => {
+                cbor_head(out, 4, 2);
+                cbor_head(out, 0, 4);
+                cbor_bytes(out, hash);
+            }
+        }
+    }
+}
+
+fn cbor_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend([major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(value.to_be_bytes());
+        }
+    }
+}
+
+fn cbor_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    cbor_head(out, 2, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn encode_witness(tree: &HashTree) -> Vec<u8> {
+    // the self-describing cbor tag
+    let mut out = vec![0xd9, 0xd9, 0xf7];
+    tree.write_cbor(&mut out);
+    out
+}
+
+fn quiz_data(quiz: &Quiz) -> Vec<u8> {
+    Encode!(quiz).unwrap()
+}
+
+fn bucket_of(id: u64) -> u64 {
+    id / BUCKET_SIZE
+}
+
+fn id_range(bucket: u64) -> std::ops::RangeInclusive<u64> {
+    let first = bucket * BUCKET_SIZE;
+    first..=first + (BUCKET_SIZE - 1)
+}
+
+// the quizzes of a bucket, pruned except for `witness_id` when it is given
+fn bucket_tree(bucket: u64, witness_id: Option<u64>) -> HashTree {
+    LEAVES.with(|leaves| {
+        let quizzes = leaves
+            .borrow()
+            .range(id_range(bucket))
+            .map(|(id, leaf)| {
+                let tree = HashTree::labeled(&id.to_be_bytes(), HashTree::Leaf(leaf.to_vec()));
+                if Some(*id) == witness_id {
+                    tree
+                } else {
+                    HashTree::Pruned(tree.digest())
+                }
+            })
+            .collect();
+        HashTree::balanced(quizzes)
+    })
+}
+
+// the whole tree, pruned except for the path to `witness_id` when it is given
+fn root_tree(witness_id: Option<u64>) -> HashTree {
+    let witness_bucket = witness_id.map(bucket_of);
+    let buckets = BUCKETS.with(|buckets| {
+        buckets
+            .borrow()
+            .iter()
+            .map(|(bucket, hash)| {
+                if Some(*bucket) == witness_bucket {
+                    HashTree::labeled(&bucket.to_be_bytes(), bucket_tree(*bucket, witness_id))
+                } else {
+                    HashTree::Pruned(*hash)
+                }
+            })
+            .collect()
+    });
+    HashTree::labeled(QUIZZES_LABEL, HashTree::balanced(buckets))
+}
+
+fn update_bucket(bucket: u64) {
+    let has_quizzes =
+        LEAVES.with(|leaves| leaves.borrow().range(id_range(bucket)).next().is_some());
+    BUCKETS.with(|buckets| {
+        let mut buckets = buckets.borrow_mut();
+        if has_quizzes {
+            let tree = HashTree::labeled(&bucket.to_be_bytes(), bucket_tree(bucket, None));
+            buckets.insert(bucket, tree.digest());
+        } else {
+            buckets.remove(&bucket);
+        }
+    });
+}
+
+fn set_root() {
+    ic_cdk::api::set_certified_data(&root_tree(None).digest());
+}
+
+// refreshes the certified hash of a quiz after it or its tallies changed, or after it was removed.
+pub fn certify(id: u64) {
+    if !READY.with(|ready| *ready.borrow()) {
+        return;
+    }
+    let leaf = _get_quiz(&id).map(|quiz| -> Hash { Sha256::digest(quiz_data(&quiz)).into() });
+    LEAVES.with(|leaves| match leaf {
+        Some(leaf) => leaves.borrow_mut().insert(id, leaf),
+        None => leaves.borrow_mut().remove(&id),
+    });
+    update_bucket(bucket_of(id));
+    set_root();
+}
+
+// builds the tree from scratch, the heap doesn't survive an upgrade.
+pub fn certify_all() {
+    let ids: Vec<u64> = STORAGE.with(|service| service.borrow().iter().map(|(id, _)| id).collect());
+    LEAVES.with(|leaves| {
+        let mut leaves = leaves.borrow_mut();
+        leaves.clear();
+        for quiz in ids.iter().filter_map(_get_quiz) {
+            leaves.insert(quiz.id, Sha256::digest(quiz_data(&quiz)).into());
+        }
+    });
+    BUCKETS.with(|buckets| buckets.borrow_mut().clear());
+    let mut buckets: Vec<u64> = ids.iter().map(|id| bucket_of(*id)).collect();
+    buckets.dedup();
+    for bucket in buckets {
+        update_bucket(bucket);
+    }
+    READY.with(|ready| *ready.borrow_mut() = true);
+    set_root();
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct CertifiedQuiz {
+    // the candid encoded quiz, its sha256 is the certified leaf
+    data: Vec<u8>,
+    certificate: Vec<u8>,
+    // cbor encoded hash tree revealing the path of the quiz, its root is the certified data
+    witness: Vec<u8>,
+}
+
+// The quiz together with the tallies it carries, in a form a client can check against the root
+// key of the IC instead of trusting the replica that answered.
+#[ic_cdk::query]
+fn get_certified_quiz(id: u64) -> Result<CertifiedQuiz, Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    let certificate = ic_cdk::api::data_certificate().ok_or_else(|| Error::CallFailed {
+        msg: "certificates are only handed out in query calls".to_string(),
+    })?;
+    Ok(CertifiedQuiz {
+        data: quiz_data(&quiz),
+        certificate,
+        witness: encode_witness(&root_tree(Some(id))),
+    })
+}
@@ -0,0 +1,77 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, allowlist, create_quiz, ensure_editor, explanations, maintenance, metrics, numeric,
+    option_items, scoring, Error, Quiz, QuizPayload,
+};
+use ic_cdk::api::time;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct CloneOptions {
+    // the tags and the category
+    keep_tags: bool,
+    // the way of voting, the visibility and how long the quiz runs, the allowed voters aren't
+    // copied
+    keep_settings: bool,
+}
+
+// The copy runs as long as the quiz did, starting now. The reveal phase of a commit-reveal quiz
+// keeps its length too.
+fn shifted_schedule(quiz: &Quiz) -> (Option<u64>, Option<u64>) {
+    let Some(ends_at) = quiz.ends_at else {
+        return (None, None);
+    };
+    let duration = ends_at.saturating_sub(quiz.starts_at.unwrap_or(quiz.created_at));
+    let new_ends_at = time().saturating_add(duration.max(1));
+    let new_reveal_ends_at = quiz
+        .reveal_ends_at
+        .map(|reveal_ends_at| new_ends_at.saturating_add(reveal_ends_at.saturating_sub(ends_at)));
+    (Some(new_ends_at), new_reveal_ends_at)
+}
+
+fn payload_of(quiz: &Quiz, options: &CloneOptions) -> QuizPayload {
+    let mut payload = QuizPayload {
+        question: quiz.question.clone(),
+        options: quiz.options.clone(),
+        rendered: quiz.rendered,
+        ..Default::default()
+    };
+    payload.option_images = option_items::option_images_of(quiz.id);
+    if options.keep_tags {
+        payload.tags = quiz.tags.clone();
+        payload.category = quiz.category.clone();
+    }
+    if options.keep_settings {
+        (payload.ends_at, payload.reveal_ends_at) = shifted_schedule(quiz);
+        payload.voting_mode = quiz.voting_mode.clone();
+        payload.weight_token = quiz.weight_token;
+        payload.credits_per_voter = quiz.credits_per_voter;
+        payload.max_selections = quiz.max_selections;
+        payload.visibility = quiz.visibility.clone();
+        payload.allow_anonymous = quiz.allow_anonymous;
+        payload.vote_cooldown_ns = quiz.vote_cooldown_ns;
+        payload.numeric_range = numeric::numeric_range_of(quiz.id);
+        // the correct options are only passed on by the people who may read them
+        if ensure_editor(quiz).is_ok() {
+            payload.correct_options =
+                Some(scoring::correct_options_of(quiz.id)).filter(|options| !options.is_empty());
+            payload.scoring_policy = scoring::scoring_policy_of(quiz.id);
+            payload.wrong_answer_penalty = scoring::wrong_answer_penalty_of(quiz.id);
+            payload.explanations = explanations::explanations_of(quiz.id);
+            payload.correct_number = numeric::correct_number_of(quiz.id);
+        }
+    }
+    payload
+}
+
+// Creates a new quiz with the question and the options of one the caller can see. The copy has
+// no votes yet and the caller as its author, it counts as a created quiz like any other.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn clone_quiz(id: u64, options: CloneOptions) -> Result<Quiz, Error> {
+    metrics::count_call("clone_quiz");
+    maintenance::ensure_available("clone_quiz")?;
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    create_quiz(payload_of(&quiz, &options))
+}
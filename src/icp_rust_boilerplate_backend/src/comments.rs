@@ -0,0 +1,257 @@
+use crate::guards::only_authenticated;
+use crate::roles::{self, Role};
+use crate::settings::{self, Feature};
+use crate::{
+    allowlist, maintenance, metrics, moderation, notifications, Error, IdCell, Memory, Quiz,
+    MAX_PAGE_SIZE, MEMORY_MANAGER, STORAGE,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MAX_BODY_LEN: usize = 2000;
+// replies to replies nest this deep at most, a top-level comment is at depth 0
+const MAX_DEPTH: u32 = 4;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Comment {
+    id: u64,
+    quiz_id: u64,
+    // the comment this one replies to, `None` for a top-level comment
+    parent_id: Option<u64>,
+    author: Principal,
+    body: String,
+    created_at: u64,
+}
+
+impl Storable for Comment {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Comment {
+    const MAX_SIZE: u32 = 2200;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct CommentPage {
+    comments: Vec<Comment>,
+    total: u64,
+}
+
+thread_local! {
+    static COMMENTS: RefCell<StableBTreeMap<u64, Comment, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(58)))
+    ));
+
+    // (quiz id, comment id) of the top-level comments, ids grow so they are ordered oldest first
+    static COMMENTS_BY_QUIZ: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(59)))
+    ));
+
+    static COMMENT_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(60))), 0)
+            .expect("Cannot create the comment counter")
+    );
+
+    // (parent id, comment id), ordered oldest first like the top-level comments
+    static REPLIES: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(61)))
+    ));
+}
+
+// the quiz comments go to, as long as the caller can see it.
+fn viewable_quiz(quiz_id: u64) -> Result<Quiz, Error> {
+    let quiz = STORAGE
+        .with(|service| service.borrow().get(&quiz_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    allowlist::ensure_can_view(&quiz)?;
+    Ok(quiz)
+}
+
+fn validate_body(body: &str) -> Result<(), Error> {
+    if body.trim().is_empty() || body.len() > MAX_BODY_LEN {
+        return Err(Error::ValidationFailed {
+            field: "body".to_string(),
+            msg: format!("the comment must be 1 to {} bytes", MAX_BODY_LEN),
+        });
+    }
+    moderation::ensure_clean("body", body)
+}
+
+fn get_comment(id: u64) -> Result<Comment, Error> {
+    COMMENTS
+        .with(|comments| comments.borrow().get(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a comment with id={} not found", id),
+        })
+}
+
+// how many replies up the top-level comment of the thread is.
+fn depth_of(comment: &Comment) -> u32 {
+    let mut depth = 0;
+    let mut parent_id = comment.parent_id;
+    while let Some(id) = parent_id {
+        depth += 1;
+        parent_id = COMMENTS
+            .with(|comments| comments.borrow().get(&id))
+            .and_then(|parent| parent.parent_id);
+    }
+    depth
+}
+
+fn reply_ids(parent_id: u64) -> Vec<u64> {
+    REPLIES.with(|replies| {
+        replies
+            .borrow()
+            .range((parent_id, 0)..=(parent_id, u64::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+// drops the comment together with every reply below it.
+fn remove_thread(comment: &Comment) {
+    let mut pending = vec![comment.clone()];
+    while let Some(comment) = pending.pop() {
+        for id in reply_ids(comment.id) {
+            if let Some(reply) = COMMENTS.with(|comments| comments.borrow().get(&id)) {
+                pending.push(reply);
+            }
+        }
+        COMMENTS.with(|comments| comments.borrow_mut().remove(&comment.id));
+        match comment.parent_id {
+            Some(parent_id) => {
+                REPLIES.with(|replies| replies.borrow_mut().remove(&(parent_id, comment.id)))
+            }
+            None => COMMENTS_BY_QUIZ
+                .with(|index| index.borrow_mut().remove(&(comment.quiz_id, comment.id))),
+        };
+    }
+}
+
+// drops the comments of a quiz that is removed for good.
+pub fn remove_comments(quiz_id: u64) {
+    let top_level: Vec<Comment> = COMMENTS_BY_QUIZ.with(|index| {
+        index
+            .borrow()
+            .range((quiz_id, 0)..=(quiz_id, u64::MAX))
+            .filter_map(|((_, id), _)| COMMENTS.with(|comments| comments.borrow().get(&id)))
+            .collect()
+    });
+    for comment in top_level {
+        remove_thread(&comment);
+    }
+}
+
+// a page of the comments one of the indexes holds under the key, with their total count.
+fn page_of(
+    index: &StableBTreeMap<(u64, u64), (), Memory>,
+    key: u64,
+    offset: u64,
+    limit: u64,
+) -> CommentPage {
+    let range = (key, 0)..=(key, u64::MAX);
+    CommentPage {
+        comments: index
+            .range(range.clone())
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .filter_map(|((_, id), _)| COMMENTS.with(|comments| comments.borrow().get(&id)))
+            .collect(),
+        total: index.range(range).count() as u64,
+    }
+}
+
+// Comments on a quiz the caller can see, closed ones included, or replies to a comment on it
+// with a `parent_id`. Principals mentioned as @name are notified.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn add_comment(quiz_id: u64, parent_id: Option<u64>, body: String) -> Result<Comment, Error> {
+    metrics::count_call("add_comment");
+    maintenance::ensure_available("add_comment")?;
+    settings::ensure_enabled(Feature::Comments)?;
+    let quiz = viewable_quiz(quiz_id)?;
+    validate_body(&body)?;
+    if let Some(parent_id) = parent_id {
+        let parent = get_comment(parent_id)?;
+        if parent.quiz_id != quiz_id {
+            return Err(Error::ValidationFailed {
+                field: "parent_id".to_string(),
+                msg: format!("the comment with id={} is on another quiz", parent_id),
+            });
+        }
+        if depth_of(&parent) >= MAX_DEPTH {
+            return Err(Error::ValidationFailed {
+                field: "parent_id".to_string(),
+                msg: format!("replies nest at most {} levels deep", MAX_DEPTH),
+            });
+        }
+    }
+    let id = COMMENT_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment comment counter".to_string(),
+        })?;
+    let comment = Comment {
+        id,
+        quiz_id,
+        parent_id,
+        author: caller(),
+        body,
+        created_at: time(),
+    };
+    COMMENTS.with(|comments| comments.borrow_mut().insert(id, comment.clone()));
+    match parent_id {
+        Some(parent_id) => REPLIES.with(|replies| replies.borrow_mut().insert((parent_id, id), ())),
+        None => COMMENTS_BY_QUIZ.with(|index| index.borrow_mut().insert((quiz_id, id), ())),
+    };
+    notifications::mentions(&comment.body, &quiz, caller());
+    Ok(comment)
+}
+
+// the top-level comments of the quiz, oldest first.
+#[ic_cdk::query]
+fn get_comments(quiz_id: u64, offset: u64, limit: u64) -> Result<CommentPage, Error> {
+    viewable_quiz(quiz_id)?;
+    Ok(COMMENTS_BY_QUIZ.with(|index| page_of(&index.borrow(), quiz_id, offset, limit)))
+}
+
+// the direct replies to the comment, oldest first.
+#[ic_cdk::query]
+fn get_replies(comment_id: u64, offset: u64, limit: u64) -> Result<CommentPage, Error> {
+    let comment = get_comment(comment_id)?;
+    viewable_quiz(comment.quiz_id)?;
+    Ok(REPLIES.with(|replies| page_of(&replies.borrow(), comment_id, offset, limit)))
+}
+
+// Authors delete their own comments, moderators anybody's. The replies below the comment go
+// with it.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn delete_comment(id: u64) -> Result<Comment, Error> {
+    metrics::count_call("delete_comment");
+    maintenance::ensure_available("delete_comment")?;
+    let comment = get_comment(id)?;
+    if comment.author != caller() && !roles::has_role(caller(), Role::Moderator) {
+        return Err(Error::Unauthorized {
+            msg: format!("you can't delete the comment with id={}", id),
+        });
+    }
+    remove_thread(&comment);
+    Ok(comment)
+}
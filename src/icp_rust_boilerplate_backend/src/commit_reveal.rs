@@ -0,0 +1,190 @@
+use crate::guards::not_banned;
+use crate::{
+    _get_quiz, after_vote, audit, ensure_can_vote, maintenance, metrics, ratelimit, record_vote,
+    remove_quiz_entries, tallies, AuditAction, Error, Memory, Quiz, StorablePrincipal, VotingMode,
+    MEMORY_MANAGER,
+};
+use candid::{Decode, Encode};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, cell::RefCell};
+
+const HASH_LEN: usize = 32;
+const MAX_SALT_LEN: usize = 64;
+
+// sha256 over the bytes of the option followed by the salt, sent while the quiz is running
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Commitment {
+    hash: Vec<u8>,
+    committed_at: u64,
+    revealed_at: Option<u64>,
+}
+
+impl Storable for Commitment {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Commitment {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static COMMITMENTS: RefCell<StableBTreeMap<(u64, StorablePrincipal), Commitment, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17)))
+    ));
+}
+
+// a commit-reveal quiz needs both windows, the reveal one starting where voting ends.
+pub fn validate_reveal_window(
+    voting_mode: &VotingMode,
+    ends_at: Option<u64>,
+    reveal_ends_at: Option<u64>,
+) -> Result<(), Error> {
+    match (voting_mode, ends_at, reveal_ends_at) {
+        (VotingMode::CommitReveal, Some(ends_at), Some(reveal_ends_at))
+            if reveal_ends_at > ends_at =>
+        {
+            Ok(())
+        }
+        (VotingMode::CommitReveal, _, _) => Err(Error::ValidationFailed {
+            field: "reveal_ends_at".to_string(),
+            msg: "a commit-reveal quiz needs an end time and a later reveal end time".to_string(),
+        }),
+        (_, _, Some(_)) => Err(Error::ValidationFailed {
+            field: "reveal_ends_at".to_string(),
+            msg: "only commit-reveal quizzes have a reveal phase".to_string(),
+        }),
+        (_, _, None) => Ok(()),
+    }
+}
+
+pub fn remove_commitments(quiz_id: u64) {
+    COMMITMENTS.with(|commitments| remove_quiz_entries(&mut commitments.borrow_mut(), quiz_id));
+}
+
+fn commit_reveal_quiz(id: u64) -> Result<Quiz, Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    if quiz.voting_mode() != VotingMode::CommitReveal {
+        return Err(Error::ValidationFailed {
+            field: "voting_mode".to_string(),
+            msg: format!("the quiz with id={} is not a commit-reveal quiz", id),
+        });
+    }
+    Ok(quiz)
+}
+
+fn vote_hash(option: &str, salt: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(option.as_bytes());
+    hasher.update(salt);
+    hasher.finalize().to_vec()
+}
+
+#[ic_cdk::update(guard = "not_banned")]
+fn commit_vote(id: u64, hash: Vec<u8>) -> Result<(), Error> {
+    metrics::count_call("commit_vote");
+    maintenance::ensure_available("commit_vote")?;
+    ratelimit::check(ratelimit::Action::Vote)?;
+    let quiz = commit_reveal_quiz(id)?;
+    let commit_key = (id, StorablePrincipal(caller()));
+    ensure_can_vote(&quiz, &commit_key)?;
+    if COMMITMENTS.with(|commitments| commitments.borrow().contains_key(&commit_key)) {
+        return Err(Error::AlreadyVoted {
+            msg: format!(
+                "you have already committed a vote on the quiz with id={}",
+                id
+            ),
+        });
+    }
+    if hash.len() != HASH_LEN {
+        return Err(Error::ValidationFailed {
+            field: "hash".to_string(),
+            msg: format!("the hash must be a {} byte sha256 digest", HASH_LEN),
+        });
+    }
+
+    let args = Encode!(&id, &hash).unwrap();
+    COMMITMENTS.with(|commitments| {
+        commitments.borrow_mut().insert(
+            commit_key,
+            Commitment {
+                hash,
+                committed_at: time(),
+                revealed_at: None,
+            },
+        )
+    });
+    audit::record(AuditAction::Commit, id, &args);
+    Ok(())
+}
+
+// only now the vote is counted, the tallies stay empty for the whole voting window.
+#[ic_cdk::update(guard = "not_banned")]
+fn reveal_vote(id: u64, option: String, salt: Vec<u8>) -> Result<Quiz, Error> {
+    metrics::count_call("reveal_vote");
+    maintenance::ensure_available("reveal_vote")?;
+    let quiz = commit_reveal_quiz(id)?;
+    if !quiz.is_closed() {
+        return Err(Error::WrongPhase {
+            msg: format!("the quiz with id={} is still taking commitments", id),
+        });
+    }
+    if quiz.is_revealed() {
+        return Err(Error::WrongPhase {
+            msg: format!("the reveal phase of the quiz with id={} is over", id),
+        });
+    }
+
+    let commit_key = (id, StorablePrincipal(caller()));
+    let mut commitment = COMMITMENTS
+        .with(|commitments| commitments.borrow().get(&commit_key))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("you have no commitment on the quiz with id={}", id),
+        })?;
+    if commitment.revealed_at.is_some() {
+        return Err(Error::AlreadyVoted {
+            msg: format!(
+                "you have already revealed your vote on the quiz with id={}",
+                id
+            ),
+        });
+    }
+    if salt.len() > MAX_SALT_LEN {
+        return Err(Error::ValidationFailed {
+            field: "salt".to_string(),
+            msg: format!("the salt can be at most {} bytes long", MAX_SALT_LEN),
+        });
+    }
+    if !quiz.options.contains(&option) {
+        return Err(Error::ValidationFailed {
+            field: "option".to_string(),
+            msg: format!("The option '{}' is not found for this quiz.", option),
+        });
+    }
+    if vote_hash(&option, &salt) != commitment.hash {
+        return Err(Error::InvalidReveal {
+            msg: "the option and salt don't match the committed hash".to_string(),
+        });
+    }
+
+    tallies::add(&quiz, &option, 1);
+    let args = Encode!(&id, &option, &salt).unwrap();
+    audit::record_vote(id, &args, vec![(option.clone(), 1)], None);
+    record_vote(commit_key, option, None);
+    commitment.revealed_at = Some(time());
+    COMMITMENTS.with(|commitments| commitments.borrow_mut().insert(commit_key, commitment));
+    after_vote(&quiz);
+    Ok(tallies::attach(quiz))
+}
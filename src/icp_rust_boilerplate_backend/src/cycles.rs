@@ -0,0 +1,127 @@
+use crate::guards::only_admin;
+use crate::{maintenance, metrics, Error, Memory, MEMORY_MANAGER};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{canister_balance128, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{Cell, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell, time::Duration};
+
+// the balance is sampled every hour and kept for 30 days
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const MAX_SAMPLES: u64 = 30 * 24;
+
+// who gets told when the balance drops below the threshold, the method is called with the id of
+// this canister and its balance as the arguments
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct AlertTarget {
+    canister: Principal,
+    method: String,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+pub struct CyclesAlert {
+    // no alerts are sent without a threshold
+    threshold: Option<u128>,
+    target: Option<AlertTarget>,
+}
+
+impl Storable for CyclesAlert {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct BalanceSample {
+    timestamp: u64,
+    balance: u128,
+}
+
+thread_local! {
+    // balances by the time they were sampled at
+    static HISTORY: RefCell<StableBTreeMap<u64, u128, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(34)))
+    ));
+
+    static ALERT: RefCell<Cell<CyclesAlert, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(35))),
+            CyclesAlert::default(),
+        )
+        .expect("Cannot create the cycles alert cell")
+    );
+
+    // set once an alert went out, so it is sent once per drop below the threshold
+    static ALERTED: RefCell<bool> = const { RefCell::new(false) };
+}
+
+// timers don't survive upgrades, so this runs from `init` and `post_upgrade`.
+pub fn start_monitoring() {
+    ic_cdk_timers::set_timer_interval(CHECK_INTERVAL, check_balance);
+}
+
+fn check_balance() {
+    let balance = canister_balance128();
+    HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        history.insert(time(), balance);
+        while history.len() > MAX_SAMPLES {
+            let oldest = history
+                .iter()
+                .next()
+                .map(|(timestamp, _)| timestamp)
+                .unwrap();
+            history.remove(&oldest);
+        }
+    });
+
+    let alert = ALERT.with(|alert| alert.borrow().get().clone());
+    let below = alert.threshold.is_some_and(|threshold| balance < threshold);
+    let alerted = ALERTED.with(|alerted| std::mem::replace(&mut *alerted.borrow_mut(), below));
+    if below && !alerted {
+        ic_cdk::println!("the cycle balance dropped to {}", balance);
+        if let Some(target) = alert.target {
+            // a one-way call, the monitoring doesn't wait for the target or care about its answer
+            let sent =
+                ic_cdk::api::call::notify(target.canister, &target.method, (ic_cdk::id(), balance));
+            if let Err(code) = sent {
+                ic_cdk::println!("the cycles alert couldn't be sent: {:?}", code);
+            }
+        }
+    }
+}
+
+#[ic_cdk::query(guard = "only_admin")]
+fn get_cycles_history() -> Result<Vec<BalanceSample>, Error> {
+    Ok(HISTORY.with(|history| {
+        history
+            .borrow()
+            .iter()
+            .map(|(timestamp, balance)| BalanceSample { timestamp, balance })
+            .collect()
+    }))
+}
+
+#[ic_cdk::query(guard = "only_admin")]
+fn get_cycles_alert() -> Result<CyclesAlert, Error> {
+    Ok(ALERT.with(|alert| alert.borrow().get().clone()))
+}
+
+#[ic_cdk::update(guard = "only_admin")]
+fn set_cycles_alert(alert: CyclesAlert) -> Result<CyclesAlert, Error> {
+    metrics::count_call("set_cycles_alert");
+    maintenance::ensure_available("set_cycles_alert")?;
+    ALERT
+        .with(|cell| cell.borrow_mut().set(alert.clone()))
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the cycles alert".to_string(),
+        })?;
+    // a new threshold is checked against the next sample from scratch
+    ALERTED.with(|alerted| *alerted.borrow_mut() = false);
+    Ok(alert)
+}
@@ -0,0 +1,483 @@
+use crate::guards::{only_admin, only_authenticated};
+use crate::icrc::{self, Account};
+use crate::{
+    governance, id_key_range, maintenance, metrics, Error, Memory, Quiz, StorablePrincipal,
+    MEMORY_MANAGER, VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, Cell, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use std::{borrow::Cow, cell::RefCell};
+
+// deposits paid ahead of the quizzes they are for, per principal
+const MAX_CREDITS: usize = 10;
+// the first byte of the subaccount holding the deposits, see `prize_pools` for tag 1
+const DEPOSIT_SUBACCOUNT_TAG: u8 = 2;
+// A failed refund is tried again every 15 minutes for 10 hours, inside the day the ledger
+// deduplicates transfers. `retry_deposit_refund` starts over after that.
+const RETRY_NS: u64 = 15 * 60 * 1_000_000_000;
+const MAX_REFUND_ATTEMPTS: u32 = 40;
+const MAX_ERROR_LEN: usize = 200;
+
+// What creating a quiz costs while it is set. The deposit comes back once the quiz has
+// `participation_threshold` voters or closes, moderators removing it keep it.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct DepositSettings {
+    ledger: Principal,
+    amount: u128,
+    participation_threshold: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Settings {
+    deposit: Option<DepositSettings>,
+}
+
+impl Storable for Settings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum DepositStatus {
+    // paid, the quiz hasn't earned it back yet
+    Held,
+    Refunding,
+    Refunded,
+    // the quiz was removed by a moderator or purged before it earned the deposit back
+    Forfeited,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Deposit {
+    depositor: Principal,
+    // None until a quiz is created with it
+    quiz_id: Option<u64>,
+    // the settings at the time it was paid, later changes don't apply to it
+    ledger: Principal,
+    amount: u128,
+    participation_threshold: u64,
+    paid_at: u64,
+    block_index: u128,
+    status: DepositStatus,
+    // every attempt of a refund sends the same transfer, so the ledger never refunds it twice
+    refund_amount: Option<u128>,
+    refund_created_at: Option<u64>,
+    refund_attempts: u32,
+    next_attempt_at: Option<u64>,
+    last_error: Option<String>,
+    refunded_at: Option<u64>,
+    refund_block_index: Option<u128>,
+    // the ledger turned the last refund down without sending it, the next one starts anew
+    rejected: Option<bool>,
+}
+
+impl Storable for Deposit {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Deposit {
+    // two principals and an error message of at most MAX_ERROR_LEN bytes
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static SETTINGS: RefCell<Cell<Settings, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(111))),
+            Settings::default(),
+        )
+        .expect("Cannot create the deposit settings cell")
+    );
+
+    // deposits not used for a quiz yet, by (depositor, paid at)
+    static CREDITS: RefCell<StableBTreeMap<(StorablePrincipal, u64), Deposit, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(112)))
+    ));
+
+    // by quiz id, kept after the quiz is gone as a record of the deposit
+    static DEPOSITS: RefCell<StableBTreeMap<u64, Deposit, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(113)))
+    ));
+
+    // principals whose deposit is being paid, a second payment is turned away meanwhile
+    static IN_FLIGHT: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
+    // set while `process_refunds` runs, so a refund isn't sent twice at once
+    static REFUNDING: RefCell<bool> = const { RefCell::new(false) };
+}
+
+fn settings() -> Option<DepositSettings> {
+    SETTINGS.with(|settings| settings.borrow().get().deposit.clone())
+}
+
+// the subaccount of the canister holding every deposit
+fn deposit_subaccount() -> Vec<u8> {
+    let mut subaccount = vec![0; 32];
+    subaccount[0] = DEPOSIT_SUBACCOUNT_TAG;
+    subaccount
+}
+
+fn credit_range(principal: Principal) -> std::ops::Range<(StorablePrincipal, u64)> {
+    (StorablePrincipal(principal), 0)..(StorablePrincipal(principal), u64::MAX)
+}
+
+fn credits_of(principal: Principal) -> Vec<((StorablePrincipal, u64), Deposit)> {
+    CREDITS.with(|credits| credits.borrow().range(credit_range(principal)).collect())
+}
+
+// Only admins set the deposit, None lets anybody create quizzes for free again. Deposits already
+// paid keep the terms they were paid under.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_creation_deposit(deposit: Option<DepositSettings>) -> Result<(), Error> {
+    metrics::count_call("set_creation_deposit");
+    maintenance::ensure_available("set_creation_deposit")?;
+    governance::ensure_ungoverned("creation deposits")?;
+    apply_creation_deposit(deposit)
+}
+
+pub fn apply_creation_deposit(deposit: Option<DepositSettings>) -> Result<(), Error> {
+    let msg = match &deposit {
+        Some(deposit) if deposit.ledger == Principal::anonymous() => {
+            "the deposit needs the principal of its ledger"
+        }
+        Some(deposit) if deposit.amount == 0 => {
+            "a deposit has to be more than nothing, None removes it"
+        }
+        _ => {
+            SETTINGS
+                .with(|settings| settings.borrow_mut().set(Settings { deposit }))
+                .map_err(|_| Error::StorageFull {
+                    msg: "cannot store the deposit settings".to_string(),
+                })?;
+            return Ok(());
+        }
+    };
+    Err(Error::ValidationFailed {
+        field: "deposit".to_string(),
+        msg: msg.to_string(),
+    })
+}
+
+#[ic_cdk::query]
+fn get_creation_deposit() -> Option<DepositSettings> {
+    settings()
+}
+
+// Pays the deposit for the next quiz of the caller with `icrc2_transfer_from`, approve the
+// canister for the amount and the fee first. A deposit paid ahead is used up by the next quiz
+// the caller creates, even once deposits are no longer required.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn pay_creation_deposit() -> Result<Deposit, Error> {
+    metrics::count_call("pay_creation_deposit");
+    maintenance::ensure_available("pay_creation_deposit")?;
+    let settings = settings().ok_or_else(|| Error::NotFound {
+        msg: "creating a quiz takes no deposit".to_string(),
+    })?;
+    let depositor = caller();
+    if credits_of(depositor).len() >= MAX_CREDITS {
+        return Err(Error::StorageFull {
+            msg: format!("at most {} deposits can be paid ahead", MAX_CREDITS),
+        });
+    }
+    let busy = IN_FLIGHT.with(|in_flight| {
+        let mut in_flight = in_flight.borrow_mut();
+        let busy = in_flight.contains(&depositor);
+        if !busy {
+            in_flight.push(depositor);
+        }
+        busy
+    });
+    if busy {
+        return Err(Error::ValidationFailed {
+            field: "deposit".to_string(),
+            msg: "another deposit of yours is being paid".to_string(),
+        });
+    }
+
+    let paid_at = time();
+    let memo = Sha256::new()
+        .chain_update(b"creation-deposit")
+        .chain_update(depositor.as_slice())
+        .chain_update(paid_at.to_be_bytes())
+        .finalize()
+        .to_vec();
+    let transfer = icrc::transfer_from(
+        settings.ledger,
+        Account::of(depositor),
+        Account {
+            owner: ic_cdk::id(),
+            subaccount: Some(deposit_subaccount()),
+        },
+        settings.amount,
+        memo,
+        paid_at,
+    )
+    .await;
+    IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().retain(|entry| *entry != depositor));
+
+    let deposit = Deposit {
+        depositor,
+        quiz_id: None,
+        ledger: settings.ledger,
+        amount: settings.amount,
+        participation_threshold: settings.participation_threshold,
+        paid_at,
+        block_index: transfer?,
+        status: DepositStatus::Held,
+        refund_amount: None,
+        refund_created_at: None,
+        refund_attempts: 0,
+        next_attempt_at: None,
+        last_error: None,
+        refunded_at: None,
+        refund_block_index: None,
+        rejected: None,
+    };
+    CREDITS.with(|credits| {
+        credits
+            .borrow_mut()
+            .insert((StorablePrincipal(depositor), paid_at), deposit.clone())
+    });
+    Ok(deposit)
+}
+
+#[ic_cdk::query]
+fn list_my_deposits() -> Vec<Deposit> {
+    let principal = caller();
+    let mut deposits: Vec<Deposit> = credits_of(principal)
+        .into_iter()
+        .map(|(_, deposit)| deposit)
+        .collect();
+    deposits.extend(DEPOSITS.with(|deposits| {
+        deposits
+            .borrow()
+            .iter()
+            .map(|(_, deposit)| deposit)
+            .filter(|deposit| deposit.depositor == principal)
+            .collect::<Vec<_>>()
+    }));
+    deposits
+}
+
+// Checks that the caller paid for `count` new quizzes, see `create_batch`.
+pub fn ensure_paid(count: usize) -> Result<(), Error> {
+    let Some(settings) = settings() else {
+        return Ok(());
+    };
+    let paid = credits_of(caller()).len();
+    if paid >= count {
+        return Ok(());
+    }
+    Err(Error::ValidationFailed {
+        field: "deposit".to_string(),
+        msg: format!(
+            "creating a quiz takes a deposit of {} on {}, {} of {} paid with pay_creation_deposit",
+            settings.amount, settings.ledger, paid, count
+        ),
+    })
+}
+
+// Ties the oldest deposit the caller paid to their new quiz.
+pub fn attach(quiz: &Quiz) {
+    let Some((key, mut deposit)) = credits_of(caller()).into_iter().next() else {
+        return;
+    };
+    CREDITS.with(|credits| credits.borrow_mut().remove(&key));
+    deposit.quiz_id = Some(quiz.id);
+    DEPOSITS.with(|deposits| deposits.borrow_mut().insert(quiz.id, deposit));
+    // a threshold of nothing is met right away
+    record_vote(quiz.id);
+}
+
+fn held(quiz_id: u64) -> Option<Deposit> {
+    DEPOSITS
+        .with(|deposits| deposits.borrow().get(&quiz_id))
+        .filter(|deposit| deposit.status == DepositStatus::Held)
+}
+
+fn release(quiz_id: u64, mut deposit: Deposit) {
+    deposit.status = DepositStatus::Refunding;
+    deposit.next_attempt_at = Some(time());
+    DEPOSITS.with(|deposits| deposits.borrow_mut().insert(quiz_id, deposit));
+    schedule_refunds(Duration::ZERO);
+}
+
+// Refunds the deposit once enough voters took part, anonymous votes aren't counted.
+pub fn record_vote(quiz_id: u64) {
+    let Some(deposit) = held(quiz_id) else {
+        return;
+    };
+    let threshold = deposit.participation_threshold as usize;
+    let voters = VOTES.with(|votes| {
+        votes
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .take(threshold)
+            .count()
+    });
+    if voters >= threshold {
+        release(quiz_id, deposit);
+    }
+}
+
+// a quiz that reached its end time without being removed earned its deposit back.
+pub fn quiz_closed(quiz: &Quiz) {
+    if quiz.deleted_at.is_some() {
+        return;
+    }
+    if let Some(deposit) = held(quiz.id) {
+        release(quiz.id, deposit);
+    }
+}
+
+// Keeps the deposit of a quiz moderators removed, or one purged before it earned the deposit
+// back. It stays on the deposit subaccount of the canister.
+pub fn forfeit(quiz_id: u64) {
+    if let Some(mut deposit) = held(quiz_id) {
+        deposit.status = DepositStatus::Forfeited;
+        DEPOSITS.with(|deposits| deposits.borrow_mut().insert(quiz_id, deposit));
+    }
+}
+
+fn schedule_refunds(delay: Duration) {
+    ic_cdk_timers::set_timer(delay, || ic_cdk::spawn(process_refunds()));
+}
+
+// Sends every refund that is due, less the fee of its transfer.
+async fn process_refunds() {
+    if REFUNDING.with(|refunding| refunding.replace(true)) {
+        return;
+    }
+    let due: Vec<(u64, Deposit)> = DEPOSITS.with(|deposits| {
+        deposits
+            .borrow()
+            .iter()
+            .filter(|(_, deposit)| deposit.next_attempt_at.is_some_and(|at| at <= time()))
+            .collect()
+    });
+    for (quiz_id, mut deposit) in due {
+        // a refund that went out may still land, only one that was turned down gets a new time
+        if deposit.rejected.take() == Some(true) {
+            deposit.refund_amount = None;
+            deposit.refund_created_at = None;
+        }
+        let result = match deposit.refund_amount.zip(deposit.refund_created_at) {
+            Some(refund) => Ok(refund),
+            None => icrc::fee(deposit.ledger)
+                .await
+                .map(|fee| (deposit.amount.saturating_sub(fee), time())),
+        };
+        let result = match result {
+            Ok((amount, created_at)) => {
+                deposit.refund_amount = Some(amount);
+                deposit.refund_created_at = Some(created_at);
+                let memo = Sha256::new()
+                    .chain_update(b"creation-deposit-refund")
+                    .chain_update(quiz_id.to_be_bytes())
+                    .chain_update(created_at.to_be_bytes())
+                    .finalize()
+                    .to_vec();
+                icrc::try_transfer(
+                    deposit.ledger,
+                    Some(deposit_subaccount()),
+                    Account::of(deposit.depositor),
+                    amount,
+                    memo,
+                    created_at,
+                )
+                .await
+            }
+            Err(error) => Err((error, false)),
+        };
+
+        deposit.refund_attempts += 1;
+        match result {
+            Ok(block_index) => {
+                deposit.status = DepositStatus::Refunded;
+                deposit.next_attempt_at = None;
+                deposit.refunded_at = Some(time());
+                deposit.refund_block_index = Some(block_index);
+            }
+            Err((error, rejected)) => {
+                deposit.rejected = rejected.then_some(true);
+                let mut msg = match error {
+                    Error::CallFailed { msg } => msg,
+                    _ => "the refund failed".to_string(),
+                };
+                if msg.len() > MAX_ERROR_LEN {
+                    let mut end = MAX_ERROR_LEN;
+                    while !msg.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    msg.truncate(end);
+                }
+                deposit.last_error = Some(msg);
+                deposit.next_attempt_at = match deposit.refund_attempts >= MAX_REFUND_ATTEMPTS {
+                    true => None,
+                    false => Some(time().saturating_add(RETRY_NS)),
+                };
+            }
+        }
+        DEPOSITS.with(|deposits| deposits.borrow_mut().insert(quiz_id, deposit));
+    }
+    REFUNDING.with(|refunding| refunding.replace(false));
+    resume_refunds();
+}
+
+// timers don't survive upgrades, see `post_upgrade`.
+pub fn resume_refunds() {
+    let next = DEPOSITS.with(|deposits| {
+        deposits
+            .borrow()
+            .iter()
+            .filter_map(|(_, deposit)| deposit.next_attempt_at)
+            .min()
+    });
+    if let Some(next) = next {
+        schedule_refunds(Duration::from_nanos(next.saturating_sub(time())));
+    }
+}
+
+// Starts the refund of a deposit over after every attempt failed. The attempts send the same
+// transfer as before unless the ledger turned it down, so the deposit is never refunded twice.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn retry_deposit_refund(quiz_id: u64) -> Result<Deposit, Error> {
+    metrics::count_call("retry_deposit_refund");
+    maintenance::ensure_available("retry_deposit_refund")?;
+    let mut deposit = DEPOSITS
+        .with(|deposits| deposits.borrow().get(&quiz_id))
+        .filter(|deposit| deposit.depositor == caller())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("you paid no deposit for the quiz with id={}", quiz_id),
+        })?;
+    if deposit.status != DepositStatus::Refunding || deposit.next_attempt_at.is_some() {
+        return Err(Error::WrongPhase {
+            msg: format!(
+                "the deposit of the quiz with id={} isn't waiting for a retry",
+                quiz_id
+            ),
+        });
+    }
+    deposit.refund_attempts = 0;
+    deposit.next_attempt_at = Some(time());
+    DEPOSITS.with(|deposits| deposits.borrow_mut().insert(quiz_id, deposit.clone()));
+    schedule_refunds(Duration::ZERO);
+    Ok(deposit)
+}
@@ -0,0 +1,324 @@
+use crate::guards::{not_banned, only_admin};
+use crate::{
+    allowlist, governance, maintenance, metrics, tallies, Error, Memory, Quiz, StorablePrincipal,
+    MAX_PAGE_SIZE, MEMORY_MANAGER, STORAGE, VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{Cell, StableBTreeMap, Storable};
+use std::cmp::Reverse;
+use std::{borrow::Cow, cell::RefCell, time::Duration};
+
+// open quizzes the principal can see and hasn't voted on yet, by id.
+pub fn unanswered_quizzes(principal: StorablePrincipal) -> Vec<u64> {
+    STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .filter(|(id, quiz)| {
+                !quiz.is_closed()
+                    && allowlist::can_view(quiz, principal.0)
+                    && !VOTES.with(|votes| votes.borrow().contains_key(&(*id, principal)))
+            })
+            .map(|(id, _)| id)
+            .collect()
+    })
+}
+
+// A "surprise me" pick. An update call, queries can't ask the management canister for
+// randomness, and replicas picking on their own would disagree.
+#[ic_cdk::update(guard = "not_banned")]
+async fn get_random_quiz() -> Result<Quiz, Error> {
+    metrics::count_call("get_random_quiz");
+    maintenance::ensure_available("get_random_quiz")?;
+    let (random,) = raw_rand().await.map_err(|(code, msg)| Error::CallFailed {
+        msg: format!("raw_rand failed ({:?}): {}", code, msg),
+    })?;
+
+    // picked once the randomness is there, quizzes may have closed in the meantime
+    let candidates = unanswered_quizzes(StorablePrincipal(caller()));
+    if candidates.is_empty() {
+        return Err(Error::NotFound {
+            msg: "there is no open quiz left you haven't answered".to_string(),
+        });
+    }
+    let mut seed = [0u8; 8];
+    seed.copy_from_slice(&random[..8]);
+    let pick = candidates[(u64::from_le_bytes(seed) % candidates.len() as u64) as usize];
+    let quiz = STORAGE.with(|service| service.borrow().get(&pick)).unwrap();
+    Ok(tallies::attach(quiz))
+}
+
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// the day is checked every hour, so a new quiz is featured within an hour past midnight utc
+const ROTATION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// a featured quiz isn't picked again for this many days, unless nothing else is open
+const RECENT_DAYS: u64 = 30;
+const MAX_HISTORY_DAYS: u64 = 365;
+
+// an admin's pick, featured every day until it is unpinned
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct FeaturedPin {
+    quiz_id: Option<u64>,
+}
+
+impl Storable for FeaturedPin {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct FeaturedQuiz {
+    // days since the unix epoch, in utc
+    day: u64,
+    quiz_id: u64,
+}
+
+thread_local! {
+    // the quiz featured on each day, days without an open quiz have no entry
+    static FEATURED: RefCell<StableBTreeMap<u64, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(43)))
+    ));
+
+    static PIN: RefCell<Cell<FeaturedPin, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(44))),
+            FeaturedPin::default(),
+        )
+        .expect("Cannot create the featured quiz pin")
+    );
+}
+
+fn today() -> u64 {
+    time() / DAY_NS
+}
+
+// quizzes anybody following a link could vote on
+fn is_featurable(quiz: &Quiz) -> bool {
+    !quiz.is_closed() && allowlist::can_view(quiz, Principal::anonymous())
+}
+
+// timers don't survive upgrades, so this runs from `init` and `post_upgrade`.
+pub fn start_rotation() {
+    rotate();
+    ic_cdk_timers::set_timer_interval(ROTATION_INTERVAL, rotate);
+}
+
+fn rotate() {
+    let day = today();
+    if FEATURED.with(|featured| featured.borrow().contains_key(&day)) {
+        return;
+    }
+    if let Some(quiz_id) = pinned_quiz().or_else(|| pick_quiz(day)) {
+        FEATURED.with(|featured| featured.borrow_mut().insert(day, quiz_id));
+    }
+    FEATURED.with(|featured| {
+        let mut featured = featured.borrow_mut();
+        let expired: Vec<u64> = featured
+            .range(..day.saturating_sub(MAX_HISTORY_DAYS))
+            .map(|(day, _)| day)
+            .collect();
+        for day in expired {
+            featured.remove(&day);
+        }
+    });
+}
+
+fn pinned_quiz() -> Option<u64> {
+    PIN.with(|pin| pin.borrow().get().quiz_id).filter(|id| {
+        STORAGE
+            .with(|service| service.borrow().get(id))
+            .is_some_and(|quiz| is_featurable(&quiz))
+    })
+}
+
+// The same day always picks the same quiz out of the same candidates, quizzes featured lately
+// only come back once nothing else is left.
+fn pick_quiz(day: u64) -> Option<u64> {
+    let recent: Vec<u64> = FEATURED.with(|featured| {
+        featured
+            .borrow()
+            .range(day.saturating_sub(RECENT_DAYS)..day)
+            .map(|(_, quiz_id)| quiz_id)
+            .collect()
+    });
+    let open: Vec<u64> = STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .filter(|(_, quiz)| is_featurable(quiz))
+            .map(|(id, _)| id)
+            .collect()
+    });
+    let fresh: Vec<u64> = open
+        .iter()
+        .copied()
+        .filter(|id| !recent.contains(id))
+        .collect();
+    let candidates = if fresh.is_empty() { open } else { fresh };
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(candidates[(day % candidates.len() as u64) as usize])
+}
+
+// The quiz featured today. Until today's pick is made, yesterday's stays featured.
+#[ic_cdk::query]
+fn get_quiz_of_the_day() -> Result<Quiz, Error> {
+    let day = today();
+    FEATURED
+        .with(|featured| {
+            let featured = featured.borrow();
+            featured
+                .get(&day)
+                .or_else(|| featured.get(&day.saturating_sub(1)))
+        })
+        .and_then(|quiz_id| STORAGE.with(|service| service.borrow().get(&quiz_id)))
+        .filter(|quiz| allowlist::can_view(quiz, caller()))
+        .map(tallies::attach)
+        .ok_or_else(|| Error::NotFound {
+            msg: "no quiz is featured today".to_string(),
+        })
+}
+
+// the quizzes featured during the last `days` days, the latest first.
+#[ic_cdk::query]
+fn get_featured_history(days: u64) -> Vec<FeaturedQuiz> {
+    let first_day = today().saturating_sub(days.min(MAX_PAGE_SIZE));
+    let mut history: Vec<FeaturedQuiz> = FEATURED.with(|featured| {
+        featured
+            .borrow()
+            .range(first_day..)
+            .map(|(day, quiz_id)| FeaturedQuiz { day, quiz_id })
+            .collect()
+    });
+    history.reverse();
+    history
+}
+
+// Features the quiz every day from now on, `None` goes back to the rotation from tomorrow on.
+#[ic_cdk::update(guard = "only_admin")]
+fn pin_quiz_of_the_day(quiz_id: Option<u64>) -> Result<(), Error> {
+    metrics::count_call("pin_quiz_of_the_day");
+    maintenance::ensure_available("pin_quiz_of_the_day")?;
+    governance::ensure_ungoverned("featured quizzes")?;
+    pin(quiz_id)
+}
+
+pub fn pin(quiz_id: Option<u64>) -> Result<(), Error> {
+    if let Some(id) = quiz_id {
+        let quiz = STORAGE
+            .with(|service| service.borrow().get(&id))
+            .ok_or_else(|| Error::NotFound {
+                msg: format!("a quiz with id={} not found", id),
+            })?;
+        if !is_featurable(&quiz) {
+            return Err(Error::ValidationFailed {
+                field: "quiz_id".to_string(),
+                msg: format!("the quiz with id={} isn't open to everybody", id),
+            });
+        }
+    }
+    PIN.with(|pin| pin.borrow_mut().set(FeaturedPin { quiz_id }))
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the featured quiz pin".to_string(),
+        })?;
+    if let Some(id) = quiz_id {
+        FEATURED.with(|featured| featured.borrow_mut().insert(today(), id));
+    }
+    Ok(())
+}
+
+// editorial picks are shown all at once, so there are only a few of them
+const MAX_EDITORIAL_PICKS: u64 = 50;
+
+thread_local! {
+    // quizzes admins feature in the editorial section, by id with the time they were featured at
+    static EDITORIAL_PICKS: RefCell<StableBTreeMap<u64, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(65)))
+    ));
+}
+
+// an editorial pick goes together with the quiz, see `remove_quiz`.
+pub fn remove_editorial_pick(quiz_id: u64) {
+    EDITORIAL_PICKS.with(|picks| picks.borrow_mut().remove(&quiz_id));
+}
+
+// the quizzes of the editorial section, the latest pick first.
+#[ic_cdk::query]
+fn get_featured() -> Vec<Quiz> {
+    let mut picks: Vec<(u64, u64)> = EDITORIAL_PICKS.with(|picks| picks.borrow().iter().collect());
+    picks.sort_by_key(|(quiz_id, featured_at)| (Reverse(*featured_at), *quiz_id));
+    picks
+        .into_iter()
+        .filter_map(|(quiz_id, _)| STORAGE.with(|service| service.borrow().get(&quiz_id)))
+        .filter(|quiz| allowlist::can_view(quiz, caller()))
+        .map(tallies::attach)
+        .collect()
+}
+
+// Adds the quiz to the editorial section. Closed quizzes can be featured for their results, but
+// only quizzes everybody can see.
+#[ic_cdk::update(guard = "only_admin")]
+fn feature_quiz(id: u64) -> Result<(), Error> {
+    metrics::count_call("feature_quiz");
+    maintenance::ensure_available("feature_quiz")?;
+    governance::ensure_ungoverned("featured quizzes")?;
+    feature(id)
+}
+
+pub fn feature(id: u64) -> Result<(), Error> {
+    let quiz = STORAGE
+        .with(|service| service.borrow().get(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", id),
+        })?;
+    if !allowlist::can_view(&quiz, Principal::anonymous()) {
+        return Err(Error::ValidationFailed {
+            field: "id".to_string(),
+            msg: format!("the quiz with id={} isn't visible to everybody", id),
+        });
+    }
+    EDITORIAL_PICKS.with(|picks| {
+        let mut picks = picks.borrow_mut();
+        if picks.contains_key(&id) {
+            return Err(Error::ValidationFailed {
+                field: "id".to_string(),
+                msg: format!("the quiz with id={} is featured already", id),
+            });
+        }
+        if picks.len() >= MAX_EDITORIAL_PICKS {
+            return Err(Error::StorageFull {
+                msg: format!("at most {} quizzes can be featured", MAX_EDITORIAL_PICKS),
+            });
+        }
+        picks.insert(id, time());
+        Ok(())
+    })
+}
+
+#[ic_cdk::update(guard = "only_admin")]
+fn unfeature_quiz(id: u64) -> Result<(), Error> {
+    metrics::count_call("unfeature_quiz");
+    maintenance::ensure_available("unfeature_quiz")?;
+    governance::ensure_ungoverned("featured quizzes")?;
+    unfeature(id)
+}
+
+pub fn unfeature(id: u64) -> Result<(), Error> {
+    EDITORIAL_PICKS
+        .with(|picks| picks.borrow_mut().remove(&id))
+        .map(|_| ())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("the quiz with id={} isn't featured", id),
+        })
+}
@@ -0,0 +1,113 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, allowlist, ensure_author, id_key_range, maintenance, metrics, remove_quiz_entries,
+    Error, Memory, Quiz, StorablePrincipal, MEMORY_MANAGER,
+};
+use candid::Principal;
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+const MAX_EDITORS: usize = 20;
+
+thread_local! {
+    // principals who may edit a quiz besides its author, they can't delete or transfer it
+    static EDITORS: RefCell<StableBTreeMap<(u64, StorablePrincipal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(70)))
+    ));
+}
+
+pub fn is_editor(quiz_id: u64, principal: Principal) -> bool {
+    EDITORS.with(|editors| {
+        editors
+            .borrow()
+            .contains_key(&(quiz_id, StorablePrincipal(principal)))
+    })
+}
+
+fn editors_of(quiz_id: u64) -> Vec<Principal> {
+    EDITORS.with(|editors| {
+        editors
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|((_, editor), _)| editor.0)
+            .collect()
+    })
+}
+
+pub fn revoke(quiz_id: u64, principal: Principal) {
+    EDITORS.with(|editors| {
+        editors
+            .borrow_mut()
+            .remove(&(quiz_id, StorablePrincipal(principal)))
+    });
+}
+
+// the editors go together with the quiz, see `remove_quiz`.
+pub fn remove_editors(quiz_id: u64) {
+    EDITORS.with(|editors| remove_quiz_entries(&mut editors.borrow_mut(), quiz_id));
+}
+
+fn live_quiz(quiz_id: u64) -> Result<Quiz, Error> {
+    _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })
+}
+
+// only the author picks the editors.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn add_editor(quiz_id: u64, principal: Principal) -> Result<(), Error> {
+    metrics::count_call("add_editor");
+    maintenance::ensure_available("add_editor")?;
+    let quiz = live_quiz(quiz_id)?;
+    ensure_author(&quiz)?;
+    if principal == Principal::anonymous() || quiz.author == Some(principal) {
+        return Err(Error::ValidationFailed {
+            field: "principal".to_string(),
+            msg: "neither the anonymous principal nor the author can be an editor".to_string(),
+        });
+    }
+    if !is_editor(quiz_id, principal) && editors_of(quiz_id).len() >= MAX_EDITORS {
+        return Err(Error::StorageFull {
+            msg: format!("a quiz can have at most {} editors", MAX_EDITORS),
+        });
+    }
+    EDITORS.with(|editors| {
+        editors
+            .borrow_mut()
+            .insert((quiz_id, StorablePrincipal(principal)), ())
+    });
+    Ok(())
+}
+
+// the author removes any editor, an editor only themselves.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn remove_editor(quiz_id: u64, principal: Principal) -> Result<(), Error> {
+    metrics::count_call("remove_editor");
+    maintenance::ensure_available("remove_editor")?;
+    let quiz = live_quiz(quiz_id)?;
+    if principal != caller() {
+        ensure_author(&quiz)?;
+    }
+    if !is_editor(quiz_id, principal) {
+        return Err(Error::NotFound {
+            msg: format!(
+                "{} isn't an editor of the quiz with id={}",
+                principal, quiz_id
+            ),
+        });
+    }
+    revoke(quiz_id, principal);
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn list_editors(quiz_id: u64) -> Result<Vec<Principal>, Error> {
+    let quiz = live_quiz(quiz_id)?;
+    allowlist::ensure_can_view(&quiz)?;
+    Ok(editors_of(quiz_id))
+}
@@ -0,0 +1,313 @@
+use crate::guards::{only_admin, only_authenticated};
+use crate::icrc::{self, Account};
+use crate::{
+    _get_quiz, allowlist, ensure_author, maintenance, metrics, prize_pools, Error, Memory, Quiz,
+    StorablePrincipal, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, Cell, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, cell::RefCell};
+
+// the ckBTC ledger on the mainnet, amounts on it are in satoshis
+const CKBTC_LEDGER: &str = "mxzaz-hqaaa-aaaar-qaada-cai";
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// how long after the end of the quiz the pool the entries went to can't be taken back
+const POOL_GRACE_NS: u64 = 30 * DAY_NS;
+
+// a local replica or a test network has a ckBTC ledger of its own
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct EntrySettings {
+    ckbtc_ledger: Option<Principal>,
+}
+
+impl Storable for EntrySettings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct EntryFee {
+    ledger: Principal,
+    // in satoshis
+    amount: u64,
+    entries: u64,
+}
+
+impl Storable for EntryFee {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for EntryFee {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    quiz_id: u64,
+    principal: Principal,
+    amount: u64,
+    paid_at: u64,
+    block_index: u128,
+}
+
+impl Storable for Entry {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Entry {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static SETTINGS: RefCell<Cell<EntrySettings, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(114))),
+            EntrySettings::default(),
+        )
+        .expect("Cannot create the entry settings cell")
+    );
+
+    static ENTRY_FEES: RefCell<StableBTreeMap<u64, EntryFee, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(115)))
+    ));
+
+    // kept after the quiz is gone, they record who paid what
+    static ENTRIES: RefCell<StableBTreeMap<(u64, StorablePrincipal), Entry, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(116)))
+    ));
+
+    // entries whose transfer is awaited, a second payment of the same voter is turned away and
+    // the fee of the quiz stays
+    static IN_FLIGHT: RefCell<Vec<(u64, Principal)>> = const { RefCell::new(Vec::new()) };
+}
+
+fn ckbtc_ledger() -> Principal {
+    SETTINGS
+        .with(|settings| settings.borrow().get().ckbtc_ledger)
+        .unwrap_or_else(|| Principal::from_text(CKBTC_LEDGER).unwrap())
+}
+
+fn fee_of(quiz_id: u64) -> Option<EntryFee> {
+    ENTRY_FEES.with(|fees| fees.borrow().get(&quiz_id))
+}
+
+pub fn remove_entry_fee(quiz_id: u64) {
+    ENTRY_FEES.with(|fees| fees.borrow_mut().remove(&quiz_id));
+}
+
+// every way of voting on a paid quiz takes a paid entry, see `ensure_open`.
+pub fn ensure_entered(quiz: &Quiz) -> Result<(), Error> {
+    let Some(fee) = fee_of(quiz.id) else {
+        return Ok(());
+    };
+    let key = (quiz.id, StorablePrincipal(caller()));
+    if ENTRIES.with(|entries| entries.borrow().contains_key(&key)) {
+        return Ok(());
+    }
+    Err(Error::NotEligible {
+        msg: format!(
+            "you haven't paid the entry fee, the quiz with id={} takes {} satoshis in ckBTC, \
+             see pay_entry",
+            quiz.id, fee.amount
+        ),
+    })
+}
+
+// None goes back to the ckBTC ledger of the mainnet.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_ckbtc_ledger(ledger: Option<Principal>) -> Result<(), Error> {
+    metrics::count_call("set_ckbtc_ledger");
+    maintenance::ensure_available("set_ckbtc_ledger")?;
+    SETTINGS
+        .with(|settings| {
+            settings.borrow_mut().set(EntrySettings {
+                ckbtc_ledger: ledger,
+            })
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the entry settings".to_string(),
+        })?;
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_ckbtc_ledger() -> Principal {
+    ckbtc_ledger()
+}
+
+// Makes voting on the quiz cost `amount` satoshis in ckBTC, paid into the prize pool of the quiz.
+// The author gets a pool if the quiz has none yet, it can be taken back 30 days after the quiz
+// ends unless a payout rule pays it out. The fee is fixed once the first entry is paid.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_entry_fee(quiz_id: u64, amount: Option<u64>) -> Result<Option<EntryFee>, Error> {
+    metrics::count_call("set_entry_fee");
+    maintenance::ensure_available("set_entry_fee")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_author(&quiz)?;
+    if quiz.is_closed() {
+        return Err(Error::QuizClosed {
+            msg: format!("the quiz with id={} is closed", quiz_id),
+        });
+    }
+    // an entry being paid right now was asked for at the current fee
+    let paying = IN_FLIGHT.with(|in_flight| {
+        in_flight
+            .borrow()
+            .iter()
+            .any(|(paying_for, _)| *paying_for == quiz_id)
+    });
+    if paying || fee_of(quiz_id).is_some_and(|fee| fee.entries > 0) {
+        return Err(Error::WrongPhase {
+            msg: format!(
+                "entries to the quiz with id={} are paid or being paid, its fee stays",
+                quiz_id
+            ),
+        });
+    }
+    let Some(amount) = amount else {
+        remove_entry_fee(quiz_id);
+        return Ok(None);
+    };
+    let Some(ends_at) = quiz.ends_at.filter(|_| amount > 0) else {
+        return Err(Error::ValidationFailed {
+            field: "amount".to_string(),
+            msg: "a paid quiz takes more than nothing and needs an end time for its pool"
+                .to_string(),
+        });
+    };
+
+    let ledger = ckbtc_ledger();
+    prize_pools::open(quiz_id, ledger, ends_at.saturating_add(POOL_GRACE_NS))?;
+    let fee = EntryFee {
+        ledger,
+        amount,
+        entries: 0,
+    };
+    ENTRY_FEES.with(|fees| fees.borrow_mut().insert(quiz_id, fee.clone()));
+    Ok(Some(fee))
+}
+
+#[ic_cdk::query]
+fn get_entry_fee(quiz_id: u64) -> Option<EntryFee> {
+    fee_of(quiz_id)
+}
+
+#[ic_cdk::query]
+fn get_my_entry(quiz_id: u64) -> Option<Entry> {
+    ENTRIES.with(|entries| {
+        entries
+            .borrow()
+            .get(&(quiz_id, StorablePrincipal(caller())))
+    })
+}
+
+// Pays the entry of the caller with `icrc2_transfer_from` on the ckBTC ledger, approve the
+// canister for the fee of the quiz and the fee of the ledger first. The entry goes to the prize
+// pool of the quiz and lets the caller vote on it.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn pay_entry(quiz_id: u64) -> Result<Entry, Error> {
+    metrics::count_call("pay_entry");
+    maintenance::ensure_available("pay_entry")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    allowlist::ensure_can_view(&quiz)?;
+    if quiz.is_closed() {
+        return Err(Error::QuizClosed {
+            msg: format!("the quiz with id={} is closed", quiz_id),
+        });
+    }
+    let fee = fee_of(quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("the quiz with id={} takes no entry fee", quiz_id),
+    })?;
+    let principal = caller();
+    let key = (quiz_id, StorablePrincipal(principal));
+    if ENTRIES.with(|entries| entries.borrow().contains_key(&key)) {
+        return Err(Error::ValidationFailed {
+            field: "quiz_id".to_string(),
+            msg: format!("you entered the quiz with id={} already", quiz_id),
+        });
+    }
+    let busy = IN_FLIGHT.with(|in_flight| {
+        let mut in_flight = in_flight.borrow_mut();
+        let busy = in_flight.contains(&(quiz_id, principal));
+        if !busy {
+            in_flight.push((quiz_id, principal));
+        }
+        busy
+    });
+    if busy {
+        return Err(Error::ValidationFailed {
+            field: "quiz_id".to_string(),
+            msg: format!("your entry to the quiz with id={} is being paid", quiz_id),
+        });
+    }
+
+    let paid_at = time();
+    let memo = Sha256::new()
+        .chain_update(b"quiz-entry")
+        .chain_update(quiz_id.to_be_bytes())
+        .chain_update(principal.as_slice())
+        .finalize()
+        .to_vec();
+    let transfer = icrc::transfer_from(
+        fee.ledger,
+        Account::of(principal),
+        prize_pools::escrow_account(quiz_id),
+        fee.amount as u128,
+        memo,
+        paid_at,
+    )
+    .await;
+    IN_FLIGHT.with(|in_flight| {
+        in_flight
+            .borrow_mut()
+            .retain(|entry| *entry != (quiz_id, principal))
+    });
+
+    // the satoshis are in escrow now, so the entry counts whatever happened to the quiz meanwhile
+    let entry = Entry {
+        quiz_id,
+        principal,
+        amount: fee.amount,
+        paid_at,
+        block_index: transfer?,
+    };
+    ENTRIES.with(|entries| entries.borrow_mut().insert(key, entry.clone()));
+    if let Some(mut fee) = fee_of(quiz_id) {
+        fee.entries += 1;
+        ENTRY_FEES.with(|fees| fees.borrow_mut().insert(quiz_id, fee));
+    }
+    prize_pools::credit(quiz_id, fee.amount as u128);
+    Ok(entry)
+}
@@ -0,0 +1,292 @@
+use crate::guards::only_authenticated;
+use crate::{maintenance, metrics, Error, Memory, StorablePrincipal, MEMORY_MANAGER};
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+// the first one is the fallback for languages without a catalog
+const LOCALES: [&str; 5] = ["en", "de", "es", "fr", "pt"];
+const MAX_LOCALE_LEN: usize = 8;
+
+type Locale = Blob<MAX_LOCALE_LEN>;
+
+// one code per variant of `Error`, stable across releases unlike the messages
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ErrorCode {
+    NotFound,
+    AlreadyVoted,
+    Unauthorized,
+    ValidationFailed,
+    StorageFull,
+    QuizClosed,
+    CallFailed,
+    WrongPhase,
+    InvalidReveal,
+    TooLarge,
+    RateLimited,
+    NotEligible,
+    Maintenance,
+}
+
+const CODES: [ErrorCode; 13] = [
+    ErrorCode::NotFound,
+    ErrorCode::AlreadyVoted,
+    ErrorCode::Unauthorized,
+    ErrorCode::ValidationFailed,
+    ErrorCode::StorageFull,
+    ErrorCode::QuizClosed,
+    ErrorCode::CallFailed,
+    ErrorCode::WrongPhase,
+    ErrorCode::InvalidReveal,
+    ErrorCode::TooLarge,
+    ErrorCode::RateLimited,
+    ErrorCode::NotEligible,
+    ErrorCode::Maintenance,
+];
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct LocalizedError {
+    code: ErrorCode,
+    locale: String,
+    // the catalog message with the field or the waiting time filled in
+    message: String,
+    field: Option<String>,
+    retry_after_ns: Option<u64>,
+    // the english message the error was raised with, it names the quiz, option, etc.
+    detail: Option<String>,
+}
+
+// a catalog entry as it is, `{field}` and `{seconds}` are left for clients to fill in
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct ErrorMessage {
+    code: ErrorCode,
+    message: String,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct ErrorCatalog {
+    locale: String,
+    messages: Vec<ErrorMessage>,
+}
+
+thread_local! {
+    // the locale a principal picked with `set_accept_language`, already negotiated
+    static LANGUAGES: RefCell<StableBTreeMap<StorablePrincipal, Locale, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(102)))
+    ));
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::NotFound { .. } => ErrorCode::NotFound,
+            Error::AlreadyVoted { .. } => ErrorCode::AlreadyVoted,
+            Error::Unauthorized { .. } => ErrorCode::Unauthorized,
+            Error::ValidationFailed { .. } => ErrorCode::ValidationFailed,
+            Error::StorageFull { .. } => ErrorCode::StorageFull,
+            Error::QuizClosed { .. } => ErrorCode::QuizClosed,
+            Error::CallFailed { .. } => ErrorCode::CallFailed,
+            Error::WrongPhase { .. } => ErrorCode::WrongPhase,
+            Error::InvalidReveal { .. } => ErrorCode::InvalidReveal,
+            Error::TooLarge { .. } => ErrorCode::TooLarge,
+            Error::RateLimited { .. } => ErrorCode::RateLimited,
+            Error::NotEligible { .. } => ErrorCode::NotEligible,
+            Error::Maintenance { .. } => ErrorCode::Maintenance,
+        }
+    }
+}
+
+// The catalog, one message per code and locale of `LOCALES`. Unknown locales get english.
+pub fn message(code: ErrorCode, locale: &str) -> &'static str {
+    use ErrorCode::*;
+    match (code, locale) {
+        (NotFound, "de") => "Nicht gefunden.",
+        (NotFound, "es") => "No encontrado.",
+        (NotFound, "fr") => "Introuvable.",
+        (NotFound, "pt") => "Não encontrado.",
+        (NotFound, _) => "Not found.",
+        (AlreadyVoted, "de") => "Du hast bereits abgestimmt.",
+        (AlreadyVoted, "es") => "Ya has votado.",
+        (AlreadyVoted, "fr") => "Vous avez déjà voté.",
+        (AlreadyVoted, "pt") => "Você já votou.",
+        (AlreadyVoted, _) => "You already voted.",
+        (Unauthorized, "de") => "Dazu bist du nicht berechtigt.",
+        (Unauthorized, "es") => "No tienes permiso para hacer esto.",
+        (Unauthorized, "fr") => "Vous n'êtes pas autorisé à faire cela.",
+        (Unauthorized, "pt") => "Você não tem permissão para fazer isso.",
+        (Unauthorized, _) => "You aren't allowed to do this.",
+        (ValidationFailed, "de") => "Der Wert von {field} ist ungültig.",
+        (ValidationFailed, "es") => "El valor de {field} no es válido.",
+        (ValidationFailed, "fr") => "La valeur de {field} n'est pas valide.",
+        (ValidationFailed, "pt") => "O valor de {field} é inválido.",
+        (ValidationFailed, _) => "The value of {field} is invalid.",
+        (StorageFull, "de") => "Es ist kein Platz mehr frei.",
+        (StorageFull, "es") => "No queda espacio libre.",
+        (StorageFull, "fr") => "Il n'y a plus de place.",
+        (StorageFull, "pt") => "Não há mais espaço livre.",
+        (StorageFull, _) => "There is no room left.",
+        (QuizClosed, "de") => "Das Quiz ist beendet.",
+        (QuizClosed, "es") => "El cuestionario ha terminado.",
+        (QuizClosed, "fr") => "Le quiz est terminé.",
+        (QuizClosed, "pt") => "O quiz foi encerrado.",
+        (QuizClosed, _) => "The quiz is closed.",
+        (CallFailed, "de") => "Ein anderer Dienst hat nicht geantwortet.",
+        (CallFailed, "es") => "Otro servicio no ha respondido.",
+        (CallFailed, "fr") => "Un autre service n'a pas répondu.",
+        (CallFailed, "pt") => "Outro serviço não respondeu.",
+        (CallFailed, _) => "Another service didn't respond.",
+        (WrongPhase, "de") => "Das ist in dieser Phase des Quiz nicht möglich.",
+        (WrongPhase, "es") => "No es posible en esta fase del cuestionario.",
+        (WrongPhase, "fr") => "Ce n'est pas possible à cette étape du quiz.",
+        (WrongPhase, "pt") => "Não é possível nesta fase do quiz.",
+        (WrongPhase, _) => "This isn't possible in the current phase of the quiz.",
+        (InvalidReveal, "de") => "Die Stimme passt nicht zu ihrer Verpflichtung.",
+        (InvalidReveal, "es") => "El voto no coincide con su compromiso.",
+        (InvalidReveal, "fr") => "Le vote ne correspond pas à son engagement.",
+        (InvalidReveal, "pt") => "O voto não corresponde ao seu compromisso.",
+        (InvalidReveal, _) => "The vote doesn't match its commitment.",
+        (TooLarge, "de") => "Der Inhalt ist zu groß.",
+        (TooLarge, "es") => "El contenido es demasiado grande.",
+        (TooLarge, "fr") => "Le contenu est trop volumineux.",
+        (TooLarge, "pt") => "O conteúdo é grande demais.",
+        (TooLarge, _) => "The content is too large.",
+        (RateLimited, "de") => "Zu viele Anfragen, versuche es in {seconds} s erneut.",
+        (RateLimited, "es") => "Demasiadas solicitudes, inténtalo de nuevo en {seconds} s.",
+        (RateLimited, "fr") => "Trop de requêtes, réessayez dans {seconds} s.",
+        (RateLimited, "pt") => "Muitas solicitações, tente novamente em {seconds} s.",
+        (RateLimited, _) => "Too many calls, try again in {seconds} s.",
+        (NotEligible, "de") => "Du erfüllst die Voraussetzungen dieses Quiz nicht.",
+        (NotEligible, "es") => "No cumples los requisitos de este cuestionario.",
+        (NotEligible, "fr") => "Vous ne remplissez pas les conditions de ce quiz.",
+        (NotEligible, "pt") => "Você não cumpre os requisitos deste quiz.",
+        (NotEligible, _) => "You don't meet the requirements of this quiz.",
+        (Maintenance, "de") => "Wegen Wartungsarbeiten ist gerade nur Lesen möglich.",
+        (Maintenance, "es") => "Por mantenimiento, ahora solo se puede leer.",
+        (Maintenance, "fr") => "En raison d'une maintenance, seule la lecture est possible.",
+        (Maintenance, "pt") => "Em manutenção, por enquanto só é possível ler.",
+        (Maintenance, _) => "The canister is read-only for maintenance right now.",
+    }
+}
+
+// Picks the locale for an Accept-Language value like "pt-BR,pt;q=0.9,en;q=0.5": the supported
+// language with the highest weight, a region falls back to its language.
+pub fn negotiate(accept_language: &str) -> &'static str {
+    let mut ranges: Vec<(f64, String)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim().to_ascii_lowercase();
+            let weight = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f64>().ok())?;
+            Some((weight, tag)).filter(|(weight, tag)| *weight > 0.0 && !tag.is_empty())
+        })
+        .collect();
+    // stable, so ranges of the same weight keep the order they were listed in
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranges
+        .iter()
+        .find_map(|(_, tag)| {
+            let language = tag.split('-').next().unwrap_or_default();
+            LOCALES.iter().find(|locale| **locale == language).copied()
+        })
+        .unwrap_or(LOCALES[0])
+}
+
+fn stored_locale() -> Option<&'static str> {
+    let locale =
+        LANGUAGES.with(|languages| languages.borrow().get(&StorablePrincipal(caller())))?;
+    LOCALES
+        .iter()
+        .find(|supported| supported.as_bytes() == locale.as_slice())
+        .copied()
+}
+
+// an Accept-Language value wins over the preference of the caller, english is the last resort.
+fn locale_for(accept_language: Option<String>) -> &'static str {
+    match accept_language {
+        Some(accept_language) => negotiate(&accept_language),
+        None => stored_locale().unwrap_or(LOCALES[0]),
+    }
+}
+
+pub fn localize(error: Error, locale: &str) -> LocalizedError {
+    let code = error.code();
+    let (field, retry_after_ns, detail) = match error {
+        Error::ValidationFailed { field, msg } => (Some(field), None, Some(msg)),
+        Error::RateLimited { retry_after_ns } => (None, Some(retry_after_ns), None),
+        Error::NotFound { msg }
+        | Error::AlreadyVoted { msg }
+        | Error::Unauthorized { msg }
+        | Error::StorageFull { msg }
+        | Error::QuizClosed { msg }
+        | Error::CallFailed { msg }
+        | Error::WrongPhase { msg }
+        | Error::InvalidReveal { msg }
+        | Error::TooLarge { msg }
+        | Error::NotEligible { msg }
+        | Error::Maintenance { msg } => (None, None, Some(msg)),
+    };
+    let message = message(code, locale)
+        .replace("{field}", field.as_deref().unwrap_or_default())
+        .replace(
+            "{seconds}",
+            &retry_after_ns
+                .unwrap_or_default()
+                .div_ceil(1_000_000_000)
+                .to_string(),
+        );
+    LocalizedError {
+        code,
+        locale: locale.to_string(),
+        message,
+        field,
+        retry_after_ns,
+        detail,
+    }
+}
+
+// Remembers the language errors are explained in for the caller when a call passes no
+// Accept-Language of its own. None forgets it.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_accept_language(accept_language: Option<String>) -> String {
+    metrics::count_call("set_accept_language");
+    maintenance::trap_if_unavailable("set_accept_language");
+    let key = StorablePrincipal(caller());
+    LANGUAGES.with(|languages| {
+        let mut languages = languages.borrow_mut();
+        match &accept_language {
+            Some(accept_language) => {
+                let locale = negotiate(accept_language);
+                languages.insert(key, Locale::try_from(locale.as_bytes()).unwrap())
+            }
+            None => languages.remove(&key),
+        }
+    });
+    locale_for(accept_language).to_string()
+}
+
+// An error any call returned, explained in the user's language.
+#[ic_cdk::query]
+fn localize_error(error: Error, accept_language: Option<String>) -> LocalizedError {
+    localize(error, locale_for(accept_language))
+}
+
+// every message in the user's language, for clients that localize errors on their own.
+#[ic_cdk::query]
+fn get_error_catalog(accept_language: Option<String>) -> ErrorCatalog {
+    let locale = locale_for(accept_language);
+    ErrorCatalog {
+        locale: locale.to_string(),
+        messages: CODES
+            .iter()
+            .map(|code| ErrorMessage {
+                code: *code,
+                message: message(*code, locale).to_string(),
+            })
+            .collect(),
+    }
+}
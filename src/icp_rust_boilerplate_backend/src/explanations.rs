@@ -0,0 +1,126 @@
+use crate::{
+    _get_quiz, allowlist, moderation, scoring, Error, Memory, StorablePrincipal, MEMORY_MANAGER,
+    VOTES,
+};
+use candid::{Decode, Encode};
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MAX_EXPLANATION_LEN: usize = 512;
+
+// one explanation per option in the order of the options, empty for options without one
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Explanations {
+    by_option: Vec<String>,
+}
+
+impl Storable for Explanations {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Explanations {
+    // an explanation for each of at most 20 options
+    const MAX_SIZE: u32 = 12 * 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct OptionExplanation {
+    option: String,
+    explanation: Option<String>,
+}
+
+thread_local! {
+    // kept out of the Quiz record, voters only see the explanation of their own answer
+    static EXPLANATIONS: RefCell<StableBTreeMap<u64, Explanations, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(95)))
+    ));
+}
+
+pub fn validate_explanations(options: &[String], explanations: &[String]) -> Result<(), Error> {
+    if explanations.len() != options.len() {
+        return Err(Error::ValidationFailed {
+            field: "explanations".to_string(),
+            msg: "there has to be one explanation per option, empty for none".to_string(),
+        });
+    }
+    for explanation in explanations {
+        if explanation.len() > MAX_EXPLANATION_LEN {
+            return Err(Error::ValidationFailed {
+                field: "explanations".to_string(),
+                msg: format!(
+                    "an explanation must not exceed {} bytes",
+                    MAX_EXPLANATION_LEN
+                ),
+            });
+        }
+        moderation::ensure_clean("explanations", explanation)?;
+    }
+    Ok(())
+}
+
+// stores the explanations of a quiz, None or only empty ones remove them.
+pub fn set_explanations(quiz_id: u64, explanations: Option<Vec<String>>) {
+    EXPLANATIONS.with(|stored| {
+        let mut stored = stored.borrow_mut();
+        match explanations.filter(|by_option| by_option.iter().any(|text| !text.is_empty())) {
+            Some(by_option) => stored.insert(quiz_id, Explanations { by_option }),
+            None => stored.remove(&quiz_id),
+        }
+    });
+}
+
+pub fn explanations_of(quiz_id: u64) -> Option<Vec<String>> {
+    EXPLANATIONS
+        .with(|stored| stored.borrow().get(&quiz_id))
+        .map(|explanations| explanations.by_option)
+}
+
+pub fn remove_explanations(quiz_id: u64) {
+    EXPLANATIONS.with(|stored| stored.borrow_mut().remove(&quiz_id));
+}
+
+// The explanation of the option the caller voted for. It can give the correct options away, so
+// a quiz whose correct options are still hashed keeps it until they are revealed.
+#[ic_cdk::query]
+fn get_explanation(quiz_id: u64) -> Result<OptionExplanation, Error> {
+    let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", quiz_id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    let vote = VOTES
+        .with(|votes| votes.borrow().get(&(quiz_id, StorablePrincipal(caller()))))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("you haven't answered the quiz with id={}", quiz_id),
+        })?;
+    if scoring::answer_commitment_of(quiz_id).is_some() {
+        return Err(Error::WrongPhase {
+            msg: format!(
+                "the explanations of the quiz with id={} are shown once its correct options are \
+                 revealed",
+                quiz_id
+            ),
+        });
+    }
+    let position = quiz
+        .options
+        .iter()
+        .position(|option| *option == vote.option);
+    let explanation = explanations_of(quiz_id)
+        .zip(position)
+        .and_then(|(by_option, position)| by_option.get(position).cloned())
+        .filter(|explanation| !explanation.is_empty());
+    Ok(OptionExplanation {
+        option: vote.option,
+        explanation,
+    })
+}
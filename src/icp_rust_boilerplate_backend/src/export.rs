@@ -0,0 +1,210 @@
+use crate::guards::only_admin;
+use crate::{maintenance, metrics, tallies, Error, Quiz, Vote, STORAGE, VOTES};
+use ic_cdk::api::time;
+use std::{cell::RefCell, collections::BTreeMap, time::Duration};
+
+// a chunk has to fit into a single query response
+const CHUNK_SIZE: usize = 1024 * 1024;
+// exports are dropped an hour after they were started
+const EXPORT_TTL: u64 = 60 * 60 * 1_000_000_000;
+// every export keeps a full copy of the data on the heap until it expires
+const MAX_EXPORTS: usize = 4;
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ExportFormat {
+    // a single object holding the `quizzes` and the `votes`
+    Json,
+    // the quizzes table, an empty line, then the votes table
+    Csv,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct ExportInfo {
+    handle: u64,
+    format: ExportFormat,
+    // total length of the export in bytes
+    size: u64,
+    chunks: u64,
+    expires_at: u64,
+}
+
+struct Export {
+    info: ExportInfo,
+    data: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct Dataset<'a> {
+    quizzes: &'a [Quiz],
+    votes: &'a [Vote],
+}
+
+thread_local! {
+    // kept on the heap only, an upgrade drops the exports in progress
+    static EXPORTS: RefCell<BTreeMap<u64, Export>> = const { RefCell::new(BTreeMap::new()) };
+    static NEXT_HANDLE: RefCell<u64> = const { RefCell::new(0) };
+}
+
+fn snapshot() -> (Vec<Quiz>, Vec<Vote>) {
+    let quizzes = STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, quiz)| tallies::attach(quiz))
+            .collect()
+    });
+    let votes = VOTES.with(|votes| votes.borrow().iter().map(|(_, vote)| vote).collect());
+    (quizzes, votes)
+}
+
+// quotes a field when it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\n", fields.join(","))
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+fn to_csv(quizzes: &[Quiz], votes: &[Vote]) -> String {
+    let mut csv = csv_row(
+        &[
+            "id",
+            "question",
+            "options",
+            "votes",
+            "author",
+            "created_at",
+            "ends_at",
+            "closed_at",
+            "deleted_at",
+        ]
+        .map(String::from),
+    );
+    for quiz in quizzes {
+        // options and their tallies are joined with `|` to keep one line per quiz
+        let votes: Vec<String> = quiz
+            .options
+            .iter()
+            .map(|option| {
+                quiz.answers
+                    .get(option)
+                    .copied()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect();
+        csv.push_str(&csv_row(&[
+            quiz.id.to_string(),
+            quiz.question.clone(),
+            quiz.options.join("|"),
+            votes.join("|"),
+            optional(&quiz.author),
+            quiz.created_at.to_string(),
+            optional(&quiz.ends_at),
+            optional(&quiz.closed_at),
+            optional(&quiz.deleted_at),
+        ]));
+    }
+
+    csv.push('\n');
+    csv.push_str(&csv_row(
+        &["quiz_id", "voter", "option", "weight", "cast_at"].map(String::from),
+    ));
+    for vote in votes {
+        csv.push_str(&csv_row(&[
+            vote.quiz_id.to_string(),
+            vote.voter.to_string(),
+            vote.option.clone(),
+            optional(&vote.weight),
+            vote.cast_at.to_string(),
+        ]));
+    }
+    csv
+}
+
+// Takes a snapshot of every quiz (deleted ones included) and every vote, which is then read
+// with `get_export_chunk`. The snapshot doesn't change while it is being read.
+#[ic_cdk::update(guard = "only_admin")]
+fn start_export(format: ExportFormat) -> Result<ExportInfo, Error> {
+    metrics::count_call("start_export");
+    maintenance::ensure_available("start_export")?;
+    if EXPORTS.with(|exports| exports.borrow().len()) >= MAX_EXPORTS {
+        return Err(Error::StorageFull {
+            msg: format!(
+                "at most {} exports can be in progress at the same time",
+                MAX_EXPORTS
+            ),
+        });
+    }
+
+    let (quizzes, votes) = snapshot();
+    let data = match format {
+        ExportFormat::Json => serde_json::to_vec(&Dataset {
+            quizzes: &quizzes,
+            votes: &votes,
+        })
+        .map_err(|err| Error::ValidationFailed {
+            field: "format".to_string(),
+            msg: format!("the data couldn't be encoded as json: {}", err),
+        })?,
+        ExportFormat::Csv => to_csv(&quizzes, &votes).into_bytes(),
+    };
+
+    let handle = NEXT_HANDLE.with(|next| {
+        let handle = *next.borrow();
+        *next.borrow_mut() = handle + 1;
+        handle
+    });
+    let info = ExportInfo {
+        handle,
+        format,
+        size: data.len() as u64,
+        chunks: data.len().div_ceil(CHUNK_SIZE) as u64,
+        expires_at: time().saturating_add(EXPORT_TTL),
+    };
+    EXPORTS.with(|exports| {
+        exports.borrow_mut().insert(
+            handle,
+            Export {
+                info: info.clone(),
+                data,
+            },
+        )
+    });
+    ic_cdk_timers::set_timer(Duration::from_nanos(EXPORT_TTL), move || {
+        EXPORTS.with(|exports| exports.borrow_mut().remove(&handle));
+    });
+    Ok(info)
+}
+
+// the chunks are parts of the encoded data, they only make sense once put back together.
+#[ic_cdk::query(guard = "only_admin")]
+fn get_export_chunk(handle: u64, index: u64) -> Result<Vec<u8>, Error> {
+    EXPORTS.with(|exports| {
+        let exports = exports.borrow();
+        let export = exports.get(&handle).ok_or_else(|| Error::NotFound {
+            msg: format!("an export with handle={} not found or expired", handle),
+        })?;
+        if index >= export.info.chunks {
+            return Err(Error::NotFound {
+                msg: format!(
+                    "the export with handle={} has only {} chunks",
+                    handle, export.info.chunks
+                ),
+            });
+        }
+        let start = index as usize * CHUNK_SIZE;
+        let end = (start + CHUNK_SIZE).min(export.data.len());
+        Ok(export.data[start..end].to_vec())
+    })
+}
@@ -0,0 +1,203 @@
+use crate::guards::{not_banned, only_authenticated};
+use crate::{
+    _get_quiz, after_vote, audit, ensure_can_vote, ensure_editor, id_key_range, maintenance,
+    metrics, moderation, ratelimit, record_vote, remove_quiz_entries, scoring,
+    validate_question_text, AuditAction, Error, Memory, Quiz, QuizPayload, StorablePrincipal,
+    VotingMode, MAX_PAGE_SIZE, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MAX_ANSWER_LEN: usize = 280;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct TextAnswer {
+    principal: Principal,
+    answer: String,
+    submitted_at: u64,
+    // set by an editor of the quiz, None until the answer is graded
+    points: Option<u64>,
+    graded_at: Option<u64>,
+}
+
+impl Storable for TextAnswer {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for TextAnswer {
+    // an answer of at most MAX_ANSWER_LEN bytes next to the principal
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct TextAnswerPage {
+    answers: Vec<TextAnswer>,
+    total: u64,
+}
+
+thread_local! {
+    static TEXT_ANSWERS: RefCell<StableBTreeMap<(u64, StorablePrincipal), TextAnswer, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(97)))
+    ));
+}
+
+// the answers are written by the voters, a free-text quiz has nothing to pick from.
+pub fn validate_free_text_quiz(payload: &QuizPayload) -> Result<(), Error> {
+    validate_question_text(&payload.question)?;
+    if !payload.options.is_empty() {
+        return Err(Error::ValidationFailed {
+            field: "options".to_string(),
+            msg: "a free-text quiz has no options".to_string(),
+        });
+    }
+    Ok(())
+}
+
+// helper method to forget the answers of a quiz, the points they were graded with are taken back.
+pub fn remove_text_answers(quiz_id: u64) {
+    TEXT_ANSWERS.with(|answers| {
+        for (_, answer) in answers.borrow().range(id_key_range(quiz_id)) {
+            if let Some(points) = answer.points {
+                scoring::revoke_graded_points(answer.principal, points);
+            }
+        }
+        remove_quiz_entries(&mut answers.borrow_mut(), quiz_id)
+    });
+}
+
+fn free_text_quiz(id: u64) -> Result<Quiz, Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    if quiz.voting_mode() != VotingMode::FreeText {
+        return Err(Error::ValidationFailed {
+            field: "voting_mode".to_string(),
+            msg: format!("the quiz with id={} is not a free-text quiz", id),
+        });
+    }
+    Ok(quiz)
+}
+
+// Answers a free-text quiz. It counts as the vote of the caller, the points follow once an
+// editor graded the answer.
+#[ic_cdk::update(guard = "not_banned")]
+fn submit_text_answer(id: u64, answer: String) -> Result<(), Error> {
+    metrics::count_call("submit_text_answer");
+    maintenance::ensure_available("submit_text_answer")?;
+    ratelimit::check(ratelimit::Action::Vote)?;
+    let quiz = free_text_quiz(id)?;
+    // an anonymous answer would have nobody to grade
+    if caller() == Principal::anonymous() {
+        return Err(Error::Unauthorized {
+            msg: "free-text answers can't be anonymous".to_string(),
+        });
+    }
+    let vote_key = (id, StorablePrincipal(caller()));
+    ensure_can_vote(&quiz, &vote_key)?;
+    let answer = answer.trim().to_string();
+    if answer.is_empty() || answer.len() > MAX_ANSWER_LEN {
+        return Err(Error::ValidationFailed {
+            field: "answer".to_string(),
+            msg: format!("the answer must be 1 to {} bytes", MAX_ANSWER_LEN),
+        });
+    }
+    moderation::ensure_clean("answer", &answer)?;
+
+    let args = Encode!(&id, &answer).unwrap();
+    TEXT_ANSWERS.with(|answers| {
+        answers.borrow_mut().insert(
+            vote_key,
+            TextAnswer {
+                principal: caller(),
+                answer,
+                submitted_at: time(),
+                points: None,
+                graded_at: None,
+            },
+        )
+    });
+    audit::record_vote(id, &args, Vec::new(), None);
+    record_vote(vote_key, String::new(), None);
+    after_vote(&quiz);
+    Ok(())
+}
+
+// the answers no editor has graded yet, the earliest principals first.
+#[ic_cdk::query]
+fn get_ungraded_answers(quiz_id: u64, offset: u64, limit: u64) -> Result<TextAnswerPage, Error> {
+    let quiz = free_text_quiz(quiz_id)?;
+    ensure_editor(&quiz)?;
+    let ungraded: Vec<TextAnswer> = TEXT_ANSWERS.with(|answers| {
+        answers
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|(_, answer)| answer)
+            .filter(|answer| answer.points.is_none())
+            .collect()
+    });
+    Ok(TextAnswerPage {
+        total: ungraded.len() as u64,
+        answers: ungraded
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .collect(),
+    })
+}
+
+// Grades an answer with up to the points of a correct answer. A graded answer can be graded again,
+// the leaderboard only moves by the difference.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn grade_text_answer(quiz_id: u64, principal: Principal, points: u64) -> Result<TextAnswer, Error> {
+    metrics::count_call("grade_text_answer");
+    maintenance::ensure_available("grade_text_answer")?;
+    let quiz = free_text_quiz(quiz_id)?;
+    ensure_editor(&quiz)?;
+    if points > scoring::POINTS_PER_CORRECT_ANSWER {
+        return Err(Error::ValidationFailed {
+            field: "points".to_string(),
+            msg: format!(
+                "an answer is worth at most {} points",
+                scoring::POINTS_PER_CORRECT_ANSWER
+            ),
+        });
+    }
+    let key = (quiz_id, StorablePrincipal(principal));
+    let mut answer = TEXT_ANSWERS
+        .with(|answers| answers.borrow().get(&key))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("{} hasn't answered the quiz with id={}", principal, quiz_id),
+        })?;
+
+    let args = Encode!(&quiz_id, &principal, &points).unwrap();
+    scoring::award_graded_points(quiz_id, principal, answer.points, points);
+    answer.points = Some(points);
+    answer.graded_at = Some(time());
+    TEXT_ANSWERS.with(|answers| answers.borrow_mut().insert(key, answer.clone()));
+    audit::record(AuditAction::GradeAnswer, quiz_id, &args);
+    Ok(answer)
+}
+
+#[ic_cdk::query]
+fn get_my_text_answer(quiz_id: u64) -> Result<TextAnswer, Error> {
+    TEXT_ANSWERS
+        .with(|answers| {
+            answers
+                .borrow()
+                .get(&(quiz_id, StorablePrincipal(caller())))
+        })
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("you haven't answered the quiz with id={}", quiz_id),
+        })
+}
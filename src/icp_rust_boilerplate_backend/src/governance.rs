@@ -0,0 +1,363 @@
+use crate::deposits::{self, DepositSettings};
+use crate::ratelimit::{self, RateLimits};
+use crate::roles::{self, Role};
+use crate::settings::{self, Settings};
+use crate::{
+    discovery, maintenance, metrics, Error, IdCell, Memory, MAX_PAGE_SIZE, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, Cell, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell, time::Duration};
+
+const HOUR_NS: u64 = 60 * 60 * 1_000_000_000;
+// a change proposed by the governance canister waits this long before it applies, so users and
+// admins see it coming
+const DEFAULT_TIMELOCK_NS: u64 = 48 * HOUR_NS;
+const MIN_TIMELOCK_NS: u64 = HOUR_NS;
+const MAX_TIMELOCK_NS: u64 = 30 * 24 * HOUR_NS;
+// the most changes waiting at once
+const MAX_PENDING: usize = 20;
+
+// Once an SNS or a DAO canister governs the canister, the global settings only change through
+// its proposals and no longer through the admin endpoints.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct ConfigGovernance {
+    governance: Option<Principal>,
+    timelock_ns: u64,
+}
+
+impl Default for ConfigGovernance {
+    fn default() -> Self {
+        ConfigGovernance {
+            governance: None,
+            timelock_ns: DEFAULT_TIMELOCK_NS,
+        }
+    }
+}
+
+impl Storable for ConfigGovernance {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub enum ConfigChange {
+    Settings(Settings),
+    RateLimits(RateLimits),
+    CreationDeposit(Option<DepositSettings>),
+    FeatureQuiz(u64),
+    UnfeatureQuiz(u64),
+    PinQuizOfTheDay(Option<u64>),
+    Timelock(u64),
+    // None hands the settings back to the admins
+    Governance(Option<Principal>),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub enum ChangeStatus {
+    Pending,
+    Applied,
+    // the change no longer fit the canister by the time it was due
+    Failed { error: Error },
+    Cancelled,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct PendingChange {
+    id: u64,
+    change: ConfigChange,
+    proposed_at: u64,
+    executable_at: u64,
+    status: ChangeStatus,
+    settled_at: Option<u64>,
+}
+
+impl Storable for PendingChange {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for PendingChange {
+    // the settings or a deposit, and the error of a failed change
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static GOVERNANCE: RefCell<Cell<ConfigGovernance, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(125))),
+            ConfigGovernance::default(),
+        )
+        .expect("Cannot create the config governance cell")
+    );
+
+    // every change the governance canister proposed, by id
+    static CHANGES: RefCell<StableBTreeMap<u64, PendingChange, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(126)))
+    ));
+
+    static CHANGE_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(127))), 0)
+            .expect("Cannot create the config change counter")
+    );
+}
+
+fn config() -> ConfigGovernance {
+    GOVERNANCE.with(|config| config.borrow().get().clone())
+}
+
+fn store_config(config: ConfigGovernance) -> Result<(), Error> {
+    GOVERNANCE
+        .with(|cell| cell.borrow_mut().set(config))
+        .map(|_| ())
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the config governance".to_string(),
+        })
+}
+
+// the admin endpoints of the governed settings go through here
+pub fn ensure_ungoverned(setting: &str) -> Result<(), Error> {
+    match config().governance {
+        Some(governance) => Err(Error::Unauthorized {
+            msg: format!(
+                "the {} are governed by {}, they change through its proposals",
+                setting, governance
+            ),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn ensure_governance() -> Result<Principal, Error> {
+    let governance = config().governance.ok_or_else(|| Error::NotFound {
+        msg: "no governance canister governs the settings".to_string(),
+    })?;
+    if caller() != governance {
+        return Err(Error::Unauthorized {
+            msg: format!("only the governance canister {} can do this", governance),
+        });
+    }
+    Ok(governance)
+}
+
+// what a change does, for the proposal and the validator
+fn describe(change: &ConfigChange) -> String {
+    match change {
+        ConfigChange::Settings(_) => "replaces the global settings".to_string(),
+        ConfigChange::RateLimits(_) => "replaces the rate limits".to_string(),
+        ConfigChange::CreationDeposit(Some(_)) => "sets the creation deposit".to_string(),
+        ConfigChange::CreationDeposit(None) => "removes the creation deposit".to_string(),
+        ConfigChange::FeatureQuiz(id) => format!("features the quiz with id={}", id),
+        ConfigChange::UnfeatureQuiz(id) => format!("unfeatures the quiz with id={}", id),
+        ConfigChange::PinQuizOfTheDay(Some(id)) => {
+            format!("pins the quiz with id={} as the quiz of the day", id)
+        }
+        ConfigChange::PinQuizOfTheDay(None) => "unpins the quiz of the day".to_string(),
+        ConfigChange::Timelock(timelock_ns) => {
+            format!("sets the timelock to {} hours", timelock_ns / HOUR_NS)
+        }
+        ConfigChange::Governance(Some(governance)) => {
+            format!(
+                "hands the settings to the governance canister {}",
+                governance
+            )
+        }
+        ConfigChange::Governance(None) => "hands the settings back to the admins".to_string(),
+    }
+}
+
+// the checks that don't depend on the state of the canister when the change is due
+fn validate(change: &ConfigChange) -> Result<(), String> {
+    match change {
+        ConfigChange::Timelock(timelock_ns)
+            if !(MIN_TIMELOCK_NS..=MAX_TIMELOCK_NS).contains(timelock_ns) =>
+        {
+            Err(format!(
+                "the timelock has to be between {} and {} hours",
+                MIN_TIMELOCK_NS / HOUR_NS,
+                MAX_TIMELOCK_NS / HOUR_NS
+            ))
+        }
+        ConfigChange::Governance(Some(governance)) if *governance == Principal::anonymous() => {
+            Err("the settings can't be handed to the anonymous principal".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validation_failed(msg: String) -> Error {
+    Error::ValidationFailed {
+        field: "change".to_string(),
+        msg,
+    }
+}
+
+fn apply(change: ConfigChange) -> Result<(), Error> {
+    match change {
+        ConfigChange::Settings(settings) => settings::apply_settings(settings).map(|_| ()),
+        ConfigChange::RateLimits(limits) => ratelimit::apply_rate_limits(limits).map(|_| ()),
+        ConfigChange::CreationDeposit(deposit) => deposits::apply_creation_deposit(deposit),
+        ConfigChange::FeatureQuiz(id) => discovery::feature(id),
+        ConfigChange::UnfeatureQuiz(id) => discovery::unfeature(id),
+        ConfigChange::PinQuizOfTheDay(quiz_id) => discovery::pin(quiz_id),
+        ConfigChange::Timelock(timelock_ns) => store_config(ConfigGovernance {
+            timelock_ns,
+            ..config()
+        }),
+        ConfigChange::Governance(governance) => store_config(ConfigGovernance {
+            governance,
+            ..config()
+        }),
+    }
+}
+
+fn schedule(change: &PendingChange) {
+    let id = change.id;
+    let delay = change.executable_at.saturating_sub(time());
+    ic_cdk_timers::set_timer(Duration::from_nanos(delay), move || execute(id));
+}
+
+fn execute(id: u64) {
+    let Some(mut change) = CHANGES.with(|changes| changes.borrow().get(&id)) else {
+        return;
+    };
+    if !matches!(change.status, ChangeStatus::Pending) {
+        return;
+    }
+    change.status = match apply(change.change.clone()) {
+        Ok(()) => ChangeStatus::Applied,
+        Err(error) => ChangeStatus::Failed { error },
+    };
+    change.settled_at = Some(time());
+    CHANGES.with(|changes| changes.borrow_mut().insert(id, change));
+}
+
+// the timers of the pending changes don't survive an upgrade
+pub fn resume_changes() {
+    CHANGES.with(|changes| {
+        changes
+            .borrow()
+            .iter()
+            .filter(|(_, change)| matches!(change.status, ChangeStatus::Pending))
+            .for_each(|(_, change)| schedule(&change));
+    });
+}
+
+fn pending_count() -> usize {
+    CHANGES.with(|changes| {
+        changes
+            .borrow()
+            .iter()
+            .filter(|(_, change)| matches!(change.status, ChangeStatus::Pending))
+            .count()
+    })
+}
+
+// Owners hand the settings to an SNS or a DAO canister, after that only its proposals hand them
+// on or back, see `ConfigChange::Governance`.
+#[ic_cdk::update]
+fn set_config_governance(governance: Principal) -> Result<(), Error> {
+    metrics::count_call("set_config_governance");
+    maintenance::ensure_available("set_config_governance")?;
+    roles::ensure_role(Role::Owner)?;
+    ensure_ungoverned("settings")?;
+    let change = ConfigChange::Governance(Some(governance));
+    validate(&change).map_err(validation_failed)?;
+    apply(change)
+}
+
+#[ic_cdk::query]
+fn get_config_governance() -> ConfigGovernance {
+    config()
+}
+
+// The validator of the SNS generic function that targets `propose_config_change`, it describes
+// the change for the proposal or says why it can't be made.
+#[ic_cdk::query]
+fn validate_config_change(change: ConfigChange) -> Result<String, String> {
+    validate(&change)?;
+    Ok(describe(&change))
+}
+
+// Called by the governance canister when a proposal is executed. The change applies once the
+// timelock has passed, unless the governance canister cancels it before.
+#[ic_cdk::update]
+fn propose_config_change(change: ConfigChange) -> Result<PendingChange, Error> {
+    metrics::count_call("propose_config_change");
+    maintenance::ensure_available("propose_config_change")?;
+    ensure_governance()?;
+    validate(&change).map_err(validation_failed)?;
+    if pending_count() >= MAX_PENDING {
+        return Err(Error::StorageFull {
+            msg: format!("at most {} changes can be pending at once", MAX_PENDING),
+        });
+    }
+    let id = CHANGE_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment config change counter".to_string(),
+        })?;
+    let proposed_at = time();
+    let pending = PendingChange {
+        id,
+        change,
+        proposed_at,
+        executable_at: proposed_at.saturating_add(config().timelock_ns),
+        status: ChangeStatus::Pending,
+        settled_at: None,
+    };
+    CHANGES.with(|changes| changes.borrow_mut().insert(id, pending.clone()));
+    schedule(&pending);
+    Ok(pending)
+}
+
+#[ic_cdk::update]
+fn cancel_config_change(id: u64) -> Result<PendingChange, Error> {
+    metrics::count_call("cancel_config_change");
+    maintenance::ensure_available("cancel_config_change")?;
+    ensure_governance()?;
+    let mut change = CHANGES
+        .with(|changes| changes.borrow().get(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a config change with id={} not found", id),
+        })?;
+    if !matches!(change.status, ChangeStatus::Pending) {
+        return Err(Error::WrongPhase {
+            msg: format!("the config change with id={} isn't pending", id),
+        });
+    }
+    change.status = ChangeStatus::Cancelled;
+    change.settled_at = Some(time());
+    CHANGES.with(|changes| changes.borrow_mut().insert(id, change.clone()));
+    Ok(change)
+}
+
+// the changes in the order they were proposed in
+#[ic_cdk::query]
+fn list_config_changes(offset: u64, limit: u64) -> Vec<PendingChange> {
+    CHANGES.with(|changes| {
+        changes
+            .borrow()
+            .range(offset..)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .map(|(_, change)| change)
+            .collect()
+    })
+}
@@ -0,0 +1,35 @@
+use crate::roles::{self, Role};
+use candid::Principal;
+use ic_cdk::api::caller;
+
+// Guards for `#[ic_cdk::update(guard = "...")]` and queries. A failing guard rejects the call
+// before the method runs, so they only cover what doesn't depend on the arguments.
+
+pub fn not_banned() -> Result<(), String> {
+    if roles::is_banned(caller()) {
+        return Err("you are banned from this canister".to_string());
+    }
+    Ok(())
+}
+
+// signed in callers that aren't banned.
+pub fn only_authenticated() -> Result<(), String> {
+    if caller() == Principal::anonymous() {
+        return Err("anonymous callers have to sign in first".to_string());
+    }
+    not_banned()
+}
+
+pub fn only_moderator() -> Result<(), String> {
+    if !roles::has_role(caller(), Role::Moderator) {
+        return Err("only moderators can call this method".to_string());
+    }
+    Ok(())
+}
+
+pub fn only_admin() -> Result<(), String> {
+    if !roles::has_role(caller(), Role::Admin) {
+        return Err("only admins can call this method".to_string());
+    }
+    Ok(())
+}
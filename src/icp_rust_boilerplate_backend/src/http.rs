@@ -0,0 +1,246 @@
+use crate::errors::{self, ErrorCode};
+use crate::{_get_quiz, allowlist, sanitizer, Quiz};
+use candid::Principal;
+
+// link previews cut longer descriptions off anyway
+const MAX_DESCRIPTION_LEN: usize = 200;
+// the counts in a preview are live, crawlers shouldn't hold on to them for long
+const PAGE_MAX_AGE_SECS: u32 = 60;
+// crawlers building link previews, some of them don't ask for html
+const PREVIEW_BOTS: [&str; 7] = [
+    "facebookexternalhit",
+    "twitterbot",
+    "slackbot",
+    "discordbot",
+    "linkedinbot",
+    "whatsapp",
+    "telegrambot",
+];
+
+// what the http gateway passes on from a browser
+#[derive(candid::CandidType, Deserialize)]
+pub struct HttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(candid::CandidType, Serialize)]
+pub struct HttpResponse {
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct OptionView<'a> {
+    option: &'a str,
+    votes: u32,
+}
+
+// the public part of a quiz, what anybody following a link gets to see
+#[derive(Serialize)]
+struct QuizView<'a> {
+    id: u64,
+    question: &'a str,
+    options: Vec<OptionView<'a>>,
+    total_votes: u64,
+    ends_at: Option<u64>,
+    closed: bool,
+    // the question and the options are Markdown, the html page renders them
+    rendered: bool,
+}
+
+impl<'a> QuizView<'a> {
+    fn of(quiz: &'a Quiz) -> Self {
+        let options: Vec<OptionView> = quiz
+            .options
+            .iter()
+            .map(|option| OptionView {
+                option,
+                votes: quiz.answers.get(option).copied().unwrap_or_default(),
+            })
+            .collect();
+        QuizView {
+            id: quiz.id,
+            question: &quiz.question,
+            total_votes: options.iter().map(|option| option.votes as u64).sum(),
+            options,
+            ends_at: quiz.ends_at,
+            closed: quiz.is_closed(),
+            rendered: quiz.rendered.unwrap_or_default(),
+        }
+    }
+}
+
+fn response(status_code: u16, content_type: &str, body: Vec<u8>) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".to_string(), content_type.to_string())],
+        body,
+    }
+}
+
+fn header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+// in the language the browser asks for
+fn not_found(request: &HttpRequest) -> HttpResponse {
+    let locale = header(request, "accept-language").map_or("en", errors::negotiate);
+    let mut response = response(
+        404,
+        "text/plain; charset=utf-8",
+        errors::message(ErrorCode::NotFound, locale)
+            .as_bytes()
+            .to_vec(),
+    );
+    response
+        .headers
+        .push(("Content-Language".to_string(), locale.to_string()));
+    response
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// "Yes: 12, No: 5 · 17 votes", what a shared link shows below the question.
+fn description(view: &QuizView) -> String {
+    let counts: Vec<String> = view
+        .options
+        .iter()
+        .map(|option| format!("{}: {}", option.option, option.votes))
+        .collect();
+    let mut description = match counts.is_empty() {
+        true => format!("{} votes", view.total_votes),
+        false => format!("{} · {} votes", counts.join(", "), view.total_votes),
+    };
+    if view.closed {
+        description.push_str(" · closed");
+    }
+    if description.len() > MAX_DESCRIPTION_LEN {
+        let mut end = MAX_DESCRIPTION_LEN - '…'.len_utf8();
+        while !description.is_char_boundary(end) {
+            end -= 1;
+        }
+        description.truncate(end);
+        description.push('…');
+    }
+    description
+}
+
+// the uncertified pages are only served on the raw domain, so that's where the previews link
+fn raw_url(path: &str) -> String {
+    format!("https://{}.raw.icp0.io{}", ic_cdk::id(), path)
+}
+
+// A bare page with the OpenGraph and Twitter card tags link previews are built from. The tags
+// get the escaped Markdown source, only the body renders it. `url` is the canonical address of
+// the page, see `raw_url`.
+fn to_html(view: &QuizView, url: &str) -> String {
+    let question = escape_html(view.question);
+    let description = escape_html(&description(view));
+    let og_url = format!(
+        "<meta property=\"og:url\" content=\"{}\">",
+        escape_html(url)
+    );
+    let body_text = |text: &str| {
+        if view.rendered {
+            sanitizer::render_markdown(text)
+        } else {
+            escape_html(text)
+        }
+    };
+    let options: String = view
+        .options
+        .iter()
+        .map(|option| format!("<li>{}: {}</li>", body_text(option.option), option.votes))
+        .collect();
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{question}</title>\
+         <meta name=\"description\" content=\"{description}\">\
+         <meta property=\"og:type\" content=\"website\">\
+         <meta property=\"og:title\" content=\"{question}\">\
+         <meta property=\"og:description\" content=\"{description}\">{og_url}\
+         <meta name=\"twitter:card\" content=\"summary\">\
+         <meta name=\"twitter:title\" content=\"{question}\">\
+         <meta name=\"twitter:description\" content=\"{description}\"></head>\
+         <body><h1>{heading}</h1><ul>{options}</ul><p>{total} votes</p></body></html>",
+        question = question,
+        description = description,
+        og_url = og_url,
+        heading = body_text(view.question),
+        options = options,
+        total = view.total_votes,
+    )
+}
+
+// Browsers and the crawlers behind link previews get html, everybody else json. `format=html`
+// or `format=json` picks one explicitly.
+fn wants_html(request: &HttpRequest, query: &str) -> bool {
+    let params: Vec<&str> = query.split('&').collect();
+    if params.contains(&"format=json") {
+        return false;
+    }
+    let user_agent = header(request, "user-agent")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    params.contains(&"format=html")
+        || header(request, "accept").is_some_and(|accept| accept.contains("text/html"))
+        || PREVIEW_BOTS.iter().any(|bot| user_agent.contains(bot))
+}
+
+// Serves `/quiz/<id>` as json or as an html page for link previews. Gateway requests are
+// anonymous, so only public quizzes are found. The responses carry no `IC-Certificate`: the
+// counts change with every vote and the body depends on the headers of the request, so only
+// the raw domain `https://<canister id>.raw.icp0.io/quiz/<id>` passes them on, the certifying
+// gateway of `icp0.io` turns them down. Clients that need to trust the counts use
+// `get_certified_quiz`.
+#[ic_cdk::query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    if request.method != "GET" {
+        return response(405, "text/plain", b"method not allowed".to_vec());
+    }
+    let (path, query) = request.url.split_once('?').unwrap_or((&request.url, ""));
+    let Some(quiz) = path
+        .strip_prefix("/quiz/")
+        .and_then(|id| id.trim_end_matches('/').parse::<u64>().ok())
+        .and_then(|id| _get_quiz(&id))
+        .filter(|quiz| allowlist::can_view(quiz, Principal::anonymous()))
+    else {
+        return not_found(&request);
+    };
+
+    let view = QuizView::of(&quiz);
+    let mut response = if wants_html(&request, query) {
+        let url = raw_url(&format!("/quiz/{}", quiz.id));
+        let mut response = response(
+            200,
+            "text/html; charset=utf-8",
+            to_html(&view, &url).into_bytes(),
+        );
+        response.headers.push((
+            "Cache-Control".to_string(),
+            format!("public, max-age={}", PAGE_MAX_AGE_SECS),
+        ));
+        response
+    } else {
+        let body = serde_json::to_vec(&view).unwrap();
+        response(200, "application/json", body)
+    };
+    // caches keep the html and the json of the same url apart, see `wants_html`
+    response
+        .headers
+        .push(("Vary".to_string(), "Accept, User-Agent".to_string()));
+    response
+}
@@ -0,0 +1,257 @@
+use crate::Error;
+use candid::Principal;
+
+// ICRC-1 account, the subaccount defaults to all zeros when missing
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+impl Account {
+    pub fn of(owner: Principal) -> Self {
+        Account {
+            owner,
+            subaccount: None,
+        }
+    }
+}
+
+// balance of the default account of `owner` on an ICRC-1 ledger.
+pub async fn balance_of(ledger: Principal, owner: Principal) -> Result<u128, Error> {
+    let (balance,): (u128,) = ic_cdk::call(ledger, "icrc1_balance_of", (Account::of(owner),))
+        .await
+        .map_err(|(code, msg)| Error::CallFailed {
+            msg: format!(
+                "icrc1_balance_of on {} failed ({:?}): {}",
+                ledger, code, msg
+            ),
+        })?;
+    Ok(balance)
+}
+
+#[derive(candid::CandidType, Serialize)]
+struct TransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: u128,
+    fee: Option<u128>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+enum TransferError {
+    BadFee { expected_fee: u128 },
+    BadBurn { min_burn_amount: u128 },
+    InsufficientFunds { balance: u128 },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
+    TemporarilyUnavailable,
+    GenericError { error_code: u128, message: String },
+}
+
+// Transfers `amount` from an account of the canister, its default one without a subaccount, and
+// returns the block index. The ledger deduplicates transfers with the same memo and
+// `created_at_time`, so a retry of one that went through returns its block instead of paying
+// twice.
+pub async fn transfer(
+    ledger: Principal,
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: u128,
+    memo: Vec<u8>,
+    created_at_time: u64,
+) -> Result<u128, Error> {
+    try_transfer(ledger, from_subaccount, to, amount, memo, created_at_time)
+        .await
+        .map_err(|(error, _)| error)
+}
+
+// Like `transfer`, the flag of an error is set when the ledger turned the transfer down after
+// its deduplication found no earlier one, so nothing with that memo and time went through.
+pub async fn try_transfer(
+    ledger: Principal,
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: u128,
+    memo: Vec<u8>,
+    created_at_time: u64,
+) -> Result<u128, (Error, bool)> {
+    let arg = TransferArg {
+        from_subaccount,
+        to,
+        amount,
+        fee: None,
+        memo: Some(memo),
+        created_at_time: Some(created_at_time),
+    };
+    let (result,): (Result<u128, TransferError>,) = ic_cdk::call(ledger, "icrc1_transfer", (arg,))
+        .await
+        .map_err(|(code, msg)| {
+            let msg = format!("icrc1_transfer on {} failed ({:?}): {}", ledger, code, msg);
+            (Error::CallFailed { msg }, false)
+        })?;
+    // a transfer that is too old or arrives while the ledger is down isn't deduplicated
+    let (msg, not_executed) = match result {
+        Ok(block_index)
+        | Err(TransferError::Duplicate {
+            duplicate_of: block_index,
+        }) => return Ok(block_index),
+        Err(TransferError::InsufficientFunds { balance }) => {
+            (format!("the account holds only {}", balance), true)
+        }
+        Err(TransferError::BadFee { expected_fee }) => (
+            format!("the ledger expects a fee of {}", expected_fee),
+            true,
+        ),
+        Err(TransferError::BadBurn { min_burn_amount }) => {
+            (format!("burns start at {}", min_burn_amount), true)
+        }
+        Err(TransferError::TooOld) => (
+            "the transfer is too old to be deduplicated".to_string(),
+            false,
+        ),
+        Err(TransferError::CreatedInFuture { ledger_time }) => (
+            format!("the ledger time {} is behind the canister", ledger_time),
+            true,
+        ),
+        Err(TransferError::TemporarilyUnavailable) => {
+            ("the ledger is temporarily unavailable".to_string(), false)
+        }
+        Err(TransferError::GenericError {
+            error_code,
+            message,
+        }) => (format!("{} ({})", message, error_code), false),
+    };
+    let msg = format!("icrc1_transfer on {} was rejected: {}", ledger, msg);
+    Err((Error::CallFailed { msg }, not_executed))
+}
+
+#[derive(candid::CandidType, Serialize)]
+struct TransferFromArgs {
+    spender_subaccount: Option<Vec<u8>>,
+    from: Account,
+    to: Account,
+    amount: u128,
+    fee: Option<u128>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+enum TransferFromError {
+    BadFee { expected_fee: u128 },
+    BadBurn { min_burn_amount: u128 },
+    InsufficientFunds { balance: u128 },
+    InsufficientAllowance { allowance: u128 },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: u128 },
+    TemporarilyUnavailable,
+    GenericError { error_code: u128, message: String },
+}
+
+// Moves `amount` out of an account that approved the canister with `icrc2_approve` and returns
+// the block index, the fee is charged to `from` on top. Deduplicated like `transfer`.
+pub async fn transfer_from(
+    ledger: Principal,
+    from: Account,
+    to: Account,
+    amount: u128,
+    memo: Vec<u8>,
+    created_at_time: u64,
+) -> Result<u128, Error> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount,
+        fee: None,
+        memo: Some(memo),
+        created_at_time: Some(created_at_time),
+    };
+    let (result,): (Result<u128, TransferFromError>,) =
+        ic_cdk::call(ledger, "icrc2_transfer_from", (args,))
+            .await
+            .map_err(|(code, msg)| Error::CallFailed {
+                msg: format!(
+                    "icrc2_transfer_from on {} failed ({:?}): {}",
+                    ledger, code, msg
+                ),
+            })?;
+    let msg = match result {
+        Ok(block_index)
+        | Err(TransferFromError::Duplicate {
+            duplicate_of: block_index,
+        }) => return Ok(block_index),
+        Err(TransferFromError::InsufficientFunds { balance }) => {
+            format!("the account holds only {}", balance)
+        }
+        Err(TransferFromError::InsufficientAllowance { allowance }) => {
+            format!("the canister is approved for only {}", allowance)
+        }
+        Err(TransferFromError::BadFee { expected_fee }) => {
+            format!("the ledger expects a fee of {}", expected_fee)
+        }
+        Err(TransferFromError::BadBurn { min_burn_amount }) => {
+            format!("burns start at {}", min_burn_amount)
+        }
+        Err(TransferFromError::TooOld) => "the transfer is too old to be deduplicated".to_string(),
+        Err(TransferFromError::CreatedInFuture { ledger_time }) => {
+            format!("the ledger time {} is behind the canister", ledger_time)
+        }
+        Err(TransferFromError::TemporarilyUnavailable) => {
+            "the ledger is temporarily unavailable".to_string()
+        }
+        Err(TransferFromError::GenericError {
+            error_code,
+            message,
+        }) => format!("{} ({})", message, error_code),
+    };
+    Err(Error::CallFailed {
+        msg: format!("icrc2_transfer_from on {} was rejected: {}", ledger, msg),
+    })
+}
+
+// the fee the ledger charges for a transfer, in its smallest unit.
+pub async fn fee(ledger: Principal) -> Result<u128, Error> {
+    let (fee,): (u128,) = ic_cdk::call(ledger, "icrc1_fee", ())
+        .await
+        .map_err(|(code, msg)| Error::CallFailed {
+            msg: format!("icrc1_fee on {} failed ({:?}): {}", ledger, code, msg),
+        })?;
+    Ok(fee)
+}
+
+// how many NFTs of an ICRC-7 collection the default account of `owner` holds.
+pub async fn icrc7_balance_of(collection: Principal, owner: Principal) -> Result<u128, Error> {
+    let (balances,): (Vec<u128>,) =
+        ic_cdk::call(collection, "icrc7_balance_of", (vec![Account::of(owner)],))
+            .await
+            .map_err(|(code, msg)| Error::CallFailed {
+                msg: format!(
+                    "icrc7_balance_of on {} failed ({:?}): {}",
+                    collection, code, msg
+                ),
+            })?;
+    Ok(balances.first().copied().unwrap_or_default())
+}
+
+// the owners of NFTs of an ICRC-7 collection, None for the ones that don't exist.
+pub async fn icrc7_owner_of(
+    collection: Principal,
+    token_ids: Vec<u128>,
+) -> Result<Vec<Option<Account>>, Error> {
+    let (owners,): (Vec<Option<Account>>,) =
+        ic_cdk::call(collection, "icrc7_owner_of", (token_ids,))
+            .await
+            .map_err(|(code, msg)| Error::CallFailed {
+                msg: format!(
+                    "icrc7_owner_of on {} failed ({:?}): {}",
+                    collection, code, msg
+                ),
+            })?;
+    Ok(owners)
+}
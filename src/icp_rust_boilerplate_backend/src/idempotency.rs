@@ -0,0 +1,94 @@
+use crate::{_get_quiz, Error, Memory, Quiz, StorablePrincipal, MEMORY_MANAGER};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+const MAX_KEY_LEN: usize = 64;
+// a retry after this long creates another quiz
+const TTL_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// expired keys are forgotten a few at a time, on the calls that bring a key
+const MAX_PRUNED_PER_CALL: usize = 100;
+
+type KeyBlob = Blob<MAX_KEY_LEN>;
+// keys of different callers don't clash
+type CallerKey = (StorablePrincipal, KeyBlob);
+// the time a key was first used at, followed by the key
+type ExpiryKey = ((u64, StorablePrincipal), KeyBlob);
+
+thread_local! {
+    // the quiz id and the time the key was first used at
+    static KEYS: RefCell<StableBTreeMap<CallerKey, (u64, u64), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(67)))
+    ));
+
+    // the same keys ordered by the time they were first used at, so they expire in order
+    static KEYS_BY_TIME: RefCell<StableBTreeMap<ExpiryKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(68)))
+    ));
+}
+
+fn to_blob(key: &str) -> Result<KeyBlob, Error> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(Error::ValidationFailed {
+            field: "idempotency_key".to_string(),
+            msg: format!("the idempotency key must be 1 to {} bytes", MAX_KEY_LEN),
+        });
+    }
+    Ok(KeyBlob::try_from(key.as_bytes()).unwrap())
+}
+
+pub fn validate_key(key: &str) -> Result<(), Error> {
+    to_blob(key).map(|_| ())
+}
+
+fn prune() {
+    let expired: Vec<ExpiryKey> = KEYS_BY_TIME.with(|index| {
+        let cutoff = time().saturating_sub(TTL_NS);
+        index
+            .borrow()
+            .iter()
+            .take_while(|(((first_used_at, _), _), _)| *first_used_at < cutoff)
+            .take(MAX_PRUNED_PER_CALL)
+            .map(|(key, _)| key)
+            .collect()
+    });
+    for entry in expired {
+        let ((_, principal), key) = entry;
+        KEYS.with(|keys| keys.borrow_mut().remove(&(principal, key)));
+        KEYS_BY_TIME.with(|index| index.borrow_mut().remove(&entry));
+    }
+}
+
+// The quiz an earlier call of the caller created with the key, if it is still around. The payload
+// of the retry isn't compared, the key alone stands for the quiz.
+pub fn replay(key: &str) -> Result<Option<Quiz>, Error> {
+    let key = to_blob(key)?;
+    prune();
+    Ok(KEYS
+        .with(|keys| keys.borrow().get(&(StorablePrincipal(caller()), key)))
+        .filter(|(_, first_used_at)| first_used_at.saturating_add(TTL_NS) > time())
+        .and_then(|(quiz_id, _)| _get_quiz(&quiz_id)))
+}
+
+// ties the key to the quiz the call created, for later retries.
+pub fn remember(key: &str, quiz_id: u64) {
+    let Ok(key) = to_blob(key) else {
+        return;
+    };
+    let principal = StorablePrincipal(caller());
+    let now = time();
+    if let Some((_, first_used_at)) =
+        KEYS.with(|keys| keys.borrow_mut().insert((principal, key), (quiz_id, now)))
+    {
+        KEYS_BY_TIME.with(|index| {
+            index
+                .borrow_mut()
+                .remove(&((first_used_at, principal), key))
+        });
+    }
+    KEYS_BY_TIME.with(|index| index.borrow_mut().insert(((now, principal), key), ()));
+}
@@ -0,0 +1,72 @@
+use crate::guards::only_admin;
+use crate::{create_batch, maintenance, metrics, BatchMode, Error, Quiz, QuizPayload, Visibility};
+
+// One record of an import, the blob is an object of the form
+// `{ "quizzes": [{ "question": "...", "options": ["..."], "tags": ["..."],
+// "category": "...", "correct_options": ["..."] }] }`. Everything but the question and the
+// options can be left out, unknown fields are ignored so an export can be imported again.
+#[derive(Deserialize)]
+struct ImportRecord {
+    question: String,
+    options: Vec<String>,
+    tags: Option<Vec<String>>,
+    category: Option<String>,
+    correct_options: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct ImportFile {
+    // kept as raw values so a malformed record only rejects itself
+    quizzes: Vec<serde_json::Value>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct ImportOptions {
+    mode: BatchMode,
+    // applied to every imported quiz, they are public when left out
+    visibility: Option<Visibility>,
+}
+
+fn to_payload(
+    position: usize,
+    record: serde_json::Value,
+    options: &ImportOptions,
+) -> Result<QuizPayload, Error> {
+    let record: ImportRecord =
+        serde_json::from_value(record).map_err(|err| Error::ValidationFailed {
+            field: format!("quizzes[{}]", position),
+            msg: format!("the record is malformed: {}", err),
+        })?;
+    Ok(QuizPayload {
+        question: record.question,
+        options: record.options,
+        correct_options: record.correct_options,
+        tags: record.tags,
+        category: record.category,
+        visibility: options.visibility.clone(),
+        ..Default::default()
+    })
+}
+
+// The imported quizzes get fresh ids and the caller as their author. There is one result per
+// record, in the order of the file.
+#[ic_cdk::update(guard = "only_admin")]
+fn import_quizzes(
+    blob: Vec<u8>,
+    options: ImportOptions,
+) -> Result<Vec<Result<Quiz, Error>>, Error> {
+    metrics::count_call("import_quizzes");
+    maintenance::ensure_available("import_quizzes")?;
+    let file: ImportFile =
+        serde_json::from_slice(&blob).map_err(|err| Error::ValidationFailed {
+            field: "blob".to_string(),
+            msg: format!("the blob isn't a valid import file: {}", err),
+        })?;
+    let payloads = file
+        .quizzes
+        .into_iter()
+        .enumerate()
+        .map(|(position, record)| to_payload(position, record, &options))
+        .collect();
+    Ok(create_batch(payloads, options.mode))
+}
@@ -0,0 +1,161 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, allowlist, cast_single_vote, ensure_editor, get_quiz, links, maintenance, metrics,
+    Error, Memory, Quiz, Visibility, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode};
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// random bytes behind a code, it is handed out hex encoded
+const CODE_BYTES: usize = 8;
+const CODE_LEN: usize = CODE_BYTES * 2;
+// codes expire a week after they were generated
+const INVITE_TTL: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+type Code = Blob<CODE_LEN>;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Invite {
+    quiz_id: u64,
+    expires_at: u64,
+}
+
+impl Storable for Invite {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Invite {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    // codes of deleted quizzes stay until they expire, looking them up reports the quiz as gone.
+    // Expired codes are dropped when the next code is generated.
+    static INVITES: RefCell<StableBTreeMap<Code, Invite, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct InviteCode {
+    code: String,
+    quiz_id: u64,
+    expires_at: u64,
+}
+
+fn to_key(code: &str) -> Option<Code> {
+    Code::try_from(code.trim().to_lowercase().as_bytes()).ok()
+}
+
+fn quiz_with_code(code: &str) -> Result<Quiz, Error> {
+    let invite = to_key(code)
+        .and_then(|key| INVITES.with(|invites| invites.borrow().get(&key)))
+        .filter(|invite| time() < invite.expires_at)
+        .ok_or_else(|| Error::NotFound {
+            msg: "the invite code is unknown or expired".to_string(),
+        })?;
+    let quiz = _get_quiz(&invite.quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", invite.quiz_id),
+    })?;
+    // a code doesn't open drafts and quizzes that haven't started, only their editors see them
+    allowlist::ensure_not_hidden(&quiz)?;
+    Ok(quiz)
+}
+
+// drops the codes that expired, every new code makes room this way
+fn prune_expired() {
+    INVITES.with(|invites| {
+        let mut invites = invites.borrow_mut();
+        let expired: Vec<Code> = invites
+            .iter()
+            .filter(|(_, invite)| time() >= invite.expires_at)
+            .map(|(key, _)| key)
+            .collect();
+        for key in expired {
+            invites.remove(&key);
+        }
+    });
+}
+
+fn authored_quiz(quiz_id: u64) -> Result<Quiz, Error> {
+    let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", quiz_id),
+    })?;
+    ensure_editor(&quiz)?;
+    Ok(quiz)
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn generate_invite_code(quiz_id: u64) -> Result<InviteCode, Error> {
+    metrics::count_call("generate_invite_code");
+    maintenance::ensure_available("generate_invite_code")?;
+    let quiz = authored_quiz(quiz_id)?;
+    if quiz.visibility() == Visibility::Public {
+        return Err(Error::ValidationFailed {
+            field: "visibility".to_string(),
+            msg: format!("the quiz with id={} is public and needs no invite", quiz_id),
+        });
+    }
+
+    let (random,) = raw_rand().await.map_err(|(code, msg)| Error::CallFailed {
+        msg: format!("raw_rand failed ({:?}): {}", code, msg),
+    })?;
+    // the quiz may have been deleted while waiting for the randomness
+    authored_quiz(quiz_id)?;
+
+    let code: String = random
+        .iter()
+        .take(CODE_BYTES)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let invite = Invite {
+        quiz_id,
+        expires_at: time().saturating_add(INVITE_TTL),
+    };
+    prune_expired();
+    INVITES.with(|invites| {
+        invites
+            .borrow_mut()
+            .insert(to_key(&code).unwrap(), invite.clone())
+    });
+    Ok(InviteCode {
+        code,
+        quiz_id,
+        expires_at: invite.expires_at,
+    })
+}
+
+// Takes an invite code or the short code of a quiz. Only an invite gets past the visibility of
+// the quiz.
+#[ic_cdk::query]
+fn get_quiz_by_code(code: String) -> Result<Quiz, Error> {
+    match links::quiz_id_of_code(&code) {
+        Some(id) => get_quiz(id),
+        None => quiz_with_code(&code),
+    }
+}
+
+// A vote cast with a code puts the caller on the allowlist, so the quiz stays visible to them.
+// A vote that fails leaves the allowlist as it was.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn answer_quiz_with_code(code: String, option: String) -> Result<Quiz, Error> {
+    metrics::count_call("answer_quiz_with_code");
+    maintenance::ensure_available("answer_quiz_with_code")?;
+    let quiz_id = quiz_with_code(&code)?.id;
+    let quiz = cast_single_vote(quiz_id, option, true).await?;
+    allowlist::allow(quiz_id, caller());
+    Ok(quiz)
+}
@@ -0,0 +1,104 @@
+use crate::{Error, Memory, StorablePrincipal, MAX_PAGE_SIZE, MEMORY_MANAGER};
+use candid::Principal;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+
+thread_local! {
+    // cumulative points of every principal across all scored quizzes
+    static POINTS: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8)))
+    ));
+
+    // the same points ordered best first, so the top of the leaderboard is read without sorting
+    static RANKING: RefCell<StableBTreeMap<(Reverse<u64>, StorablePrincipal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    principal: Principal,
+    points: u64,
+    // principals with the same points share a rank
+    rank: u64,
+}
+
+pub fn add_points(principal: Principal, points: u64) {
+    let current = get_points(principal).unwrap_or_default();
+    set_points(principal, current.saturating_add(points));
+}
+
+// returns what was actually taken off, a principal never goes below zero
+pub fn remove_points(principal: Principal, points: u64) -> u64 {
+    let Some(current) = get_points(principal) else {
+        return 0;
+    };
+    set_points(principal, current.saturating_sub(points));
+    current.min(points)
+}
+
+pub fn points_of(principal: Principal) -> u64 {
+    get_points(principal).unwrap_or_default()
+}
+
+fn get_points(principal: Principal) -> Option<u64> {
+    POINTS.with(|points| points.borrow().get(&StorablePrincipal(principal)))
+}
+
+// keeps the points map and the ranking index in sync.
+fn set_points(principal: Principal, new_points: u64) {
+    let key = StorablePrincipal(principal);
+    let previous = POINTS.with(|points| points.borrow_mut().insert(key, new_points));
+    RANKING.with(|ranking| {
+        let mut ranking = ranking.borrow_mut();
+        if let Some(previous) = previous {
+            ranking.remove(&(Reverse(previous), key));
+        }
+        ranking.insert((Reverse(new_points), key), ());
+    });
+}
+
+#[ic_cdk::query]
+fn get_leaderboard(limit: u64) -> Vec<LeaderboardEntry> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    RANKING.with(|ranking| {
+        let mut entries: Vec<LeaderboardEntry> = Vec::with_capacity(limit);
+        for (position, ((Reverse(points), principal), _)) in
+            ranking.borrow().iter().take(limit).enumerate()
+        {
+            let rank = match entries.last() {
+                Some(previous) if previous.points == points => previous.rank,
+                _ => position as u64 + 1,
+            };
+            entries.push(LeaderboardEntry {
+                principal: principal.0,
+                points,
+                rank,
+            });
+        }
+        entries
+    })
+}
+
+// only the principals ranked above are visited, the rest of the index is never read.
+#[ic_cdk::query]
+fn get_rank(principal: Principal) -> Result<LeaderboardEntry, Error> {
+    let points = get_points(principal).ok_or_else(|| Error::NotFound {
+        msg: format!("the principal {} has no points yet", principal),
+    })?;
+    let ahead = RANKING.with(|ranking| {
+        ranking
+            .borrow()
+            .range(..(Reverse(points), StorablePrincipal::MIN))
+            .count() as u64
+    });
+    Ok(LeaderboardEntry {
+        principal,
+        points,
+        rank: ahead + 1,
+    })
+}
@@ -0,0 +1,123 @@
+use crate::{Memory, StorablePrincipal, MAX_PAGE_SIZE, MEMORY_MANAGER};
+use candid::Principal;
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+
+// what experience is earned for, it is never taken back
+#[derive(Clone, Copy)]
+pub enum XpReason {
+    QuizCreated,
+    Vote,
+    CorrectAnswer,
+}
+
+impl XpReason {
+    fn xp(self) -> u64 {
+        match self {
+            XpReason::QuizCreated => 10,
+            XpReason::Vote => 2,
+            XpReason::CorrectAnswer => 5,
+        }
+    }
+}
+
+thread_local! {
+    // experience earned since levels exist, earlier activity doesn't count
+    static XP: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(52)))
+    ));
+
+    // the same experience ordered best first, like the points of the leaderboard
+    static XP_RANKING: RefCell<StableBTreeMap<(Reverse<u64>, StorablePrincipal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(53)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct Level {
+    xp: u64,
+    // starts at 1
+    level: u64,
+    // the experience the next level starts at
+    next_level_xp: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct LevelEntry {
+    principal: Principal,
+    xp: u64,
+    level: u64,
+    // principals with the same experience share a rank
+    rank: u64,
+}
+
+// level n starts at 25 * n * (n - 1) xp, so every level takes 50 xp more than the one before
+fn level_start(level: u64) -> u64 {
+    25u64.saturating_mul(level).saturating_mul(level - 1)
+}
+
+fn level_of(xp: u64) -> u64 {
+    let mut level = 1;
+    while level_start(level + 1) <= xp {
+        level += 1;
+    }
+    level
+}
+
+pub fn award(principal: Principal, reason: XpReason) {
+    if principal == Principal::anonymous() {
+        return;
+    }
+    let key = StorablePrincipal(principal);
+    let previous = XP.with(|xp| xp.borrow().get(&key));
+    let new_xp = previous.unwrap_or_default().saturating_add(reason.xp());
+    XP.with(|xp| xp.borrow_mut().insert(key, new_xp));
+    XP_RANKING.with(|ranking| {
+        let mut ranking = ranking.borrow_mut();
+        if let Some(previous) = previous {
+            ranking.remove(&(Reverse(previous), key));
+        }
+        ranking.insert((Reverse(new_xp), key), ());
+    });
+}
+
+#[ic_cdk::query]
+fn get_my_level() -> Level {
+    let xp = XP
+        .with(|xp| xp.borrow().get(&StorablePrincipal(caller())))
+        .unwrap_or_default();
+    let level = level_of(xp);
+    Level {
+        xp,
+        level,
+        next_level_xp: level_start(level + 1),
+    }
+}
+
+#[ic_cdk::query]
+fn get_xp_leaderboard(limit: u64) -> Vec<LevelEntry> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    XP_RANKING.with(|ranking| {
+        let mut entries: Vec<LevelEntry> = Vec::with_capacity(limit);
+        for (position, ((Reverse(xp), principal), _)) in
+            ranking.borrow().iter().take(limit).enumerate()
+        {
+            let rank = match entries.last() {
+                Some(previous) if previous.xp == xp => previous.rank,
+                _ => position as u64 + 1,
+            };
+            entries.push(LevelEntry {
+                principal: principal.0,
+                xp,
+                level: level_of(xp),
+                rank,
+            });
+        }
+        entries
+    })
+}
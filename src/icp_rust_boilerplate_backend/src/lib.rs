@@ -1,42 +1,386 @@
 #[macro_use]
 extern crate serde;
-use candid::{Decode, Encode};
-use ic_cdk::api::time;
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::management_canister::http_request::{HttpResponse as OutcallResponse, TransformArgs};
+use ic_cdk::api::{caller, time};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
 use std::{borrow::Cow, cell::RefCell};
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::ops::Bound;
+use std::time::Duration;
+
+mod account_age;
+mod achievements;
+mod allowlist;
+mod audit;
+mod badges;
+mod banks;
+mod certification;
+mod cloning;
+mod comments;
+mod commit_reveal;
+mod cycles;
+mod deposits;
+mod discovery;
+mod editors;
+mod entry_fees;
+mod errors;
+mod explanations;
+mod export;
+mod free_text;
+mod governance;
+mod guards;
+mod http;
+mod icrc;
+mod idempotency;
+mod import;
+mod invites;
+mod leaderboard;
+mod levels;
+mod links;
+mod maintenance;
+mod metrics;
+mod migrations;
+mod moderation;
+mod neurons;
+mod nft_gates;
+mod notifications;
+mod numeric;
+mod opentdb;
+mod option_items;
+mod organizations;
+mod ownership;
+mod personhood;
+mod prize_pools;
+mod profiles;
+mod quadratic;
+mod ranked_choice;
+mod ratings;
+mod reactions;
+mod revisions;
+mod rewards;
+mod ratelimit;
+mod roles;
+mod sanitizer;
+mod scoring;
+mod search;
+mod sessions;
+mod settings;
+mod streaks;
+mod study;
+mod survey;
+mod tags;
+mod tallies;
+mod templates;
+mod timeline;
+mod token_gates;
+mod translations;
+mod trash;
+mod trending;
+
+use achievements::{Achievement, AchievementRule, AchievementRulePayload};
+use audit::{AuditAction, AuditEvent};
+use badges::{Badge, SupportedStandard, Value};
+use banks::{BankQuestion, BankQuestionPage, QuestionBank};
+use certification::CertifiedQuiz;
+use cloning::CloneOptions;
+use comments::{Comment, CommentPage};
+use cycles::{BalanceSample, CyclesAlert};
+use deposits::{Deposit, DepositSettings};
+use discovery::FeaturedQuiz;
+use entry_fees::{Entry, EntryFee};
+use errors::{ErrorCatalog, LocalizedError};
+use explanations::OptionExplanation;
+use export::{ExportFormat, ExportInfo};
+use free_text::{TextAnswer, TextAnswerPage};
+use governance::{ConfigChange, ConfigGovernance, PendingChange};
+use guards::{not_banned, only_admin, only_authenticated, only_moderator};
+use http::{HttpRequest, HttpResponse};
+use icrc::Account;
+use import::ImportOptions;
+use invites::InviteCode;
+use leaderboard::LeaderboardEntry;
+use levels::{Level, LevelEntry};
+use links::QuizLinks;
+use maintenance::{MaintenanceMode, PausedEndpoint};
+use metrics::Metrics;
+use nft_gates::NftGate;
+use notifications::NotificationPage;
+use numeric::{NumericRange, NumericResults};
+use option_items::{AssetRef, OptionItem};
+use organizations::{OrgMember, OrgRole, Organization};
+use reactions::{QuizReactions, Reaction};
+use revisions::QuizRevision;
+use rewards::{RewardClaim, RewardRule};
+use prize_pools::{Payout, PayoutRule, PrizePoolStatus};
+use profiles::{Profile, ProfilePayload};
+use quadratic::CreditLedger;
+use ranked_choice::RunoffRound;
+use ratings::{PlayerRating, QuestionRating};
+use ratelimit::RateLimits;
+use roles::{Role, RoleAssignment};
+use scoring::{ScoreEntry, ScoringPolicy};
+use sessions::{ScoreboardEntry, Session, SessionView};
+use settings::{Feature, Settings};
+use streaks::{Streak, StreakEntry, StreakSettings};
+use study::StudyCard;
+use survey::{Survey, SurveyPayload, SurveyQuestionPayload, SurveyResults};
+use tags::TagCount;
+use templates::{Template, TemplateOverrides};
+use timeline::VoteBucket;
+use token_gates::TokenGate;
+use translations::{LocalizedQuiz, TranslationPayload};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = Cell<u64, Memory>;
+// (created_at, id) reversed so that iterating yields the newest quiz first
+type CreationKey = (Reverse<u64>, Reverse<u64>);
 
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct Quiz {
     id: u64,
     question: String,
     options: Vec<String>,
+    // kept in `tallies` and only filled in when the quiz is read, stored quizzes leave it empty
     answers: HashMap<String, u32>,
     created_at: u64,
     updated_at: Option<u64>,
+    // None for quizzes created before authorship was recorded
+    author: Option<Principal>,
+    // the quiz is hidden and votes are rejected until this point in time
+    starts_at: Option<u64>,
+    // votes are rejected from this point in time on
+    ends_at: Option<u64>,
+    // set by the closing timer once the deadline passed, the tallies are frozen afterwards
+    closed_at: Option<u64>,
+    // None for quizzes created before voting modes existed, these are single choice
+    voting_mode: Option<VotingMode>,
+    // normalized to lowercase, see `tags::normalize_tags`
+    tags: Option<Vec<String>>,
+    category: Option<String>,
+    // ICRC-1 ledger whose balances weight the votes, `answers` keeps the raw counts
+    weight_token: Option<Principal>,
+    weighted_answers: Option<HashMap<String, u128>>,
+    // credits every voter of a quadratic quiz starts with
+    credits_per_voter: Option<u32>,
+    // how many options a voter of an approval quiz may pick at most
+    max_selections: Option<u32>,
+    // end of the reveal phase of a commit-reveal quiz, it starts at `ends_at`
+    reveal_ends_at: Option<u64>,
+    // set by the reveal timer once the reveal phase is over
+    revealed_at: Option<u64>,
+    // None for quizzes created before visibilities existed, these are public
+    visibility: Option<Visibility>,
+    // set while the quiz sits in the trash, see `trash::soft_delete`
+    deleted_at: Option<u64>,
+    // lets callers vote without signing in, as often as they like
+    allow_anonymous: Option<bool>,
+    // lets every voter vote again once this many nanoseconds passed since their last vote
+    vote_cooldown_ns: Option<u64>,
+    // None for quizzes created before drafts existed, these are published
+    status: Option<QuizStatus>,
+    // the organization owning the quiz next to its author, fixed when the quiz is created
+    organization: Option<u64>,
+    // the question and the options are Markdown, sanitized before they were stored
+    rendered: Option<bool>,
+}
+
+impl Quiz {
+    fn is_closed(&self) -> bool {
+        self.closed_at.is_some() || self.ends_at.is_some_and(|ends_at| time() >= ends_at)
+    }
+
+    fn is_revealed(&self) -> bool {
+        self.revealed_at.is_some()
+            || self.reveal_ends_at.is_some_and(|reveal_ends_at| time() >= reveal_ends_at)
+    }
+
+    fn voting_mode(&self) -> VotingMode {
+        self.voting_mode.clone().unwrap_or_default()
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.visibility.clone().unwrap_or_default()
+    }
+
+    // the answers of a weighted quiz are weighted by token balance or by neuron stake
+    fn is_weighted(&self) -> bool {
+        self.weight_token.is_some() || neurons::is_stake_weighted(self.id)
+    }
+
+    fn allows_anonymous(&self) -> bool {
+        self.allow_anonymous.unwrap_or_default()
+    }
+
+    fn is_draft(&self) -> bool {
+        self.status == Some(QuizStatus::Draft)
+    }
+
+    // checked on every read, the start timer may not have run yet
+    fn has_started(&self) -> bool {
+        self.starts_at.is_none_or(|starts_at| time() >= starts_at)
+    }
+
+    // drafts and quizzes that haven't started only show to their author
+    fn is_hidden(&self) -> bool {
+        self.is_draft() || !self.has_started()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq)]
+enum VotingMode {
+    // voters pick exactly one option through `answer_quiz`
+    #[default]
+    SingleChoice,
+    // voters rank the options through `submit_ranking`, the winner is found by instant runoff
+    RankedChoice,
+    // voters spread credits over the options through `cast_quadratic_votes`, n votes cost n²
+    Quadratic,
+    // voters approve up to `max_selections` options through `answer_quiz_multi`
+    Approval,
+    // voters send a hash through `commit_vote` until `ends_at` and open it through `reveal_vote`
+    // until `reveal_ends_at`, nothing is tallied before the reveal
+    CommitReveal,
+    // voters write a short answer through `submit_text_answer`, the quiz has no options and its
+    // editors grade the answers through `grade_text_answer`
+    FreeText,
+    // voters guess a number within `numeric_range` through `submit_number`, the guesses closest to
+    // `correct_number` win once the quiz closes
+    Numeric,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq)]
+enum Visibility {
+    #[default]
+    Public,
+    // only the author and the principals on the quiz's allowlist can see and answer it
+    Private,
+    // left out of every listing, an invite code gives access like the allowlist does
+    Unlisted,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq)]
+enum QuizStatus {
+    // only the author sees it and nobody can vote, `publish_quiz` opens it
+    Draft,
+    // published, but hidden and locked like a draft until `starts_at`, the start timer opens it
+    Scheduled,
+    #[default]
+    Published,
+}
+
+// the status a published quiz starts out with.
+fn published_status(starts_at: Option<u64>) -> QuizStatus {
+    if starts_at.is_some_and(|starts_at| time() < starts_at) {
+        QuizStatus::Scheduled
+    } else {
+        QuizStatus::Published
+    }
 }
 
 // a trait that must be implemented for a struct that is stored in a stable struct
 impl Storable for Quiz {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
-    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
         Decode!(bytes.as_ref(), Self).unwrap()
     }
 }
 
 // another trait that must be implemented for a struct that is stored in a stable struct
 impl BoundedStorable for Quiz {
+    // Leaves room for a long question next to about a dozen full-length options. Each btree node
+    // reserves space for 11 values of this size, which is why it isn't larger.
+    const MAX_SIZE: u32 = 8 * 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// records which option a principal picked for a quiz, so each principal only votes once
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Vote {
+    quiz_id: u64,
+    voter: Principal,
+    option: String,
+    // token balance of the voter at voting time, for token-weighted quizzes
+    weight: Option<u128>,
+    cast_at: u64,
+}
+
+impl Storable for Vote {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Vote {
+    // holds a single option of at most MAX_OPTION_LEN bytes next to the voter
     const MAX_SIZE: u32 = 1024;
     const IS_FIXED_SIZE: bool = false;
 }
 
+// the full ranking submitted by a principal for a ranked choice quiz, or the approved options
+// of an approval quiz in the order they were given
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Ballot {
+    ranking: Vec<String>,
+    submitted_at: u64,
+}
+
+impl Storable for Ballot {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Ballot {
+    // a ranking holds at most every option of the quiz
+    const MAX_SIZE: u32 = Quiz::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Principal doesn't implement Storable, so it is wrapped to be usable inside stable map keys
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct StorablePrincipal(Principal);
+
+impl StorablePrincipal {
+    // the empty principal sorts before every other principal, which makes it a handy range bound
+    const MIN: StorablePrincipal = StorablePrincipal(Principal::from_slice(&[]));
+}
+
+impl Default for StorablePrincipal {
+    fn default() -> Self {
+        StorablePrincipal(Principal::anonymous())
+    }
+}
+
+impl Storable for StorablePrincipal {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_slice())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        StorablePrincipal(Principal::from_slice(bytes.as_ref()))
+    }
+}
+
+impl BoundedStorable for StorablePrincipal {
+    // principals are at most 29 bytes long
+    const MAX_SIZE: u32 = 29;
+    const IS_FIXED_SIZE: bool = false;
+}
+
 thread_local! {
         static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
             MemoryManager::init(DefaultMemoryImpl::default())
@@ -49,108 +393,1011 @@ thread_local! {
 
         static STORAGE: RefCell<StableBTreeMap<u64, Quiz, Memory>> =
             RefCell::new(StableBTreeMap::init(
-                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27)))
+        ));
+
+        static VOTES: RefCell<StableBTreeMap<(u64, StorablePrincipal), Vote, Memory>> =
+            RefCell::new(StableBTreeMap::init(
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(33)))
+        ));
+
+        static BALLOTS: RefCell<StableBTreeMap<(u64, StorablePrincipal), Ballot, Memory>> =
+            RefCell::new(StableBTreeMap::init(
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28)))
+        ));
+
+        // the keys of VOTES flipped around, so a principal's votes can be listed without a scan
+        static VOTES_BY_VOTER: RefCell<StableBTreeMap<(StorablePrincipal, u64), (), Memory>> =
+            RefCell::new(StableBTreeMap::init(
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+        ));
+
+        static QUIZZES_BY_AUTHOR: RefCell<StableBTreeMap<(StorablePrincipal, u64), (), Memory>> =
+            RefCell::new(StableBTreeMap::init(
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+        ));
+
+        static QUIZZES_BY_CREATION: RefCell<StableBTreeMap<CreationKey, (), Memory>> =
+            RefCell::new(StableBTreeMap::init(
+                MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15)))
         ));
     }
 
-#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct QuizPayload {
     question: String,
     options: Vec<String>,
+    starts_at: Option<u64>,
+    ends_at: Option<u64>,
+    voting_mode: Option<VotingMode>,
+    // turns the quiz into trivia, the correct options are never returned to voters
+    correct_options: Option<Vec<String>>,
+    // stands in for the correct options until the quiz closes, see `reveal_correct_options`
+    correct_options_hash: Option<Vec<u8>>,
+    // all or nothing when None, see `scoring::grade`
+    scoring_policy: Option<ScoringPolicy>,
+    // the share of the points of a correct answer each wrong pick costs, e.g. 0.25
+    wrong_answer_penalty: Option<f64>,
+    // one per option, shown to a voter after answering, see `get_explanation`
+    explanations: Option<Vec<String>>,
+    // Markdown in the question and the options, see `sanitizer`
+    rendered: Option<bool>,
+    // one per option, None for options without an image, see `get_option_items`
+    option_images: Option<Vec<Option<AssetRef>>>,
+    // numeric quizzes only, the correct number is never returned to voters before the end
+    numeric_range: Option<NumericRange>,
+    correct_number: Option<f64>,
+    tags: Option<Vec<String>>,
+    category: Option<String>,
+    weight_token: Option<Principal>,
+    credits_per_voter: Option<u32>,
+    max_selections: Option<u32>,
+    reveal_ends_at: Option<u64>,
+    visibility: Option<Visibility>,
+    allow_anonymous: Option<bool>,
+    vote_cooldown_ns: Option<u64>,
+    // creates the quiz as a draft, updates keep the status of the quiz
+    draft: Option<bool>,
+    // lets a retried `create_quiz` return the quiz the first call created, updates ignore it
+    idempotency_key: Option<String>,
+    // creates the quiz for an organization of the caller, updates ignore it
+    organization: Option<u64>,
+}
+
+// how a batch of quizzes is created when some of them are rejected
+#[derive(candid::CandidType, Serialize, Deserialize, PartialEq)]
+enum BatchMode {
+    // nothing is created unless every quiz is valid
+    AllOrNothing,
+    // the valid quizzes are created, the rejected ones report their error
+    BestEffort,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct QuizCursorPage {
+    quizzes: Vec<Quiz>,
+    // (created_at, id) of the last quiz of the page, None once there are no older quizzes
+    next_cursor: Option<(u64, u64)>,
 }
 
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct VoteHistoryEntry {
+    quiz_id: u64,
+    option: String,
+    voted_at: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct QuizResults {
+    quiz_id: u64,
+    voting_mode: VotingMode,
+    // a single choice quiz has exactly one round holding the plain tallies
+    rounds: Vec<RunoffRound>,
+    winner: Option<String>,
+    // per option sum of the voters' token balances, for token-weighted quizzes
+    weighted_tallies: Option<Vec<(String, u128)>>,
+}
 
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct OptionResult {
+    option: String,
+    votes: u64,
+    // share of all votes counted, 0 when nobody voted
+    percentage: f64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct QuizResultSummary {
+    quiz_id: u64,
+    total_votes: u64,
+    options: Vec<OptionResult>,
+    leading_option: Option<String>,
+    // nothing changes anymore once the quiz closed, or a commit-reveal quiz revealed
+    is_final: bool,
+}
+
+// upper bound for the number of quizzes returned in a single page
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct QuizPage {
+    quizzes: Vec<Quiz>,
+    // the quizzes the caller can see on every page together
+    total: u64,
+}
+
+// Deprecated: loads every quiz at once and will hit the instruction limit on large canisters.
+// Use `get_quizzes` instead. An empty canister gives an empty page like every other listing.
 #[ic_cdk::query]
-fn get_all_quiz() -> Result<Vec<Quiz>, Error> {
-    let quizzesMap : Vec<(u64, Quiz)> =  STORAGE.with(|service| service.borrow().iter().collect());
-    let length = quizzesMap.len();
-    let mut quizzes: Vec<Quiz> = Vec::new();
-    for key in 0..length {
-        quizzes.push(quizzesMap.get(key).unwrap().clone().1);
-    }
-
-    if quizzes.len() > 0 {
-        Ok(quizzes)
-    }else {
-        Err(Error::NotFound {
-            msg: format!("There are currently no quiz"),
-        })
+fn get_all_quiz() -> QuizPage {
+    let quizzes: Vec<Quiz> = STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .map(|(_, quiz)| tallies::attach(quiz))
+            .filter(|quiz| allowlist::can_view(quiz, caller()))
+            .collect()
+    });
+    QuizPage {
+        total: quizzes.len() as u64,
+        quizzes,
     }
 }
 
+#[ic_cdk::query]
+fn get_quizzes(offset: u64, limit: u64) -> QuizPage {
+    let limit = limit.min(MAX_PAGE_SIZE);
+    STORAGE.with(|service| {
+        let service = service.borrow();
+        let visible = || {
+            service
+                .iter()
+                .map(|(_, quiz)| quiz)
+                .filter(|quiz| allowlist::can_view(quiz, caller()))
+        };
+        QuizPage {
+            quizzes: visible()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .map(tallies::attach)
+                .collect(),
+            total: visible().count() as u64,
+        }
+    })
+}
+
+// Newest quizzes first. Pass the `next_cursor` of the previous page to continue after it,
+// quizzes created in the meantime don't shift the pages like an offset would.
+#[ic_cdk::query]
+fn list_quizzes_after(cursor: Option<(u64, u64)>, limit: u64) -> QuizCursorPage {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    let start = match cursor {
+        Some((created_at, id)) => Bound::Excluded((Reverse(created_at), Reverse(id))),
+        None => Bound::Unbounded,
+    };
+    QUIZZES_BY_CREATION.with(|index| {
+        // one extra quiz tells whether another page follows
+        let mut quizzes: Vec<Quiz> = index
+            .borrow()
+            .range((start, Bound::Unbounded))
+            .filter_map(|((_, Reverse(id)), _)| _get_quiz(&id))
+            .filter(|quiz| allowlist::can_view(quiz, caller()))
+            .take(limit + 1)
+            .collect();
+        let next_cursor = if quizzes.len() > limit {
+            quizzes.truncate(limit);
+            quizzes.last().map(|quiz| (quiz.created_at, quiz.id))
+        } else {
+            None
+        };
+        QuizCursorPage {
+            quizzes,
+            next_cursor,
+        }
+    })
+}
+
+
+#[ic_cdk::query]
+fn get_quizzes_by_author(author: Principal, offset: u64, limit: u64) -> QuizPage {
+    let author = StorablePrincipal(author);
+    QUIZZES_BY_AUTHOR.with(|index| {
+        let index = index.borrow();
+        // a second pass over the quizzes is needed for the total
+        let visible = || {
+            index
+                .range((author, 0)..=(author, u64::MAX))
+                .filter_map(|((_, quiz_id), _)| _get_quiz(&quiz_id))
+                .filter(|quiz| allowlist::can_view(quiz, caller()))
+        };
+        QuizPage {
+            quizzes: visible()
+                .skip(offset as usize)
+                .take(limit.min(MAX_PAGE_SIZE) as usize)
+                .collect(),
+            total: visible().count() as u64,
+        }
+    })
+}
+
+
+// the quizzes of the caller, paged like `get_quizzes_by_author` but with the ones only they see
+#[ic_cdk::query]
+fn get_my_quizzes(offset: u64, limit: u64) -> QuizPage {
+    let author = StorablePrincipal(caller());
+    QUIZZES_BY_AUTHOR.with(|index| {
+        let index = index.borrow();
+        let authored = || {
+            index
+                .range((author, 0)..=(author, u64::MAX))
+                .filter_map(|((_, quiz_id), _)| _get_quiz(&quiz_id))
+        };
+        QuizPage {
+            quizzes: authored()
+                .skip(offset as usize)
+                .take(limit.min(MAX_PAGE_SIZE) as usize)
+                .collect(),
+            total: authored().count() as u64,
+        }
+    })
+}
+
 
 #[ic_cdk::query]
 fn get_quiz(id: u64) -> Result<Quiz, Error> {
     match _get_quiz(&id) {
-        Some(message) => Ok(message),
+        Some(message) => {
+            allowlist::ensure_can_view(&message)?;
+            Ok(message)
+        }
         None => Err(Error::NotFound {
             msg: format!("a quiz with id={} not found", id),
         }),
     }
 }
 
+// one result per id in the order asked for, ids past the page size are ignored.
+#[ic_cdk::query]
+fn get_quizzes_by_ids(ids: Vec<u64>) -> Vec<Result<Quiz, Error>> {
+    ids.into_iter()
+        .take(MAX_PAGE_SIZE as usize)
+        .map(get_quiz)
+        .collect()
+}
+
 fn _get_quiz(id: &u64) -> Option<Quiz> {
-    STORAGE.with(|s| s.borrow().get(id))
+    STORAGE.with(|s| s.borrow().get(id)).map(tallies::attach)
 }
 
+#[ic_cdk::update(guard = "only_authenticated")]
+fn create_quiz(payload: QuizPayload) -> Result<Quiz, Error> {
+    metrics::count_call("create_quiz");
+    maintenance::ensure_available("create_quiz")?;
+    // a retry isn't another quiz, so it doesn't count against the rate limit either
+    if let Some(key) = &payload.idempotency_key {
+        if let Some(quiz) = idempotency::replay(key)? {
+            return Ok(quiz);
+        }
+    }
+    ratelimit::check(ratelimit::Action::Create)?;
+    let payload = sanitizer::sanitize_payload(payload);
+    validate_payload(&payload)?;
+    if let Some(org_id) = payload.organization {
+        organizations::ensure_member(org_id)?;
+    }
+    let args = Encode!(&payload).unwrap();
+    let mut quiz = new_quiz(&payload);
+    // check the size before taking an id so a rejected payload doesn't burn one
+    ensure_fits(&quiz)?;
+    deposits::ensure_paid(1)?;
 
-#[ic_cdk::update]
-fn create_quiz(payload: QuizPayload) -> Option<Quiz> {
-    let id = ID_COUNTER
+    quiz.id = ID_COUNTER
         .with(|counter| {
             let current_value = *counter.borrow().get();
             counter.borrow_mut().set(current_value + 1)
         })
-        .expect("cannot increment id counter");
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment id counter".to_string(),
+        })?;
+    do_insert(&quiz);
+    index_listings(&quiz);
+    links::assign(&quiz);
+    deposits::attach(&quiz);
+    scoring::set_answer_key(quiz.id, payload.correct_options.unwrap_or_default());
+    scoring::set_scoring_rule(
+        quiz.id,
+        payload.scoring_policy,
+        payload.wrong_answer_penalty,
+        &quiz,
+    );
+    scoring::set_answer_commitment(quiz.id, payload.correct_options_hash.as_deref());
+    explanations::set_explanations(quiz.id, payload.explanations);
+    option_items::set_option_images(quiz.id, payload.option_images);
+    numeric::set_numeric_settings(quiz.id, payload.numeric_range, payload.correct_number);
+    schedule_closing(&quiz);
+    audit::record(AuditAction::Create, quiz.id, &args);
+    if let Some(key) = &payload.idempotency_key {
+        idempotency::remember(key, quiz.id);
+    }
+    notifications::mentions(&quiz.question, &quiz, caller());
+    levels::award(caller(), levels::XpReason::QuizCreated);
+    achievements::evaluate(caller());
+    Ok(quiz)
+}
 
+// the quiz a payload creates, without an id yet.
+fn new_quiz(payload: &QuizPayload) -> Quiz {
     let mut answers = HashMap::new();
 
     for option in &payload.options {
         answers.insert(String::from(option), 0);
     }
 
-
-    let quiz = Quiz {
-        id,
-        question: payload.question,
-        options: payload.options,
+    Quiz {
+        id: 0,
+        question: payload.question.clone(),
+        options: payload.options.clone(),
         answers,
         created_at: time(),
         updated_at: None,
+        author: Some(caller()),
+        starts_at: payload.starts_at,
+        ends_at: payload.ends_at.or_else(|| {
+            settings::default_duration_ns().map(|duration| {
+                payload.starts_at.unwrap_or_else(time).saturating_add(duration)
+            })
+        }),
+        closed_at: None,
+        voting_mode: payload.voting_mode.clone(),
+        tags: payload.tags.as_ref().map(|tags| tags::normalize_tags(tags)),
+        category: payload.category.as_ref().map(|category| category.trim().to_string()),
+        weight_token: payload.weight_token,
+        weighted_answers: None,
+        credits_per_voter: payload.credits_per_voter,
+        max_selections: payload.max_selections,
+        reveal_ends_at: payload.reveal_ends_at,
+        revealed_at: None,
+        visibility: payload.visibility.clone(),
+        deleted_at: None,
+        allow_anonymous: payload.allow_anonymous,
+        vote_cooldown_ns: payload.vote_cooldown_ns,
+        status: Some(if payload.draft.unwrap_or_default() {
+            QuizStatus::Draft
+        } else {
+            published_status(payload.starts_at)
+        }),
+        organization: payload.organization,
+        rendered: payload.rendered,
+    }
+}
+
+// Creates one quiz per payload, the results come back in the same order. In the all-or-nothing
+// mode every payload is checked first and a single rejected one keeps the whole batch out.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn create_quizzes(payloads: Vec<QuizPayload>, mode: BatchMode) -> Vec<Result<Quiz, Error>> {
+    metrics::count_call("create_quizzes");
+    if let Err(error) = maintenance::ensure_available("create_quizzes") {
+        return payloads.iter().map(|_| Err(error.clone())).collect();
+    }
+    create_batch(payloads.into_iter().map(Ok).collect(), mode)
+}
+
+// payloads that are already rejected count as rejected ones in the all-or-nothing mode.
+fn create_batch(
+    payloads: Vec<Result<QuizPayload, Error>>,
+    mode: BatchMode,
+) -> Vec<Result<Quiz, Error>> {
+    if payloads.len() > MAX_PAGE_SIZE as usize {
+        let msg = format!("at most {} quizzes can be created per call", MAX_PAGE_SIZE);
+        return payloads
+            .iter()
+            .map(|_| {
+                Err(Error::ValidationFailed {
+                    field: "payloads".to_string(),
+                    msg: msg.clone(),
+                })
+            })
+            .collect();
+    }
+    if mode == BatchMode::AllOrNothing {
+        // every quiz counts against the rate limit, the batch has to fit as a whole
+        if let Err(err) = ratelimit::ensure_room(ratelimit::Action::Create, payloads.len() as u32) {
+            return payloads.iter().map(|_| Err(err.clone())).collect();
+        }
+        if let Err(err) = deposits::ensure_paid(payloads.len()) {
+            return payloads.iter().map(|_| Err(err.clone())).collect();
+        }
+        let rejected = payloads.iter().any(|payload| {
+            payload
+                .as_ref()
+                .map_or(true, |payload| check_new_quiz(payload).is_err())
+        });
+        if rejected {
+            return payloads
+                .into_iter()
+                .map(|payload| {
+                    payload
+                        .and_then(|payload| check_new_quiz(&payload))
+                        .and(Err(Error::ValidationFailed {
+                            field: "payloads".to_string(),
+                            msg: "not created, another quiz of the batch was rejected".to_string(),
+                        }))
+                })
+                .collect();
+        }
+    }
+    payloads.into_iter().map(|payload| payload.and_then(create_quiz)).collect()
+}
+
+// everything `create_quiz` checks before it stores anything.
+fn check_new_quiz(payload: &QuizPayload) -> Result<(), Error> {
+    let payload = &sanitizer::sanitize_payload(payload.clone());
+    validate_payload(payload)?;
+    if let Some(key) = &payload.idempotency_key {
+        idempotency::validate_key(key)?;
+    }
+    if let Some(org_id) = payload.organization {
+        organizations::ensure_member(org_id)?;
+    }
+    ensure_fits(&new_quiz(payload))
+}
+
+// checks the parts of a payload that don't depend on the stored quiz.
+fn validate_payload(payload: &QuizPayload) -> Result<(), Error> {
+    match payload.voting_mode.clone().unwrap_or_default() {
+        VotingMode::FreeText => free_text::validate_free_text_quiz(payload)?,
+        VotingMode::Numeric => numeric::validate_numeric_quiz(payload)?,
+        _ => validate_question(&payload.question, &payload.options)?,
+    }
+    if (payload.numeric_range.is_some() || payload.correct_number.is_some())
+        && payload.voting_mode.clone().unwrap_or_default() != VotingMode::Numeric
+    {
+        return Err(Error::ValidationFailed {
+            field: "numeric_range".to_string(),
+            msg: "only numeric quizzes have a range and a correct number".to_string(),
+        });
+    }
+    if payload.ends_at.is_some_and(|ends_at| ends_at <= time()) {
+        return Err(Error::ValidationFailed {
+            field: "ends_at".to_string(),
+            msg: "the end time must be in the future".to_string(),
+        });
+    }
+    if let (Some(starts_at), Some(ends_at)) = (payload.starts_at, payload.ends_at) {
+        if starts_at >= ends_at {
+            return Err(Error::ValidationFailed {
+                field: "starts_at".to_string(),
+                msg: "the start time must be before the end time".to_string(),
+            });
+        }
+    }
+    if let Some(correct_options) = &payload.correct_options {
+        if !correct_options.is_empty()
+            && !matches!(
+                payload.voting_mode.clone().unwrap_or_default(),
+                VotingMode::SingleChoice | VotingMode::Approval
+            )
+        {
+            return Err(Error::ValidationFailed {
+                field: "correct_options".to_string(),
+                msg: "only single choice and approval quizzes can have correct options"
+                    .to_string(),
+            });
+        }
+        scoring::validate_correct_options(&payload.options, correct_options)?;
+    }
+    if let Some(hash) = &payload.correct_options_hash {
+        scoring::validate_answer_commitment(payload, hash)?;
+    }
+    if let Some(policy) = &payload.scoring_policy {
+        scoring::validate_scoring_policy(payload, policy)?;
+    }
+    if let Some(penalty) = payload.wrong_answer_penalty {
+        scoring::validate_wrong_answer_penalty(payload, penalty)?;
+    }
+    if let Some(explanations) = &payload.explanations {
+        explanations::validate_explanations(&payload.options, explanations)?;
+    }
+    if let Some(images) = &payload.option_images {
+        option_items::validate_option_images(&payload.options, images)?;
+    }
+    if payload.weight_token.is_some()
+        && payload.voting_mode.clone().unwrap_or_default() != VotingMode::SingleChoice
+    {
+        return Err(Error::ValidationFailed {
+            field: "weight_token".to_string(),
+            msg: "only single choice quizzes can be token-weighted".to_string(),
+        });
+    }
+    // the other ways of voting keep per-voter state, which every anonymous caller would share
+    if payload.allow_anonymous.unwrap_or_default()
+        && (payload.voting_mode.clone().unwrap_or_default() != VotingMode::SingleChoice
+            || payload.weight_token.is_some())
+    {
+        return Err(Error::ValidationFailed {
+            field: "allow_anonymous".to_string(),
+            msg: "only single choice quizzes without a weight token allow anonymous votes"
+                .to_string(),
+        });
+    }
+    if let Some(cooldown) = payload.vote_cooldown_ns {
+        validate_cooldown(payload, cooldown)?;
+    }
+    quadratic::validate_credits(
+        &payload.voting_mode.clone().unwrap_or_default(),
+        payload.credits_per_voter,
+    )?;
+    commit_reveal::validate_reveal_window(
+        &payload.voting_mode.clone().unwrap_or_default(),
+        payload.ends_at,
+        payload.reveal_ends_at,
+    )?;
+    match (payload.voting_mode.clone().unwrap_or_default(), payload.max_selections) {
+        (VotingMode::Approval, Some(max))
+            if max >= 1 && max as usize <= payload.options.len() => {}
+        (VotingMode::Approval, _) => {
+            return Err(Error::ValidationFailed {
+                field: "max_selections".to_string(),
+                msg: "an approval quiz allows between 1 and all of its options".to_string(),
+            })
+        }
+        (_, Some(_)) => {
+            return Err(Error::ValidationFailed {
+                field: "max_selections".to_string(),
+                msg: "only approval quizzes allow several selections".to_string(),
+            })
+        }
+        (_, None) => {}
+    }
+    if let Some(quiz_tags) = &payload.tags {
+        tags::validate_tags(&tags::normalize_tags(quiz_tags))?;
+    }
+    if let Some(category) = &payload.category {
+        tags::validate_category(category)?;
+    }
+    Ok(())
+}
+
+// Repeated votes only add to the tallies, trivia points would be handed out again for every one of
+// them and anonymous callers can vote as often as they like anyway.
+fn validate_cooldown(payload: &QuizPayload, cooldown: u64) -> Result<(), Error> {
+    let msg = if cooldown == 0 {
+        "the cooldown must be longer than zero"
+    } else if payload.voting_mode.clone().unwrap_or_default() != VotingMode::SingleChoice {
+        "only single choice quizzes can be voted on repeatedly"
+    } else if payload.correct_options.as_ref().is_some_and(|correct| !correct.is_empty()) {
+        "trivia quizzes can't be voted on repeatedly"
+    } else if payload.allow_anonymous.unwrap_or_default() {
+        "anonymous votes have no cooldown"
+    } else {
+        return Ok(());
     };
-    do_insert(&quiz);
-    Some(quiz)
+    Err(Error::ValidationFailed {
+        field: "vote_cooldown_ns".to_string(),
+        msg: msg.to_string(),
+    })
+}
+
+// a question with its options, shared by quizzes and survey questions.
+// longer options wouldn't fit into a vote record
+const MAX_OPTION_LEN: usize = 512;
+// the most the settings can allow, a quiz has no room for more
+const MAX_QUESTION_LEN: usize = 1024;
+const MAX_OPTIONS: usize = 20;
+
+fn validate_question(question: &str, options: &[String]) -> Result<(), Error> {
+    validate_question_text(question)?;
+    let max_options = settings::max_options();
+    if options.len() < 2 || options.len() > max_options {
+        return Err(Error::ValidationFailed {
+            field: "options".to_string(),
+            msg: format!("a question needs between 2 and {} options", max_options),
+        });
+    }
+    if options.iter().any(|option| option.trim().is_empty()) {
+        return Err(Error::ValidationFailed {
+            field: "options".to_string(),
+            msg: "an option must not be empty".to_string(),
+        });
+    }
+    if options.iter().any(|option| option.len() > MAX_OPTION_LEN) {
+        return Err(Error::ValidationFailed {
+            field: "options".to_string(),
+            msg: format!("an option must not exceed {} bytes", MAX_OPTION_LEN),
+        });
+    }
+    // options only differing in case or surrounding spaces look the same to voters
+    for (index, option) in options.iter().enumerate() {
+        let normalized = option.trim().to_lowercase();
+        if options[..index].iter().any(|other| other.trim().to_lowercase() == normalized) {
+            return Err(Error::ValidationFailed {
+                field: "options".to_string(),
+                msg: format!("the option '{}' is listed more than once", option),
+            });
+        }
+    }
+    for option in options {
+        moderation::ensure_clean("options", option)?;
+    }
+    Ok(())
 }
 
+// the question on its own, free-text quizzes have no options to go with it.
+fn validate_question_text(question: &str) -> Result<(), Error> {
+    if question.trim().is_empty() {
+        return Err(Error::ValidationFailed {
+            field: "question".to_string(),
+            msg: "the question must not be empty".to_string(),
+        });
+    }
+    let max_question_len = settings::max_question_len();
+    if question.len() > max_question_len {
+        return Err(Error::ValidationFailed {
+            field: "question".to_string(),
+            msg: format!("the question must not exceed {} bytes", max_question_len),
+        });
+    }
+    moderation::ensure_clean("question", question)
+}
 
-// helper method to perform insert.
+// the stable map traps on values above MAX_SIZE, so oversized quizzes are rejected up front.
+fn ensure_fits(quiz: &Quiz) -> Result<(), Error> {
+    if quiz.to_bytes().len() > Quiz::MAX_SIZE as usize {
+        return Err(Error::TooLarge {
+            msg: format!("a quiz must not exceed {} bytes once encoded", Quiz::MAX_SIZE),
+        });
+    }
+    Ok(())
+}
+
+// only the author of a quiz may change or delete it, or an admin of the organization owning it.
+fn ensure_author(quiz: &Quiz) -> Result<(), Error> {
+    if quiz.author != Some(caller())
+        && !organizations::has_quiz_role(quiz, caller(), OrgRole::Admin)
+    {
+        return Err(Error::Unauthorized {
+            msg: format!("only the author of the quiz with id={} can modify it", quiz.id),
+        });
+    }
+    Ok(())
+}
+
+// Editors may change everything the author can, except for deleting and transferring the quiz.
+// The members of the organization owning the quiz are its editors too.
+fn ensure_editor(quiz: &Quiz) -> Result<(), Error> {
+    if quiz.author != Some(caller())
+        && !editors::is_editor(quiz.id, caller())
+        && !organizations::has_quiz_role(quiz, caller(), OrgRole::Member)
+    {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only the author or an editor of the quiz with id={} can edit it",
+                quiz.id
+            ),
+        });
+    }
+    Ok(())
+}
+
+
+// helper method to perform insert, the tallies are stored separately.
 fn do_insert(quiz: &Quiz) {
-    STORAGE.with(|service| service.borrow_mut().insert(quiz.id, quiz.clone()));
+    let mut stored = quiz.clone();
+    stored.answers = HashMap::new();
+    stored.weighted_answers = None;
+    STORAGE.with(|service| service.borrow_mut().insert(quiz.id, stored));
+    certification::certify(quiz.id);
+}
+
+// registers the timers that open the quiz at its start time and close it at its end time.
+fn schedule_closing(quiz: &Quiz) {
+    if let Some(starts_at) = quiz.starts_at.filter(|starts_at| time() < *starts_at) {
+        let id = quiz.id;
+        let delay = Duration::from_nanos(starts_at - time());
+        ic_cdk_timers::set_timer(delay, move || open_quiz(id));
+    }
+    if let Some(ends_at) = quiz.ends_at {
+        let id = quiz.id;
+        let delay = Duration::from_nanos(ends_at.saturating_sub(time()));
+        ic_cdk_timers::set_timer(delay, move || close_quiz(id));
+    }
+    if let Some(reveal_ends_at) = quiz.reveal_ends_at {
+        let id = quiz.id;
+        let delay = Duration::from_nanos(reveal_ends_at.saturating_sub(time()));
+        ic_cdk_timers::set_timer(delay, move || close_reveal(id));
+    }
+}
+
+// timer callback, drafts stay drafts and wait for `publish_quiz`.
+fn open_quiz(id: u64) {
+    if let Some(mut quiz) = _get_quiz(&id) {
+        if quiz.status == Some(QuizStatus::Scheduled) && quiz.has_started() {
+            quiz.status = Some(QuizStatus::Published);
+            do_insert(&quiz);
+            if let Some(author) = quiz.author {
+                notifications::mentions(&quiz.question, &quiz, author);
+            }
+        }
+    }
+}
+
+// timer callback, the end time is re-checked because the quiz may have been updated in between.
+fn close_quiz(id: u64) {
+    if let Some(mut quiz) = _get_quiz(&id) {
+        if quiz.closed_at.is_none() && quiz.ends_at.is_some_and(|ends_at| time() >= ends_at) {
+            quiz.closed_at = Some(time());
+            do_insert(&quiz);
+            numeric::settle(&quiz);
+            prize_pools::quiz_closed(id);
+            deposits::quiz_closed(&quiz);
+            notifications::quiz_closed(id);
+        }
+    }
+}
+
+fn close_reveal(id: u64) {
+    if let Some(mut quiz) = _get_quiz(&id) {
+        if quiz.revealed_at.is_none()
+            && quiz.reveal_ends_at.is_some_and(|reveal_ends_at| time() >= reveal_ends_at)
+        {
+            quiz.revealed_at = Some(time());
+            do_insert(&quiz);
+            prize_pools::quiz_closed(id);
+        }
+    }
+}
+
+// a fresh canister has nothing to migrate.
+#[ic_cdk::init]
+fn init() {
+    migrations::set_schema_version(migrations::CURRENT_SCHEMA_VERSION);
+    achievements::seed_default_rules();
+    certification::certify_all();
+    cycles::start_monitoring();
+    discovery::start_rotation();
+}
+
+// Ingress calls of the anonymous principal are turned away before they cost anything, except for
+// votes on quizzes that allow them, and so are calls of paused endpoints. It only runs on one
+// replica, so the endpoints check as well.
+#[ic_cdk::inspect_message]
+fn inspect_message() {
+    if maintenance::is_paused(&ic_cdk::api::call::method_name()) {
+        ic_cdk::trap("the endpoint is paused");
+    }
+    if caller() != Principal::anonymous() || is_anonymous_vote() {
+        ic_cdk::api::call::accept_message();
+    } else {
+        ic_cdk::trap("anonymous callers have to sign in first");
+    }
+}
+
+fn is_anonymous_vote() -> bool {
+    if ic_cdk::api::call::method_name() != "answer_quiz" {
+        return false;
+    }
+    let args = ic_cdk::api::call::arg_data_raw();
+    candid::de::IDLDeserialize::new(&args)
+        .and_then(|mut args| args.get_value::<u64>())
+        .ok()
+        .and_then(|id| STORAGE.with(|service| service.borrow().get(&id)))
+        .is_some_and(|quiz| quiz.allows_anonymous())
+}
+
+// The stable structures write through, so there is no pre_upgrade hook saving anything. The data
+// is migrated to the format of the new build first. Timers don't survive upgrades, so they are
+// registered again for every quiz still running.
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    migrations::run();
+    STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .filter(|(_, quiz)| {
+                quiz.closed_at.is_none()
+                    || quiz.reveal_ends_at.is_some() && quiz.revealed_at.is_none()
+            })
+            .for_each(|(_, quiz)| schedule_closing(&quiz));
+    });
+    trash::reschedule_purges();
+    sessions::reschedule_windows();
+    certification::certify_all();
+    cycles::start_monitoring();
+    discovery::start_rotation();
+    prize_pools::resume_settlements();
+    prize_pools::resume_payouts();
+    deposits::resume_refunds();
+    governance::resume_changes();
+}
+
+fn index_author(quiz: &Quiz) {
+    if let Some(author) = quiz.author {
+        let key = (StorablePrincipal(author), quiz.id);
+        QUIZZES_BY_AUTHOR.with(|index| index.borrow_mut().insert(key, ()));
+    }
+}
+
+fn unindex_author(quiz: &Quiz) {
+    if let Some(author) = quiz.author {
+        let key = (StorablePrincipal(author), quiz.id);
+        QUIZZES_BY_AUTHOR.with(|index| index.borrow_mut().remove(&key));
+    }
 }
 
+fn creation_key(quiz: &Quiz) -> CreationKey {
+    (Reverse(quiz.created_at), Reverse(quiz.id))
+}
+
+fn index_creation(quiz: &Quiz) {
+    QUIZZES_BY_CREATION.with(|index| index.borrow_mut().insert(creation_key(quiz), ()));
+}
+
+// adds the quiz to every index a listing is served from.
+fn index_listings(quiz: &Quiz) {
+    index_author(quiz);
+    index_creation(quiz);
+    search::index_quiz(quiz);
+    tags::index_quiz(quiz);
+    organizations::index_quiz(quiz);
+}
+
+fn unindex_listings(quiz: &Quiz) {
+    unindex_author(quiz);
+    QUIZZES_BY_CREATION.with(|index| index.borrow_mut().remove(&creation_key(quiz)));
+    search::unindex_quiz(quiz);
+    tags::unindex_quiz(quiz);
+    organizations::unindex_quiz(quiz);
+}
+
+// helper method to forget every vote, ballot, credit ledger and tally of a quiz.
+// Points earned on the quiz are taken back, so it has to run before the answer key changes.
+fn remove_votes(quiz_id: u64) {
+    VOTES.with(|votes| {
+        for ((_, voter), vote) in votes.borrow().range(id_key_range(quiz_id)) {
+            scoring::revoke_points(quiz_id, voter.0, &picked_options(&vote));
+            VOTES_BY_VOTER.with(|index| index.borrow_mut().remove(&(voter, quiz_id)));
+        }
+    });
+    free_text::remove_text_answers(quiz_id);
+    numeric::remove_guesses(quiz_id);
+    VOTES.with(|votes| remove_quiz_entries(&mut votes.borrow_mut(), quiz_id));
+    BALLOTS.with(|ballots| remove_quiz_entries(&mut ballots.borrow_mut(), quiz_id));
+    quadratic::remove_credit_ledgers(quiz_id);
+    commit_reveal::remove_commitments(quiz_id);
+    tallies::remove(quiz_id);
+    timeline::remove_timeline(quiz_id);
+    trending::remove_score(quiz_id);
+}
+
+fn remove_quiz_entries<V: BoundedStorable>(
+    map: &mut StableBTreeMap<(u64, StorablePrincipal), V, Memory>,
+    quiz_id: u64,
+) {
+    let keys: Vec<(u64, StorablePrincipal)> = map
+        .range(id_key_range(quiz_id))
+        .map(|(key, _)| key)
+        .collect();
+    for key in keys {
+        map.remove(&key);
+    }
+}
+
+// every (id, principal) key belonging to the given quiz or survey.
+fn id_key_range(id: u64) -> std::ops::Range<(u64, StorablePrincipal)> {
+    (id, StorablePrincipal::MIN)..(id + 1, StorablePrincipal::MIN)
+}
 
-#[ic_cdk::update]
+
+#[ic_cdk::update(guard = "only_authenticated")]
 fn update_quiz(id: u64, payload: QuizPayload) -> Result<Quiz, Error> {
+    metrics::count_call("update_quiz");
+    maintenance::ensure_available("update_quiz")?;
+    let args = Encode!(&id, &payload).unwrap();
+    apply_payload(id, payload, &args, false)
+}
 
+// Helper method behind `update_quiz` and `rollback_quiz`, the replaced version is kept as a
+// revision. With `keep_votes` the votes stay as long as nothing they depend on changed, otherwise
+// the tallies start over.
+fn apply_payload(
+    id: u64,
+    payload: QuizPayload,
+    args: &[u8],
+    keep_votes: bool,
+) -> Result<Quiz, Error> {
+    let payload = sanitizer::sanitize_payload(payload);
+    validate_payload(&payload)?;
     let quiz_option: Option<Quiz> = STORAGE.with(|service| service.borrow().get(&id));
 
-    match quiz_option {
+    match quiz_option.filter(|quiz| quiz.deleted_at.is_none()) {
 
         Some(mut quiz) => {
-
+            ensure_editor(&quiz)?;
+            // nobody voted on a draft, so it stays editable once its end time passed
+            if quiz.is_closed() && !quiz.is_draft() {
+                return Err(Error::QuizClosed {
+                    msg: format!("couldn't update a quiz with id={}. quiz is closed", id),
+                });
+            }
 
             let mut answers = HashMap::new();
 
             for option in &payload.options {
                 answers.insert(String::from(option), 0);
             }
+            let correct_options = payload.correct_options.unwrap_or_default();
+            let correct_options_hash = payload.correct_options_hash;
+            let scoring_policy = payload.scoring_policy;
+            let (numeric_range, correct_number) = (payload.numeric_range, payload.correct_number);
+            let wrong_answer_penalty =
+                payload.wrong_answer_penalty.filter(|penalty| *penalty > 0.0);
+            let previous = quiz.clone();
+            let previous_correct_options = scoring::correct_options_of(id);
+            let keeps_votes = keep_votes
+                && previous.options == payload.options
+                && previous.voting_mode() == payload.voting_mode.clone().unwrap_or_default()
+                && previous.weight_token == payload.weight_token
+                && previous.credits_per_voter == payload.credits_per_voter
+                && previous.max_selections == payload.max_selections
+                && previous_correct_options == correct_options
+                && scoring::answer_commitment_of(id) == correct_options_hash
+                && scoring::scoring_policy_of(id) == scoring_policy
+                && scoring::wrong_answer_penalty_of(id) == wrong_answer_penalty
+                && numeric::numeric_range_of(id) == numeric_range
+                && numeric::correct_number_of(id) == correct_number;
 
             quiz.question = payload.question;
             quiz.options = payload.options;
             quiz.answers = answers;
+            quiz.starts_at = payload.starts_at;
+            quiz.ends_at = payload.ends_at.or_else(|| {
+                settings::default_duration_ns().map(|duration| {
+                    payload.starts_at.unwrap_or_else(time).saturating_add(duration)
+                })
+            });
+            quiz.voting_mode = payload.voting_mode;
+            quiz.tags = payload.tags.map(|tags| tags::normalize_tags(&tags));
+            quiz.category = payload.category.map(|category| category.trim().to_string());
+            quiz.weight_token = payload.weight_token;
+            quiz.weighted_answers = None;
+            quiz.credits_per_voter = payload.credits_per_voter;
+            quiz.max_selections = payload.max_selections;
+            quiz.reveal_ends_at = payload.reveal_ends_at;
+            quiz.visibility = payload.visibility;
+            quiz.allow_anonymous = payload.allow_anonymous;
+            quiz.vote_cooldown_ns = payload.vote_cooldown_ns;
+            quiz.rendered = payload.rendered;
+            if quiz.is_draft() {
+                quiz.closed_at = None;
+                quiz.revealed_at = None;
+            } else {
+                quiz.status = Some(published_status(quiz.starts_at));
+            }
             quiz.updated_at = Some(time());
+            ensure_fits(&quiz)?;
+            revisions::save(&previous, previous_correct_options);
             do_insert(&quiz);
-            Ok(quiz)
+            explanations::set_explanations(id, payload.explanations);
+            option_items::set_option_images(id, payload.option_images);
+            if previous.question != quiz.question || previous.options != quiz.options {
+                translations::remove_translations(id);
+            }
+            search::reindex_quiz(&previous, &quiz);
+            tags::reindex_quiz(&previous, &quiz);
+            if keeps_votes {
+                audit::record(AuditAction::Rollback, id, args);
+            } else {
+                // the tallies were reset, so previous voters may vote again
+                remove_votes(id);
+                scoring::set_answer_key(id, correct_options);
+                scoring::set_answer_commitment(id, correct_options_hash.as_deref());
+                scoring::set_scoring_rule(id, scoring_policy, wrong_answer_penalty, &quiz);
+                numeric::set_numeric_settings(id, numeric_range, correct_number);
+                audit::record(AuditAction::Update, id, args);
+            }
+            schedule_closing(&quiz);
+            Ok(tallies::attach(quiz))
         }
         None => Err(Error::NotFound {
             msg: format!(
@@ -162,10 +1409,51 @@ fn update_quiz(id: u64, payload: QuizPayload) -> Result<Quiz, Error> {
 }
 
 
-#[ic_cdk::update]
+// Opens a draft to everybody the visibility lets see it, or schedules it when its start time is
+// ahead. Principals mentioned in it are notified once it opens, they couldn't see the draft.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn publish_quiz(id: u64) -> Result<Quiz, Error> {
+    metrics::count_call("publish_quiz");
+    maintenance::ensure_available("publish_quiz")?;
+    let mut quiz = _get_quiz(&id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", id),
+        })?;
+    ensure_editor(&quiz)?;
+    if !quiz.is_draft() {
+        return Err(Error::WrongPhase {
+            msg: format!("the quiz with id={} is published already", id),
+        });
+    }
+    if quiz.is_closed() {
+        return Err(Error::QuizClosed {
+            msg: format!("the end time of the quiz with id={} passed, update it first", id),
+        });
+    }
+    quiz.status = Some(published_status(quiz.starts_at));
+    quiz.updated_at = Some(time());
+    do_insert(&quiz);
+    audit::record(AuditAction::Publish, id, &Encode!(&id).unwrap());
+    // the start timer notifies them of a scheduled quiz
+    if !quiz.is_hidden() {
+        notifications::mentions(&quiz.question, &quiz, caller());
+    }
+    Ok(quiz)
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+// moves the quiz to the trash, `restore_quiz` brings it back until it is purged.
 fn delete_quiz(id: u64) -> Result<Quiz, Error> {
-    match STORAGE.with(|service| service.borrow_mut().remove(&id)) {
-        Some(quiz) => Ok(quiz),
+    metrics::count_call("delete_quiz");
+    maintenance::ensure_available("delete_quiz")?;
+    match _get_quiz(&id).filter(|quiz| quiz.deleted_at.is_none()) {
+        Some(mut quiz) => {
+            ensure_author(&quiz)?;
+            trash::soft_delete(&mut quiz);
+            audit::record(AuditAction::Delete, id, &Encode!(&id).unwrap());
+            Ok(quiz)
+        }
         None => Err(Error::NotFound {
             msg: format!(
                 "couldn't delete a quiz with id={}. quiz not found.",
@@ -175,27 +1463,131 @@ fn delete_quiz(id: u64) -> Result<Quiz, Error> {
     }
 }
 
+// moderators remove quizzes they didn't write, e.g. spam or abuse.
+#[ic_cdk::update(guard = "only_moderator")]
+fn force_delete_quiz(id: u64) -> Result<Quiz, Error> {
+    metrics::count_call("force_delete_quiz");
+    maintenance::ensure_available("force_delete_quiz")?;
+    let mut quiz = _get_quiz(&id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("couldn't delete a quiz with id={}. quiz not found.", id),
+        })?;
+    trash::soft_delete(&mut quiz);
+    deposits::forfeit(id);
+    audit::record(AuditAction::Delete, id, &Encode!(&id).unwrap());
+    Ok(quiz)
+}
+
+// helper method to drop a quiz for good together with its index entries and votes.
+fn remove_quiz(quiz: &Quiz) {
+    let id = quiz.id;
+    STORAGE.with(|service| service.borrow_mut().remove(&id));
+    unindex_listings(quiz);
+    remove_votes(id);
+    scoring::remove_answer_key(id);
+    ratings::remove_rating(id);
+    explanations::remove_explanations(id);
+    option_items::remove_option_images(id);
+    numeric::remove_numeric_settings(id);
+    translations::remove_translations(id);
+    links::remove_links(id);
+    rewards::remove_reward_rule(id);
+    entry_fees::remove_entry_fee(id);
+    token_gates::remove_token_gate(id);
+    nft_gates::remove_nft_gate(id);
+    neurons::remove_stake_weighting(id);
+    personhood::remove_personhood_gate(id);
+    account_age::remove_min_account_age(id);
+    deposits::forfeit(id);
+    allowlist::remove_allowlist(id);
+    comments::remove_comments(id);
+    reactions::remove_reactions(id);
+    discovery::remove_editorial_pick(id);
+    revisions::remove_revisions(id);
+    ownership::remove_transfer(id);
+    editors::remove_editors(id);
+    certification::certify(id);
+}
+
+
+#[ic_cdk::update(guard = "not_banned")]
+async fn answer_quiz(id: u64, option: String) -> Result<Quiz, Error> {
+    metrics::count_call("answer_quiz");
+    maintenance::ensure_available("answer_quiz")?;
+    cast_single_vote(id, option, false).await
+}
+
+// a single choice vote of the caller, shared by `answer_quiz`, `answer_quiz_option` and
+// `answer_quiz_with_code`. `invited` is set when the caller answers with an invite code.
+async fn cast_single_vote(id: u64, option: String, invited: bool) -> Result<Quiz, Error> {
+    ratelimit::check(ratelimit::Action::Vote)?;
+    let voter = caller();
+    // answers the gates of the quiz from the ledgers, the cache covers the other ways of voting
+    token_gates::refresh(id, voter).await?;
+    nft_gates::refresh(id, voter).await?;
+    personhood::refresh(id, voter).await?;
+    let vote_key = (id, StorablePrincipal(voter));
+    let quiz = validate_vote(id, &vote_key, &option, invited)?;
+    let args = Encode!(&id, &option).unwrap();
+
+    // the weight lookups await other canisters, so every check is repeated once they return
+    let (weight, neurons) = match quiz.weight_token {
+        Some(ledger) => (Some(icrc::balance_of(ledger, voter).await?), None),
+        None => (None, neurons::neurons_of(id, voter).await?),
+    };
+    let quiz = if weight.is_some() || neurons.is_some() {
+        validate_vote(id, &vote_key, &option, invited)?
+    } else {
+        quiz
+    };
+    let weight = match neurons {
+        Some(neurons) => Some(neurons::claim(id, voter, neurons)?),
+        None => weight,
+    };
 
-#[ic_cdk::update]
-fn answer_quiz(id: u64, option: String) -> Result<Quiz, Error> {
+    tallies::add(&quiz, &option, 1);
+    if let Some(weight) = weight {
+        tallies::add_weight(&quiz, &option, weight);
+    }
+    audit::record_vote(id, &args, vec![(option.clone(), 1)], weight);
+    // anonymous votes are only counted, there's nobody to score or remember them for
+    if voter != Principal::anonymous() {
+        scoring::award_points(id, voter, std::slice::from_ref(&option));
+        record_vote(vote_key, option, weight);
+    }
+    after_vote(&quiz);
+    Ok(tallies::attach(quiz))
+}
 
+// checks a single choice vote without changing anything, returns the quiz to vote on.
+fn validate_vote(
+    id: u64,
+    vote_key: &(u64, StorablePrincipal),
+    option: &str,
+    invited: bool,
+) -> Result<Quiz, Error> {
     let quiz_option: Option<Quiz> = STORAGE.with(|service| service.borrow().get(&id));
 
     match quiz_option {
 
-        Some(mut quiz) => {
+        Some(quiz) => {
+
+            if quiz.voting_mode() != VotingMode::SingleChoice {
+                return Err(Error::ValidationFailed {
+                    field: "voting_mode".to_string(),
+                    msg: format!("the quiz with id={} is not a single choice quiz", id),
+                });
+            }
+            ensure_can_vote_to(&quiz, vote_key, invited)?;
 
             // Check if the selected option is valid
-            if quiz.options.contains(&option) {
-                if let Some(answer_count) = quiz.answers.get_mut(&option) {
-                    *answer_count += 1;
-                }
-                quiz.updated_at = Some(time());
-                do_insert(&quiz);
+            if quiz.options.iter().any(|candidate| candidate == option) {
                 Ok(quiz)
             } else {
                 // Return an error if the selected option is not valid
-                Err(Error::NotFound {
+                Err(Error::ValidationFailed {
+                    field: "option".to_string(),
                     msg: format!("The option '{}' is not found for this quiz.", option),
                 })
             }
@@ -209,9 +1601,443 @@ fn answer_quiz(id: u64, option: String) -> Result<Quiz, Error> {
     }
 }
 
-#[derive(candid::CandidType, Deserialize, Serialize)]
+#[ic_cdk::update(guard = "not_banned")]
+fn submit_ranking(id: u64, ranking: Vec<String>) -> Result<Quiz, Error> {
+    metrics::count_call("submit_ranking");
+    maintenance::ensure_available("submit_ranking")?;
+    ratelimit::check(ratelimit::Action::Vote)?;
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("couldn't rank a quiz with id={}. quiz not found", id),
+    })?;
+
+    if quiz.voting_mode() != VotingMode::RankedChoice {
+        return Err(Error::ValidationFailed {
+            field: "voting_mode".to_string(),
+            msg: format!("the quiz with id={} is not a ranked choice quiz", id),
+        });
+    }
+    let vote_key = (id, StorablePrincipal(caller()));
+    ensure_can_vote(&quiz, &vote_key)?;
+
+    if ranking.is_empty() {
+        return Err(Error::ValidationFailed {
+            field: "ranking".to_string(),
+            msg: "the ranking must contain at least one option".to_string(),
+        });
+    }
+    for (position, option) in ranking.iter().enumerate() {
+        if !quiz.options.contains(option) {
+            return Err(Error::ValidationFailed {
+                field: "ranking".to_string(),
+                msg: format!("The option '{}' is not found for this quiz.", option),
+            });
+        }
+        if ranking[..position].contains(option) {
+            return Err(Error::ValidationFailed {
+                field: "ranking".to_string(),
+                msg: format!("The option '{}' is ranked more than once.", option),
+            });
+        }
+    }
+
+    let args = Encode!(&id, &ranking).unwrap();
+    // the answers map keeps counting first preferences so it stays meaningful for ranked quizzes
+    let first_choice = ranking[0].clone();
+    tallies::add(&quiz, &first_choice, 1);
+    record_vote(vote_key, first_choice.clone(), None);
+    BALLOTS.with(|ballots| {
+        ballots.borrow_mut().insert(
+            vote_key,
+            Ballot {
+                ranking,
+                submitted_at: time(),
+            },
+        )
+    });
+    audit::record_vote(id, &args, vec![(first_choice, 1)], None);
+    after_vote(&quiz);
+    Ok(tallies::attach(quiz))
+}
+
+#[ic_cdk::update(guard = "not_banned")]
+fn answer_quiz_multi(id: u64, selections: Vec<String>) -> Result<Quiz, Error> {
+    metrics::count_call("answer_quiz_multi");
+    maintenance::ensure_available("answer_quiz_multi")?;
+    ratelimit::check(ratelimit::Action::Vote)?;
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("couldn't cast a quiz with id={}. quiz not found", id),
+    })?;
+
+    if quiz.voting_mode() != VotingMode::Approval {
+        return Err(Error::ValidationFailed {
+            field: "voting_mode".to_string(),
+            msg: format!("the quiz with id={} is not an approval quiz", id),
+        });
+    }
+    let vote_key = (id, StorablePrincipal(caller()));
+    ensure_can_vote(&quiz, &vote_key)?;
+
+    let max_selections = quiz.max_selections.unwrap_or(1) as usize;
+    if selections.is_empty() || selections.len() > max_selections {
+        return Err(Error::ValidationFailed {
+            field: "selections".to_string(),
+            msg: format!("between 1 and {} options have to be selected", max_selections),
+        });
+    }
+    for (position, option) in selections.iter().enumerate() {
+        if !quiz.options.contains(option) {
+            return Err(Error::ValidationFailed {
+                field: "selections".to_string(),
+                msg: format!("The option '{}' is not found for this quiz.", option),
+            });
+        }
+        if selections[..position].contains(option) {
+            return Err(Error::ValidationFailed {
+                field: "selections".to_string(),
+                msg: format!("The option '{}' is selected more than once.", option),
+            });
+        }
+    }
+
+    let args = Encode!(&id, &selections).unwrap();
+    for option in &selections {
+        tallies::add(&quiz, option, 1);
+    }
+    let tally = selections.iter().map(|option| (option.clone(), 1)).collect();
+    audit::record_vote(id, &args, tally, None);
+    if caller() != Principal::anonymous() {
+        scoring::award_points(id, caller(), &selections);
+    }
+    record_vote(vote_key, selections[0].clone(), None);
+    BALLOTS.with(|ballots| {
+        ballots.borrow_mut().insert(
+            vote_key,
+            Ballot {
+                ranking: selections,
+                submitted_at: time(),
+            },
+        )
+    });
+    after_vote(&quiz);
+    Ok(tallies::attach(quiz))
+}
+
+// everything that follows a vote of the caller besides the tallies, the audit log and its record.
+fn after_vote(quiz: &Quiz) {
+    timeline::record_vote(quiz.id);
+    trending::record_vote(quiz.id);
+    streaks::record_participation(caller());
+    levels::award(caller(), levels::XpReason::Vote);
+    achievements::record_vote(quiz, caller());
+    notifications::quiz_answered(quiz, caller());
+    deposits::record_vote(quiz.id);
+}
+
+// the options a vote picked, the approved ones on an approval quiz. Only ranked choice quizzes
+// have ballots besides those and they are never scored.
+fn picked_options(vote: &Vote) -> Vec<String> {
+    BALLOTS
+        .with(|ballots| {
+            ballots
+                .borrow()
+                .get(&(vote.quiz_id, StorablePrincipal(vote.voter)))
+        })
+        .map(|ballot| ballot.ranking)
+        .unwrap_or_else(|| vec![vote.option.clone()])
+}
+
+// helper method to store a vote together with its by-voter index entry.
+fn record_vote(vote_key: (u64, StorablePrincipal), option: String, weight: Option<u128>) {
+    VOTES.with(|votes| {
+        votes.borrow_mut().insert(
+            vote_key,
+            Vote {
+                quiz_id: vote_key.0,
+                voter: vote_key.1 .0,
+                option,
+                weight,
+                cast_at: time(),
+            },
+        )
+    });
+    VOTES_BY_VOTER.with(|index| index.borrow_mut().insert((vote_key.1, vote_key.0), ()));
+}
+
+// every way of voting goes through here, so private quizzes only take votes from allowed voters.
+fn ensure_open(quiz: &Quiz) -> Result<(), Error> {
+    ensure_open_to(quiz, false)
+}
+
+// `invited` lets a caller holding an invite code of the quiz past its visibility, not past its
+// moderation.
+fn ensure_open_to(quiz: &Quiz, invited: bool) -> Result<(), Error> {
+    if !quiz.allows_anonymous() {
+        roles::ensure_authenticated()?;
+    } else if caller() == Principal::anonymous() {
+        settings::ensure_enabled(Feature::AnonymousVotes)?;
+    }
+    if invited {
+        allowlist::ensure_not_hidden(quiz)?;
+    } else {
+        allowlist::ensure_can_view(quiz)?;
+    }
+    if quiz.is_draft() {
+        return Err(Error::WrongPhase {
+            msg: format!("the quiz with id={} is a draft", quiz.id),
+        });
+    }
+    if !quiz.has_started() {
+        return Err(Error::WrongPhase {
+            msg: format!("the quiz with id={} hasn't started yet", quiz.id),
+        });
+    }
+    if quiz.is_closed() {
+        return Err(Error::QuizClosed {
+            msg: format!("the quiz with id={} is closed", quiz.id),
+        });
+    }
+    token_gates::ensure_holder(quiz)?;
+    nft_gates::ensure_holder(quiz)?;
+    personhood::ensure_human(quiz)?;
+    account_age::ensure_old_enough(quiz)?;
+    entry_fees::ensure_entered(quiz)
+}
+
+// checks shared by one-shot ways of voting: the quiz is running and the caller hasn't voted yet,
+// or not within the cooldown of a quiz that takes repeated votes.
+fn ensure_can_vote(quiz: &Quiz, vote_key: &(u64, StorablePrincipal)) -> Result<(), Error> {
+    ensure_can_vote_to(quiz, vote_key, false)
+}
+
+fn ensure_can_vote_to(
+    quiz: &Quiz,
+    vote_key: &(u64, StorablePrincipal),
+    invited: bool,
+) -> Result<(), Error> {
+    ensure_open_to(quiz, invited)?;
+    // anonymous votes can't be told apart
+    if caller() == Principal::anonymous() {
+        return Ok(());
+    }
+    let Some(last_vote) = VOTES.with(|votes| votes.borrow().get(vote_key)) else {
+        return Ok(());
+    };
+    // the vote record keeps the time of the latest vote only
+    match quiz.vote_cooldown_ns {
+        Some(cooldown) => {
+            let next_vote_at = last_vote.cast_at.saturating_add(cooldown);
+            if time() < next_vote_at {
+                return Err(Error::RateLimited {
+                    retry_after_ns: next_vote_at - time(),
+                });
+            }
+            Ok(())
+        }
+        None => Err(Error::AlreadyVoted {
+            msg: format!("you have already voted on the quiz with id={}", quiz.id),
+        }),
+    }
+}
+
+
+#[ic_cdk::query]
+fn get_results(id: u64) -> Result<QuizResults, Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    Ok(results_of(&quiz))
+}
+
+// The results as a client shows them. A ranked choice quiz reports the counts of its last runoff
+// round, the only one that decides the winner.
+#[ic_cdk::query]
+fn get_quiz_results(id: u64) -> Result<QuizResultSummary, Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+
+    let results = results_of(&quiz);
+    let tallies = results.rounds.last().map(|round| round.tallies.clone()).unwrap_or_default();
+    let total_votes: u64 = tallies.iter().map(|(_, votes)| votes).sum();
+    let options = tallies
+        .into_iter()
+        .map(|(option, votes)| OptionResult {
+            option,
+            votes,
+            percentage: if total_votes == 0 {
+                0.0
+            } else {
+                votes as f64 * 100.0 / total_votes as f64
+            },
+        })
+        .collect();
+    let is_final = match quiz.voting_mode() {
+        VotingMode::CommitReveal => quiz.is_revealed(),
+        _ => quiz.is_closed(),
+    };
+    Ok(QuizResultSummary {
+        quiz_id: id,
+        total_votes,
+        options,
+        leading_option: results.winner,
+        is_final,
+    })
+}
+
+fn results_of(quiz: &Quiz) -> QuizResults {
+    let id = quiz.id;
+    let (rounds, winner) = match quiz.voting_mode() {
+        VotingMode::SingleChoice
+        | VotingMode::Quadratic
+        | VotingMode::Approval
+        | VotingMode::CommitReveal
+        | VotingMode::FreeText
+        | VotingMode::Numeric => {
+            let tallies: Vec<(String, u64)> = quiz
+                .options
+                .iter()
+                .map(|option| {
+                    let count = quiz.answers.get(option).copied().unwrap_or_default();
+                    (option.clone(), count as u64)
+                })
+                .collect();
+            let winner = leading_option(&tallies);
+            (vec![RunoffRound { tallies, eliminated: Vec::new() }], winner)
+        }
+        VotingMode::RankedChoice => {
+            let ballots: Vec<Vec<String>> = BALLOTS.with(|ballots| {
+                ballots
+                    .borrow()
+                    .range(id_key_range(id))
+                    .map(|(_, ballot)| ballot.ranking)
+                    .collect()
+            });
+            ranked_choice::instant_runoff(&quiz.options, &ballots)
+        }
+    };
+
+    let weighted_tallies = quiz.is_weighted().then(|| {
+        let weighted = quiz.weighted_answers.clone().unwrap_or_default();
+        quiz.options
+            .iter()
+            .map(|option| (option.clone(), weighted.get(option).copied().unwrap_or_default()))
+            .collect()
+    });
+
+    QuizResults {
+        quiz_id: id,
+        voting_mode: quiz.voting_mode(),
+        rounds,
+        winner,
+        weighted_tallies,
+    }
+}
+
+// the option with the most votes, None when nobody voted or the top spot is tied.
+fn leading_option(tallies: &[(String, u64)]) -> Option<String> {
+    let most = tallies.iter().map(|(_, count)| *count).max().filter(|most| *most > 0)?;
+    let mut leaders = tallies.iter().filter(|(_, count)| *count == most);
+    match (leaders.next(), leaders.next()) {
+        (Some((option, _)), None) => Some(option.clone()),
+        _ => None,
+    }
+}
+
+#[ic_cdk::query]
+fn get_my_votes() -> Vec<VoteHistoryEntry> {
+    votes_of(caller())
+}
+
+#[ic_cdk::query]
+fn get_my_vote(id: u64) -> Result<Vote, Error> {
+    VOTES
+        .with(|votes| votes.borrow().get(&(id, StorablePrincipal(caller()))))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("you haven't voted on the quiz with id={}", id),
+        })
+}
+
+
+// only admins may look into the votes of other principals.
+#[ic_cdk::query(guard = "only_admin")]
+fn get_votes_of(principal: Principal) -> Result<Vec<VoteHistoryEntry>, Error> {
+    Ok(votes_of(principal))
+}
+
+fn votes_of(principal: Principal) -> Vec<VoteHistoryEntry> {
+    let voter = StorablePrincipal(principal);
+    let quiz_ids: Vec<u64> = VOTES_BY_VOTER.with(|index| {
+        index
+            .borrow()
+            .range((voter, 0)..=(voter, u64::MAX))
+            .map(|((_, quiz_id), _)| quiz_id)
+            .collect()
+    });
+    VOTES.with(|votes| {
+        let votes = votes.borrow();
+        quiz_ids
+            .into_iter()
+            .filter_map(|quiz_id| {
+                votes.get(&(quiz_id, voter)).map(|vote| VoteHistoryEntry {
+                    quiz_id,
+                    option: vote.option,
+                    voted_at: vote.cast_at,
+                })
+            })
+            .collect()
+    })
+}
+
+// Clients tell the errors apart by their variant, the messages are for people and may change.
+// They are english, `localize_error` explains an error in the user's language.
+// Calls rejected by a guard never get this far, they fail with a reject message instead.
+#[derive(candid::CandidType, Clone, Deserialize, Serialize)]
 enum Error {
+    // the quiz, survey, vote or whatever else the call refers to doesn't exist (anymore)
     NotFound { msg: String },
+    // the caller already voted or answered, and may not do so again
+    AlreadyVoted { msg: String },
+    // the caller lacks a role, isn't the author or isn't allowed to see the quiz
+    Unauthorized { msg: String },
+    // `field` names the argument, or the part of it, that was rejected
+    ValidationFailed { field: String, msg: String },
+    // a stable structure couldn't be written
+    StorageFull { msg: String },
+    // the quiz ended and takes no more votes or changes
+    QuizClosed { msg: String },
+    // a call to another canister or outside the IC failed
+    CallFailed { msg: String },
+    // a commit-reveal quiz isn't in the phase the call belongs to
+    WrongPhase { msg: String },
+    // a revealed vote doesn't match its commitment
+    InvalidReveal { msg: String },
+    // the quiz or survey wouldn't fit into its stable record once encoded
+    TooLarge { msg: String },
+    // the caller made too many calls, the next one fits in after `retry_after_ns`
+    RateLimited { retry_after_ns: u64 },
+    // the quiz is gated and the caller doesn't pass the gate, like holding its tokens or NFTs
+    NotEligible { msg: String },
+    // the canister is read-only for maintenance, queries still work
+    Maintenance { msg: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ensure_fits, Error, Quiz};
+
+    #[test]
+    fn quizzes_above_the_stable_bound_are_too_large() {
+        let mut quiz = Quiz {
+            question: "x".repeat(2048),
+            options: vec!["x".repeat(1024); 4],
+            ..Default::default()
+        };
+        assert!(ensure_fits(&quiz).is_ok());
+        quiz.options.extend(vec!["x".repeat(1024); 4]);
+        assert!(matches!(ensure_fits(&quiz), Err(Error::TooLarge { .. })));
+    }
 }
 
 // need this to generate candid
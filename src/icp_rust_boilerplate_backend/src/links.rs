@@ -0,0 +1,210 @@
+use crate::{get_quiz, Error, Memory, Quiz, MEMORY_MANAGER};
+use candid::{Decode, Encode};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, cell::RefCell};
+
+const MAX_SLUG_LEN: usize = 64;
+// room for a "-<n>" suffix telling quizzes with the same question apart
+const MAX_SLUG_BASE_LEN: usize = 56;
+const CODE_LEN: usize = 8;
+// Crockford's base32, no letters that are easily mistaken for digits
+const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+type Slug = Blob<MAX_SLUG_LEN>;
+type Code = Blob<CODE_LEN>;
+
+// what links to a quiz can use instead of its id, both stay the same when the question changes
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct QuizLinks {
+    slug: String,
+    code: String,
+}
+
+impl Storable for QuizLinks {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for QuizLinks {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static LINKS: RefCell<StableBTreeMap<u64, QuizLinks, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(103)))
+    ));
+
+    static SLUGS: RefCell<StableBTreeMap<Slug, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(104)))
+    ));
+
+    static CODES: RefCell<StableBTreeMap<Code, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(105)))
+    ));
+}
+
+// "What's the **best** language?" becomes "whats-the-best-language". Letters outside ascii are
+// dropped, a question without any ascii letter or digit gets "quiz".
+fn slugify(question: &str) -> String {
+    let mut slug = String::new();
+    for word in question
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_' || c == '/')
+        .map(|word| {
+            word.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+    {
+        if slug.len() + word.len() + 1 > MAX_SLUG_BASE_LEN {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    if slug.is_empty() {
+        slug.push_str("quiz");
+    }
+    slug
+}
+
+fn slug_key(slug: &str) -> Option<Slug> {
+    Slug::try_from(slug.as_bytes()).ok()
+}
+
+fn code_key(code: &str) -> Option<Code> {
+    Code::try_from(code.as_bytes()).ok()
+}
+
+// 40 bits of a sha256 of the id, `attempt` draws another code should one be taken already.
+fn short_code(id: u64, attempt: u32) -> String {
+    let digest = Sha256::new()
+        .chain_update(id.to_be_bytes())
+        .chain_update(attempt.to_be_bytes())
+        .finalize();
+    let bits = digest[..5]
+        .iter()
+        .fold(0u64, |bits, byte| bits << 8 | *byte as u64);
+    (0..CODE_LEN)
+        .rev()
+        .map(|index| CODE_ALPHABET[(bits >> (index * 5) & 31) as usize] as char)
+        .collect()
+}
+
+// Gives a new quiz its slug and short code, a quiz that has them already keeps them.
+pub fn assign(quiz: &Quiz) {
+    if LINKS.with(|links| links.borrow().contains_key(&quiz.id)) {
+        return;
+    }
+    let base = slugify(&quiz.question);
+    let slug = (1..)
+        .map(|n| match n {
+            1 => base.clone(),
+            n => format!("{}-{}", base, n),
+        })
+        .find(|slug| !SLUGS.with(|slugs| slugs.borrow().contains_key(&slug_key(slug).unwrap())))
+        .unwrap();
+    let code = (0..)
+        .map(|attempt| short_code(quiz.id, attempt))
+        .find(|code| !CODES.with(|codes| codes.borrow().contains_key(&code_key(code).unwrap())))
+        .unwrap();
+    SLUGS.with(|slugs| slugs.borrow_mut().insert(slug_key(&slug).unwrap(), quiz.id));
+    CODES.with(|codes| codes.borrow_mut().insert(code_key(&code).unwrap(), quiz.id));
+    LINKS.with(|links| links.borrow_mut().insert(quiz.id, QuizLinks { slug, code }));
+}
+
+// frees the slug and the code of a purged quiz for others.
+pub fn remove_links(quiz_id: u64) {
+    if let Some(links) = LINKS.with(|links| links.borrow_mut().remove(&quiz_id)) {
+        SLUGS.with(|slugs| slugs.borrow_mut().remove(&slug_key(&links.slug).unwrap()));
+        CODES.with(|codes| codes.borrow_mut().remove(&code_key(&links.code).unwrap()));
+    }
+}
+
+#[ic_cdk::query]
+fn get_quiz_links(id: u64) -> Result<QuizLinks, Error> {
+    get_quiz(id)?;
+    LINKS
+        .with(|links| links.borrow().get(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("the quiz with id={} has no links yet", id),
+        })
+}
+
+#[ic_cdk::query]
+fn get_quiz_by_slug(slug: String) -> Result<Quiz, Error> {
+    let slug = slug.trim().to_ascii_lowercase();
+    slug_key(&slug)
+        .and_then(|key| SLUGS.with(|slugs| slugs.borrow().get(&key)))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with slug '{}' not found", slug),
+        })
+        .and_then(get_quiz)
+}
+
+// The quiz a short code belongs to, `get_quiz_by_code` takes these next to invite codes. Codes
+// are read case insensitively, and the letters Crockford's base32 leaves out as the digits they
+// look like.
+pub fn quiz_id_of_code(code: &str) -> Option<u64> {
+    let code: String = code
+        .trim()
+        .to_ascii_uppercase()
+        .chars()
+        .map(|c| match c {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect();
+    code_key(&code).and_then(|key| CODES.with(|codes| codes.borrow().get(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{slugify, MAX_SLUG_BASE_LEN};
+
+    #[test]
+    fn keeps_lowercase_ascii_words_joined_by_dashes() {
+        assert_eq!(
+            slugify("What's your favourite colour?"),
+            "whats-your-favourite-colour"
+        );
+    }
+
+    #[test]
+    fn splits_on_dashes_underscores_and_slashes() {
+        assert_eq!(slugify("rust_vs-go / ts"), "rust-vs-go-ts");
+    }
+
+    #[test]
+    fn drops_what_isnt_ascii() {
+        assert_eq!(slugify("Café über alles"), "caf-ber-alles");
+    }
+
+    #[test]
+    fn falls_back_to_quiz() {
+        assert_eq!(slugify("???"), "quiz");
+        assert_eq!(slugify(""), "quiz");
+    }
+
+    #[test]
+    fn stops_at_the_last_whole_word_that_fits() {
+        let slug = slugify(&"abcdefghij ".repeat(10));
+        assert!(slug.len() <= MAX_SLUG_BASE_LEN);
+        assert_eq!(slug, ["abcdefghij"; 5].join("-"));
+    }
+}
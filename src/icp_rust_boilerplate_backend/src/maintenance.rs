@@ -0,0 +1,291 @@
+use crate::guards::only_admin;
+use crate::{account_age, metrics, Error, Memory, MEMORY_MANAGER};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{BoundedStorable, Cell, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MAX_REASON_LEN: usize = 200;
+// the longest method name that can be paused, every endpoint has a shorter one
+const MAX_METHOD_LEN: usize = 64;
+// pausing these could lock the admins out of undoing it
+const UNPAUSABLE: [&str; 3] = ["set_maintenance_mode", "pause_endpoint", "resume_endpoint"];
+
+type MethodKey = Blob<MAX_METHOD_LEN>;
+
+// While it is on every update call fails with `Error::Maintenance`, queries keep working. The
+// timers keep settling pools and paying out, they only finish what was started before.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+pub struct MaintenanceMode {
+    enabled: bool,
+    // shown to the callers that are turned away
+    reason: Option<String>,
+    changed_at: Option<u64>,
+    changed_by: Option<Principal>,
+}
+
+impl Storable for MaintenanceMode {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// A single update method that is turned off, e.g. while an exploit on its path is fixed. The rest
+// of the canister keeps working.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct PausedEndpoint {
+    method: String,
+    reason: Option<String>,
+    paused_at: u64,
+    paused_by: Principal,
+}
+
+impl Storable for PausedEndpoint {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for PausedEndpoint {
+    // the method name, the reason and a principal
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    // kept in stable memory, so an upgrade in the middle of a migration stays read-only
+    static MODE: RefCell<Cell<MaintenanceMode, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(133))),
+            MaintenanceMode::default(),
+        )
+        .expect("Cannot create the maintenance mode cell")
+    );
+
+    // the paused update methods, by name
+    static PAUSED: RefCell<StableBTreeMap<MethodKey, PausedEndpoint, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(134)))
+    ));
+}
+
+fn mode() -> MaintenanceMode {
+    MODE.with(|mode| mode.borrow().get().clone())
+}
+
+fn paused(method: &str) -> Option<PausedEndpoint> {
+    let key = MethodKey::try_from(method.as_bytes()).ok()?;
+    PAUSED.with(|paused| paused.borrow().get(&key))
+}
+
+pub fn is_paused(method: &str) -> bool {
+    paused(method).is_some()
+}
+
+fn with_reason(msg: String, reason: Option<String>) -> String {
+    match reason {
+        Some(reason) => format!("{}: {}", msg, reason),
+        None => msg,
+    }
+}
+
+// Every update call but the ones of `UNPAUSABLE` starts with this, right after counting the call
+// under the same name, the tests below hold every endpoint to it. A guard can't do it: the name
+// of the called method is only readable in `inspect_message`, which turns the paused ones away
+// early but never sees the calls of other canisters.
+pub fn ensure_available(method: &str) -> Result<(), Error> {
+    let mode = mode();
+    if mode.enabled {
+        return Err(Error::Maintenance {
+            msg: with_reason(
+                "the canister is read-only for maintenance".to_string(),
+                mode.reason,
+            ),
+        });
+    }
+    if let Some(endpoint) = paused(method) {
+        return Err(Error::Maintenance {
+            msg: with_reason(format!("{} is paused", method), endpoint.reason),
+        });
+    }
+    // calls turned away for maintenance don't count as the canister seeing the caller
+    account_age::record(caller());
+    Ok(())
+}
+
+// for the update calls whose result has no room for the error, they are rejected instead
+pub fn trap_if_unavailable(method: &str) {
+    if let Err(Error::Maintenance { msg }) = ensure_available(method) {
+        ic_cdk::trap(&msg);
+    }
+}
+
+fn clean_reason(reason: Option<String>) -> Result<Option<String>, Error> {
+    let reason = reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    if reason
+        .as_ref()
+        .is_some_and(|reason| reason.len() > MAX_REASON_LEN)
+    {
+        return Err(Error::ValidationFailed {
+            field: "reason".to_string(),
+            msg: format!("the reason must not exceed {} bytes", MAX_REASON_LEN),
+        });
+    }
+    Ok(reason)
+}
+
+#[ic_cdk::update(guard = "only_admin")]
+fn set_maintenance_mode(enabled: bool, reason: Option<String>) -> Result<MaintenanceMode, Error> {
+    metrics::count_call("set_maintenance_mode");
+    let reason = clean_reason(reason)?;
+    let mode = MaintenanceMode {
+        enabled,
+        reason: reason.filter(|_| enabled),
+        changed_at: Some(time()),
+        changed_by: Some(caller()),
+    };
+    MODE.with(|cell| cell.borrow_mut().set(mode.clone()))
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the maintenance mode".to_string(),
+        })?;
+    Ok(mode)
+}
+
+#[ic_cdk::query]
+fn get_maintenance_mode() -> MaintenanceMode {
+    mode()
+}
+
+// Pauses a single update method by its candid name, calls to it fail until it is resumed. Names
+// of methods that don't exist are taken as well, they never match a call.
+#[ic_cdk::update(guard = "only_admin")]
+fn pause_endpoint(method: String, reason: Option<String>) -> Result<PausedEndpoint, Error> {
+    metrics::count_call("pause_endpoint");
+    let reason = clean_reason(reason)?;
+    let method = method.trim().to_string();
+    let key = MethodKey::try_from(method.as_bytes()).ok();
+    let msg = if method.is_empty()
+        || !method
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+    {
+        "a method name is made of letters, digits and underscores".to_string()
+    } else if UNPAUSABLE.contains(&method.as_str()) {
+        format!("{} can't be paused", method)
+    } else if let Some(key) = key {
+        let endpoint = PausedEndpoint {
+            method,
+            reason,
+            paused_at: time(),
+            paused_by: caller(),
+        };
+        PAUSED.with(|paused| paused.borrow_mut().insert(key, endpoint.clone()));
+        return Ok(endpoint);
+    } else {
+        format!("a method name has at most {} bytes", MAX_METHOD_LEN)
+    };
+    Err(Error::ValidationFailed {
+        field: "method".to_string(),
+        msg,
+    })
+}
+
+#[ic_cdk::update(guard = "only_admin")]
+fn resume_endpoint(method: String) -> Result<PausedEndpoint, Error> {
+    metrics::count_call("resume_endpoint");
+    MethodKey::try_from(method.trim().as_bytes())
+        .ok()
+        .and_then(|key| PAUSED.with(|paused| paused.borrow_mut().remove(&key)))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("{} isn't paused", method.trim()),
+        })
+}
+
+#[ic_cdk::query]
+fn list_paused_endpoints() -> Vec<PausedEndpoint> {
+    PAUSED.with(|paused| {
+        paused
+            .borrow()
+            .iter()
+            .map(|(_, endpoint)| endpoint)
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UNPAUSABLE;
+    use std::fs;
+
+    // A pause or the read-only mode turns away every update but the ones undoing them. The
+    // endpoints are read off the sources, so they have to keep the shape rustfmt gives them:
+    //
+    //     #[ic_cdk::update(guard = "...")]
+    //     async fn name(
+    //         ...
+    //     ) -> Result<.., Error> {
+    //         metrics::count_call("name");
+    //         maintenance::ensure_available("name")?;
+    //
+    // The signature ends at the first line ending in `{`. Instead of `ensure_available("name")?`
+    // the line after the count may use `trap_if_unavailable("name")`, or match on the result.
+    #[test]
+    fn every_update_checks_availability_first() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+        let mut checked = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+            let lines: Vec<&str> = source.lines().collect();
+            for (at, line) in lines.iter().enumerate() {
+                if !line.starts_with("#[ic_cdk::update") {
+                    continue;
+                }
+                let opened = lines[at..]
+                    .iter()
+                    .position(|line| line.ends_with('{'))
+                    .map(|offset| at + offset)
+                    .unwrap();
+                let name = lines[at + 1..=opened]
+                    .iter()
+                    .find_map(|line| line.split("fn ").nth(1))
+                    .and_then(|rest| rest.split('(').next())
+                    .unwrap();
+                if UNPAUSABLE.contains(&name) {
+                    continue;
+                }
+                let count = lines.get(opened + 1).map(|line| line.trim());
+                let check = lines.get(opened + 2).copied().unwrap_or_default();
+                let counted = format!("metrics::count_call(\"{}\");", name);
+                assert!(
+                    count == Some(counted.as_str())
+                        && (check.contains(&format!("maintenance::ensure_available(\"{}\")", name))
+                            || check.contains(&format!(
+                                "maintenance::trap_if_unavailable(\"{}\")",
+                                name
+                            ))),
+                    "{} in {} has to start with {} and check maintenance::ensure_available(\"{}\") \
+                     right after",
+                    name,
+                    path.display(),
+                    counted,
+                    name
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+}
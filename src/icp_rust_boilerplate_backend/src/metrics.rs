@@ -0,0 +1,67 @@
+use crate::guards::only_admin;
+use crate::{Error, StorablePrincipal, STORAGE, VOTES, VOTES_BY_VOTER};
+use std::{cell::RefCell, collections::BTreeMap};
+
+thread_local! {
+    // update calls per method since the last upgrade, queries can't keep what they change
+    static CALLS: RefCell<BTreeMap<&'static str, u64>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct Metrics {
+    quiz_count: u64,
+    total_votes: u64,
+    unique_voters: u64,
+    stable_memory_pages: u64,
+    heap_size: u64,
+    cycle_balance: u128,
+    calls: Vec<(String, u64)>,
+}
+
+// counts a call of an update method, every one of them starts with this.
+pub fn count_call(method: &'static str) {
+    CALLS.with(|calls| *calls.borrow_mut().entry(method).or_insert(0) += 1);
+}
+
+fn heap_size() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) as u64 * 65536
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+// the index is sorted by voter, so every voter is counted once without collecting them
+fn unique_voters() -> u64 {
+    VOTES_BY_VOTER.with(|index| {
+        let mut previous: Option<StorablePrincipal> = None;
+        index
+            .borrow()
+            .iter()
+            .filter(|((voter, _), _)| previous.replace(*voter) != Some(*voter))
+            .count() as u64
+    })
+}
+
+// An endpoint that calls another one, like `answer_quiz_with_code`, counts for both of them.
+#[ic_cdk::query(guard = "only_admin")]
+fn get_metrics() -> Result<Metrics, Error> {
+    Ok(Metrics {
+        quiz_count: STORAGE.with(|service| service.borrow().len()),
+        total_votes: VOTES.with(|votes| votes.borrow().len()),
+        unique_voters: unique_voters(),
+        stable_memory_pages: ic_cdk::api::stable::stable64_size(),
+        heap_size: heap_size(),
+        cycle_balance: ic_cdk::api::canister_balance128(),
+        calls: CALLS.with(|calls| {
+            calls
+                .borrow()
+                .iter()
+                .map(|(method, count)| (method.to_string(), *count))
+                .collect()
+        }),
+    })
+}
@@ -0,0 +1,278 @@
+use crate::{
+    account_age, achievements, do_insert, id_key_range, index_author, index_creation, links,
+    quadratic, scoring, search, tallies, Memory, Quiz, StorablePrincipal, Visibility, Vote,
+    VotingMode, BALLOTS, MEMORY_MANAGER, STORAGE, VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, Cell, StableBTreeMap, Storable};
+use std::cell::RefCell;
+
+// Every entry migrates the stored data from the version matching its position to the next one.
+// New entries go at the end, existing ones must never change once released.
+const MIGRATIONS: &[fn()] = &[
+    backfill_indexes,
+    fill_quiz_defaults,
+    move_to_larger_bounds,
+    move_tallies,
+    move_votes,
+    seed_achievements,
+    assign_links,
+    backfill_first_seen,
+];
+
+// the quizzes, ballots, answer keys and credit ledgers lived here until version 3
+const LEGACY_QUIZZES: u8 = 1;
+// the votes before they carried their quiz and voter, until version 5
+const LEGACY_VOTES: u8 = 2;
+const LEGACY_BALLOTS: u8 = 3;
+const LEGACY_ANSWER_KEYS: u8 = 7;
+const LEGACY_CREDIT_LEDGERS: u8 = 16;
+const LEGACY_MAX_SIZE: u32 = 1024;
+
+pub const CURRENT_SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+thread_local! {
+    // canisters installed before the version was recorded start at 0 and run every migration
+    static SCHEMA_VERSION: RefCell<Cell<u32, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26))), 0)
+            .expect("Cannot create the schema version cell")
+    );
+}
+
+pub fn schema_version() -> u32 {
+    SCHEMA_VERSION.with(|version| *version.borrow().get())
+}
+
+pub fn set_schema_version(version: u32) {
+    SCHEMA_VERSION
+        .with(|cell| cell.borrow_mut().set(version))
+        .expect("Cannot store the schema version");
+}
+
+// runs the pending migrations in order, a trap rolls back the whole upgrade.
+pub fn run() {
+    let from = schema_version();
+    assert!(
+        from <= CURRENT_SCHEMA_VERSION,
+        "the stored schema version {} is newer than this build ({})",
+        from,
+        CURRENT_SCHEMA_VERSION
+    );
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration();
+        set_schema_version(version as u32 + 1);
+    }
+}
+
+// A value of a map created while everything was bounded to 1024 bytes. The bound of a map can't
+// grow once it exists, so the maps are read through this wrapper and moved to new regions.
+struct Legacy<T>(T);
+
+impl<T: Storable> Storable for Legacy<T> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        self.0.to_bytes()
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Legacy(T::from_bytes(bytes))
+    }
+}
+
+impl<T: Storable> BoundedStorable for Legacy<T> {
+    const MAX_SIZE: u32 = LEGACY_MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+fn legacy_map<K, V>(memory_id: u8) -> StableBTreeMap<K, Legacy<V>, Memory>
+where
+    K: BoundedStorable + Ord + Clone,
+    V: Storable,
+{
+    StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(memory_id))))
+}
+
+// copies every entry of a legacy region into its replacement and empties the legacy one.
+pub fn move_legacy_map<K, V>(memory_id: u8, map: &mut StableBTreeMap<K, V, Memory>)
+where
+    K: BoundedStorable + Ord + Clone,
+    V: BoundedStorable,
+{
+    let legacy = legacy_map::<K, V>(memory_id);
+    for (key, Legacy(value)) in legacy.iter() {
+        map.insert(key, value);
+    }
+    legacy.clear();
+}
+
+// 0 -> 1: quizzes created before the search, author and creation indexes existed.
+fn backfill_indexes() {
+    for (_, Legacy(quiz)) in legacy_map::<u64, Quiz>(LEGACY_QUIZZES)
+        .iter()
+        .filter(|(_, Legacy(quiz))| quiz.deleted_at.is_none())
+    {
+        search::index_quiz(&quiz);
+        index_author(&quiz);
+        index_creation(&quiz);
+    }
+}
+
+// 1 -> 2: quizzes created before voting modes and visibilities store the defaults they had.
+fn fill_quiz_defaults() {
+    let mut quizzes = legacy_map::<u64, Quiz>(LEGACY_QUIZZES);
+    let ids: Vec<u64> = quizzes.iter().map(|(id, _)| id).collect();
+    for id in ids {
+        let Some(Legacy(mut quiz)) = quizzes.get(&id) else {
+            continue;
+        };
+        quiz.voting_mode.get_or_insert(VotingMode::SingleChoice);
+        quiz.visibility.get_or_insert(Visibility::Public);
+        // the accessors default the same way, so a quiz right at the old bound is left alone
+        if quiz.to_bytes().len() <= LEGACY_MAX_SIZE as usize {
+            quizzes.insert(id, Legacy(quiz));
+        }
+    }
+}
+
+// 2 -> 3: everything bounded by the size of a quiz moves to regions with the larger bound.
+fn move_to_larger_bounds() {
+    STORAGE.with(|service| move_legacy_map(LEGACY_QUIZZES, &mut service.borrow_mut()));
+    BALLOTS.with(|ballots| move_legacy_map(LEGACY_BALLOTS, &mut ballots.borrow_mut()));
+    scoring::move_legacy_answer_keys(LEGACY_ANSWER_KEYS);
+    quadratic::move_legacy_credit_ledgers(LEGACY_CREDIT_LEDGERS);
+}
+
+// 3 -> 4: the answers maps of the stored quizzes move into the tallies, `do_insert` strips them.
+fn move_tallies() {
+    let quizzes: Vec<Quiz> =
+        STORAGE.with(|service| service.borrow().iter().map(|(_, quiz)| quiz).collect());
+    for quiz in quizzes {
+        let answers = quiz
+            .answers
+            .iter()
+            .map(|(option, count)| (option.clone(), *count as u64))
+            .collect();
+        tallies::replace(
+            &quiz,
+            &answers,
+            &quiz.weighted_answers.clone().unwrap_or_default(),
+        );
+        do_insert(&quiz);
+    }
+}
+
+// a vote as stored until version 5
+#[derive(candid::CandidType, Deserialize)]
+struct VoteRecord {
+    option: String,
+    voted_at: u64,
+    weight: Option<u128>,
+}
+
+impl Storable for VoteRecord {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        std::borrow::Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// 4 -> 5: the votes move to their own region as records naming their quiz and voter.
+fn move_votes() {
+    let legacy = legacy_map::<(u64, StorablePrincipal), VoteRecord>(LEGACY_VOTES);
+    VOTES.with(|votes| {
+        let mut votes = votes.borrow_mut();
+        for ((quiz_id, voter), Legacy(record)) in legacy.iter() {
+            let vote = Vote {
+                quiz_id,
+                voter: voter.0,
+                option: record.option,
+                weight: record.weight,
+                cast_at: record.voted_at,
+            };
+            votes.insert((quiz_id, voter), vote);
+        }
+    });
+    legacy.clear();
+}
+
+// 5 -> 6: the default achievement rules, and the votes the authors received so far.
+fn seed_achievements() {
+    achievements::seed_default_rules();
+    let quizzes: Vec<(u64, Principal)> = STORAGE.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .filter_map(|(id, quiz)| quiz.author.map(|author| (id, author)))
+            .collect()
+    });
+    for (quiz_id, author) in quizzes {
+        let received = VOTES.with(|votes| {
+            votes
+                .borrow()
+                .range(id_key_range(quiz_id))
+                .filter(|((_, voter), _)| voter.0 != author)
+                .count() as u64
+        });
+        if received > 0 {
+            achievements::add_votes_received(author, received);
+        }
+    }
+}
+
+// 6 -> 7: quizzes created before slugs and short codes, in the order they were created.
+fn assign_links() {
+    let quizzes: Vec<Quiz> =
+        STORAGE.with(|service| service.borrow().iter().map(|(_, quiz)| quiz).collect());
+    for quiz in &quizzes {
+        links::assign(quiz);
+    }
+}
+
+// 7 -> 8: principals seen before first calls were noted, by their oldest quiz or their votes.
+// A vote keeps the time of the latest one only, so some principals look younger than they are.
+fn backfill_first_seen() {
+    STORAGE.with(|service| {
+        for (_, quiz) in service.borrow().iter() {
+            if let Some(author) = quiz.author {
+                account_age::backfill(author, quiz.created_at);
+            }
+        }
+    });
+    VOTES.with(|votes| {
+        for ((_, voter), vote) in votes.borrow().iter() {
+            account_age::backfill(voter.0, vote.cast_at);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{legacy_map, move_legacy_map, Legacy, LEGACY_MAX_SIZE, LEGACY_QUIZZES};
+    use crate::{Quiz, STORAGE};
+    use ic_stable_structures::Storable;
+
+    #[test]
+    fn moved_quizzes_may_outgrow_the_legacy_bound() {
+        let quiz = Quiz {
+            id: 7,
+            question: "Which option?".to_string(),
+            ..Default::default()
+        };
+        legacy_map::<u64, Quiz>(LEGACY_QUIZZES).insert(7, Legacy(quiz));
+        STORAGE.with(|service| move_legacy_map(LEGACY_QUIZZES, &mut service.borrow_mut()));
+        assert!(legacy_map::<u64, Quiz>(LEGACY_QUIZZES).is_empty());
+
+        let mut moved = STORAGE
+            .with(|service| service.borrow().get(&7))
+            .expect("the quiz was moved");
+        assert_eq!(moved.question, "Which option?");
+        moved.options = vec!["x".repeat(1024); 2];
+        assert!(moved.to_bytes().len() > LEGACY_MAX_SIZE as usize);
+        STORAGE.with(|service| service.borrow_mut().insert(7, moved));
+        let options = STORAGE.with(|service| service.borrow().get(&7).map(|quiz| quiz.options));
+        assert_eq!(options.map(|options| options.len()), Some(2));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::guards::{only_admin, only_moderator};
+use crate::{maintenance, metrics, Error, Memory, MEMORY_MANAGER};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+const MAX_WORD_LEN: usize = 32;
+// every question and option is checked against the whole list
+const MAX_BANNED_WORDS: u64 = 1000;
+
+type Word = Blob<MAX_WORD_LEN>;
+
+thread_local! {
+    // stored lowercase, texts are lowercased before they are checked
+    static BANNED_WORDS: RefCell<StableBTreeMap<Word, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(39)))
+    ));
+}
+
+fn to_word(word: &str) -> Option<Word> {
+    Word::try_from(word.as_bytes()).ok()
+}
+
+// Rejects a text that contains a banned word. Only whole words count, so banning "ass" leaves
+// "class" alone, and words too long to be banned can't match.
+pub fn ensure_clean(field: &str, text: &str) -> Result<(), Error> {
+    let banned = BANNED_WORDS.with(|words| {
+        let words = words.borrow();
+        text.split(|c: char| !c.is_alphanumeric())
+            .map(|word| word.to_lowercase())
+            .find(|word| to_word(word).is_some_and(|word| words.contains_key(&word)))
+    });
+    match banned {
+        Some(word) => Err(Error::ValidationFailed {
+            field: field.to_string(),
+            msg: format!("the word '{}' isn't allowed", word),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn normalize_word(word: &str) -> Result<String, Error> {
+    let word = word.trim().to_lowercase();
+    if word.is_empty() || word.len() > MAX_WORD_LEN || !word.chars().all(char::is_alphanumeric) {
+        return Err(Error::ValidationFailed {
+            field: "words".to_string(),
+            msg: format!(
+                "the word '{}' must be 1 to {} letters or digits",
+                word, MAX_WORD_LEN
+            ),
+        });
+    }
+    Ok(word)
+}
+
+fn banned_words() -> Vec<String> {
+    BANNED_WORDS.with(|words| {
+        words
+            .borrow()
+            .iter()
+            .map(|(word, _)| String::from_utf8_lossy(word.as_slice()).into_owned())
+            .collect()
+    })
+}
+
+#[ic_cdk::query(guard = "only_moderator")]
+fn get_banned_words() -> Result<Vec<String>, Error> {
+    Ok(banned_words())
+}
+
+// Bans every word or none of them, existing quizzes are left as they are. Returns the new list.
+#[ic_cdk::update(guard = "only_admin")]
+fn add_banned_words(words: Vec<String>) -> Result<Vec<String>, Error> {
+    metrics::count_call("add_banned_words");
+    maintenance::ensure_available("add_banned_words")?;
+    let mut words = words
+        .iter()
+        .map(|word| normalize_word(word))
+        .collect::<Result<Vec<String>, Error>>()?;
+    words.sort();
+    words.dedup();
+    BANNED_WORDS.with(|banned| {
+        let mut banned = banned.borrow_mut();
+        let new_words = words
+            .iter()
+            .filter(|word| !banned.contains_key(&to_word(word).unwrap()))
+            .count() as u64;
+        if banned.len() + new_words > MAX_BANNED_WORDS {
+            return Err(Error::StorageFull {
+                msg: format!("at most {} words can be banned", MAX_BANNED_WORDS),
+            });
+        }
+        for word in &words {
+            banned.insert(to_word(word).unwrap(), ());
+        }
+        Ok(())
+    })?;
+    Ok(banned_words())
+}
+
+#[ic_cdk::update(guard = "only_admin")]
+fn remove_banned_words(words: Vec<String>) -> Result<Vec<String>, Error> {
+    metrics::count_call("remove_banned_words");
+    maintenance::ensure_available("remove_banned_words")?;
+    BANNED_WORDS.with(|banned| {
+        let mut banned = banned.borrow_mut();
+        for word in &words {
+            if let Some(word) = to_word(&word.trim().to_lowercase()) {
+                banned.remove(&word);
+            }
+        }
+    });
+    Ok(banned_words())
+}
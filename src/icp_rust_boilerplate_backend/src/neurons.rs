@@ -0,0 +1,238 @@
+use crate::guards::{only_admin, only_authenticated};
+use crate::{
+    _get_quiz, ensure_author, id_key_range, maintenance, metrics, Error, Memory, StorablePrincipal,
+    VotingMode, MEMORY_MANAGER, VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{Cell, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// the most neurons `list_neurons` returns per call, the ones past it don't weight the vote
+const MAX_NEURONS: u32 = 100;
+// SNS neuron ids are subaccounts of the governance canister
+const NEURON_ID_LEN: usize = 32;
+
+type NeuronKey = Blob<NEURON_ID_LEN>;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct GovernanceSettings {
+    sns_governance: Option<Principal>,
+}
+
+impl Storable for GovernanceSettings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// the parts of the SNS governance interface the weights are read from
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct NeuronId {
+    id: Vec<u8>,
+}
+
+#[derive(candid::CandidType, Serialize)]
+struct ListNeurons {
+    of_principal: Option<Principal>,
+    limit: u32,
+    start_page_at: Option<NeuronId>,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+struct Neuron {
+    id: Option<NeuronId>,
+    cached_neuron_stake_e8s: u64,
+    neuron_fees_e8s: u64,
+    staked_maturity_e8s_equivalent: Option<u64>,
+}
+
+#[derive(candid::CandidType, Deserialize)]
+struct ListNeuronsResponse {
+    neurons: Vec<Neuron>,
+}
+
+thread_local! {
+    static SETTINGS: RefCell<Cell<GovernanceSettings, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(122))),
+            GovernanceSettings::default(),
+        )
+        .expect("Cannot create the governance settings cell")
+    );
+
+    // the quizzes whose votes are weighted by neuron stake
+    static STAKE_WEIGHTED: RefCell<StableBTreeMap<u64, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(123)))
+    ));
+
+    // the neurons that weighted a vote already, by (quiz id, neuron id), with who cast it
+    static NEURON_VOTES: RefCell<StableBTreeMap<(u64, NeuronKey), StorablePrincipal, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(124)))
+    ));
+}
+
+fn sns_governance() -> Option<Principal> {
+    SETTINGS.with(|settings| settings.borrow().get().sns_governance)
+}
+
+pub fn is_stake_weighted(quiz_id: u64) -> bool {
+    STAKE_WEIGHTED.with(|quizzes| quizzes.borrow().contains_key(&quiz_id))
+}
+
+pub fn remove_stake_weighting(quiz_id: u64) {
+    STAKE_WEIGHTED.with(|quizzes| quizzes.borrow_mut().remove(&quiz_id));
+    NEURON_VOTES.with(|votes| {
+        let mut votes = votes.borrow_mut();
+        let keys: Vec<(u64, NeuronKey)> = votes
+            .range((quiz_id, NeuronKey::default())..(quiz_id + 1, NeuronKey::default()))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            votes.remove(&key);
+        }
+    });
+}
+
+// Only admins pick the SNS whose neurons weight the votes, None stops stake weighted votes.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_sns_governance(governance: Option<Principal>) -> Result<(), Error> {
+    metrics::count_call("set_sns_governance");
+    maintenance::ensure_available("set_sns_governance")?;
+    SETTINGS
+        .with(|settings| {
+            settings.borrow_mut().set(GovernanceSettings {
+                sns_governance: governance,
+            })
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the governance settings".to_string(),
+        })?;
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_sns_governance() -> Option<Principal> {
+    sns_governance()
+}
+
+// Weights the votes on a single choice quiz by the neuron stake of the voters, for temperature
+// checks of the community. It is decided before the first vote, a weight token takes precedence.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_stake_weighted(quiz_id: u64, weighted: bool) -> Result<(), Error> {
+    metrics::count_call("set_stake_weighted");
+    maintenance::ensure_available("set_stake_weighted")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_author(&quiz)?;
+    let has_votes =
+        VOTES.with(|votes| votes.borrow().range(id_key_range(quiz_id)).next().is_some());
+    if has_votes {
+        return Err(Error::WrongPhase {
+            msg: format!(
+                "the quiz with id={} has votes, their weights can't change",
+                quiz_id
+            ),
+        });
+    }
+    if !weighted {
+        remove_stake_weighting(quiz_id);
+        return Ok(());
+    }
+    let msg = if sns_governance().is_none() {
+        "there is no SNS governance canister to read the neurons from"
+    } else if quiz.voting_mode() != VotingMode::SingleChoice || quiz.weight_token.is_some() {
+        "only single choice quizzes without a weight token can be stake weighted"
+    } else if quiz.allows_anonymous() {
+        "anonymous voters have no neurons"
+    } else {
+        STAKE_WEIGHTED.with(|quizzes| quizzes.borrow_mut().insert(quiz_id, ()));
+        return Ok(());
+    };
+    Err(Error::ValidationFailed {
+        field: "weighted".to_string(),
+        msg: msg.to_string(),
+    })
+}
+
+// The neurons the voter controls or is a hotkey of with their stakes, None if the quiz isn't
+// stake weighted.
+pub async fn neurons_of(
+    quiz_id: u64,
+    voter: Principal,
+) -> Result<Option<Vec<(Vec<u8>, u64)>>, Error> {
+    if !is_stake_weighted(quiz_id) {
+        return Ok(None);
+    }
+    let governance = sns_governance().ok_or_else(|| Error::NotEligible {
+        msg: "there is no SNS governance canister to read the neurons from".to_string(),
+    })?;
+    let args = ListNeurons {
+        of_principal: Some(voter),
+        limit: MAX_NEURONS,
+        start_page_at: None,
+    };
+    let (response,): (ListNeuronsResponse,) = ic_cdk::call(governance, "list_neurons", (args,))
+        .await
+        .map_err(|(code, msg)| Error::CallFailed {
+            msg: format!(
+                "list_neurons on {} failed ({:?}): {}",
+                governance, code, msg
+            ),
+        })?;
+    Ok(Some(
+        response
+            .neurons
+            .into_iter()
+            .filter_map(|neuron| {
+                let stake = neuron
+                    .cached_neuron_stake_e8s
+                    .saturating_sub(neuron.neuron_fees_e8s)
+                    .saturating_add(neuron.staked_maturity_e8s_equivalent.unwrap_or_default());
+                neuron.id.map(|id| (id.id, stake))
+            })
+            .collect(),
+    ))
+}
+
+// Spends the neurons on the vote of the voter and returns its weight. Every neuron weights one
+// vote per quiz, whichever of its hotkeys casts it.
+pub fn claim(quiz_id: u64, voter: Principal, neurons: Vec<(Vec<u8>, u64)>) -> Result<u128, Error> {
+    let unused: Vec<(NeuronKey, u64)> = neurons
+        .into_iter()
+        .filter(|(_, stake)| *stake > 0)
+        .filter_map(|(id, stake)| {
+            NeuronKey::try_from(id.as_slice())
+                .ok()
+                .map(|key| (key, stake))
+        })
+        .filter(|(key, _)| {
+            !NEURON_VOTES.with(|votes| votes.borrow().contains_key(&(quiz_id, *key)))
+        })
+        .collect();
+    if unused.is_empty() {
+        return Err(Error::NotEligible {
+            msg: format!(
+                "none of your neurons has a stake that didn't weight a vote on the quiz with id={}",
+                quiz_id
+            ),
+        });
+    }
+    NEURON_VOTES.with(|votes| {
+        let mut votes = votes.borrow_mut();
+        for (key, _) in &unused {
+            votes.insert((quiz_id, *key), StorablePrincipal(voter));
+        }
+    });
+    Ok(unused.iter().map(|(_, stake)| *stake as u128).sum())
+}
@@ -0,0 +1,179 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, ensure_author, icrc, maintenance, metrics, Error, Memory, Quiz, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::collections::HashMap;
+use std::{borrow::Cow, cell::RefCell};
+
+// ownership is looked up again after 5 minutes, like the balances of `token_gates`
+const CACHE_TTL_NS: u64 = 5 * 60 * 1_000_000_000;
+const MAX_CACHED_CHECKS: usize = 10_000;
+// a gate on specific tokens is answered by one `icrc7_owner_of` call
+const MAX_GATE_TOKENS: usize = 50;
+
+// Voting takes an NFT of the ICRC-7 collection in the default account of the voter, one of
+// `token_ids` if there are any.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct NftGate {
+    collection: Principal,
+    token_ids: Option<Vec<u128>>,
+}
+
+impl Storable for NftGate {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for NftGate {
+    // a principal and MAX_GATE_TOKENS token ids
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static NFT_GATES: RefCell<StableBTreeMap<u64, NftGate, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(121)))
+    ));
+
+    // whether a voter passed the gate of a quiz, by (quiz id, voter), lost on upgrades
+    static CHECKS: RefCell<HashMap<(u64, Principal), (bool, u64)>> = RefCell::new(HashMap::new());
+}
+
+fn gate_of(quiz_id: u64) -> Option<NftGate> {
+    NFT_GATES.with(|gates| gates.borrow().get(&quiz_id))
+}
+
+// the checks against the old gate no longer say anything
+fn forget_checks(quiz_id: u64) {
+    CHECKS.with(|checks| checks.borrow_mut().retain(|(id, _), _| *id != quiz_id));
+}
+
+pub fn remove_nft_gate(quiz_id: u64) {
+    NFT_GATES.with(|gates| gates.borrow_mut().remove(&quiz_id));
+    forget_checks(quiz_id);
+}
+
+fn cached_check(quiz_id: u64, voter: Principal) -> Option<bool> {
+    CHECKS
+        .with(|checks| checks.borrow().get(&(quiz_id, voter)).copied())
+        .filter(|(_, checked_at)| time() < checked_at.saturating_add(CACHE_TTL_NS))
+        .map(|(eligible, _)| eligible)
+}
+
+async fn holds(gate: &NftGate, voter: Principal) -> Result<bool, Error> {
+    match &gate.token_ids {
+        Some(token_ids) => {
+            let owners = icrc::icrc7_owner_of(gate.collection, token_ids.clone()).await?;
+            Ok(owners.iter().flatten().any(|owner| {
+                owner.owner == voter
+                    && owner
+                        .subaccount
+                        .as_ref()
+                        .is_none_or(|subaccount| subaccount.iter().all(|byte| *byte == 0))
+            }))
+        }
+        None => Ok(icrc::icrc7_balance_of(gate.collection, voter).await? > 0),
+    }
+}
+
+// Asks the collection whether the voter holds an NFT for the gate of the quiz, unless it was
+// asked recently.
+pub async fn refresh(quiz_id: u64, voter: Principal) -> Result<(), Error> {
+    let Some(gate) = gate_of(quiz_id) else {
+        return Ok(());
+    };
+    if voter == Principal::anonymous() || cached_check(quiz_id, voter).is_some() {
+        return Ok(());
+    }
+    let eligible = holds(&gate, voter).await?;
+    CHECKS.with(|checks| {
+        let mut checks = checks.borrow_mut();
+        if checks.len() >= MAX_CACHED_CHECKS {
+            checks.retain(|_, (_, checked_at)| time() < checked_at.saturating_add(CACHE_TTL_NS));
+        }
+        checks.insert((quiz_id, voter), (eligible, time()));
+    });
+    Ok(())
+}
+
+// Every way of voting goes through here, see `ensure_open`. Like `token_gates`, only
+// `answer_quiz` asks the collection itself, the others go by `check_nft_gate`.
+pub fn ensure_holder(quiz: &Quiz) -> Result<(), Error> {
+    let Some(gate) = gate_of(quiz.id) else {
+        return Ok(());
+    };
+    let msg = match cached_check(quiz.id, caller()) {
+        Some(true) => return Ok(()),
+        Some(false) => "you hold none",
+        None => "see check_nft_gate",
+    };
+    Err(Error::NotEligible {
+        msg: format!(
+            "voting on the quiz with id={} takes an NFT of the collection {}, {}",
+            quiz.id, gate.collection, msg
+        ),
+    })
+}
+
+// None opens the quiz to every voter again.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_nft_gate(quiz_id: u64, gate: Option<NftGate>) -> Result<(), Error> {
+    metrics::count_call("set_nft_gate");
+    maintenance::ensure_available("set_nft_gate")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_author(&quiz)?;
+    let Some(gate) = gate else {
+        remove_nft_gate(quiz_id);
+        return Ok(());
+    };
+    let token_count = gate.token_ids.as_ref().map(Vec::len);
+    let msg = if gate.collection == Principal::anonymous() {
+        "the gate needs the principal of the collection".to_string()
+    } else if token_count == Some(0) {
+        "a gate on specific tokens needs at least one, None takes any".to_string()
+    } else if token_count.is_some_and(|count| count > MAX_GATE_TOKENS) {
+        format!("a gate can name at most {} tokens", MAX_GATE_TOKENS)
+    } else {
+        NFT_GATES.with(|gates| gates.borrow_mut().insert(quiz_id, gate));
+        forget_checks(quiz_id);
+        return Ok(());
+    };
+    Err(Error::ValidationFailed {
+        field: "gate".to_string(),
+        msg,
+    })
+}
+
+#[ic_cdk::query]
+fn get_nft_gate(quiz_id: u64) -> Option<NftGate> {
+    gate_of(quiz_id)
+}
+
+// Asks the collection whether the caller holds an NFT for the gate of the quiz, so the ways of
+// voting that don't call it themselves let them in for a while. Returns whether they may vote.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn check_nft_gate(quiz_id: u64) -> Result<bool, Error> {
+    metrics::count_call("check_nft_gate");
+    maintenance::ensure_available("check_nft_gate")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    refresh(quiz_id, caller()).await?;
+    Ok(ensure_holder(&quiz).is_ok())
+}
@@ -0,0 +1,261 @@
+use crate::guards::only_authenticated;
+use crate::{
+    allowlist, id_key_range, maintenance, metrics, profiles, IdCell, Memory, Quiz,
+    StorablePrincipal, MAX_PAGE_SIZE, MEMORY_MANAGER, VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// an inbox keeps the latest notifications of the last 30 days
+const MAX_NOTIFICATIONS: usize = 200;
+const RETENTION_NS: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+// a quiz notifies at most this many principals mentioned in it
+const MAX_MENTIONS: usize = 10;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub enum NotificationKind {
+    // votes others cast on a quiz of the recipient, counted up while the notification is unread
+    QuizAnswered { quiz_id: u64, votes: u64 },
+    // a quiz the recipient voted on closed
+    QuizClosed { quiz_id: u64 },
+    // the recipient's display name was mentioned as @name
+    Mentioned { quiz_id: u64, by: Principal },
+    // the author offers the quiz to the recipient, see `accept_ownership`
+    OwnershipOffered { quiz_id: u64, by: Principal },
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    id: u64,
+    kind: NotificationKind,
+    created_at: u64,
+    read: bool,
+}
+
+impl Storable for Notification {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Notification {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct NotificationPage {
+    notifications: Vec<Notification>,
+    total: u64,
+    unread: u64,
+}
+
+thread_local! {
+    // by (recipient, id), ids grow so an inbox is ordered oldest first
+    static INBOX: RefCell<StableBTreeMap<(StorablePrincipal, u64), Notification, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(56)))
+    ));
+
+    static NOTIFICATION_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(57))), 0)
+            .expect("Cannot create the notification counter")
+    );
+}
+
+fn inbox_range(key: StorablePrincipal) -> std::ops::RangeInclusive<(StorablePrincipal, u64)> {
+    (key, 0)..=(key, u64::MAX)
+}
+
+fn is_expired(notification: &Notification) -> bool {
+    notification.created_at.saturating_add(RETENTION_NS) < time()
+}
+
+// the inbox of the principal, oldest first, without the expired notifications.
+fn inbox_of(key: StorablePrincipal) -> Vec<Notification> {
+    INBOX.with(|inbox| {
+        inbox
+            .borrow()
+            .range(inbox_range(key))
+            .map(|(_, notification)| notification)
+            .filter(|notification| !is_expired(notification))
+            .collect()
+    })
+}
+
+fn notify(recipient: Principal, kind: NotificationKind) {
+    if recipient == Principal::anonymous() {
+        return;
+    }
+    let key = StorablePrincipal(recipient);
+    // a notification that can't get an id is dropped, it must not fail the call causing it
+    let id = match NOTIFICATION_ID_COUNTER.with(|counter| {
+        let current_value = *counter.borrow().get();
+        counter.borrow_mut().set(current_value + 1)
+    }) {
+        Ok(id) => id,
+        Err(_) => return,
+    };
+    INBOX.with(|inbox| {
+        inbox.borrow_mut().insert(
+            (key, id),
+            Notification {
+                id,
+                kind,
+                created_at: time(),
+                read: false,
+            },
+        )
+    });
+    prune(key);
+}
+
+// drops the expired notifications and the oldest ones beyond the limit.
+fn prune(key: StorablePrincipal) {
+    INBOX.with(|inbox| {
+        let mut inbox = inbox.borrow_mut();
+        let ids: Vec<(u64, bool)> = inbox
+            .range(inbox_range(key))
+            .map(|((_, id), notification)| (id, is_expired(&notification)))
+            .collect();
+        let excess = ids.len().saturating_sub(MAX_NOTIFICATIONS);
+        for (position, (id, expired)) in ids.into_iter().enumerate() {
+            if expired || position < excess {
+                inbox.remove(&(key, id));
+            }
+        }
+    });
+}
+
+// tells the author about a vote of someone else, one unread notification per quiz keeps counting.
+pub fn quiz_answered(quiz: &Quiz, voter: Principal) {
+    let author = match quiz.author.filter(|author| *author != voter) {
+        Some(author) => author,
+        None => return,
+    };
+    let key = StorablePrincipal(author);
+    let unread = inbox_of(key).into_iter().find(|notification| {
+        !notification.read
+            && matches!(notification.kind,
+                NotificationKind::QuizAnswered { quiz_id, .. } if quiz_id == quiz.id)
+    });
+    match unread {
+        Some(mut notification) => {
+            if let NotificationKind::QuizAnswered { votes, .. } = &mut notification.kind {
+                *votes += 1;
+            }
+            INBOX.with(|inbox| {
+                inbox
+                    .borrow_mut()
+                    .insert((key, notification.id), notification)
+            });
+        }
+        None => notify(
+            author,
+            NotificationKind::QuizAnswered {
+                quiz_id: quiz.id,
+                votes: 1,
+            },
+        ),
+    }
+}
+
+pub fn quiz_closed(quiz_id: u64) {
+    let voters: Vec<Principal> = VOTES.with(|votes| {
+        votes
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|((_, voter), _)| voter.0)
+            .collect()
+    });
+    for voter in voters {
+        notify(voter, NotificationKind::QuizClosed { quiz_id });
+    }
+}
+
+pub fn ownership_offered(recipient: Principal, quiz_id: u64, by: Principal) {
+    notify(
+        recipient,
+        NotificationKind::OwnershipOffered { quiz_id, by },
+    );
+}
+
+// Notifies the principals whose display names the text mentions as @name. Names with spaces
+// can't be mentioned, a mention ends at the first character a name can't have. Principals who
+// can't see the quiz aren't told about it.
+pub fn mentions(text: &str, quiz: &Quiz, by: Principal) {
+    let mut mentioned: Vec<Principal> = Vec::new();
+    for word in text.split('@').skip(1) {
+        let name: String = word
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .collect();
+        if let Some(principal) = profiles::principal_of(&name) {
+            if principal != by
+                && !mentioned.contains(&principal)
+                && allowlist::can_view(quiz, principal)
+            {
+                mentioned.push(principal);
+            }
+        }
+        if mentioned.len() == MAX_MENTIONS {
+            break;
+        }
+    }
+    for principal in mentioned {
+        notify(
+            principal,
+            NotificationKind::Mentioned {
+                quiz_id: quiz.id,
+                by,
+            },
+        );
+    }
+}
+
+// the caller's notifications, the latest first.
+#[ic_cdk::query]
+fn get_notifications(offset: u64, limit: u64) -> NotificationPage {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    let inbox = inbox_of(StorablePrincipal(caller()));
+    NotificationPage {
+        total: inbox.len() as u64,
+        unread: inbox
+            .iter()
+            .filter(|notification| !notification.read)
+            .count() as u64,
+        notifications: inbox
+            .into_iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit)
+            .collect(),
+    }
+}
+
+// marks the caller's notifications with the ids as read, returns how many were unread.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn mark_read(ids: Vec<u64>) -> u64 {
+    metrics::count_call("mark_read");
+    maintenance::trap_if_unavailable("mark_read");
+    let key = StorablePrincipal(caller());
+    INBOX.with(|inbox| {
+        let mut inbox = inbox.borrow_mut();
+        let mut marked = 0;
+        for id in ids.into_iter().take(MAX_NOTIFICATIONS) {
+            if let Some(mut notification) = inbox.get(&(key, id)).filter(|n| !n.read) {
+                notification.read = true;
+                inbox.insert((key, id), notification);
+                marked += 1;
+            }
+        }
+        marked
+    })
+}
@@ -0,0 +1,347 @@
+use crate::guards::not_banned;
+use crate::{
+    _get_quiz, after_vote, allowlist, audit, ensure_can_vote, id_key_range, maintenance, metrics,
+    ratelimit, record_vote, remove_quiz_entries, scoring, validate_question_text, Error, Memory,
+    Quiz, QuizPayload, StorablePrincipal, VotingMode, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const HISTOGRAM_BUCKETS: usize = 10;
+
+// the smallest and the largest number a guess may be
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct NumericRange {
+    min: f64,
+    max: f64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct NumericSettings {
+    range: NumericRange,
+    // kept from the voters, the guesses closest to it win once the quiz closes
+    correct_number: Option<f64>,
+    // when the closest guesses were credited
+    settled_at: Option<u64>,
+}
+
+impl Storable for NumericSettings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for NumericSettings {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Guess {
+    value: f64,
+    submitted_at: u64,
+}
+
+impl Storable for Guess {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Guess {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    from: f64,
+    to: f64,
+    count: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct NumericResults {
+    quiz_id: u64,
+    range: NumericRange,
+    count: u64,
+    // None until the first guess
+    min: Option<f64>,
+    max: Option<f64>,
+    mean: Option<f64>,
+    median: Option<f64>,
+    // the range split into equal buckets, the last one includes the maximum
+    histogram: Vec<HistogramBucket>,
+    // both only once the quiz closed
+    correct_number: Option<f64>,
+    closest_guesses: Vec<Principal>,
+}
+
+thread_local! {
+    static NUMERIC_SETTINGS: RefCell<StableBTreeMap<u64, NumericSettings, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(98)))
+    ));
+
+    static GUESSES: RefCell<StableBTreeMap<(u64, StorablePrincipal), Guess, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(99)))
+    ));
+}
+
+// A numeric quiz is answered with a number within its range instead of an option. Its correct
+// number decides the winners when the quiz closes, so it needs an end time.
+pub fn validate_numeric_quiz(payload: &QuizPayload) -> Result<(), Error> {
+    validate_question_text(&payload.question)?;
+    let invalid = |field: &str, msg: &str| {
+        Err(Error::ValidationFailed {
+            field: field.to_string(),
+            msg: msg.to_string(),
+        })
+    };
+    if !payload.options.is_empty() {
+        return invalid("options", "a numeric quiz has no options");
+    }
+    let Some(range) = payload.numeric_range else {
+        return invalid(
+            "numeric_range",
+            "a numeric quiz needs the range of the guesses",
+        );
+    };
+    if !range.min.is_finite() || !range.max.is_finite() || range.min >= range.max {
+        return invalid(
+            "numeric_range",
+            "the range needs a finite minimum below a finite maximum",
+        );
+    }
+    if let Some(correct_number) = payload.correct_number {
+        if !(range.min..=range.max).contains(&correct_number) {
+            return invalid(
+                "correct_number",
+                "the correct number must be within the range",
+            );
+        }
+        if payload.ends_at.is_none() {
+            return invalid(
+                "correct_number",
+                "the closest guesses win once the quiz closes, so it needs an end time",
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn set_numeric_settings(
+    quiz_id: u64,
+    range: Option<NumericRange>,
+    correct_number: Option<f64>,
+) {
+    NUMERIC_SETTINGS.with(|settings| {
+        let mut settings = settings.borrow_mut();
+        match range {
+            Some(range) => settings.insert(
+                quiz_id,
+                NumericSettings {
+                    range,
+                    correct_number,
+                    settled_at: None,
+                },
+            ),
+            None => settings.remove(&quiz_id),
+        }
+    });
+}
+
+fn numeric_settings_of(quiz_id: u64) -> Option<NumericSettings> {
+    NUMERIC_SETTINGS.with(|settings| settings.borrow().get(&quiz_id))
+}
+
+pub fn numeric_range_of(quiz_id: u64) -> Option<NumericRange> {
+    numeric_settings_of(quiz_id).map(|settings| settings.range)
+}
+
+pub fn correct_number_of(quiz_id: u64) -> Option<f64> {
+    numeric_settings_of(quiz_id).and_then(|settings| settings.correct_number)
+}
+
+pub fn remove_numeric_settings(quiz_id: u64) {
+    NUMERIC_SETTINGS.with(|settings| settings.borrow_mut().remove(&quiz_id));
+}
+
+fn guesses_of(quiz_id: u64) -> Vec<(Principal, f64)> {
+    GUESSES.with(|guesses| {
+        guesses
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|((_, principal), guess)| (principal.0, guess.value))
+            .collect()
+    })
+}
+
+// every principal whose guess is as close to the correct number as the closest one.
+fn closest_guesses(guesses: &[(Principal, f64)], correct_number: f64) -> Vec<Principal> {
+    let distance = |value: f64| (value - correct_number).abs();
+    let Some(closest) = guesses
+        .iter()
+        .map(|(_, value)| distance(*value))
+        .min_by(f64::total_cmp)
+    else {
+        return Vec::new();
+    };
+    guesses
+        .iter()
+        .filter(|(_, value)| distance(*value) == closest)
+        .map(|(principal, _)| *principal)
+        .collect()
+}
+
+// Credits the closest guesses with the points of a correct answer once the quiz closed, every
+// other guess counts as a wrong answer. Called by `close_quiz`.
+pub fn settle(quiz: &Quiz) {
+    let Some(mut settings) = numeric_settings_of(quiz.id).filter(|s| s.settled_at.is_none()) else {
+        return;
+    };
+    let Some(correct_number) = settings.correct_number else {
+        return;
+    };
+    let guesses = guesses_of(quiz.id);
+    let winners = closest_guesses(&guesses, correct_number);
+    for (principal, _) in guesses {
+        let points = if winners.contains(&principal) {
+            scoring::POINTS_PER_CORRECT_ANSWER
+        } else {
+            0
+        };
+        scoring::award_graded_points(quiz.id, principal, None, points);
+    }
+    settings.settled_at = Some(time());
+    NUMERIC_SETTINGS.with(|stored| stored.borrow_mut().insert(quiz.id, settings));
+}
+
+// helper method to forget the guesses of a quiz, the points of a settled one are taken back.
+pub fn remove_guesses(quiz_id: u64) {
+    let settled = numeric_settings_of(quiz_id).filter(|settings| settings.settled_at.is_some());
+    if let Some(mut settings) = settled {
+        if let Some(correct_number) = settings.correct_number {
+            for principal in closest_guesses(&guesses_of(quiz_id), correct_number) {
+                scoring::revoke_graded_points(principal, scoring::POINTS_PER_CORRECT_ANSWER);
+            }
+        }
+        settings.settled_at = None;
+        NUMERIC_SETTINGS.with(|stored| stored.borrow_mut().insert(quiz_id, settings));
+    }
+    GUESSES.with(|guesses| remove_quiz_entries(&mut guesses.borrow_mut(), quiz_id));
+}
+
+fn numeric_quiz(id: u64) -> Result<(Quiz, NumericSettings), Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    match numeric_settings_of(id) {
+        Some(settings) if quiz.voting_mode() == VotingMode::Numeric => Ok((quiz, settings)),
+        _ => Err(Error::ValidationFailed {
+            field: "voting_mode".to_string(),
+            msg: format!("the quiz with id={} is not a numeric quiz", id),
+        }),
+    }
+}
+
+// Answers a numeric quiz with a number within its range, it counts as the vote of the caller.
+#[ic_cdk::update(guard = "not_banned")]
+fn submit_number(id: u64, value: f64) -> Result<(), Error> {
+    metrics::count_call("submit_number");
+    maintenance::ensure_available("submit_number")?;
+    ratelimit::check(ratelimit::Action::Vote)?;
+    let (quiz, settings) = numeric_quiz(id)?;
+    // an anonymous guess couldn't win anything
+    if caller() == Principal::anonymous() {
+        return Err(Error::Unauthorized {
+            msg: "numeric guesses can't be anonymous".to_string(),
+        });
+    }
+    let vote_key = (id, StorablePrincipal(caller()));
+    ensure_can_vote(&quiz, &vote_key)?;
+    if !(settings.range.min..=settings.range.max).contains(&value) {
+        return Err(Error::ValidationFailed {
+            field: "value".to_string(),
+            msg: format!(
+                "the number must be between {} and {}",
+                settings.range.min, settings.range.max
+            ),
+        });
+    }
+
+    let args = Encode!(&id, &value).unwrap();
+    GUESSES.with(|guesses| {
+        guesses.borrow_mut().insert(
+            vote_key,
+            Guess {
+                value,
+                submitted_at: time(),
+            },
+        )
+    });
+    audit::record_vote(id, &args, Vec::new(), None);
+    record_vote(vote_key, String::new(), None);
+    after_vote(&quiz);
+    Ok(())
+}
+
+fn histogram(range: NumericRange, values: &[f64]) -> Vec<HistogramBucket> {
+    let width = (range.max - range.min) / HISTOGRAM_BUCKETS as f64;
+    let mut counts = [0u64; HISTOGRAM_BUCKETS];
+    for value in values {
+        let bucket = ((value - range.min) / width) as usize;
+        counts[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+    counts
+        .iter()
+        .enumerate()
+        .map(|(bucket, count)| HistogramBucket {
+            from: range.min + width * bucket as f64,
+            to: range.min + width * (bucket + 1) as f64,
+            count: *count,
+        })
+        .collect()
+}
+
+#[ic_cdk::query]
+fn get_numeric_results(id: u64) -> Result<NumericResults, Error> {
+    let (quiz, settings) = numeric_quiz(id)?;
+    allowlist::ensure_can_view(&quiz)?;
+    let guesses = guesses_of(id);
+    let mut values: Vec<f64> = guesses.iter().map(|(_, value)| *value).collect();
+    values.sort_by(f64::total_cmp);
+    let count = values.len();
+    let median = match count {
+        0 => None,
+        _ if count.is_multiple_of(2) => Some((values[count / 2 - 1] + values[count / 2]) / 2.0),
+        _ => Some(values[count / 2]),
+    };
+    let correct_number = settings.correct_number.filter(|_| quiz.is_closed());
+    Ok(NumericResults {
+        quiz_id: id,
+        range: settings.range,
+        count: count as u64,
+        min: values.first().copied(),
+        max: values.last().copied(),
+        mean: Some(values.iter().sum::<f64>() / count as f64).filter(|_| count > 0),
+        median,
+        histogram: histogram(settings.range, &values),
+        correct_number,
+        closest_guesses: correct_number
+            .map(|correct_number| closest_guesses(&guesses, correct_number))
+            .unwrap_or_default(),
+    })
+}
@@ -0,0 +1,159 @@
+use crate::guards::only_admin;
+use crate::{create_batch, maintenance, metrics, BatchMode, Error, Quiz, QuizPayload};
+use ic_cdk::api::management_canister::http_request::{
+    http_request, CanisterHttpRequestArgument, HttpMethod, HttpResponse as OutcallResponse,
+    TransformArgs, TransformContext,
+};
+
+// the api hands out at most 50 questions per request
+const MAX_AMOUNT: u32 = 50;
+// 50 url encoded questions stay well below this
+const MAX_RESPONSE_BYTES: u64 = 100_000;
+// more than the outcall costs on a 13 node subnet, the rest is refunded
+const OUTCALL_CYCLES: u128 = 2_000_000_000;
+// every fetched quiz is tagged with it, next to the category of the question
+const SOURCE_TAG: &str = "opentdb";
+
+#[derive(Deserialize)]
+struct OpenTdbResponse {
+    response_code: u32,
+    results: Vec<OpenTdbQuestion>,
+}
+
+// strings come url encoded, as asked for with `encode=url3986`
+#[derive(Deserialize)]
+struct OpenTdbQuestion {
+    category: String,
+    question: String,
+    correct_answer: String,
+    incorrect_answers: Vec<String>,
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut position = 0;
+    while position < bytes.len() {
+        let escaped = value
+            .get(position + 1..position + 3)
+            .filter(|_| bytes[position] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                position += 3;
+            }
+            None => {
+                decoded.push(bytes[position]);
+                position += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// the options are sorted so the position of the correct one gives nothing away.
+fn to_payload(question: OpenTdbQuestion) -> QuizPayload {
+    let correct_answer = percent_decode(&question.correct_answer);
+    let mut options: Vec<String> = question
+        .incorrect_answers
+        .iter()
+        .map(|answer| percent_decode(answer))
+        .collect();
+    options.push(correct_answer.clone());
+    options.sort();
+    QuizPayload {
+        question: percent_decode(&question.question),
+        options,
+        correct_options: Some(vec![correct_answer]),
+        tags: Some(vec![SOURCE_TAG.to_string()]),
+        category: Some(percent_decode(&question.category)),
+        ..Default::default()
+    }
+}
+
+// Replicas only agree on a response that is the same for all of them, so everything but the
+// status and the body is dropped.
+#[ic_cdk::query]
+fn transform_opentdb(args: TransformArgs) -> OutcallResponse {
+    OutcallResponse {
+        status: args.response.status,
+        headers: vec![],
+        body: args.response.body,
+    }
+}
+
+// Creates a trivia quiz for every question fetched from the Open Trivia DB, `category` is one of
+// its numeric category ids. The questions that fail validation report their error. The api picks
+// the questions at random, so the call fails whenever the replicas are sent different ones.
+#[ic_cdk::update(guard = "only_admin")]
+async fn import_from_opentdb(
+    category: Option<u32>,
+    amount: u32,
+) -> Result<Vec<Result<Quiz, Error>>, Error> {
+    metrics::count_call("import_from_opentdb");
+    maintenance::ensure_available("import_from_opentdb")?;
+    if amount == 0 || amount > MAX_AMOUNT {
+        return Err(Error::ValidationFailed {
+            field: "amount".to_string(),
+            msg: format!(
+                "between 1 and {} questions can be fetched at once",
+                MAX_AMOUNT
+            ),
+        });
+    }
+
+    let mut url = format!(
+        "https://opentdb.com/api.php?amount={}&encode=url3986",
+        amount
+    );
+    if let Some(category) = category {
+        url.push_str(&format!("&category={}", category));
+    }
+    let request = CanisterHttpRequestArgument {
+        url,
+        method: HttpMethod::GET,
+        body: None,
+        max_response_bytes: Some(MAX_RESPONSE_BYTES),
+        transform: Some(TransformContext::from_name(
+            "transform_opentdb".to_string(),
+            vec![],
+        )),
+        headers: vec![],
+    };
+    let (response,) = http_request(request, OUTCALL_CYCLES)
+        .await
+        .map_err(|(code, msg)| Error::CallFailed {
+            msg: format!(
+                "the request to the Open Trivia DB failed ({:?}): {}",
+                code, msg
+            ),
+        })?;
+    if response.status != 200u32 {
+        return Err(Error::CallFailed {
+            msg: format!(
+                "the Open Trivia DB answered with status {}",
+                response.status
+            ),
+        });
+    }
+
+    let response: OpenTdbResponse =
+        serde_json::from_slice(&response.body).map_err(|err| Error::CallFailed {
+            msg: format!("the Open Trivia DB sent an unexpected response: {}", err),
+        })?;
+    if response.response_code != 0 {
+        return Err(Error::CallFailed {
+            msg: format!(
+                "the Open Trivia DB couldn't serve the questions (response code {})",
+                response.response_code
+            ),
+        });
+    }
+    let payloads = response
+        .results
+        .into_iter()
+        .map(|question| Ok(to_payload(question)))
+        .collect();
+    Ok(create_batch(payloads, BatchMode::BestEffort))
+}
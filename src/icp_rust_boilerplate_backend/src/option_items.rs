@@ -0,0 +1,160 @@
+use crate::guards::not_banned;
+use crate::{
+    _get_quiz, allowlist, cast_single_vote, maintenance, metrics, Error, Memory, Quiz,
+    MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MAX_ASSET_PATH_LEN: usize = 256;
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "svg"];
+
+// an image served by an asset canister, e.g. /images/cat.png
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AssetRef {
+    canister: Principal,
+    path: String,
+}
+
+// an option of a quiz as clients show it, the id is its position in the options
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct OptionItem {
+    id: u32,
+    label: String,
+    image: Option<AssetRef>,
+}
+
+// one entry per option in the order of the options, None for options without an image
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct OptionImages {
+    by_option: Vec<Option<AssetRef>>,
+}
+
+impl Storable for OptionImages {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for OptionImages {
+    // a canister id and a path for each of at most 20 options
+    const MAX_SIZE: u32 = 6 * 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    // kept out of the Quiz record, which has no room left for them
+    static OPTION_IMAGES: RefCell<StableBTreeMap<u64, OptionImages, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(100)))
+    ));
+}
+
+// An absolute path of an image file without any dot segments, the asset canister serves it as
+// is so it goes into image urls unescaped.
+fn validate_asset(asset: &AssetRef) -> Result<(), Error> {
+    let extension = asset.path.rsplit_once('.').map(|(_, extension)| extension);
+    let msg = if asset.canister == Principal::anonymous()
+        || asset.canister == Principal::management_canister()
+    {
+        "an image must reference an asset canister".to_string()
+    } else if !asset.path.starts_with('/') || asset.path.len() > MAX_ASSET_PATH_LEN {
+        format!(
+            "an image path must start with '/' and not exceed {} bytes",
+            MAX_ASSET_PATH_LEN
+        )
+    } else if !asset
+        .path
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/-_.~".contains(c))
+        || asset.path.split('/').any(|segment| segment == "..")
+    {
+        "an image path may only hold letters, digits and '/-_.~' without '..'".to_string()
+    } else if !extension.is_some_and(|extension| {
+        IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+    }) {
+        format!("an image must be one of {}", IMAGE_EXTENSIONS.join(", "))
+    } else {
+        return Ok(());
+    };
+    Err(Error::ValidationFailed {
+        field: "option_images".to_string(),
+        msg,
+    })
+}
+
+pub fn validate_option_images(
+    options: &[String],
+    images: &[Option<AssetRef>],
+) -> Result<(), Error> {
+    if images.len() != options.len() {
+        return Err(Error::ValidationFailed {
+            field: "option_images".to_string(),
+            msg: "there has to be one entry per option, None for no image".to_string(),
+        });
+    }
+    for asset in images.iter().flatten() {
+        validate_asset(asset)?;
+    }
+    Ok(())
+}
+
+// stores the images of the options, None or no image at all removes them.
+pub fn set_option_images(quiz_id: u64, images: Option<Vec<Option<AssetRef>>>) {
+    OPTION_IMAGES.with(|stored| {
+        let mut stored = stored.borrow_mut();
+        match images.filter(|by_option| by_option.iter().any(Option::is_some)) {
+            Some(by_option) => stored.insert(quiz_id, OptionImages { by_option }),
+            None => stored.remove(&quiz_id),
+        }
+    });
+}
+
+pub fn option_images_of(quiz_id: u64) -> Option<Vec<Option<AssetRef>>> {
+    OPTION_IMAGES
+        .with(|stored| stored.borrow().get(&quiz_id))
+        .map(|images| images.by_option)
+}
+
+pub fn remove_option_images(quiz_id: u64) {
+    OPTION_IMAGES.with(|stored| stored.borrow_mut().remove(&quiz_id));
+}
+
+#[ic_cdk::query]
+fn get_option_items(quiz_id: u64) -> Result<Vec<OptionItem>, Error> {
+    let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", quiz_id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    let mut images = option_images_of(quiz_id).unwrap_or_default().into_iter();
+    Ok((0..)
+        .zip(quiz.options)
+        .map(|(id, label)| OptionItem {
+            id,
+            label,
+            image: images.next().flatten(),
+        })
+        .collect())
+}
+
+// `answer_quiz` with the id of the option from `get_option_items` instead of its label.
+#[ic_cdk::update(guard = "not_banned")]
+async fn answer_quiz_option(quiz_id: u64, option_id: u32) -> Result<Quiz, Error> {
+    metrics::count_call("answer_quiz_option");
+    maintenance::ensure_available("answer_quiz_option")?;
+    let option = _get_quiz(&quiz_id)
+        .and_then(|quiz| quiz.options.get(option_id as usize).cloned())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "the quiz with id={} has no option with id={}",
+                quiz_id, option_id
+            ),
+        })?;
+    cast_single_vote(quiz_id, option, false).await
+}
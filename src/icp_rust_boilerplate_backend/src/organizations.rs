@@ -0,0 +1,344 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, allowlist, maintenance, metrics, moderation, Error, IdCell, Memory, Quiz, QuizPage,
+    StorablePrincipal, MAX_PAGE_SIZE, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MIN_NAME_LEN: usize = 3;
+const MAX_NAME_LEN: usize = 64;
+const MAX_MEMBERS: usize = 100;
+
+// ordered by what a member may do, every role includes the ones before it
+#[derive(
+    candid::CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum OrgRole {
+    // edits the organization's quizzes like an editor
+    Member,
+    // acts as the author of the organization's quizzes and manages the members
+    Admin,
+    // manages the admins and owners too, an organization always keeps one
+    Owner,
+}
+
+impl Storable for OrgRole {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for OrgRole {
+    const MAX_SIZE: u32 = 32;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    id: u64,
+    name: String,
+    created_by: Principal,
+    created_at: u64,
+}
+
+impl Storable for Organization {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Organization {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct OrgMember {
+    principal: Principal,
+    role: OrgRole,
+}
+
+thread_local! {
+    static ORGANIZATIONS: RefCell<StableBTreeMap<u64, Organization, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(71)))
+    ));
+
+    static ORG_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(72))), 0)
+            .expect("Cannot create the organization counter")
+    );
+
+    // by (organization, member)
+    static MEMBERS: RefCell<StableBTreeMap<(u64, StorablePrincipal), OrgRole, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(73)))
+    ));
+
+    // the same memberships by (member, organization)
+    static MEMBERSHIPS: RefCell<StableBTreeMap<(StorablePrincipal, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(74)))
+    ));
+
+    // (organization, quiz id) of the quizzes it owns and that aren't in the trash
+    static QUIZZES_BY_ORG: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(75)))
+    ));
+}
+
+pub fn role_in(org_id: u64, principal: Principal) -> Option<OrgRole> {
+    MEMBERS.with(|members| {
+        members
+            .borrow()
+            .get(&(org_id, StorablePrincipal(principal)))
+    })
+}
+
+// whether the principal holds at least the role in the organization owning the quiz.
+pub fn has_quiz_role(quiz: &Quiz, principal: Principal, required: OrgRole) -> bool {
+    quiz.organization
+        .and_then(|org_id| role_in(org_id, principal))
+        .is_some_and(|role| role >= required)
+}
+
+// quizzes are only created for organizations the caller is a member of.
+pub fn ensure_member(org_id: u64) -> Result<(), Error> {
+    if role_in(org_id, caller()).is_none() {
+        return Err(Error::Unauthorized {
+            msg: format!("you aren't a member of the organization with id={}", org_id),
+        });
+    }
+    Ok(())
+}
+
+pub fn index_quiz(quiz: &Quiz) {
+    if let Some(org_id) = quiz.organization {
+        QUIZZES_BY_ORG.with(|index| index.borrow_mut().insert((org_id, quiz.id), ()));
+    }
+}
+
+pub fn unindex_quiz(quiz: &Quiz) {
+    if let Some(org_id) = quiz.organization {
+        QUIZZES_BY_ORG.with(|index| index.borrow_mut().remove(&(org_id, quiz.id)));
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), Error> {
+    let len = name.trim().len();
+    if !(MIN_NAME_LEN..=MAX_NAME_LEN).contains(&len) {
+        return Err(Error::ValidationFailed {
+            field: "name".to_string(),
+            msg: format!(
+                "the name must be {} to {} bytes",
+                MIN_NAME_LEN, MAX_NAME_LEN
+            ),
+        });
+    }
+    moderation::ensure_clean("name", name)
+}
+
+fn get_org(org_id: u64) -> Result<Organization, Error> {
+    ORGANIZATIONS
+        .with(|organizations| organizations.borrow().get(&org_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("an organization with id={} not found", org_id),
+        })
+}
+
+fn members_of(org_id: u64) -> Vec<OrgMember> {
+    MEMBERS.with(|members| {
+        members
+            .borrow()
+            .range(crate::id_key_range(org_id))
+            .map(|((_, member), role)| OrgMember {
+                principal: member.0,
+                role,
+            })
+            .collect()
+    })
+}
+
+fn ensure_role_in(org_id: u64, required: OrgRole) -> Result<(), Error> {
+    get_org(org_id)?;
+    if role_in(org_id, caller()).is_none_or(|role| role < required) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "this needs the {:?} role in the organization with id={}",
+                required, org_id
+            ),
+        });
+    }
+    Ok(())
+}
+
+fn set_member(org_id: u64, principal: Principal, role: Option<OrgRole>) {
+    let key = StorablePrincipal(principal);
+    match role {
+        Some(role) => {
+            MEMBERS.with(|members| members.borrow_mut().insert((org_id, key), role));
+            MEMBERSHIPS.with(|memberships| memberships.borrow_mut().insert((key, org_id), ()));
+        }
+        None => {
+            MEMBERS.with(|members| members.borrow_mut().remove(&(org_id, key)));
+            MEMBERSHIPS.with(|memberships| memberships.borrow_mut().remove(&(key, org_id)));
+        }
+    }
+}
+
+// the organization would be left without an owner if the principal stopped being one.
+fn is_last_owner(org_id: u64, principal: Principal) -> bool {
+    role_in(org_id, principal) == Some(OrgRole::Owner)
+        && members_of(org_id)
+            .iter()
+            .filter(|member| member.role == OrgRole::Owner)
+            .count()
+            == 1
+}
+
+// the caller becomes the first owner of the new organization.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn create_organization(name: String) -> Result<Organization, Error> {
+    metrics::count_call("create_organization");
+    maintenance::ensure_available("create_organization")?;
+    validate_name(&name)?;
+    let id = ORG_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment organization counter".to_string(),
+        })?;
+    let organization = Organization {
+        id,
+        name: name.trim().to_string(),
+        created_by: caller(),
+        created_at: time(),
+    };
+    ORGANIZATIONS.with(|organizations| organizations.borrow_mut().insert(id, organization.clone()));
+    set_member(id, caller(), Some(OrgRole::Owner));
+    Ok(organization)
+}
+
+#[ic_cdk::query]
+fn get_organization(org_id: u64) -> Result<Organization, Error> {
+    get_org(org_id)
+}
+
+#[ic_cdk::query]
+fn get_my_organizations() -> Vec<Organization> {
+    let member = StorablePrincipal(caller());
+    MEMBERSHIPS.with(|memberships| {
+        memberships
+            .borrow()
+            .range((member, 0)..=(member, u64::MAX))
+            .filter_map(|((_, org_id), _)| get_org(org_id).ok())
+            .collect()
+    })
+}
+
+// who belongs to an organization is only told to its members.
+#[ic_cdk::query]
+fn list_org_members(org_id: u64) -> Result<Vec<OrgMember>, Error> {
+    ensure_role_in(org_id, OrgRole::Member)?;
+    Ok(members_of(org_id))
+}
+
+// Adds a member or changes their role. Admins manage the members, only owners hand out and take
+// away the admin and owner roles.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_org_member(org_id: u64, principal: Principal, role: OrgRole) -> Result<(), Error> {
+    metrics::count_call("set_org_member");
+    maintenance::ensure_available("set_org_member")?;
+    let current = role_in(org_id, principal);
+    let required = if role > OrgRole::Member || current > Some(OrgRole::Member) {
+        OrgRole::Owner
+    } else {
+        OrgRole::Admin
+    };
+    ensure_role_in(org_id, required)?;
+    if principal == Principal::anonymous() {
+        return Err(Error::ValidationFailed {
+            field: "principal".to_string(),
+            msg: "the anonymous principal can't be a member".to_string(),
+        });
+    }
+    if role != OrgRole::Owner && is_last_owner(org_id, principal) {
+        return Err(Error::ValidationFailed {
+            field: "role".to_string(),
+            msg: "the last owner of an organization can't step down".to_string(),
+        });
+    }
+    if current.is_none() && members_of(org_id).len() >= MAX_MEMBERS {
+        return Err(Error::StorageFull {
+            msg: format!("an organization can have at most {} members", MAX_MEMBERS),
+        });
+    }
+    set_member(org_id, principal, Some(role));
+    Ok(())
+}
+
+// Admins remove members and owners anybody, every member may leave. The last owner has to hand
+// the organization to another owner first.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn remove_org_member(org_id: u64, principal: Principal) -> Result<(), Error> {
+    metrics::count_call("remove_org_member");
+    maintenance::ensure_available("remove_org_member")?;
+    let current = role_in(org_id, principal).ok_or_else(|| Error::NotFound {
+        msg: format!(
+            "{} isn't a member of the organization with id={}",
+            principal, org_id
+        ),
+    })?;
+    if principal != caller() {
+        let required = if current > OrgRole::Member {
+            OrgRole::Owner
+        } else {
+            OrgRole::Admin
+        };
+        ensure_role_in(org_id, required)?;
+    }
+    if is_last_owner(org_id, principal) {
+        return Err(Error::ValidationFailed {
+            field: "principal".to_string(),
+            msg: "the last owner of an organization can't leave it".to_string(),
+        });
+    }
+    set_member(org_id, principal, None);
+    Ok(())
+}
+
+// the quizzes the organization owns, the oldest first.
+#[ic_cdk::query]
+fn get_org_quizzes(org_id: u64, offset: u64, limit: u64) -> Result<QuizPage, Error> {
+    get_org(org_id)?;
+    Ok(QUIZZES_BY_ORG.with(|index| {
+        let index = index.borrow();
+        // a second pass over the keys is needed for the total
+        let total = index.range((org_id, 0)..=(org_id, u64::MAX)).count() as u64;
+        QuizPage {
+            quizzes: index
+                .range((org_id, 0)..=(org_id, u64::MAX))
+                .filter_map(|((_, quiz_id), _)| _get_quiz(&quiz_id))
+                .filter(|quiz| allowlist::can_view(quiz, caller()))
+                .skip(offset as usize)
+                .take(limit.min(MAX_PAGE_SIZE) as usize)
+                .collect(),
+            total,
+        }
+    }))
+}
@@ -0,0 +1,84 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, audit, do_insert, editors, ensure_author, index_author, maintenance, metrics,
+    notifications, unindex_author, AuditAction, Error, Memory, Quiz, StorablePrincipal,
+    MEMORY_MANAGER,
+};
+use candid::{Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+thread_local! {
+    // the principal each quiz is offered to, until they accept or the author offers it elsewhere
+    static PENDING_TRANSFERS: RefCell<StableBTreeMap<u64, StorablePrincipal, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(69)))
+    ));
+}
+
+fn live_quiz(quiz_id: u64) -> Result<Quiz, Error> {
+    _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })
+}
+
+// a pending transfer goes together with the quiz, see `remove_quiz`.
+pub fn remove_transfer(quiz_id: u64) {
+    PENDING_TRANSFERS.with(|pending| pending.borrow_mut().remove(&quiz_id));
+}
+
+// Offers the quiz to another principal, who becomes its author once they accept. Offering it again
+// replaces the earlier offer, offering it to the author withdraws it.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn transfer_ownership(quiz_id: u64, new_owner: Principal) -> Result<(), Error> {
+    metrics::count_call("transfer_ownership");
+    maintenance::ensure_available("transfer_ownership")?;
+    let quiz = live_quiz(quiz_id)?;
+    ensure_author(&quiz)?;
+    if new_owner == Principal::anonymous() {
+        return Err(Error::ValidationFailed {
+            field: "new_owner".to_string(),
+            msg: "a quiz can't be handed to the anonymous principal".to_string(),
+        });
+    }
+    if new_owner == caller() {
+        remove_transfer(quiz_id);
+        return Ok(());
+    }
+    PENDING_TRANSFERS.with(|pending| {
+        pending
+            .borrow_mut()
+            .insert(quiz_id, StorablePrincipal(new_owner))
+    });
+    notifications::ownership_offered(new_owner, quiz_id, caller());
+    Ok(())
+}
+
+// makes the caller the author of the quiz offered to them.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn accept_ownership(quiz_id: u64) -> Result<Quiz, Error> {
+    metrics::count_call("accept_ownership");
+    maintenance::ensure_available("accept_ownership")?;
+    let mut quiz = live_quiz(quiz_id)?;
+    if PENDING_TRANSFERS.with(|pending| pending.borrow().get(&quiz_id))
+        != Some(StorablePrincipal(caller()))
+    {
+        return Err(Error::Unauthorized {
+            msg: format!("the quiz with id={} isn't offered to you", quiz_id),
+        });
+    }
+    remove_transfer(quiz_id);
+    // an editor who takes over is the author from now on
+    editors::revoke(quiz_id, caller());
+    unindex_author(&quiz);
+    quiz.author = Some(caller());
+    quiz.updated_at = Some(time());
+    do_insert(&quiz);
+    index_author(&quiz);
+    audit::record(AuditAction::Transfer, quiz_id, &Encode!(&quiz_id).unwrap());
+    Ok(quiz)
+}
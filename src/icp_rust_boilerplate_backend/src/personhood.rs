@@ -0,0 +1,195 @@
+use crate::guards::{only_admin, only_authenticated};
+use crate::{_get_quiz, ensure_author, maintenance, metrics, Error, Memory, Quiz, MEMORY_MANAGER};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{Cell, StableBTreeMap, Storable};
+use std::collections::HashMap;
+use std::{borrow::Cow, cell::RefCell};
+
+// attestations rarely go away, a principal that isn't attested yet is asked again sooner
+const ATTESTED_TTL_NS: u64 = 60 * 60 * 1_000_000_000;
+const UNATTESTED_TTL_NS: u64 = 5 * 60 * 1_000_000_000;
+const MAX_CACHED_ATTESTATIONS: usize = 10_000;
+
+// The canister that attests principals as unique humans answers `is_unique_human : (principal)
+// -> (bool)`, a proof-of-personhood canister or an adapter in front of one.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct PersonhoodSettings {
+    attestor: Option<Principal>,
+}
+
+impl Storable for PersonhoodSettings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+thread_local! {
+    static SETTINGS: RefCell<Cell<PersonhoodSettings, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(128))),
+            PersonhoodSettings::default(),
+        )
+        .expect("Cannot create the personhood settings cell")
+    );
+
+    // the quizzes only attested humans vote on
+    static HUMANS_ONLY: RefCell<StableBTreeMap<u64, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(129)))
+    ));
+
+    // whether a principal is attested with the time it was asked at, lost on upgrades
+    static ATTESTATIONS: RefCell<HashMap<Principal, (bool, u64)>> = RefCell::new(HashMap::new());
+}
+
+fn attestor() -> Option<Principal> {
+    SETTINGS.with(|settings| settings.borrow().get().attestor)
+}
+
+fn is_humans_only(quiz_id: u64) -> bool {
+    HUMANS_ONLY.with(|quizzes| quizzes.borrow().contains_key(&quiz_id))
+}
+
+pub fn remove_personhood_gate(quiz_id: u64) {
+    HUMANS_ONLY.with(|quizzes| quizzes.borrow_mut().remove(&quiz_id));
+}
+
+fn is_fresh((attested, checked_at): (bool, u64)) -> bool {
+    let ttl = if attested {
+        ATTESTED_TTL_NS
+    } else {
+        UNATTESTED_TTL_NS
+    };
+    time() < checked_at.saturating_add(ttl)
+}
+
+fn cached_attestation(principal: Principal) -> Option<bool> {
+    ATTESTATIONS
+        .with(|attestations| attestations.borrow().get(&principal).copied())
+        .filter(|attestation| is_fresh(*attestation))
+        .map(|(attested, _)| attested)
+}
+
+// Only admins pick the attestor, None lets nobody pass the gates until there is one again.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_personhood_attestor(attestor: Option<Principal>) -> Result<(), Error> {
+    metrics::count_call("set_personhood_attestor");
+    maintenance::ensure_available("set_personhood_attestor")?;
+    SETTINGS
+        .with(|settings| settings.borrow_mut().set(PersonhoodSettings { attestor }))
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the personhood settings".to_string(),
+        })?;
+    // the answers of the old attestor no longer say anything
+    ATTESTATIONS.with(|attestations| attestations.borrow_mut().clear());
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_personhood_attestor() -> Option<Principal> {
+    attestor()
+}
+
+// Asks the attestor whether the voter is a unique human for a quiz that takes one, unless it
+// was asked recently.
+pub async fn refresh(quiz_id: u64, voter: Principal) -> Result<(), Error> {
+    if !is_humans_only(quiz_id)
+        || voter == Principal::anonymous()
+        || cached_attestation(voter).is_some()
+    {
+        return Ok(());
+    }
+    let attestor = attestor().ok_or_else(|| Error::NotEligible {
+        msg: "there is no attestor to prove you are a unique human to".to_string(),
+    })?;
+    let (attested,): (bool,) = ic_cdk::call(attestor, "is_unique_human", (voter,))
+        .await
+        .map_err(|(code, msg)| Error::CallFailed {
+            msg: format!(
+                "is_unique_human on {} failed ({:?}): {}",
+                attestor, code, msg
+            ),
+        })?;
+    ATTESTATIONS.with(|attestations| {
+        let mut attestations = attestations.borrow_mut();
+        if attestations.len() >= MAX_CACHED_ATTESTATIONS {
+            attestations.retain(|_, attestation| is_fresh(*attestation));
+        }
+        attestations.insert(voter, (attested, time()));
+    });
+    Ok(())
+}
+
+// Every way of voting goes through here, see `ensure_open`. Like the token gates, only
+// `answer_quiz` asks the attestor itself, the others go by `check_personhood`.
+pub fn ensure_human(quiz: &Quiz) -> Result<(), Error> {
+    if !is_humans_only(quiz.id) {
+        return Ok(());
+    }
+    let msg = match cached_attestation(caller()) {
+        Some(true) => return Ok(()),
+        Some(false) => "you aren't attested",
+        None => "see check_personhood",
+    };
+    Err(Error::NotEligible {
+        msg: format!(
+            "only principals attested as unique humans vote on the quiz with id={}, {}",
+            quiz.id, msg
+        ),
+    })
+}
+
+// Lets only principals the attestor vouches for as unique humans vote on the quiz.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_humans_only(quiz_id: u64, humans_only: bool) -> Result<(), Error> {
+    metrics::count_call("set_humans_only");
+    maintenance::ensure_available("set_humans_only")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_author(&quiz)?;
+    if !humans_only {
+        remove_personhood_gate(quiz_id);
+        return Ok(());
+    }
+    let msg = if attestor().is_none() {
+        "there is no attestor to prove the voters are unique humans"
+    } else if quiz.allows_anonymous() {
+        "anonymous voters can't be attested"
+    } else {
+        HUMANS_ONLY.with(|quizzes| quizzes.borrow_mut().insert(quiz_id, ()));
+        return Ok(());
+    };
+    Err(Error::ValidationFailed {
+        field: "humans_only".to_string(),
+        msg: msg.to_string(),
+    })
+}
+
+#[ic_cdk::query]
+fn is_humans_only_quiz(quiz_id: u64) -> bool {
+    is_humans_only(quiz_id)
+}
+
+// Asks the attestor whether the caller is a unique human, so the ways of voting that don't call
+// it themselves let them in for a while. Returns whether they may vote on the quiz.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn check_personhood(quiz_id: u64) -> Result<bool, Error> {
+    metrics::count_call("check_personhood");
+    maintenance::ensure_available("check_personhood")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    refresh(quiz_id, caller()).await?;
+    Ok(ensure_human(&quiz).is_ok())
+}
@@ -0,0 +1,785 @@
+use crate::guards::only_authenticated;
+use crate::icrc::{self, Account};
+use crate::{
+    _get_quiz, allowlist, ensure_author, id_key_range, maintenance, metrics, scoring, Error,
+    Memory, Quiz, MEMORY_MANAGER, VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use std::{borrow::Cow, cell::RefCell};
+
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// a pool can't be locked up longer than that
+const MAX_POOL_DURATION_NS: u64 = 365 * DAY_NS;
+// the first byte of an escrow subaccount, telling it apart from other subaccounts of the canister
+const POOL_SUBACCOUNT_TAG: u8 = 1;
+// A failed payout is tried again after a minute, then after twice as long every time. The last
+// of the attempts happens within hours, well inside the day the ledger deduplicates transfers.
+const FIRST_RETRY_NS: u64 = 60 * 1_000_000_000;
+const MAX_PAYOUT_ATTEMPTS: u32 = 8;
+const MAX_ERROR_LEN: usize = 200;
+
+// how the pool of a quiz goes to its voters once the quiz closed
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PayoutRule {
+    // split between the voters with the best net score
+    TopScore,
+    // the whole pool to one voter drawn at random
+    RandomVoter,
+    // split between every voter who answered fully correct
+    SplitAmongCorrect,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct PrizePool {
+    quiz_id: u64,
+    ledger: Principal,
+    funder: Principal,
+    // what the escrow subaccount of the quiz holds, the fees of transfers out of it included
+    balance: u128,
+    funded_at: u64,
+    // the funder can take back what is left from then on
+    expires_at: u64,
+    // the refund being made, a retry transfers the same amount with the same time so the ledger
+    // deduplicates it
+    refund_amount: Option<u128>,
+    refund_created_at: Option<u64>,
+    refunded_at: Option<u64>,
+    refund_block_index: Option<u128>,
+    // None leaves the pool to the funder, it can only be set while the quiz runs
+    payout_rule: Option<PayoutRule>,
+    // when the winners were drawn, only once per pool
+    settled_at: Option<u64>,
+    // what the payouts still being made take out of the balance, their fees included
+    reserved: Option<u128>,
+}
+
+impl Storable for PrizePool {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for PrizePool {
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PoolStatus {
+    // takes funds until it expires
+    Open,
+    // the funder can take back the balance
+    Expired,
+    // nothing is left to take back
+    Refunded,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PayoutStatus {
+    Pending,
+    Paid,
+    // gave up after MAX_PAYOUT_ATTEMPTS, its amount went back to the pool
+    Failed,
+}
+
+// an entry of the payout log, kept for auditing after the quiz is gone
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Payout {
+    quiz_id: u64,
+    principal: Principal,
+    amount: u128,
+    fee: u128,
+    // every attempt sends the same transfer, so the ledger never pays it twice
+    created_at: u64,
+    status: PayoutStatus,
+    attempts: u32,
+    next_attempt_at: Option<u64>,
+    last_error: Option<String>,
+    block_index: Option<u128>,
+    paid_at: Option<u64>,
+}
+
+impl Storable for Payout {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Payout {
+    // a principal and an error message of at most MAX_ERROR_LEN bytes
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct PrizePoolStatus {
+    pool: PrizePool,
+    status: PoolStatus,
+}
+
+thread_local! {
+    // kept after the quiz is gone, so the funder can still take back what's left
+    static POOLS: RefCell<StableBTreeMap<u64, PrizePool, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(109)))
+    ));
+
+    // by (quiz id, position among the winners)
+    static PAYOUTS: RefCell<StableBTreeMap<(u64, u64), Payout, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(110)))
+    ));
+
+    // pools whose funding or refund is awaited
+    static IN_FLIGHT: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    // set while `process_payouts` runs, so a payout isn't sent twice at once
+    static PAYING: RefCell<bool> = const { RefCell::new(false) };
+}
+
+// the subaccount of the canister holding the pool of a quiz, apart from every other pool.
+pub fn escrow_subaccount(quiz_id: u64) -> Vec<u8> {
+    let mut subaccount = vec![0; 32];
+    subaccount[0] = POOL_SUBACCOUNT_TAG;
+    subaccount[24..].copy_from_slice(&quiz_id.to_be_bytes());
+    subaccount
+}
+
+pub fn escrow_account(quiz_id: u64) -> Account {
+    Account {
+        owner: ic_cdk::id(),
+        subaccount: Some(escrow_subaccount(quiz_id)),
+    }
+}
+
+fn pool_of(quiz_id: u64) -> Option<PrizePool> {
+    POOLS.with(|pools| pools.borrow().get(&quiz_id))
+}
+
+// Opens an empty pool for the caller on `ledger`, or checks that the pool there is on it, see
+// `entry_fees`. The caller can top it up and refund it as if they had funded it.
+pub fn open(quiz_id: u64, ledger: Principal, expires_at: u64) -> Result<(), Error> {
+    match pool_of(quiz_id) {
+        Some(pool) if pool.ledger != ledger || pool.funder != caller() => {
+            Err(Error::ValidationFailed {
+                field: "quiz_id".to_string(),
+                msg: format!(
+                    "the pool of the quiz with id={} is funded by {} with the token of {}",
+                    quiz_id, pool.funder, pool.ledger
+                ),
+            })
+        }
+        Some(_) => Ok(()),
+        None => {
+            validate_expiry(None, expires_at)?;
+            let pool = PrizePool {
+                quiz_id,
+                ledger,
+                funder: caller(),
+                balance: 0,
+                funded_at: time(),
+                expires_at,
+                refund_amount: None,
+                refund_created_at: None,
+                refunded_at: None,
+                refund_block_index: None,
+                payout_rule: None,
+                settled_at: None,
+                reserved: None,
+            };
+            POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool));
+            Ok(())
+        }
+    }
+}
+
+// adds tokens that were moved to the escrow subaccount of the pool by someone else.
+pub fn credit(quiz_id: u64, amount: u128) {
+    if let Some(mut pool) = pool_of(quiz_id) {
+        pool.balance = pool.balance.saturating_add(amount);
+        POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool));
+    }
+}
+
+// the balance the payouts being made leave over
+fn available(pool: &PrizePool) -> u128 {
+    pool.balance
+        .saturating_sub(pool.reserved.unwrap_or_default())
+}
+
+fn status_of(pool: &PrizePool) -> PoolStatus {
+    if time() < pool.expires_at {
+        PoolStatus::Open
+    } else if available(pool) == 0 {
+        PoolStatus::Refunded
+    } else {
+        PoolStatus::Expired
+    }
+}
+
+fn with_status(pool: PrizePool) -> PrizePoolStatus {
+    PrizePoolStatus {
+        status: status_of(&pool),
+        pool,
+    }
+}
+
+// Marks a pool busy for the length of a transfer, the guard clears the mark again even if the
+// call returns an error.
+struct InFlight(u64);
+
+impl InFlight {
+    fn start(quiz_id: u64) -> Result<Self, Error> {
+        IN_FLIGHT.with(|in_flight| {
+            let mut in_flight = in_flight.borrow_mut();
+            if in_flight.contains(&quiz_id) {
+                return Err(Error::ValidationFailed {
+                    field: "quiz_id".to_string(),
+                    msg: format!(
+                        "a transfer of the pool of the quiz with id={} is under way",
+                        quiz_id
+                    ),
+                });
+            }
+            in_flight.push(quiz_id);
+            Ok(InFlight(quiz_id))
+        })
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().retain(|id| *id != self.0));
+    }
+}
+
+fn validate_expiry(quiz_ends_at: Option<u64>, expires_at: u64) -> Result<(), Error> {
+    let msg = if expires_at <= time() || quiz_ends_at.is_some_and(|ends_at| expires_at <= ends_at) {
+        "a pool has to expire after the quiz ends"
+    } else if expires_at > time().saturating_add(MAX_POOL_DURATION_NS) {
+        "a pool can't expire more than a year from now"
+    } else {
+        return Ok(());
+    };
+    Err(Error::ValidationFailed {
+        field: "expires_at".to_string(),
+        msg: msg.to_string(),
+    })
+}
+
+// Funds the prize pool of a quiz with `amount` of a token the caller approved the canister for
+// with `icrc2_approve`, the ledger charges its fee on top. Another call tops the pool up with
+// the same token, and a later `expires_at` pushes the expiry back.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn fund_prize_pool(
+    quiz_id: u64,
+    ledger: Principal,
+    amount: u128,
+    expires_at: u64,
+) -> Result<PrizePoolStatus, Error> {
+    metrics::count_call("fund_prize_pool");
+    maintenance::ensure_available("fund_prize_pool")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_author(&quiz)?;
+    if quiz.is_closed() {
+        return Err(Error::QuizClosed {
+            msg: format!("the quiz with id={} is closed and takes no prizes", quiz_id),
+        });
+    }
+    validate_expiry(quiz.ends_at, expires_at)?;
+    if amount == 0 {
+        return Err(Error::ValidationFailed {
+            field: "amount".to_string(),
+            msg: "a pool needs more than nothing".to_string(),
+        });
+    }
+    if let Some(pool) = pool_of(quiz_id) {
+        if pool.ledger != ledger || pool.funder != caller() {
+            return Err(Error::ValidationFailed {
+                field: "ledger".to_string(),
+                msg: format!(
+                    "the pool of the quiz with id={} is funded by {} with the token of {}",
+                    quiz_id, pool.funder, pool.ledger
+                ),
+            });
+        }
+    }
+
+    let funder = caller();
+    let created_at = time();
+    let memo = Sha256::new()
+        .chain_update(b"prize-pool")
+        .chain_update(quiz_id.to_be_bytes())
+        .chain_update(created_at.to_be_bytes())
+        .finalize()
+        .to_vec();
+    let in_flight = InFlight::start(quiz_id)?;
+    icrc::transfer_from(
+        ledger,
+        Account::of(funder),
+        escrow_account(quiz_id),
+        amount,
+        memo,
+        created_at,
+    )
+    .await?;
+    drop(in_flight);
+
+    // the tokens are in escrow now, so they are credited whatever happened to the quiz meanwhile
+    let pool = match pool_of(quiz_id) {
+        Some(mut pool) => {
+            pool.balance = pool.balance.saturating_add(amount);
+            pool.expires_at = pool.expires_at.max(expires_at);
+            pool
+        }
+        None => PrizePool {
+            quiz_id,
+            ledger,
+            funder,
+            balance: amount,
+            funded_at: created_at,
+            expires_at,
+            refund_amount: None,
+            refund_created_at: None,
+            refunded_at: None,
+            refund_block_index: None,
+            payout_rule: None,
+            settled_at: None,
+            reserved: None,
+        },
+    };
+    POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool.clone()));
+    Ok(with_status(pool))
+}
+
+// Sends what is left in an expired pool back to its funder, less the fee of the transfer. The
+// payouts still being made keep their share.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn refund_prize_pool(quiz_id: u64) -> Result<PrizePoolStatus, Error> {
+    metrics::count_call("refund_prize_pool");
+    maintenance::ensure_available("refund_prize_pool")?;
+    let pool = pool_of(quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("the quiz with id={} has no prize pool", quiz_id),
+    })?;
+    if pool.funder != caller() {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only {} can take back the pool of the quiz with id={}",
+                pool.funder, quiz_id
+            ),
+        });
+    }
+    if status_of(&pool) != PoolStatus::Expired {
+        return Err(Error::WrongPhase {
+            msg: format!(
+                "the pool of the quiz with id={} can be taken back once it expired and while \
+                 anything is left",
+                quiz_id
+            ),
+        });
+    }
+    let _in_flight = InFlight::start(quiz_id)?;
+    let fee = icrc::fee(pool.ledger).await?;
+
+    let mut pool = pool_of(quiz_id).unwrap();
+    let (amount, created_at) = match pool.refund_amount.zip(pool.refund_created_at) {
+        Some(refund) => refund,
+        None if available(&pool) > fee => (available(&pool) - fee, time()),
+        None => {
+            return Err(Error::ValidationFailed {
+                field: "quiz_id".to_string(),
+                msg: format!(
+                    "the pool has {} left, not more than the fee of {}",
+                    available(&pool),
+                    fee
+                ),
+            })
+        }
+    };
+    pool.refund_amount = Some(amount);
+    pool.refund_created_at = Some(created_at);
+    POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool.clone()));
+
+    let memo = Sha256::new()
+        .chain_update(b"prize-pool-refund")
+        .chain_update(quiz_id.to_be_bytes())
+        .chain_update(created_at.to_be_bytes())
+        .finalize()
+        .to_vec();
+    let block_index = icrc::transfer(
+        pool.ledger,
+        Some(escrow_subaccount(quiz_id)),
+        Account::of(pool.funder),
+        amount,
+        memo,
+        created_at,
+    )
+    .await?;
+
+    let mut pool = pool_of(quiz_id).unwrap();
+    pool.balance = pool.balance.saturating_sub(amount.saturating_add(fee));
+    pool.refund_amount = None;
+    pool.refund_created_at = None;
+    pool.refunded_at = Some(time());
+    pool.refund_block_index = Some(block_index);
+    POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool.clone()));
+    Ok(with_status(pool))
+}
+
+// anybody who can see the quiz sees its pool, the funder even once the quiz is gone
+#[ic_cdk::query]
+fn get_prize_pool(quiz_id: u64) -> Result<PrizePoolStatus, Error> {
+    let pool = pool_of(quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("the quiz with id={} has no prize pool", quiz_id),
+    })?;
+    if pool.funder != caller() {
+        let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+        allowlist::ensure_can_view(&quiz)?;
+    }
+    Ok(with_status(pool))
+}
+
+#[ic_cdk::query]
+fn list_my_prize_pools() -> Vec<PrizePoolStatus> {
+    POOLS.with(|pools| {
+        pools
+            .borrow()
+            .iter()
+            .map(|(_, pool)| pool)
+            .filter(|pool| pool.funder == caller())
+            .map(with_status)
+            .collect()
+    })
+}
+
+// The funder picks how the pool goes to the voters. The quiz needs an end time to close at, and
+// correct options unless the winner is drawn at random.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_payout_rule(quiz_id: u64, rule: Option<PayoutRule>) -> Result<PrizePoolStatus, Error> {
+    metrics::count_call("set_payout_rule");
+    maintenance::ensure_available("set_payout_rule")?;
+    let mut pool = pool_of(quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("the quiz with id={} has no prize pool", quiz_id),
+    })?;
+    if pool.funder != caller() {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only {} can decide on the pool of the quiz with id={}",
+                pool.funder, quiz_id
+            ),
+        });
+    }
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    if quiz.is_closed() {
+        return Err(Error::QuizClosed {
+            msg: format!(
+                "the quiz with id={} is closed, its winners are drawn",
+                quiz_id
+            ),
+        });
+    }
+    let msg = if rule.is_some() && quiz.ends_at.is_none() {
+        "the winners are drawn when the quiz closes, so it needs an end time"
+    } else if rule.is_some_and(|rule| rule != PayoutRule::RandomVoter)
+        && !scoring::is_scored(quiz_id)
+    {
+        "only a quiz with correct options has a top score or correct answers"
+    } else {
+        pool.payout_rule = rule;
+        POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool.clone()));
+        return Ok(with_status(pool));
+    };
+    Err(Error::ValidationFailed {
+        field: "rule".to_string(),
+        msg: msg.to_string(),
+    })
+}
+
+// The votes are final once the quiz closed, its votes are revealed and so are its correct
+// options.
+fn is_final(quiz: &Quiz) -> bool {
+    quiz.is_closed()
+        && (quiz.reveal_ends_at.is_none() || quiz.is_revealed())
+        && scoring::answer_commitment_of(quiz.id).is_none()
+}
+
+fn is_deleted(quiz_id: u64) -> bool {
+    _get_quiz(&quiz_id).is_none_or(|quiz| quiz.deleted_at.is_some())
+}
+
+// nobody wins on a deleted quiz, the funder can take the pool back right away
+fn return_to_funder(quiz_id: u64, mut pool: PrizePool) {
+    pool.expires_at = pool.expires_at.min(time());
+    POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool));
+}
+
+// Called whenever a quiz might have become final, see `is_final`. Drawing the winners may need
+// randomness, so it happens in a timer of its own.
+pub fn quiz_closed(quiz_id: u64) {
+    let Some(pool) = pool_of(quiz_id).filter(|pool| pool.settled_at.is_none()) else {
+        return;
+    };
+    if is_deleted(quiz_id) {
+        return_to_funder(quiz_id, pool);
+    } else if pool.payout_rule.is_some() {
+        ic_cdk_timers::set_timer(Duration::ZERO, move || ic_cdk::spawn(settle(quiz_id)));
+    }
+}
+
+async fn winners_of(quiz_id: u64, rule: PayoutRule) -> Result<Vec<Principal>, Error> {
+    let results = scoring::results_of(quiz_id);
+    let winners = match rule {
+        PayoutRule::TopScore => {
+            let best = results.iter().map(|(_, net, _)| *net).max();
+            results
+                .iter()
+                .filter(|(_, net, _)| Some(*net) == best && *net > 0)
+                .map(|(principal, _, _)| *principal)
+                .collect()
+        }
+        PayoutRule::SplitAmongCorrect => results
+            .iter()
+            .filter(|(_, _, correct)| *correct)
+            .map(|(principal, _, _)| *principal)
+            .collect(),
+        PayoutRule::RandomVoter => {
+            let voters: Vec<Principal> = VOTES.with(|votes| {
+                votes
+                    .borrow()
+                    .range(id_key_range(quiz_id))
+                    .map(|((_, voter), _)| voter.0)
+                    .collect()
+            });
+            if voters.is_empty() {
+                return Ok(Vec::new());
+            }
+            let (random,) = raw_rand().await.map_err(|(code, msg)| Error::CallFailed {
+                msg: format!("raw_rand failed ({:?}): {}", code, msg),
+            })?;
+            let draw = random
+                .iter()
+                .take(8)
+                .fold(0u64, |draw, byte| draw << 8 | *byte as u64);
+            vec![voters[(draw % voters.len() as u64) as usize]]
+        }
+    };
+    // an anonymous vote has nobody to pay
+    Ok(winners
+        .into_iter()
+        .filter(|winner| *winner != Principal::anonymous())
+        .collect())
+}
+
+// Draws the winners of a final quiz and splits what is left in its pool evenly between them,
+// every payout paying its own fee. The remainder stays for the funder to take back.
+async fn settle(quiz_id: u64) {
+    let Some(pool) = pool_of(quiz_id).filter(|pool| pool.settled_at.is_none()) else {
+        return;
+    };
+    let Some(rule) = pool.payout_rule else {
+        return;
+    };
+    if is_deleted(quiz_id) {
+        return_to_funder(quiz_id, pool);
+        return;
+    }
+    if !_get_quiz(&quiz_id).is_some_and(|quiz| is_final(&quiz)) {
+        return;
+    }
+    let drawn = match icrc::fee(pool.ledger).await {
+        Ok(fee) => winners_of(quiz_id, rule)
+            .await
+            .map(|winners| (fee, winners)),
+        Err(error) => Err(error),
+    };
+    let Ok((fee, winners)) = drawn else {
+        ic_cdk_timers::set_timer(Duration::from_nanos(FIRST_RETRY_NS), move || {
+            ic_cdk::spawn(settle(quiz_id))
+        });
+        return;
+    };
+
+    // a concurrent settle may have won the race while this one awaited
+    let Some(mut pool) = pool_of(quiz_id).filter(|pool| pool.settled_at.is_none()) else {
+        return;
+    };
+    // and the quiz may have been deleted
+    if is_deleted(quiz_id) {
+        return_to_funder(quiz_id, pool);
+        return;
+    }
+    let share = match winners.len() as u128 {
+        0 => 0,
+        count => (available(&pool) / count).saturating_sub(fee),
+    };
+    if share > 0 {
+        for (position, principal) in (0..).zip(winners) {
+            let payout = Payout {
+                quiz_id,
+                principal,
+                amount: share,
+                fee,
+                created_at: time(),
+                status: PayoutStatus::Pending,
+                attempts: 0,
+                next_attempt_at: Some(time()),
+                last_error: None,
+                block_index: None,
+                paid_at: None,
+            };
+            PAYOUTS.with(|payouts| payouts.borrow_mut().insert((quiz_id, position), payout));
+            pool.reserved = Some(pool.reserved.unwrap_or_default() + share + fee);
+        }
+    }
+    pool.settled_at = Some(time());
+    POOLS.with(|pools| pools.borrow_mut().insert(quiz_id, pool));
+    schedule_payouts(Duration::ZERO);
+}
+
+fn schedule_payouts(delay: Duration) {
+    ic_cdk_timers::set_timer(delay, || ic_cdk::spawn(process_payouts()));
+}
+
+fn payout_memo(key: (u64, u64)) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(b"prize-pool-payout")
+        .chain_update(key.0.to_be_bytes())
+        .chain_update(key.1.to_be_bytes())
+        .finalize()
+        .to_vec()
+}
+
+// Sends every payout that is due. A failed one waits twice as long as before for its next
+// attempt, after MAX_PAYOUT_ATTEMPTS its share goes back to the pool.
+async fn process_payouts() {
+    if PAYING.with(|paying| paying.replace(true)) {
+        return;
+    }
+    let due: Vec<((u64, u64), Payout)> = PAYOUTS.with(|payouts| {
+        payouts
+            .borrow()
+            .iter()
+            .filter(|(_, payout)| payout.next_attempt_at.is_some_and(|at| at <= time()))
+            .collect()
+    });
+    for (key, payout) in due {
+        let Some(pool) = pool_of(key.0) else {
+            continue;
+        };
+        let result = icrc::transfer(
+            pool.ledger,
+            Some(escrow_subaccount(key.0)),
+            Account::of(payout.principal),
+            payout.amount,
+            payout_memo(key),
+            payout.created_at,
+        )
+        .await;
+
+        let mut payout = payout;
+        let mut pool = pool_of(key.0).unwrap();
+        let cost = payout.amount + payout.fee;
+        payout.attempts += 1;
+        match result {
+            Ok(block_index) => {
+                payout.status = PayoutStatus::Paid;
+                payout.next_attempt_at = None;
+                payout.block_index = Some(block_index);
+                payout.paid_at = Some(time());
+                pool.balance = pool.balance.saturating_sub(cost);
+                pool.reserved = pool.reserved.map(|reserved| reserved.saturating_sub(cost));
+            }
+            Err(error) => {
+                let mut msg = match error {
+                    Error::CallFailed { msg } => msg,
+                    _ => "the transfer failed".to_string(),
+                };
+                if msg.len() > MAX_ERROR_LEN {
+                    let mut end = MAX_ERROR_LEN;
+                    while !msg.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    msg.truncate(end);
+                }
+                payout.last_error = Some(msg);
+                if payout.attempts >= MAX_PAYOUT_ATTEMPTS {
+                    payout.status = PayoutStatus::Failed;
+                    payout.next_attempt_at = None;
+                    pool.reserved = pool.reserved.map(|reserved| reserved.saturating_sub(cost));
+                } else {
+                    let backoff = FIRST_RETRY_NS << (payout.attempts - 1);
+                    payout.next_attempt_at = Some(time().saturating_add(backoff));
+                }
+            }
+        }
+        PAYOUTS.with(|payouts| payouts.borrow_mut().insert(key, payout));
+        POOLS.with(|pools| pools.borrow_mut().insert(key.0, pool));
+    }
+    PAYING.with(|paying| paying.replace(false));
+    resume_payouts();
+}
+
+// Timers don't survive upgrades: the payouts still due and the pools of quizzes that became
+// final in the meantime are picked up again, see `post_upgrade`.
+pub fn resume_payouts() {
+    let next = PAYOUTS.with(|payouts| {
+        payouts
+            .borrow()
+            .iter()
+            .filter_map(|(_, payout)| payout.next_attempt_at)
+            .min()
+    });
+    if let Some(next) = next {
+        schedule_payouts(Duration::from_nanos(next.saturating_sub(time())));
+    }
+}
+
+pub fn resume_settlements() {
+    let unsettled: Vec<u64> = POOLS.with(|pools| {
+        pools
+            .borrow()
+            .iter()
+            .filter(|(_, pool)| pool.payout_rule.is_some() && pool.settled_at.is_none())
+            .map(|(quiz_id, _)| quiz_id)
+            .collect()
+    });
+    for quiz_id in unsettled {
+        quiz_closed(quiz_id);
+    }
+}
+
+// the payout log of a quiz, visible to whoever can see its pool
+#[ic_cdk::query]
+fn get_payouts(quiz_id: u64) -> Result<Vec<Payout>, Error> {
+    get_prize_pool(quiz_id)?;
+    Ok(PAYOUTS.with(|payouts| {
+        payouts
+            .borrow()
+            .range((quiz_id, 0)..(quiz_id + 1, 0))
+            .map(|(_, payout)| payout)
+            .collect()
+    }))
+}
@@ -0,0 +1,164 @@
+use crate::guards::only_authenticated;
+use crate::{maintenance, metrics, moderation, Error, Memory, StorablePrincipal, MEMORY_MANAGER};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MIN_NAME_LEN: usize = 3;
+const MAX_NAME_LEN: usize = 32;
+const MAX_BIO_LEN: usize = 280;
+const MAX_AVATAR_URL_LEN: usize = 256;
+
+// display names are unique regardless of case
+type NameKey = Blob<MAX_NAME_LEN>;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    principal: Principal,
+    display_name: String,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+    created_at: u64,
+}
+
+impl Storable for Profile {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Profile {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct ProfilePayload {
+    display_name: String,
+    bio: Option<String>,
+    avatar_url: Option<String>,
+}
+
+thread_local! {
+    static PROFILES: RefCell<StableBTreeMap<StorablePrincipal, Profile, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(54)))
+    ));
+
+    // lowercase display names to the principal holding them
+    static NAMES: RefCell<StableBTreeMap<NameKey, StorablePrincipal, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(55)))
+    ));
+}
+
+fn name_key(display_name: &str) -> NameKey {
+    NameKey::try_from(display_name.to_lowercase().as_bytes()).unwrap()
+}
+
+fn validate_profile(payload: &ProfilePayload) -> Result<(), Error> {
+    let name = payload.display_name.trim();
+    // the lowercase form has to fit into a key as well, it can be longer for some letters
+    if name.len() < MIN_NAME_LEN
+        || name.len() > MAX_NAME_LEN
+        || name.to_lowercase().len() > MAX_NAME_LEN
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-')
+    {
+        return Err(Error::ValidationFailed {
+            field: "display_name".to_string(),
+            msg: format!(
+                "the display name must be {} to {} letters, digits, spaces, dashes or underscores",
+                MIN_NAME_LEN, MAX_NAME_LEN
+            ),
+        });
+    }
+    moderation::ensure_clean("display_name", name)?;
+    if let Some(bio) = &payload.bio {
+        if bio.len() > MAX_BIO_LEN {
+            return Err(Error::ValidationFailed {
+                field: "bio".to_string(),
+                msg: format!("the bio must not exceed {} bytes", MAX_BIO_LEN),
+            });
+        }
+        moderation::ensure_clean("bio", bio)?;
+    }
+    if let Some(avatar_url) = &payload.avatar_url {
+        if !avatar_url.starts_with("https://") || avatar_url.len() > MAX_AVATAR_URL_LEN {
+            return Err(Error::ValidationFailed {
+                field: "avatar_url".to_string(),
+                msg: format!(
+                    "the avatar must be an https url of at most {} bytes",
+                    MAX_AVATAR_URL_LEN
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Creates or replaces the caller's profile. A display name belongs to whoever took it first, the
+// previous one of the caller is free again afterwards.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_profile(payload: ProfilePayload) -> Result<Profile, Error> {
+    metrics::count_call("set_profile");
+    maintenance::ensure_available("set_profile")?;
+    validate_profile(&payload)?;
+    let owner = StorablePrincipal(caller());
+    let display_name = payload.display_name.trim().to_string();
+    let key = name_key(&display_name);
+    if NAMES
+        .with(|names| names.borrow().get(&key))
+        .is_some_and(|holder| holder != owner)
+    {
+        return Err(Error::ValidationFailed {
+            field: "display_name".to_string(),
+            msg: format!("the display name '{}' is taken", display_name),
+        });
+    }
+
+    let previous = PROFILES.with(|profiles| profiles.borrow().get(&owner));
+    let profile = Profile {
+        principal: caller(),
+        display_name,
+        bio: payload.bio,
+        avatar_url: payload.avatar_url,
+        created_at: previous
+            .as_ref()
+            .map_or_else(time, |previous| previous.created_at),
+    };
+    NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+        if let Some(previous) = &previous {
+            names.remove(&name_key(&previous.display_name));
+        }
+        names.insert(key, owner);
+    });
+    PROFILES.with(|profiles| profiles.borrow_mut().insert(owner, profile.clone()));
+    Ok(profile)
+}
+
+// the principal holding the display name, in any case.
+pub fn principal_of(display_name: &str) -> Option<Principal> {
+    let key = NameKey::try_from(display_name.to_lowercase().as_bytes()).ok()?;
+    NAMES
+        .with(|names| names.borrow().get(&key))
+        .map(|holder| holder.0)
+}
+
+#[ic_cdk::query]
+fn get_profile(principal: Principal) -> Result<Profile, Error> {
+    PROFILES
+        .with(|profiles| profiles.borrow().get(&StorablePrincipal(principal)))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("{} has no profile", principal),
+        })
+}
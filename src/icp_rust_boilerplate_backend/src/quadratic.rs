@@ -0,0 +1,185 @@
+use crate::guards::not_banned;
+use crate::{
+    _get_quiz, after_vote, audit, ensure_open, maintenance, metrics, migrations, ratelimit,
+    record_vote, remove_quiz_entries, tallies, Error, Memory, Quiz, StorablePrincipal, VotingMode,
+    MEMORY_MANAGER,
+};
+use candid::{Decode, Encode};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+pub const MAX_CREDITS_PER_VOTER: u32 = 10_000;
+
+// what a principal has spent on a quadratic quiz so far, casting v votes on an option costs v²
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+pub struct CreditLedger {
+    // votes per option, in the order they were first allocated
+    votes: Vec<(String, u32)>,
+    credits_spent: u32,
+    credits_left: u32,
+    updated_at: u64,
+}
+
+impl Storable for CreditLedger {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for CreditLedger {
+    // holds at most every option of the quiz
+    const MAX_SIZE: u32 = Quiz::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static CREDIT_LEDGERS: RefCell<StableBTreeMap<(u64, StorablePrincipal), CreditLedger, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30)))
+    ));
+}
+
+pub fn validate_credits(
+    voting_mode: &VotingMode,
+    credits_per_voter: Option<u32>,
+) -> Result<(), Error> {
+    match (voting_mode, credits_per_voter) {
+        (VotingMode::Quadratic, Some(credits))
+            if (1..=MAX_CREDITS_PER_VOTER).contains(&credits) =>
+        {
+            Ok(())
+        }
+        (VotingMode::Quadratic, _) => Err(Error::ValidationFailed {
+            field: "credits_per_voter".to_string(),
+            msg: format!(
+                "a quadratic quiz needs between 1 and {} credits per voter",
+                MAX_CREDITS_PER_VOTER
+            ),
+        }),
+        (_, Some(_)) => Err(Error::ValidationFailed {
+            field: "credits_per_voter".to_string(),
+            msg: "only quadratic quizzes hand out credits".to_string(),
+        }),
+        (_, None) => Ok(()),
+    }
+}
+
+pub fn move_legacy_credit_ledgers(legacy_memory_id: u8) {
+    CREDIT_LEDGERS
+        .with(|ledgers| migrations::move_legacy_map(legacy_memory_id, &mut ledgers.borrow_mut()));
+}
+
+pub fn remove_credit_ledgers(quiz_id: u64) {
+    CREDIT_LEDGERS.with(|ledgers| remove_quiz_entries(&mut ledgers.borrow_mut(), quiz_id));
+}
+
+fn new_ledger(quiz: &Quiz) -> CreditLedger {
+    CreditLedger {
+        credits_left: quiz.credits_per_voter.unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+// Adds votes on top of what the caller already allocated. Calls can be repeated until the
+// credits run out, each one is applied completely or not at all.
+#[ic_cdk::update(guard = "not_banned")]
+fn cast_quadratic_votes(id: u64, votes: Vec<(String, u32)>) -> Result<CreditLedger, Error> {
+    metrics::count_call("cast_quadratic_votes");
+    maintenance::ensure_available("cast_quadratic_votes")?;
+    ratelimit::check(ratelimit::Action::Vote)?;
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!(
+            "couldn't cast votes on a quiz with id={}. quiz not found",
+            id
+        ),
+    })?;
+    if quiz.voting_mode() != VotingMode::Quadratic {
+        return Err(Error::ValidationFailed {
+            field: "voting_mode".to_string(),
+            msg: format!("the quiz with id={} is not a quadratic quiz", id),
+        });
+    }
+    ensure_open(&quiz)?;
+    if votes.is_empty() {
+        return Err(Error::ValidationFailed {
+            field: "votes".to_string(),
+            msg: "at least one option has to receive votes".to_string(),
+        });
+    }
+
+    let ledger_key = (id, StorablePrincipal(caller()));
+    let mut ledger = CREDIT_LEDGERS
+        .with(|ledgers| ledgers.borrow().get(&ledger_key))
+        .unwrap_or_else(|| new_ledger(&quiz));
+
+    for (option, count) in &votes {
+        if !quiz.options.contains(option) {
+            return Err(Error::ValidationFailed {
+                field: "votes".to_string(),
+                msg: format!("The option '{}' is not found for this quiz.", option),
+            });
+        }
+        match ledger
+            .votes
+            .iter_mut()
+            .find(|(allocated, _)| allocated == option)
+        {
+            Some((_, allocated)) => *allocated = allocated.saturating_add(*count),
+            None => ledger.votes.push((option.clone(), *count)),
+        }
+    }
+
+    let credits = quiz.credits_per_voter.unwrap_or_default() as u64;
+    let cost: u64 = ledger
+        .votes
+        .iter()
+        .map(|(_, count)| (*count as u64).pow(2))
+        .sum();
+    if cost > credits {
+        return Err(Error::ValidationFailed {
+            field: "votes".to_string(),
+            msg: format!(
+                "these votes cost {} credits in total but only {} are available",
+                cost, credits
+            ),
+        });
+    }
+    ledger.credits_spent = cost as u32;
+    ledger.credits_left = (credits - cost) as u32;
+    ledger.updated_at = time();
+
+    for (option, count) in &votes {
+        tallies::add(&quiz, option, *count as u64);
+    }
+
+    // the vote record points at the option currently holding most of the caller's votes
+    if let Some((top_choice, _)) = ledger.votes.iter().max_by_key(|(_, count)| *count) {
+        record_vote(ledger_key, top_choice.clone(), None);
+    }
+    CREDIT_LEDGERS.with(|ledgers| ledgers.borrow_mut().insert(ledger_key, ledger.clone()));
+    audit::record_vote(id, &Encode!(&id, &votes).unwrap(), votes, None);
+    after_vote(&quiz);
+    Ok(ledger)
+}
+
+#[ic_cdk::query]
+fn get_my_credits(id: u64) -> Result<CreditLedger, Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    if quiz.voting_mode() != VotingMode::Quadratic {
+        return Err(Error::ValidationFailed {
+            field: "voting_mode".to_string(),
+            msg: format!("the quiz with id={} is not a quadratic quiz", id),
+        });
+    }
+    Ok(CREDIT_LEDGERS
+        .with(|ledgers| ledgers.borrow().get(&(id, StorablePrincipal(caller()))))
+        .unwrap_or_else(|| new_ledger(&quiz)))
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+// the first preferences still counted for every remaining option in one round of the runoff
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+pub struct RunoffRound {
+    pub tallies: Vec<(String, u64)>,
+    pub eliminated: Vec<String>,
+}
+
+// Instant-runoff tally: every round counts each ballot for its highest ranked option that is
+// still in the race, then drops the option(s) with the fewest votes until one option holds a
+// strict majority of the ballots that haven't been exhausted.
+// Returns every round that was played and the winner, if there is one.
+pub fn instant_runoff(
+    options: &[String],
+    ballots: &[Vec<String>],
+) -> (Vec<RunoffRound>, Option<String>) {
+    let mut remaining: Vec<String> = options.to_vec();
+    let mut rounds = Vec::new();
+
+    while !remaining.is_empty() {
+        let mut counts: HashMap<&str, u64> = remaining
+            .iter()
+            .map(|option| (option.as_str(), 0))
+            .collect();
+        for ballot in ballots {
+            if let Some(choice) = ballot
+                .iter()
+                .find(|choice| counts.contains_key(choice.as_str()))
+            {
+                *counts.get_mut(choice.as_str()).unwrap() += 1;
+            }
+        }
+
+        let tallies: Vec<(String, u64)> = remaining
+            .iter()
+            .map(|option| (option.clone(), counts[option.as_str()]))
+            .collect();
+        let total: u64 = tallies.iter().map(|(_, count)| count).sum();
+
+        if total == 0 {
+            rounds.push(RunoffRound {
+                tallies,
+                eliminated: Vec::new(),
+            });
+            return (rounds, None);
+        }
+
+        if let Some((leader, _)) = tallies.iter().find(|(_, count)| count * 2 > total) {
+            let winner = leader.clone();
+            rounds.push(RunoffRound {
+                tallies,
+                eliminated: Vec::new(),
+            });
+            return (rounds, Some(winner));
+        }
+
+        let fewest = tallies
+            .iter()
+            .map(|(_, count)| *count)
+            .min()
+            .unwrap_or_default();
+        let eliminated: Vec<String> = tallies
+            .iter()
+            .filter(|(_, count)| *count == fewest)
+            .map(|(option, _)| option.clone())
+            .collect();
+
+        // every remaining option is tied, there is no way to break the tie
+        if eliminated.len() == remaining.len() {
+            rounds.push(RunoffRound {
+                tallies,
+                eliminated: Vec::new(),
+            });
+            return (rounds, None);
+        }
+
+        remaining.retain(|option| !eliminated.contains(option));
+        rounds.push(RunoffRound {
+            tallies,
+            eliminated,
+        });
+    }
+
+    (rounds, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::instant_runoff;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn ballots(ballots: &[&[&str]]) -> Vec<Vec<String>> {
+        ballots.iter().map(|ballot| strings(ballot)).collect()
+    }
+
+    #[test]
+    fn a_first_round_majority_wins_right_away() {
+        let (rounds, winner) = instant_runoff(
+            &strings(&["A", "B", "C"]),
+            &ballots(&[&["A"], &["A"], &["B"]]),
+        );
+        assert_eq!(winner.as_deref(), Some("A"));
+        assert_eq!(rounds.len(), 1);
+        assert_eq!(
+            rounds[0].tallies,
+            vec![
+                ("A".to_string(), 2),
+                ("B".to_string(), 1),
+                ("C".to_string(), 0)
+            ]
+        );
+        assert!(rounds[0].eliminated.is_empty());
+    }
+
+    #[test]
+    fn ballots_of_an_eliminated_option_move_on() {
+        let (rounds, winner) = instant_runoff(
+            &strings(&["A", "B", "C"]),
+            &ballots(&[&["A", "B"], &["B"], &["B"], &["C", "A"], &["A"]]),
+        );
+        assert_eq!(winner.as_deref(), Some("A"));
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].eliminated, strings(&["C"]));
+        assert_eq!(
+            rounds[1].tallies,
+            vec![("A".to_string(), 3), ("B".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn exhausted_ballots_dont_count_towards_the_majority() {
+        let (rounds, winner) = instant_runoff(
+            &strings(&["A", "B", "C", "D"]),
+            &ballots(&[&["A"], &["A"], &["A"], &["B"], &["B"], &["C"], &["D"]]),
+        );
+        // the options tied for the fewest votes go out together
+        assert_eq!(rounds[0].eliminated, strings(&["C", "D"]));
+        // three of the five ballots left are a majority, seven were cast
+        assert_eq!(winner.as_deref(), Some("A"));
+        assert_eq!(
+            rounds[1].tallies,
+            vec![("A".to_string(), 3), ("B".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn a_tie_between_every_remaining_option_has_no_winner() {
+        let (rounds, winner) = instant_runoff(
+            &strings(&["A", "B", "C"]),
+            &ballots(&[&["A"], &["A"], &["B"], &["B"], &["C"]]),
+        );
+        assert_eq!(winner, None);
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].eliminated, strings(&["C"]));
+        assert!(rounds[1].eliminated.is_empty());
+    }
+
+    #[test]
+    fn unknown_options_on_a_ballot_are_skipped() {
+        let (_, winner) = instant_runoff(
+            &strings(&["A", "B"]),
+            &ballots(&[&["X", "B"], &["B"], &["A"]]),
+        );
+        assert_eq!(winner.as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn no_ballots_play_a_single_empty_round() {
+        let (rounds, winner) = instant_runoff(&strings(&["A", "B"]), &[]);
+        assert_eq!(winner, None);
+        assert_eq!(rounds.len(), 1);
+        assert!(rounds[0].tallies.iter().all(|(_, count)| *count == 0));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::guards::only_admin;
+use crate::roles::{self, Role};
+use crate::{governance, maintenance, metrics, Error, Memory, StorablePrincipal, MEMORY_MANAGER};
+use candid::{Decode, Encode};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{Cell, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MINUTE_NS: u64 = 60 * 1_000_000_000;
+// a longer window would keep more calls of every principal around
+const MAX_WINDOW_NS: u64 = 24 * 60 * MINUTE_NS;
+
+// the update calls that are limited, each one with a window of its own
+#[derive(Clone, Copy)]
+pub enum Action {
+    Create,
+    Vote,
+}
+
+// at most `max_calls` calls in any `window_ns` long stretch of time
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimit {
+    max_calls: u32,
+    window_ns: u64,
+}
+
+// a limit that is left out isn't enforced
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct RateLimits {
+    create: Option<RateLimit>,
+    vote: Option<RateLimit>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            create: Some(RateLimit {
+                max_calls: 20,
+                window_ns: 60 * MINUTE_NS,
+            }),
+            vote: Some(RateLimit {
+                max_calls: 60,
+                window_ns: MINUTE_NS,
+            }),
+        }
+    }
+}
+
+impl Storable for RateLimits {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+type CallLog = RefCell<StableBTreeMap<(StorablePrincipal, u64), u32, Memory>>;
+
+thread_local! {
+    // calls by (caller, time), messages of the same round share the time so it's a count
+    static CREATE_CALLS: CallLog = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(36)))
+    ));
+    static VOTE_CALLS: CallLog = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(37)))
+    ));
+
+    static LIMITS: RefCell<Cell<RateLimits, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(38))),
+            RateLimits::default(),
+        )
+        .expect("Cannot create the rate limits cell")
+    );
+}
+
+fn with_log<R>(action: Action, f: impl FnOnce(&CallLog) -> R) -> R {
+    match action {
+        Action::Create => CREATE_CALLS.with(f),
+        Action::Vote => VOTE_CALLS.with(f),
+    }
+}
+
+pub fn rate_limits() -> RateLimits {
+    LIMITS.with(|limits| limits.borrow().get().clone())
+}
+
+fn limit_of(action: Action) -> Option<RateLimit> {
+    let limits = rate_limits();
+    match action {
+        Action::Create => limits.create,
+        Action::Vote => limits.vote,
+    }
+}
+
+// Counts a call of the caller, or rejects it when the window is full. Everybody who isn't signed
+// in shares the anonymous principal and with it one window.
+pub fn check(action: Action) -> Result<(), Error> {
+    use_window(action, 1, true)
+}
+
+// rejects when `calls` more calls wouldn't fit into the window, without counting any.
+pub fn ensure_room(action: Action, calls: u32) -> Result<(), Error> {
+    use_window(action, calls, false)
+}
+
+fn use_window(action: Action, calls: u32, record: bool) -> Result<(), Error> {
+    let Some(limit) = limit_of(action) else {
+        return Ok(());
+    };
+    // admins set the limits and import quizzes in bulk, they aren't held to them
+    if roles::has_role(caller(), Role::Admin) {
+        return Ok(());
+    }
+    let sender = StorablePrincipal(caller());
+    let now = time();
+    let window_start = now.saturating_sub(limit.window_ns);
+    with_log(action, |log| {
+        let mut log = log.borrow_mut();
+        // the calls that slid out of the window aren't needed anymore
+        let expired: Vec<u64> = log
+            .range((sender, 0)..(sender, window_start))
+            .map(|((_, at), _)| at)
+            .collect();
+        for at in expired {
+            log.remove(&(sender, at));
+        }
+
+        let recent: Vec<(u64, u32)> = log
+            .range((sender, window_start)..)
+            .take_while(|((principal, _), _)| *principal == sender)
+            .map(|((_, at), count)| (at, count))
+            .collect();
+        let mut used: u64 = recent.iter().map(|(_, count)| *count as u64).sum();
+        let allowed = limit.max_calls as u64;
+        if used + calls as u64 > allowed {
+            // the oldest calls have to leave the window until the new ones fit
+            let retry_at = recent
+                .iter()
+                .find(|(_, count)| {
+                    used -= *count as u64;
+                    used + calls as u64 <= allowed
+                })
+                .map(|(at, _)| at + limit.window_ns)
+                .unwrap_or(now + limit.window_ns);
+            return Err(Error::RateLimited {
+                retry_after_ns: retry_at.saturating_sub(now),
+            });
+        }
+        if record {
+            let count = log.get(&(sender, now)).unwrap_or_default();
+            log.insert((sender, now), count + calls);
+        }
+        Ok(())
+    })
+}
+
+fn validate_limit(field: &str, limit: &Option<RateLimit>) -> Result<(), Error> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    if limit.max_calls == 0 {
+        return Err(Error::ValidationFailed {
+            field: field.to_string(),
+            msg: "at least one call has to be allowed, leave the limit out instead".to_string(),
+        });
+    }
+    if limit.window_ns == 0 || limit.window_ns > MAX_WINDOW_NS {
+        return Err(Error::ValidationFailed {
+            field: field.to_string(),
+            msg: format!("the window has to be between 1 and {} ns", MAX_WINDOW_NS),
+        });
+    }
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_rate_limits() -> RateLimits {
+    rate_limits()
+}
+
+// The new limits apply to the calls made so far as well, a shorter window forgets older ones.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_rate_limits(limits: RateLimits) -> Result<RateLimits, Error> {
+    metrics::count_call("set_rate_limits");
+    maintenance::ensure_available("set_rate_limits")?;
+    governance::ensure_ungoverned("rate limits")?;
+    apply_rate_limits(limits)
+}
+
+pub fn apply_rate_limits(limits: RateLimits) -> Result<RateLimits, Error> {
+    validate_limit("create", &limits.create)?;
+    validate_limit("vote", &limits.vote)?;
+    LIMITS
+        .with(|cell| cell.borrow_mut().set(limits.clone()))
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the rate limits".to_string(),
+        })?;
+    Ok(limits)
+}
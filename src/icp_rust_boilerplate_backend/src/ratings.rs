@@ -0,0 +1,163 @@
+use crate::{
+    _get_quiz, allowlist, discovery, scoring, Error, Memory, Quiz, StorablePrincipal,
+    MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// where every question and every participant starts
+const INITIAL_RATING: f64 = 1200.0;
+// how far a single answer moves both ratings at most
+const K_FACTOR: f64 = 32.0;
+
+// An Elo rating. A participant answering a question correctly counts as winning against it, so
+// hard questions and strong participants end up high.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Rating {
+    rating: f64,
+    answers: u64,
+    correct: u64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: INITIAL_RATING,
+            answers: 0,
+            correct: 0,
+        }
+    }
+}
+
+impl Storable for Rating {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Rating {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct QuestionRating {
+    quiz_id: u64,
+    rating: f64,
+    answers: u64,
+    // share of the answers that were correct, 0 before the first one
+    correct_share: f64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct PlayerRating {
+    principal: Principal,
+    rating: f64,
+    answers: u64,
+    correct: u64,
+}
+
+thread_local! {
+    static QUESTION_RATINGS: RefCell<StableBTreeMap<u64, Rating, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(91)))
+    ));
+
+    static PLAYER_RATINGS: RefCell<StableBTreeMap<StorablePrincipal, Rating, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(92)))
+    ));
+}
+
+fn question_rating(quiz_id: u64) -> Rating {
+    QUESTION_RATINGS
+        .with(|ratings| ratings.borrow().get(&quiz_id))
+        .unwrap_or_default()
+}
+
+fn player_rating(principal: Principal) -> Rating {
+    PLAYER_RATINGS
+        .with(|ratings| ratings.borrow().get(&StorablePrincipal(principal)))
+        .unwrap_or_default()
+}
+
+// Moves both ratings after a scored answer. Discarded votes leave the ratings as they are, the
+// answers after them were rated against the moved ratings already.
+pub fn record_answer(quiz_id: u64, principal: Principal, correct: bool) {
+    let mut question = question_rating(quiz_id);
+    let mut player = player_rating(principal);
+    let expected = 1.0 / (1.0 + 10f64.powf((question.rating - player.rating) / 400.0));
+    let outcome = if correct { 1.0 } else { 0.0 };
+    let change = K_FACTOR * (outcome - expected);
+    player.rating += change;
+    question.rating -= change;
+    for rating in [&mut player, &mut question] {
+        rating.answers += 1;
+        rating.correct += correct as u64;
+    }
+    QUESTION_RATINGS.with(|ratings| ratings.borrow_mut().insert(quiz_id, question));
+    PLAYER_RATINGS.with(|ratings| {
+        ratings
+            .borrow_mut()
+            .insert(StorablePrincipal(principal), player)
+    });
+}
+
+// the rating goes together with the quiz, see `remove_quiz`.
+pub fn remove_rating(quiz_id: u64) {
+    QUESTION_RATINGS.with(|ratings| ratings.borrow_mut().remove(&quiz_id));
+}
+
+#[ic_cdk::query]
+fn get_question_rating(quiz_id: u64) -> Result<QuestionRating, Error> {
+    let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", quiz_id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    let rating = question_rating(quiz_id);
+    Ok(QuestionRating {
+        quiz_id,
+        rating: rating.rating,
+        answers: rating.answers,
+        correct_share: if rating.answers == 0 {
+            0.0
+        } else {
+            rating.correct as f64 / rating.answers as f64
+        },
+    })
+}
+
+#[ic_cdk::query]
+fn get_my_rating() -> PlayerRating {
+    let rating = player_rating(caller());
+    PlayerRating {
+        principal: caller(),
+        rating: rating.rating,
+        answers: rating.answers,
+        correct: rating.correct,
+    }
+}
+
+// the open scored quiz the caller hasn't answered whose rating is closest to their own.
+#[ic_cdk::query]
+fn get_recommended_quiz() -> Result<Quiz, Error> {
+    let skill = player_rating(caller()).rating;
+    discovery::unanswered_quizzes(StorablePrincipal(caller()))
+        .into_iter()
+        .filter(|id| scoring::is_scored(*id))
+        .min_by(|a, b| {
+            let distance = |id: &u64| (question_rating(*id).rating - skill).abs();
+            distance(a).total_cmp(&distance(b))
+        })
+        .and_then(|id| _get_quiz(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: "there is no open scored quiz left you haven't answered".to_string(),
+        })
+}
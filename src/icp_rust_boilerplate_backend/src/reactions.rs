@@ -0,0 +1,203 @@
+use crate::guards::only_authenticated;
+use crate::settings::{self, Feature};
+use crate::{
+    _get_quiz, allowlist, id_key_range, maintenance, metrics, Error, Memory, Quiz,
+    StorablePrincipal, MAX_PAGE_SIZE, MEMORY_MANAGER, STORAGE,
+};
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+
+#[derive(candid::CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Reaction {
+    Like,
+    Love,
+    Laugh,
+    Insightful,
+    Confused,
+}
+
+// stored as its position in here, so new reactions only go at the end
+const REACTIONS_IN_ORDER: [Reaction; 5] = [
+    Reaction::Like,
+    Reaction::Love,
+    Reaction::Laugh,
+    Reaction::Insightful,
+    Reaction::Confused,
+];
+
+fn code_of(reaction: Reaction) -> u8 {
+    REACTIONS_IN_ORDER
+        .iter()
+        .position(|candidate| *candidate == reaction)
+        .unwrap() as u8
+}
+
+fn reaction_of(code: u8) -> Option<Reaction> {
+    REACTIONS_IN_ORDER.get(code as usize).copied()
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct ReactionCount {
+    reaction: Reaction,
+    count: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct QuizReactions {
+    quiz_id: u64,
+    // only the reactions somebody picked
+    counts: Vec<ReactionCount>,
+    // the caller's own reaction
+    mine: Option<Reaction>,
+}
+
+// (likes, id) reversed so that iterating yields the most liked quiz first
+type LikeKey = (Reverse<u64>, Reverse<u64>);
+
+thread_local! {
+    // the one reaction of every principal per quiz
+    static REACTIONS: RefCell<StableBTreeMap<(u64, StorablePrincipal), u8, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(62)))
+    ));
+
+    // reactions per (quiz, reaction), kept apart so a reaction doesn't rewrite the quiz
+    static COUNTS: RefCell<StableBTreeMap<(u64, u8), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(63)))
+    ));
+
+    static LIKE_RANKING: RefCell<StableBTreeMap<LikeKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(64)))
+    ));
+}
+
+// adds one reaction, or takes one back with a `delta` of -1, and keeps the like ranking in order.
+fn add_count(quiz_id: u64, code: u8, delta: i64) {
+    let previous = COUNTS
+        .with(|counts| counts.borrow().get(&(quiz_id, code)))
+        .unwrap_or_default();
+    let count = previous.saturating_add_signed(delta);
+    COUNTS.with(|counts| {
+        let mut counts = counts.borrow_mut();
+        if count == 0 {
+            counts.remove(&(quiz_id, code));
+        } else {
+            counts.insert((quiz_id, code), count);
+        }
+    });
+    if reaction_of(code) == Some(Reaction::Like) {
+        LIKE_RANKING.with(|ranking| {
+            let mut ranking = ranking.borrow_mut();
+            ranking.remove(&(Reverse(previous), Reverse(quiz_id)));
+            if count > 0 {
+                ranking.insert((Reverse(count), Reverse(quiz_id)), ());
+            }
+        });
+    }
+}
+
+fn reactions_of(quiz_id: u64) -> QuizReactions {
+    let counts = COUNTS.with(|counts| {
+        counts
+            .borrow()
+            .range((quiz_id, 0)..=(quiz_id, u8::MAX))
+            .filter_map(|((_, code), count)| {
+                reaction_of(code).map(|reaction| ReactionCount { reaction, count })
+            })
+            .collect()
+    });
+    let mine = REACTIONS
+        .with(|reactions| {
+            reactions
+                .borrow()
+                .get(&(quiz_id, StorablePrincipal(caller())))
+        })
+        .and_then(reaction_of);
+    QuizReactions {
+        quiz_id,
+        counts,
+        mine,
+    }
+}
+
+// the reactions go together with the quiz, see `remove_quiz`.
+pub fn remove_reactions(quiz_id: u64) {
+    REACTIONS.with(|reactions| {
+        let mut reactions = reactions.borrow_mut();
+        let keys: Vec<(u64, StorablePrincipal)> = reactions
+            .range(id_key_range(quiz_id))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            reactions.remove(&key);
+        }
+    });
+    for reaction in REACTIONS_IN_ORDER {
+        let count = COUNTS.with(|counts| counts.borrow_mut().remove(&(quiz_id, code_of(reaction))));
+        if let (Reaction::Like, Some(likes)) = (reaction, count) {
+            LIKE_RANKING.with(|ranking| {
+                ranking
+                    .borrow_mut()
+                    .remove(&(Reverse(likes), Reverse(quiz_id)))
+            });
+        }
+    }
+}
+
+fn viewable_quiz(id: u64) -> Result<Quiz, Error> {
+    let quiz = STORAGE
+        .with(|service| service.borrow().get(&id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", id),
+        })?;
+    allowlist::ensure_can_view(&quiz)?;
+    Ok(quiz)
+}
+
+// Sets the caller's one reaction to the quiz, replacing an earlier one. `None` takes it back.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn react_to_quiz(id: u64, reaction: Option<Reaction>) -> Result<QuizReactions, Error> {
+    metrics::count_call("react_to_quiz");
+    maintenance::ensure_available("react_to_quiz")?;
+    settings::ensure_enabled(Feature::Reactions)?;
+    viewable_quiz(id)?;
+    let key = (id, StorablePrincipal(caller()));
+    let previous = REACTIONS.with(|reactions| match reaction {
+        Some(reaction) => reactions.borrow_mut().insert(key, code_of(reaction)),
+        None => reactions.borrow_mut().remove(&key),
+    });
+    if previous != reaction.map(code_of) {
+        if let Some(code) = previous {
+            add_count(id, code, -1);
+        }
+        if let Some(reaction) = reaction {
+            add_count(id, code_of(reaction), 1);
+        }
+    }
+    Ok(reactions_of(id))
+}
+
+#[ic_cdk::query]
+fn get_reactions(id: u64) -> Result<QuizReactions, Error> {
+    viewable_quiz(id)?;
+    Ok(reactions_of(id))
+}
+
+// The quizzes with the most likes first, closed ones included. Quizzes nobody liked are left out.
+#[ic_cdk::query]
+fn get_most_liked(limit: u64) -> Vec<Quiz> {
+    LIKE_RANKING.with(|ranking| {
+        ranking
+            .borrow()
+            .iter()
+            .filter_map(|((_, Reverse(quiz_id)), _)| _get_quiz(&quiz_id))
+            .filter(|quiz| allowlist::can_view(quiz, caller()))
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .collect()
+    })
+}
@@ -0,0 +1,165 @@
+use crate::guards::only_authenticated;
+use crate::roles::{self, Role};
+use crate::{
+    _get_quiz, apply_payload, ensure_editor, explanations, maintenance, metrics, numeric,
+    option_items, scoring, Error, Memory, Quiz, QuizPayload, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// the oldest revisions of a quiz are dropped beyond this many
+const MAX_REVISIONS: usize = 20;
+
+// a version of the quiz as `update_quiz` found it before replacing it
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct QuizRevision {
+    // counts up from 1 for every quiz
+    revision: u64,
+    // holds the correct options too, so only editors and admins see it
+    payload: QuizPayload,
+    replaced_at: u64,
+}
+
+impl Storable for QuizRevision {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for QuizRevision {
+    // the quiz and its answer key, which holds at most every option
+    const MAX_SIZE: u32 = 2 * Quiz::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    // by (quiz id, revision)
+    static REVISIONS: RefCell<StableBTreeMap<(u64, u64), QuizRevision, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(66)))
+    ));
+}
+
+// the payload that recreates the settings of the quiz.
+fn payload_of(quiz: &Quiz, correct_options: Vec<String>) -> QuizPayload {
+    QuizPayload {
+        question: quiz.question.clone(),
+        options: quiz.options.clone(),
+        starts_at: quiz.starts_at,
+        ends_at: quiz.ends_at,
+        voting_mode: quiz.voting_mode.clone(),
+        correct_options: Some(correct_options).filter(|options| !options.is_empty()),
+        correct_options_hash: scoring::answer_commitment_of(quiz.id),
+        scoring_policy: scoring::scoring_policy_of(quiz.id),
+        wrong_answer_penalty: scoring::wrong_answer_penalty_of(quiz.id),
+        explanations: explanations::explanations_of(quiz.id),
+        rendered: quiz.rendered,
+        option_images: option_items::option_images_of(quiz.id),
+        numeric_range: numeric::numeric_range_of(quiz.id),
+        correct_number: numeric::correct_number_of(quiz.id),
+        tags: quiz.tags.clone(),
+        category: quiz.category.clone(),
+        weight_token: quiz.weight_token,
+        credits_per_voter: quiz.credits_per_voter,
+        max_selections: quiz.max_selections,
+        reveal_ends_at: quiz.reveal_ends_at,
+        visibility: quiz.visibility.clone(),
+        allow_anonymous: quiz.allow_anonymous,
+        vote_cooldown_ns: quiz.vote_cooldown_ns,
+        draft: None,
+        idempotency_key: None,
+        organization: None,
+    }
+}
+
+fn revision_numbers(quiz_id: u64) -> Vec<u64> {
+    REVISIONS.with(|revisions| {
+        revisions
+            .borrow()
+            .range((quiz_id, 0)..=(quiz_id, u64::MAX))
+            .map(|((_, revision), _)| revision)
+            .collect()
+    })
+}
+
+// keeps the version of the quiz an update is about to replace.
+pub fn save(quiz: &Quiz, correct_options: Vec<String>) {
+    let numbers = revision_numbers(quiz.id);
+    let revision = numbers.last().map_or(1, |last| last + 1);
+    REVISIONS.with(|revisions| {
+        let mut revisions = revisions.borrow_mut();
+        revisions.insert(
+            (quiz.id, revision),
+            QuizRevision {
+                revision,
+                payload: payload_of(quiz, correct_options),
+                replaced_at: time(),
+            },
+        );
+        let excess = (numbers.len() + 1).saturating_sub(MAX_REVISIONS);
+        for number in numbers.into_iter().take(excess) {
+            revisions.remove(&(quiz.id, number));
+        }
+    });
+}
+
+// the revisions go together with the quiz, see `remove_quiz`.
+pub fn remove_revisions(quiz_id: u64) {
+    REVISIONS.with(|revisions| {
+        let mut revisions = revisions.borrow_mut();
+        for revision in revision_numbers(quiz_id) {
+            revisions.remove(&(quiz_id, revision));
+        }
+    });
+}
+
+// the earlier versions of the quiz, the oldest first. They hold the correct options, so they are
+// limited to the people who can edit the quiz and admins.
+#[ic_cdk::query]
+fn get_quiz_revisions(id: u64) -> Result<Vec<QuizRevision>, Error> {
+    let quiz = _get_quiz(&id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", id),
+        })?;
+    if ensure_editor(&quiz).is_err() && !roles::has_role(caller(), Role::Admin) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only its editors or an admin can read the revisions of the quiz with id={}",
+                id
+            ),
+        });
+    }
+    Ok(REVISIONS.with(|revisions| {
+        revisions
+            .borrow()
+            .range((id, 0)..=(id, u64::MAX))
+            .map(|(_, revision)| revision)
+            .collect()
+    }))
+}
+
+// Updates the quiz back to the revision, the version it replaces becomes a revision itself. The
+// votes stay when the options, the way of voting and the correct options are the same as now,
+// otherwise the tallies start over like on every update. A revision whose end time passed can't
+// be restored as it is.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn rollback_quiz(id: u64, revision: u64) -> Result<Quiz, Error> {
+    metrics::count_call("rollback_quiz");
+    maintenance::ensure_available("rollback_quiz")?;
+    let payload = REVISIONS
+        .with(|revisions| revisions.borrow().get(&(id, revision)))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("the quiz with id={} has no revision {}", id, revision),
+        })?
+        .payload;
+    let args = Encode!(&id, &revision).unwrap();
+    apply_payload(id, payload, &args, true)
+}
@@ -0,0 +1,314 @@
+use crate::guards::{only_admin, only_authenticated};
+use crate::icrc::{self, Account};
+use crate::{
+    _get_quiz, maintenance, metrics, scoring, Error, Memory, StorablePrincipal, MEMORY_MANAGER,
+    VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, Cell, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, cell::RefCell};
+
+// the ICRC-1 ledger the canister pays rewards from, out of its default account
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct RewardSettings {
+    ledger: Option<Principal>,
+}
+
+impl Storable for RewardSettings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// What a voter of a quiz can claim once it closed, in the smallest unit of the reward token.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct RewardRule {
+    // for every vote on the quiz
+    per_answer: u128,
+    // on top of it for a fully correct answer, see `scoring`
+    per_correct_answer: u128,
+}
+
+impl Storable for RewardRule {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for RewardRule {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct RewardClaim {
+    quiz_id: u64,
+    principal: Principal,
+    ledger: Principal,
+    amount: u128,
+    // the transfer is deduplicated by the ledger on it, a retry reuses it unless `rejected`
+    created_at: u64,
+    // None while the transfer is under way or after it failed
+    block_index: Option<u128>,
+    paid_at: Option<u64>,
+    // the ledger turned the last transfer down without paying it, a retry starts a new one
+    rejected: Option<bool>,
+}
+
+impl Storable for RewardClaim {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for RewardClaim {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static SETTINGS: RefCell<Cell<RewardSettings, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(106))),
+            RewardSettings::default(),
+        )
+        .expect("Cannot create the reward settings cell")
+    );
+
+    static REWARD_RULES: RefCell<StableBTreeMap<u64, RewardRule, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(107)))
+    ));
+
+    // kept after the quiz is gone, they record what was paid out
+    static CLAIMS: RefCell<StableBTreeMap<(u64, StorablePrincipal), RewardClaim, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(108)))
+    ));
+
+    // claims whose transfer is awaited, a second claim of the same voter is turned away meanwhile
+    static IN_FLIGHT: RefCell<Vec<(u64, Principal)>> = const { RefCell::new(Vec::new()) };
+}
+
+fn reward_ledger() -> Option<Principal> {
+    SETTINGS.with(|settings| settings.borrow().get().ledger)
+}
+
+pub fn remove_reward_rule(quiz_id: u64) {
+    REWARD_RULES.with(|rules| rules.borrow_mut().remove(&quiz_id));
+}
+
+// the memo of the transfer of a claim, the same for every retry of it
+fn claim_memo(quiz_id: u64, principal: Principal) -> Vec<u8> {
+    Sha256::new()
+        .chain_update(b"quiz-reward")
+        .chain_update(quiz_id.to_be_bytes())
+        .chain_update(principal.as_slice())
+        .finalize()
+        .to_vec()
+}
+
+// Only admins pick the token, the canister pays from its own balance. None stops the rewards,
+// claims already paid stay.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_reward_token(ledger: Option<Principal>) -> Result<(), Error> {
+    metrics::count_call("set_reward_token");
+    maintenance::ensure_available("set_reward_token")?;
+    if ledger.is_some_and(|ledger| ledger == Principal::anonymous()) {
+        return Err(Error::ValidationFailed {
+            field: "ledger".to_string(),
+            msg: "the reward token needs the principal of its ledger".to_string(),
+        });
+    }
+    SETTINGS
+        .with(|settings| settings.borrow_mut().set(RewardSettings { ledger }))
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the reward settings".to_string(),
+        })?;
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_reward_token() -> Option<Principal> {
+    reward_ledger()
+}
+
+// Admins set the rule of a quiz since the rewards come out of the balance of the canister. None
+// removes it, unclaimed rewards included.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_reward_rule(quiz_id: u64, rule: Option<RewardRule>) -> Result<(), Error> {
+    metrics::count_call("set_reward_rule");
+    maintenance::ensure_available("set_reward_rule")?;
+    _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    match rule {
+        Some(rule) if rule.per_answer == 0 && rule.per_correct_answer == 0 => {
+            Err(Error::ValidationFailed {
+                field: "rule".to_string(),
+                msg: "a reward rule has to pay something, None removes it".to_string(),
+            })
+        }
+        Some(rule) => {
+            REWARD_RULES.with(|rules| rules.borrow_mut().insert(quiz_id, rule));
+            Ok(())
+        }
+        None => {
+            remove_reward_rule(quiz_id);
+            Ok(())
+        }
+    }
+}
+
+#[ic_cdk::query]
+fn get_reward_rule(quiz_id: u64) -> Option<RewardRule> {
+    REWARD_RULES.with(|rules| rules.borrow().get(&quiz_id))
+}
+
+#[ic_cdk::query]
+fn get_my_reward_claim(quiz_id: u64) -> Option<RewardClaim> {
+    CLAIMS.with(|claims| claims.borrow().get(&(quiz_id, StorablePrincipal(caller()))))
+}
+
+// What the caller earned on the quiz, once it closed and its correct options are known.
+fn reward_of(quiz_id: u64, principal: Principal) -> Result<(Principal, u128), Error> {
+    let ledger = reward_ledger().ok_or_else(|| Error::NotFound {
+        msg: "there is no reward token".to_string(),
+    })?;
+    let rule = REWARD_RULES
+        .with(|rules| rules.borrow().get(&quiz_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("the quiz with id={} has no rewards", quiz_id),
+        })?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    if !quiz.is_closed() || scoring::answer_commitment_of(quiz_id).is_some() {
+        return Err(Error::WrongPhase {
+            msg: format!(
+                "the rewards of the quiz with id={} can be claimed once it closed and its \
+                 correct options are known",
+                quiz_id
+            ),
+        });
+    }
+    let vote = VOTES
+        .with(|votes| votes.borrow().get(&(quiz_id, StorablePrincipal(principal))))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("you haven't answered the quiz with id={}", quiz_id),
+        })?;
+    let bonus = match scoring::is_correct(quiz_id, &vote) {
+        true => rule.per_correct_answer,
+        false => 0,
+    };
+    Ok((ledger, rule.per_answer.saturating_add(bonus)))
+}
+
+// Pays the reward of the caller for a closed quiz with an `icrc1_transfer`. A claim is paid once,
+// a failed one can be claimed again and the ledger deduplicates it should the first transfer
+// have gone through after all.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn claim_reward(quiz_id: u64) -> Result<RewardClaim, Error> {
+    metrics::count_call("claim_reward");
+    maintenance::ensure_available("claim_reward")?;
+    let principal = caller();
+    let key = (quiz_id, StorablePrincipal(principal));
+    let previous = CLAIMS.with(|claims| claims.borrow().get(&key));
+    if previous
+        .as_ref()
+        .is_some_and(|claim| claim.paid_at.is_some())
+    {
+        return Err(Error::ValidationFailed {
+            field: "quiz_id".to_string(),
+            msg: format!(
+                "you claimed the reward of the quiz with id={} already",
+                quiz_id
+            ),
+        });
+    }
+    if IN_FLIGHT.with(|in_flight| in_flight.borrow().contains(&(quiz_id, principal))) {
+        return Err(Error::ValidationFailed {
+            field: "quiz_id".to_string(),
+            msg: format!("the reward of the quiz with id={} is being paid", quiz_id),
+        });
+    }
+    let mut claim = match previous {
+        // the ledger only deduplicates for a day, so a retry of a transfer that is known not to
+        // have gone through gets a new time instead of failing as too old
+        Some(claim) if claim.rejected == Some(true) => RewardClaim {
+            created_at: time(),
+            rejected: None,
+            ..claim
+        },
+        Some(claim) => claim,
+        None => {
+            let (ledger, amount) = reward_of(quiz_id, principal)?;
+            if amount == 0 {
+                return Err(Error::NotFound {
+                    msg: format!("you earned no reward on the quiz with id={}", quiz_id),
+                });
+            }
+            RewardClaim {
+                quiz_id,
+                principal,
+                ledger,
+                amount,
+                created_at: time(),
+                block_index: None,
+                paid_at: None,
+                rejected: None,
+            }
+        }
+    };
+    CLAIMS.with(|claims| claims.borrow_mut().insert(key, claim.clone()));
+
+    IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().push((quiz_id, principal)));
+    let transfer = icrc::try_transfer(
+        claim.ledger,
+        None,
+        Account::of(principal),
+        claim.amount,
+        claim_memo(quiz_id, principal),
+        claim.created_at,
+    )
+    .await;
+    IN_FLIGHT.with(|in_flight| {
+        in_flight
+            .borrow_mut()
+            .retain(|entry| *entry != (quiz_id, principal))
+    });
+
+    let block_index = match transfer {
+        Ok(block_index) => block_index,
+        Err((error, rejected)) => {
+            if rejected {
+                claim.rejected = Some(true);
+                CLAIMS.with(|claims| claims.borrow_mut().insert(key, claim));
+            }
+            return Err(error);
+        }
+    };
+    claim.block_index = Some(block_index);
+    claim.paid_at = Some(time());
+    CLAIMS.with(|claims| claims.borrow_mut().insert(key, claim.clone()));
+    Ok(claim)
+}
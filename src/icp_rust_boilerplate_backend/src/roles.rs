@@ -0,0 +1,170 @@
+use crate::guards::{only_admin, only_moderator};
+use crate::{maintenance, metrics, Error, Memory, StorablePrincipal, MEMORY_MANAGER};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, is_controller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// ordered from the least to the most powerful, every role includes the ones below it
+#[derive(
+    candid::CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Role {
+    // can delete any quiz and ban principals
+    Moderator,
+    // can also curate the canister and grant the moderator role
+    Admin,
+    // can also grant and revoke every role, the controllers are owners without an entry
+    Owner,
+}
+
+impl Storable for Role {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Role {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static ROLES: RefCell<StableBTreeMap<StorablePrincipal, Role, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20)))
+    ));
+
+    // banned principals with the time of the ban, they can neither create quizzes nor vote
+    static BANS: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct RoleAssignment {
+    principal: Principal,
+    role: Role,
+}
+
+fn role_of(principal: Principal) -> Option<Role> {
+    if is_controller(&principal) {
+        return Some(Role::Owner);
+    }
+    ROLES.with(|roles| roles.borrow().get(&StorablePrincipal(principal)))
+}
+
+pub fn has_role(principal: Principal, required: Role) -> bool {
+    role_of(principal).is_some_and(|role| role >= required)
+}
+
+pub fn ensure_role(required: Role) -> Result<(), Error> {
+    if !has_role(caller(), required) {
+        return Err(Error::Unauthorized {
+            msg: format!("this needs the {:?} role", required),
+        });
+    }
+    Ok(())
+}
+
+// everybody who isn't signed in shares the anonymous principal.
+pub fn ensure_authenticated() -> Result<(), Error> {
+    if caller() == Principal::anonymous() {
+        return Err(Error::Unauthorized {
+            msg: "anonymous callers have to sign in first".to_string(),
+        });
+    }
+    Ok(())
+}
+
+pub fn is_banned(principal: Principal) -> bool {
+    BANS.with(|bans| bans.borrow().contains_key(&StorablePrincipal(principal)))
+}
+
+// admins hand out moderator roles, everything above needs an owner. The guard of the endpoints
+// turns away everybody below an admin before this.
+fn ensure_can_manage(role: Role) -> Result<(), Error> {
+    match role {
+        Role::Moderator => ensure_role(Role::Admin),
+        Role::Admin | Role::Owner => ensure_role(Role::Owner),
+    }
+}
+
+#[ic_cdk::update(guard = "only_admin")]
+fn grant_role(principal: Principal, role: Role) -> Result<(), Error> {
+    metrics::count_call("grant_role");
+    maintenance::ensure_available("grant_role")?;
+    ensure_can_manage(role)?;
+    if let Some(current) = role_of(principal) {
+        ensure_can_manage(current)?;
+    }
+    ROLES.with(|roles| {
+        roles
+            .borrow_mut()
+            .insert(StorablePrincipal(principal), role)
+    });
+    Ok(())
+}
+
+// controllers keep their implicit owner role, it can't be revoked here.
+#[ic_cdk::update(guard = "only_admin")]
+fn revoke_role(principal: Principal) -> Result<(), Error> {
+    metrics::count_call("revoke_role");
+    maintenance::ensure_available("revoke_role")?;
+    let key = StorablePrincipal(principal);
+    let role = ROLES
+        .with(|roles| roles.borrow().get(&key))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("{} has no role", principal),
+        })?;
+    ensure_can_manage(role)?;
+    ROLES.with(|roles| roles.borrow_mut().remove(&key));
+    Ok(())
+}
+
+#[ic_cdk::query(guard = "only_moderator")]
+fn list_roles() -> Result<Vec<RoleAssignment>, Error> {
+    Ok(ROLES.with(|roles| {
+        roles
+            .borrow()
+            .iter()
+            .map(|(principal, role)| RoleAssignment {
+                principal: principal.0,
+                role,
+            })
+            .collect()
+    }))
+}
+
+#[ic_cdk::update(guard = "only_moderator")]
+fn ban_principal(principal: Principal) -> Result<(), Error> {
+    metrics::count_call("ban_principal");
+    maintenance::ensure_available("ban_principal")?;
+    if has_role(principal, Role::Moderator) {
+        return Err(Error::Unauthorized {
+            msg: format!("{} has a role and can't be banned", principal),
+        });
+    }
+    BANS.with(|bans| {
+        bans.borrow_mut()
+            .insert(StorablePrincipal(principal), time())
+    });
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "only_moderator")]
+fn unban_principal(principal: Principal) -> Result<(), Error> {
+    metrics::count_call("unban_principal");
+    maintenance::ensure_available("unban_principal")?;
+    BANS.with(|bans| bans.borrow_mut().remove(&StorablePrincipal(principal)))
+        .map(|_| ())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("{} is not banned", principal),
+        })
+}
@@ -0,0 +1,188 @@
+use crate::http::escape_html;
+use crate::QuizPayload;
+
+// link targets that can't run anything in a browser, relative ones stay on the canister
+const SAFE_URL_PREFIXES: [&str; 4] = ["https://", "http://", "mailto:", "/"];
+
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    SAFE_URL_PREFIXES
+        .iter()
+        .any(|prefix| url.starts_with(prefix))
+        && !url.starts_with("//")
+        && !url.contains(char::is_whitespace)
+}
+
+// Strips what Markdown would pass on to a browser as is: html tags, comments and autolinks go
+// entirely, links and images to unsafe targets keep their text only. Control characters besides
+// line breaks are dropped as well.
+pub fn sanitize_markdown(text: &str) -> String {
+    let mut clean = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        // `<` followed by a letter, `/`, `!` or `?` opens a tag up to the next `>`
+        if c == '<'
+            && rest[1..]
+                .chars()
+                .next()
+                .is_some_and(|next| next.is_ascii_alphabetic() || "/!?".contains(next))
+        {
+            rest = rest.split_once('>').map_or("", |(_, after)| after);
+            continue;
+        }
+        if let Some((url, after)) = rest.strip_prefix("](").and_then(|r| r.split_once(')')) {
+            clean.push(']');
+            if is_safe_url(url) {
+                clean.push('(');
+                clean.push_str(url);
+                clean.push(')');
+            }
+            rest = after;
+            continue;
+        }
+        if !c.is_control() || c == '\n' {
+            clean.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    clean.trim().to_string()
+}
+
+// Markdown applies to the question and the option labels, the correct options name labels so
+// they are cleaned the same way.
+pub fn sanitize_payload(mut payload: QuizPayload) -> QuizPayload {
+    if payload.rendered.unwrap_or_default() {
+        payload.question = sanitize_markdown(&payload.question);
+        payload.options = payload
+            .options
+            .iter()
+            .map(|option| sanitize_markdown(option))
+            .collect();
+        if let Some(correct_options) = &mut payload.correct_options {
+            for option in correct_options {
+                *option = sanitize_markdown(option);
+            }
+        }
+    }
+    payload
+}
+
+// the text between `open` and the next `close`, if there is any.
+fn delimited<'a>(text: &'a str, open: &str, close: &str) -> Option<(&'a str, &'a str)> {
+    text.strip_prefix(open)
+        .and_then(|rest| rest.split_once(close))
+        .filter(|(inner, _)| !inner.is_empty())
+}
+
+fn render_inline(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some((code, after)) = delimited(rest, "`", "`") {
+            html.push_str(&format!("<code>{}</code>", code));
+            rest = after;
+        } else if let Some((inner, after)) = delimited(rest, "**", "**") {
+            html.push_str(&format!("<strong>{}</strong>", render_inline(inner)));
+            rest = after;
+        } else if let Some((inner, after)) =
+            delimited(rest, "*", "*").or_else(|| delimited(rest, "_", "_"))
+        {
+            html.push_str(&format!("<em>{}</em>", render_inline(inner)));
+            rest = after;
+        } else if let Some((label, url, after)) = delimited(rest, "[", "](")
+            .and_then(|(label, r)| r.split_once(')').map(|(url, after)| (label, url, after)))
+            .filter(|(_, url, _)| is_safe_url(url))
+        {
+            html.push_str(&format!(
+                "<a href=\"{}\" rel=\"nofollow noopener\">{}</a>",
+                url,
+                render_inline(label)
+            ));
+            rest = after;
+        } else {
+            html.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    html
+}
+
+// Renders the inline Markdown of a question or an option as html: emphasis, code and links,
+// line breaks become `<br>`. The text is escaped first, so no markup of the author gets through
+// even if it skipped `sanitize_markdown`.
+pub fn render_markdown(text: &str) -> String {
+    render_inline(&escape_html(text)).replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_markdown, sanitize_markdown, sanitize_payload};
+    use crate::QuizPayload;
+
+    #[test]
+    fn strips_tags_and_comments() {
+        assert_eq!(sanitize_markdown("a <b>bold</b> move"), "a bold move");
+        assert_eq!(sanitize_markdown("x<!-- hidden -->y"), "xy");
+        assert_eq!(sanitize_markdown("<script>alert(1)</script>"), "alert(1)");
+    }
+
+    #[test]
+    fn keeps_a_less_than_sign_that_opens_no_tag() {
+        assert_eq!(sanitize_markdown("1 < 2 and 3 <= 4"), "1 < 2 and 3 <= 4");
+    }
+
+    #[test]
+    fn keeps_only_the_text_of_links_to_unsafe_targets() {
+        assert_eq!(
+            sanitize_markdown("[docs](https://internetcomputer.org)"),
+            "[docs](https://internetcomputer.org)"
+        );
+        assert_eq!(
+            sanitize_markdown("[relative](/quiz/1)"),
+            "[relative](/quiz/1)"
+        );
+        assert_eq!(sanitize_markdown("[click](javascript:void)"), "[click]");
+        assert_eq!(sanitize_markdown("![pixel](data:image/png)"), "![pixel]");
+        assert_eq!(sanitize_markdown("[other](//evil.example)"), "[other]");
+    }
+
+    #[test]
+    fn drops_control_characters_but_line_breaks() {
+        assert_eq!(sanitize_markdown("  a\u{7}b\nc\u{0}  "), "ab\nc");
+    }
+
+    #[test]
+    fn only_cleans_payloads_that_are_rendered() {
+        let payload = |rendered| QuizPayload {
+            question: "<i>Which</i>?".to_string(),
+            options: vec!["<b>A</b>".to_string(), "B".to_string()],
+            correct_options: Some(vec!["<b>A</b>".to_string()]),
+            rendered,
+            ..Default::default()
+        };
+        let plain = sanitize_payload(payload(None));
+        assert_eq!(plain.question, "<i>Which</i>?");
+        let rendered = sanitize_payload(payload(Some(true)));
+        assert_eq!(rendered.question, "Which?");
+        assert_eq!(rendered.options, vec!["A".to_string(), "B".to_string()]);
+        assert_eq!(rendered.correct_options, Some(vec!["A".to_string()]));
+    }
+
+    #[test]
+    fn renders_emphasis_code_links_and_line_breaks() {
+        assert_eq!(
+            render_markdown("**a** and *b* or _c_\n`d`"),
+            "<strong>a</strong> and <em>b</em> or <em>c</em><br><code>d</code>"
+        );
+        assert_eq!(
+            render_markdown("[x](https://a.example)"),
+            "<a href=\"https://a.example\" rel=\"nofollow noopener\">x</a>"
+        );
+    }
+
+    #[test]
+    fn renders_markup_and_unsafe_links_as_text() {
+        assert_eq!(render_markdown("<b>hi</b>"), "&lt;b&gt;hi&lt;/b&gt;");
+        assert_eq!(render_markdown("[x](javascript:y)"), "[x](javascript:y)");
+    }
+}
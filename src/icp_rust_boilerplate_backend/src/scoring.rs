@@ -0,0 +1,742 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, allowlist, audit, ensure_editor, id_key_range, leaderboard, levels, maintenance,
+    metrics, migrations, picked_options, prize_pools, ratings, remove_quiz_entries, study,
+    AuditAction, Error, Memory, Quiz, QuizPayload, StorablePrincipal, Vote, VotingMode,
+    MEMORY_MANAGER, VOTES,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, cell::RefCell};
+
+// points awarded for a fully correct answer
+pub const POINTS_PER_CORRECT_ANSWER: u64 = 100;
+const MAX_OPTION_POINTS: u64 = 1000;
+// a wrong pick costs at most the points of a fully correct answer
+const MAX_WRONG_ANSWER_PENALTY: f64 = 1.0;
+const HASH_LEN: usize = 32;
+const MAX_SALT_LEN: usize = 64;
+
+// the correct options of a scored quiz, kept out of the Quiz record so voters never see them
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct AnswerKey {
+    correct_options: Vec<String>,
+}
+
+impl Storable for AnswerKey {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for AnswerKey {
+    // holds at most every option of the quiz
+    const MAX_SIZE: u32 = Quiz::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// how the picks of a voter are turned into points
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub enum ScoringPolicy {
+    // the full points for picking as many correct options as a voter can and no wrong one
+    #[default]
+    AllOrNothing,
+    // a share of the points for every correct pick, every wrong pick takes a share back
+    Proportional,
+    // the points of the picked options added up, options left out are worth nothing
+    CustomWeights(Vec<OptionWeight>),
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OptionWeight {
+    option: String,
+    points: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct ScoringRule {
+    policy: ScoringPolicy,
+    // how many options a voter can pick, more than one for approval quizzes
+    max_picks: u32,
+    // the share of POINTS_PER_CORRECT_ANSWER every wrong pick costs
+    wrong_answer_penalty: Option<f64>,
+}
+
+struct Grade {
+    points: u64,
+    penalty: u64,
+    // as many correct options as the voter could pick and no wrong one
+    correct: bool,
+}
+
+impl Storable for ScoringRule {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for ScoringRule {
+    // the weights name every option at most once, like the tallies of a quiz
+    const MAX_SIZE: u32 = Quiz::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static ANSWER_KEYS: RefCell<StableBTreeMap<u64, AnswerKey, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29)))
+    ));
+
+    // the hash a scored quiz holds instead of its answer key until the correct options are revealed
+    static ANSWER_COMMITMENTS: RefCell<StableBTreeMap<u64, Blob<HASH_LEN>, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(90)))
+    ));
+
+    // scored quizzes without one pay out all or nothing for a single pick
+    static SCORING_RULES: RefCell<StableBTreeMap<u64, ScoringRule, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(96)))
+    ));
+
+    // the points a vote's penalty actually took off the leaderboard, by (quiz id, voter)
+    static APPLIED_PENALTIES: RefCell<StableBTreeMap<(u64, StorablePrincipal), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(135)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    principal: Principal,
+    option: String,
+    // every option the voter picked, more than one on approval quizzes
+    selections: Vec<String>,
+    // whether the answer earns the full points under all or nothing scoring
+    correct: bool,
+    points: u64,
+    // taken off the points for the wrong picks, the score is the difference
+    penalty: u64,
+    answered_at: u64,
+}
+
+// checks that every correct option is one of the quiz options.
+pub fn validate_correct_options(
+    options: &[String],
+    correct_options: &[String],
+) -> Result<(), Error> {
+    for (position, option) in correct_options.iter().enumerate() {
+        if !options.contains(option) {
+            return Err(Error::ValidationFailed {
+                field: "correct_options".to_string(),
+                msg: format!(
+                    "The correct option '{}' is not an option of this quiz.",
+                    option
+                ),
+            });
+        }
+        if correct_options[..position].contains(option) {
+            return Err(Error::ValidationFailed {
+                field: "correct_options".to_string(),
+                msg: format!("The correct option '{}' is listed more than once.", option),
+            });
+        }
+    }
+    Ok(())
+}
+
+// stores the correct options of a quiz, an empty list turns it back into a plain poll.
+pub fn set_answer_key(quiz_id: u64, correct_options: Vec<String>) {
+    ANSWER_KEYS.with(|keys| {
+        let mut keys = keys.borrow_mut();
+        if correct_options.is_empty() {
+            keys.remove(&quiz_id);
+        } else {
+            keys.insert(quiz_id, AnswerKey { correct_options });
+        }
+    });
+}
+
+// the weights name options of the quiz, a policy needs correct options to go with.
+pub fn validate_scoring_policy(payload: &QuizPayload, policy: &ScoringPolicy) -> Result<(), Error> {
+    let invalid = |msg: String| Error::ValidationFailed {
+        field: "scoring_policy".to_string(),
+        msg,
+    };
+    if payload
+        .correct_options
+        .as_ref()
+        .is_none_or(|options| options.is_empty())
+    {
+        return Err(invalid(
+            "only quizzes with correct options have a scoring policy".to_string(),
+        ));
+    }
+    if let ScoringPolicy::CustomWeights(weights) = policy {
+        for (position, weight) in weights.iter().enumerate() {
+            if !payload.options.contains(&weight.option) {
+                return Err(invalid(format!(
+                    "The option '{}' is not an option of this quiz.",
+                    weight.option
+                )));
+            }
+            if weights[..position]
+                .iter()
+                .any(|earlier| earlier.option == weight.option)
+            {
+                return Err(invalid(format!(
+                    "The option '{}' is weighted more than once.",
+                    weight.option
+                )));
+            }
+            if weight.points > MAX_OPTION_POINTS {
+                return Err(invalid(format!(
+                    "an option is worth at most {} points",
+                    MAX_OPTION_POINTS
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+// negative marking only makes sense on a scored quiz, hashed correct options count as well.
+pub fn validate_wrong_answer_penalty(payload: &QuizPayload, penalty: f64) -> Result<(), Error> {
+    let msg = if !(0.0..=MAX_WRONG_ANSWER_PENALTY).contains(&penalty) {
+        format!(
+            "the penalty must be between 0 and {} of the points of a correct answer",
+            MAX_WRONG_ANSWER_PENALTY
+        )
+    } else if penalty > 0.0 && payload.scoring_policy == Some(ScoringPolicy::Proportional) {
+        "proportional scoring takes a share back for every wrong pick already".to_string()
+    } else if payload
+        .correct_options
+        .as_ref()
+        .is_none_or(|options| options.is_empty())
+        && payload.correct_options_hash.is_none()
+    {
+        "only quizzes with correct options penalize wrong answers".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(Error::ValidationFailed {
+        field: "wrong_answer_penalty".to_string(),
+        msg,
+    })
+}
+
+// stores how the votes on a scored quiz are turned into points, see `grade`.
+pub fn set_scoring_rule(
+    quiz_id: u64,
+    policy: Option<ScoringPolicy>,
+    wrong_answer_penalty: Option<f64>,
+    quiz: &Quiz,
+) {
+    let max_picks = match quiz.voting_mode() {
+        VotingMode::Approval => quiz.max_selections.unwrap_or(1),
+        _ => 1,
+    };
+    let wrong_answer_penalty = wrong_answer_penalty.filter(|penalty| *penalty > 0.0);
+    SCORING_RULES.with(|rules| {
+        let mut rules = rules.borrow_mut();
+        if policy.is_none() && wrong_answer_penalty.is_none() && max_picks <= 1 {
+            rules.remove(&quiz_id);
+        } else {
+            rules.insert(
+                quiz_id,
+                ScoringRule {
+                    policy: policy.unwrap_or_default(),
+                    max_picks,
+                    wrong_answer_penalty,
+                },
+            );
+        }
+    });
+}
+
+fn scoring_rule_of(quiz_id: u64) -> ScoringRule {
+    SCORING_RULES
+        .with(|rules| rules.borrow().get(&quiz_id))
+        .unwrap_or_default()
+}
+
+// None for the default all or nothing policy.
+pub fn scoring_policy_of(quiz_id: u64) -> Option<ScoringPolicy> {
+    Some(scoring_rule_of(quiz_id).policy).filter(|policy| *policy != ScoringPolicy::default())
+}
+
+pub fn wrong_answer_penalty_of(quiz_id: u64) -> Option<f64> {
+    scoring_rule_of(quiz_id).wrong_answer_penalty
+}
+
+// a hashed answer key takes the place of the correct options of a single choice quiz with an end.
+pub fn validate_answer_commitment(payload: &QuizPayload, hash: &[u8]) -> Result<(), Error> {
+    let msg = if hash.len() != HASH_LEN {
+        format!("the hash must be {} bytes long", HASH_LEN)
+    } else if payload
+        .correct_options
+        .as_ref()
+        .is_some_and(|options| !options.is_empty())
+    {
+        "a quiz has either correct options or their hash".to_string()
+    } else if payload.voting_mode.clone().unwrap_or_default() != VotingMode::SingleChoice {
+        "only single choice quizzes can have correct options".to_string()
+    } else if payload.ends_at.is_none() {
+        "the correct options are revealed once the quiz closes, so it needs an end time".to_string()
+    } else {
+        return Ok(());
+    };
+    Err(Error::ValidationFailed {
+        field: "correct_options_hash".to_string(),
+        msg,
+    })
+}
+
+pub fn set_answer_commitment(quiz_id: u64, hash: Option<&[u8]>) {
+    ANSWER_COMMITMENTS.with(|commitments| {
+        let mut commitments = commitments.borrow_mut();
+        match hash.and_then(|hash| Blob::try_from(hash).ok()) {
+            Some(hash) => commitments.insert(quiz_id, hash),
+            None => commitments.remove(&quiz_id),
+        }
+    });
+}
+
+pub fn answer_commitment_of(quiz_id: u64) -> Option<Vec<u8>> {
+    ANSWER_COMMITMENTS
+        .with(|commitments| commitments.borrow().get(&quiz_id))
+        .map(|hash| hash.as_slice().to_vec())
+}
+
+// sha256 over the bytes of every correct option followed by a zero byte, then the salt.
+fn answer_key_hash(correct_options: &[String], salt: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for option in correct_options {
+        hasher.update(option.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(salt);
+    hasher.finalize().to_vec()
+}
+
+pub fn correct_options_of(quiz_id: u64) -> Vec<String> {
+    ANSWER_KEYS
+        .with(|keys| keys.borrow().get(&quiz_id))
+        .map(|key| key.correct_options)
+        .unwrap_or_default()
+}
+
+pub fn move_legacy_answer_keys(legacy_memory_id: u8) {
+    ANSWER_KEYS.with(|keys| migrations::move_legacy_map(legacy_memory_id, &mut keys.borrow_mut()));
+}
+
+pub fn remove_answer_key(quiz_id: u64) {
+    ANSWER_KEYS.with(|keys| keys.borrow_mut().remove(&quiz_id));
+    ANSWER_COMMITMENTS.with(|commitments| commitments.borrow_mut().remove(&quiz_id));
+    SCORING_RULES.with(|rules| rules.borrow_mut().remove(&quiz_id));
+    APPLIED_PENALTIES.with(|penalties| remove_quiz_entries(&mut penalties.borrow_mut(), quiz_id));
+}
+
+// whether the quiz has correct options, revealed or not.
+pub fn is_scored(quiz_id: u64) -> bool {
+    ANSWER_KEYS.with(|keys| keys.borrow().contains_key(&quiz_id))
+        || ANSWER_COMMITMENTS.with(|commitments| commitments.borrow().contains_key(&quiz_id))
+}
+
+// whether a vote on a scored quiz was fully correct, never while the correct options are hashed.
+pub fn is_correct(quiz_id: u64, vote: &Vote) -> bool {
+    ANSWER_KEYS
+        .with(|keys| keys.borrow().get(&quiz_id))
+        .is_some_and(|answer_key| {
+            grade(
+                &answer_key,
+                &scoring_rule_of(quiz_id),
+                &picked_options(vote),
+            )
+            .correct
+        })
+}
+
+// The points of the picked options, the penalty for the wrong ones and whether they were fully
+// correct. A single choice quiz with several correct options counts any one of them as fully
+// correct. Proportional scoring takes its share back for the wrong picks itself, so they aren't
+// penalized on top.
+fn grade(answer_key: &AnswerKey, rule: &ScoringRule, selections: &[String]) -> Grade {
+    let right = selections
+        .iter()
+        .filter(|option| answer_key.correct_options.contains(option))
+        .count() as u64;
+    let wrong = selections.len() as u64 - right;
+    let needed = (answer_key.correct_options.len() as u64)
+        .min(rule.max_picks as u64)
+        .max(1);
+    let correct = wrong == 0 && right >= needed;
+    let points = match &rule.policy {
+        ScoringPolicy::AllOrNothing if correct => POINTS_PER_CORRECT_ANSWER,
+        ScoringPolicy::AllOrNothing => 0,
+        ScoringPolicy::Proportional => {
+            right.saturating_sub(wrong).min(needed) * POINTS_PER_CORRECT_ANSWER / needed
+        }
+        ScoringPolicy::CustomWeights(weights) => weights
+            .iter()
+            .filter(|weight| selections.contains(&weight.option))
+            .map(|weight| weight.points)
+            .sum(),
+    };
+    let penalty = match rule.policy {
+        ScoringPolicy::Proportional => 0.0,
+        _ => {
+            rule.wrong_answer_penalty.unwrap_or_default()
+                * POINTS_PER_CORRECT_ANSWER as f64
+                * wrong as f64
+        }
+    };
+    Grade {
+        points,
+        penalty: penalty.round() as u64,
+        correct,
+    }
+}
+
+// Credits the leaderboard with the points of a vote on a scored quiz and takes its penalty off,
+// the leaderboard doesn't go below zero so what was taken is kept. Every scored vote moves the
+// ratings, see `ratings::record_answer`, and adds the quiz to the study deck.
+pub fn award_points(quiz_id: u64, principal: Principal, selections: &[String]) {
+    let Some(answer_key) = ANSWER_KEYS.with(|keys| keys.borrow().get(&quiz_id)) else {
+        return;
+    };
+    let grade = grade(&answer_key, &scoring_rule_of(quiz_id), selections);
+    ratings::record_answer(quiz_id, principal, grade.correct);
+    study::add_card(quiz_id, principal, grade.correct);
+    if grade.points > 0 {
+        leaderboard::add_points(principal, grade.points);
+        levels::award(principal, levels::XpReason::CorrectAnswer);
+    }
+    if grade.penalty > 0 {
+        let applied = leaderboard::remove_points(principal, grade.penalty);
+        if applied > 0 {
+            APPLIED_PENALTIES.with(|penalties| {
+                penalties
+                    .borrow_mut()
+                    .insert((quiz_id, StorablePrincipal(principal)), applied)
+            });
+        }
+    }
+}
+
+// Credits the points an editor graded a free-text answer with, see `free_text`, or a numeric
+// guess earned, see `numeric`. Grading it again moves the leaderboard by the difference, only the
+// first grade counts for the ratings and the study deck like the first vote on a scored quiz would.
+pub fn award_graded_points(quiz_id: u64, principal: Principal, previous: Option<u64>, points: u64) {
+    match previous {
+        Some(previous) if previous > points => {
+            leaderboard::remove_points(principal, previous - points);
+        }
+        Some(previous) => leaderboard::add_points(principal, points - previous),
+        None => {
+            let correct = points == POINTS_PER_CORRECT_ANSWER;
+            ratings::record_answer(quiz_id, principal, correct);
+            study::add_card(quiz_id, principal, correct);
+            if points > 0 {
+                leaderboard::add_points(principal, points);
+            }
+        }
+    }
+    if previous.unwrap_or_default() == 0 && points > 0 {
+        levels::award(principal, levels::XpReason::CorrectAnswer);
+    }
+}
+
+pub fn revoke_graded_points(principal: Principal, points: u64) {
+    leaderboard::remove_points(principal, points);
+}
+
+// Takes back what `award_points` credited and gives back the part of the penalty it took off,
+// used when votes are discarded.
+pub fn revoke_points(quiz_id: u64, principal: Principal, selections: &[String]) {
+    let Some(answer_key) = ANSWER_KEYS.with(|keys| keys.borrow().get(&quiz_id)) else {
+        return;
+    };
+    let grade = grade(&answer_key, &scoring_rule_of(quiz_id), selections);
+    if grade.points > 0 {
+        leaderboard::remove_points(principal, grade.points);
+    }
+    let applied = APPLIED_PENALTIES.with(|penalties| {
+        penalties
+            .borrow_mut()
+            .remove(&(quiz_id, StorablePrincipal(principal)))
+    });
+    if let Some(applied) = applied {
+        leaderboard::add_points(principal, applied);
+    }
+}
+
+fn get_answer_key(quiz_id: u64) -> Result<AnswerKey, Error> {
+    let quiz = _get_quiz(&quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", quiz_id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    if answer_commitment_of(quiz_id).is_some() {
+        return Err(Error::WrongPhase {
+            msg: format!(
+                "the correct options of the quiz with id={} aren't revealed yet",
+                quiz_id
+            ),
+        });
+    }
+    ANSWER_KEYS
+        .with(|keys| keys.borrow().get(&quiz_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("the quiz with id={} is not scored", quiz_id),
+        })
+}
+
+fn score(answer_key: &AnswerKey, rule: &ScoringRule, vote: Vote) -> ScoreEntry {
+    let selections = picked_options(&vote);
+    let grade = grade(answer_key, rule, &selections);
+    ScoreEntry {
+        principal: vote.voter,
+        option: vote.option,
+        selections,
+        correct: grade.correct,
+        points: grade.points,
+        penalty: grade.penalty,
+        answered_at: vote.cast_at,
+    }
+}
+
+// Every voter of a scored quiz with the net points of their vote and whether it was fully
+// correct, nothing while the correct options are hashed.
+pub fn results_of(quiz_id: u64) -> Vec<(Principal, i64, bool)> {
+    let Some(answer_key) = ANSWER_KEYS.with(|keys| keys.borrow().get(&quiz_id)) else {
+        return Vec::new();
+    };
+    let rule = scoring_rule_of(quiz_id);
+    VOTES.with(|votes| {
+        votes
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|(_, vote)| score(&answer_key, &rule, vote))
+            .map(|entry| {
+                let net = entry.points as i64 - entry.penalty as i64;
+                (entry.principal, net, entry.correct)
+            })
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_my_score(quiz_id: u64) -> Result<ScoreEntry, Error> {
+    let answer_key = get_answer_key(quiz_id)?;
+    let vote = VOTES
+        .with(|votes| votes.borrow().get(&(quiz_id, StorablePrincipal(caller()))))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("you haven't answered the quiz with id={}", quiz_id),
+        })?;
+    Ok(score(&answer_key, &scoring_rule_of(quiz_id), vote))
+}
+
+// every submission of a scored quiz, best score first and faster answers breaking ties.
+#[ic_cdk::query]
+fn get_scoreboard(quiz_id: u64) -> Result<Vec<ScoreEntry>, Error> {
+    let answer_key = get_answer_key(quiz_id)?;
+    let rule = scoring_rule_of(quiz_id);
+    let mut scoreboard: Vec<ScoreEntry> = VOTES.with(|votes| {
+        votes
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|(_, vote)| score(&answer_key, &rule, vote))
+            .collect()
+    });
+    let net = |entry: &ScoreEntry| entry.points as i64 - entry.penalty as i64;
+    scoreboard.sort_by(|a, b| net(b).cmp(&net(a)).then(a.answered_at.cmp(&b.answered_at)));
+    Ok(scoreboard)
+}
+
+// Reveals the correct options of a closed quiz that was created with their hash only. Nothing in
+// the canister knew them while the quiz ran, every vote is scored now.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn reveal_correct_options(
+    quiz_id: u64,
+    correct_options: Vec<String>,
+    salt: Vec<u8>,
+) -> Result<Quiz, Error> {
+    metrics::count_call("reveal_correct_options");
+    maintenance::ensure_available("reveal_correct_options")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_editor(&quiz)?;
+    let hash = answer_commitment_of(quiz_id).ok_or_else(|| Error::NotFound {
+        msg: format!("the quiz with id={} has no hashed correct options", quiz_id),
+    })?;
+    if !quiz.is_closed() {
+        return Err(Error::WrongPhase {
+            msg: format!("the quiz with id={} is still running", quiz_id),
+        });
+    }
+    if salt.len() > MAX_SALT_LEN {
+        return Err(Error::ValidationFailed {
+            field: "salt".to_string(),
+            msg: format!("the salt must not exceed {} bytes", MAX_SALT_LEN),
+        });
+    }
+    if correct_options.is_empty() || answer_key_hash(&correct_options, &salt) != hash {
+        return Err(Error::InvalidReveal {
+            msg: "the correct options and the salt don't match the hash".to_string(),
+        });
+    }
+    validate_correct_options(&quiz.options, &correct_options)?;
+
+    let args = Encode!(&quiz_id, &correct_options, &salt).unwrap();
+    set_answer_commitment(quiz_id, None);
+    set_answer_key(quiz_id, correct_options);
+    let votes: Vec<(Principal, Vec<String>)> = VOTES.with(|votes| {
+        votes
+            .borrow()
+            .range(id_key_range(quiz_id))
+            .map(|(_, vote)| (vote.voter, picked_options(&vote)))
+            .collect()
+    });
+    for (voter, selections) in votes {
+        award_points(quiz_id, voter, &selections);
+    }
+    prize_pools::quiz_closed(quiz_id);
+    audit::record(AuditAction::RevealAnswers, quiz_id, &args);
+    Ok(quiz)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{grade, AnswerKey, OptionWeight, ScoringPolicy, ScoringRule};
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn key(correct_options: &[&str]) -> AnswerKey {
+        AnswerKey {
+            correct_options: strings(correct_options),
+        }
+    }
+
+    fn rule(policy: ScoringPolicy, max_picks: u32, penalty: Option<f64>) -> ScoringRule {
+        ScoringRule {
+            policy,
+            max_picks,
+            wrong_answer_penalty: penalty,
+        }
+    }
+
+    // points, penalty and whether it was fully correct
+    fn graded(answer_key: &AnswerKey, rule: &ScoringRule, picks: &[&str]) -> (u64, u64, bool) {
+        let grade = grade(answer_key, rule, &strings(picks));
+        (grade.points, grade.penalty, grade.correct)
+    }
+
+    #[test]
+    fn all_or_nothing_pays_a_correct_single_pick_in_full() {
+        let answer_key = key(&["A"]);
+        let rule = ScoringRule::default();
+        assert_eq!(graded(&answer_key, &rule, &["A"]), (100, 0, true));
+        assert_eq!(graded(&answer_key, &rule, &["B"]), (0, 0, false));
+    }
+
+    #[test]
+    fn any_correct_option_is_enough_for_a_single_pick() {
+        let answer_key = key(&["A", "B"]);
+        assert_eq!(
+            graded(&answer_key, &ScoringRule::default(), &["B"]),
+            (100, 0, true)
+        );
+    }
+
+    #[test]
+    fn all_or_nothing_approval_needs_every_pick_right() {
+        let answer_key = key(&["A", "B"]);
+        let rule = rule(ScoringPolicy::AllOrNothing, 3, None);
+        assert_eq!(graded(&answer_key, &rule, &["A"]), (0, 0, false));
+        assert_eq!(graded(&answer_key, &rule, &["A", "B"]), (100, 0, true));
+        assert_eq!(graded(&answer_key, &rule, &["A", "B", "C"]), (0, 0, false));
+    }
+
+    #[test]
+    fn all_or_nothing_penalizes_every_wrong_pick() {
+        let answer_key = key(&["A", "B"]);
+        let rule = rule(ScoringPolicy::AllOrNothing, 4, Some(0.5));
+        assert_eq!(graded(&answer_key, &rule, &["C"]), (0, 50, false));
+        assert_eq!(
+            graded(&answer_key, &rule, &["A", "C", "D"]),
+            (0, 100, false)
+        );
+    }
+
+    #[test]
+    fn proportional_pays_a_share_per_correct_pick_and_takes_one_back_per_wrong_pick() {
+        let answer_key = key(&["A", "B"]);
+        let rule = rule(ScoringPolicy::Proportional, 3, None);
+        assert_eq!(graded(&answer_key, &rule, &["A"]), (50, 0, false));
+        assert_eq!(graded(&answer_key, &rule, &["A", "B"]), (100, 0, true));
+        assert_eq!(graded(&answer_key, &rule, &["A", "C"]), (0, 0, false));
+        assert_eq!(graded(&answer_key, &rule, &["A", "B", "C"]), (50, 0, false));
+        assert_eq!(graded(&answer_key, &rule, &["C"]), (0, 0, false));
+    }
+
+    #[test]
+    fn proportional_scoring_isnt_penalized_on_top() {
+        let answer_key = key(&["A", "B"]);
+        let rule = rule(ScoringPolicy::Proportional, 3, Some(1.0));
+        assert_eq!(graded(&answer_key, &rule, &["A", "B", "C"]), (50, 0, false));
+    }
+
+    #[test]
+    fn custom_weights_add_up_the_picked_options() {
+        let weights = vec![
+            OptionWeight {
+                option: "A".to_string(),
+                points: 30,
+            },
+            OptionWeight {
+                option: "B".to_string(),
+                points: 70,
+            },
+            OptionWeight {
+                option: "C".to_string(),
+                points: 5,
+            },
+        ];
+        let answer_key = key(&["B"]);
+        let without_penalty = rule(ScoringPolicy::CustomWeights(weights.clone()), 3, None);
+        assert_eq!(graded(&answer_key, &without_penalty, &["B"]), (70, 0, true));
+        assert_eq!(
+            graded(&answer_key, &without_penalty, &["A", "C"]),
+            (35, 0, false)
+        );
+        let with_penalty = rule(ScoringPolicy::CustomWeights(weights), 3, Some(0.25));
+        assert_eq!(
+            graded(&answer_key, &with_penalty, &["A", "C"]),
+            (35, 50, false)
+        );
+    }
+
+    #[test]
+    fn penalties_are_rounded_to_whole_points() {
+        let answer_key = key(&["A"]);
+        let rule = rule(ScoringPolicy::AllOrNothing, 1, Some(1.0 / 3.0));
+        assert_eq!(graded(&answer_key, &rule, &["B"]), (0, 33, false));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{_get_quiz, allowlist, Memory, Quiz, MAX_PAGE_SIZE, MEMORY_MANAGER};
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+// longer words are cut down to this many bytes before they are indexed
+const MAX_TERM_LEN: usize = 32;
+// shorter words are too common to be worth indexing
+const MIN_TERM_LEN: usize = 2;
+
+type Term = Blob<MAX_TERM_LEN>;
+
+thread_local! {
+    // inverted index from every word of a question or option to the quizzes containing it
+    static SEARCH_INDEX: RefCell<StableBTreeMap<(Term, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
+    ));
+}
+
+// splits text into lowercase alphanumeric words, the same way for quizzes and queries.
+fn tokenize(text: &str) -> BTreeSet<Term> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_TERM_LEN)
+        .map(|word| {
+            let mut word = word.to_lowercase();
+            if word.len() > MAX_TERM_LEN {
+                let mut end = MAX_TERM_LEN;
+                while !word.is_char_boundary(end) {
+                    end -= 1;
+                }
+                word.truncate(end);
+            }
+            Term::try_from(word.as_bytes()).unwrap()
+        })
+        .collect()
+}
+
+fn quiz_terms(quiz: &Quiz) -> BTreeSet<Term> {
+    let mut terms = tokenize(&quiz.question);
+    for option in &quiz.options {
+        terms.extend(tokenize(option));
+    }
+    terms
+}
+
+pub fn index_quiz(quiz: &Quiz) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for term in quiz_terms(quiz) {
+            index.insert((term, quiz.id), ());
+        }
+    });
+}
+
+pub fn unindex_quiz(quiz: &Quiz) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for term in quiz_terms(quiz) {
+            index.remove(&(term, quiz.id));
+        }
+    });
+}
+
+// swaps the terms of the previous version of a quiz for the current ones.
+pub fn reindex_quiz(previous: &Quiz, current: &Quiz) {
+    unindex_quiz(previous);
+    index_quiz(current);
+}
+
+fn quiz_ids_with(term: Term) -> BTreeSet<u64> {
+    SEARCH_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((term, 0)..=(term, u64::MAX))
+            .map(|((_, quiz_id), _)| quiz_id)
+            .collect()
+    })
+}
+
+// quizzes containing every word of the query, oldest first.
+#[ic_cdk::query]
+fn search_quizzes(query: String, limit: u64) -> Vec<Quiz> {
+    let mut matches: Option<BTreeSet<u64>> = None;
+    for term in tokenize(&query) {
+        let quiz_ids = quiz_ids_with(term);
+        matches = Some(match matches {
+            Some(matches) => matches.intersection(&quiz_ids).copied().collect(),
+            None => quiz_ids,
+        });
+    }
+
+    matches
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|quiz_id| _get_quiz(&quiz_id))
+        .filter(|quiz| allowlist::can_view(quiz, caller()))
+        .take(limit.min(MAX_PAGE_SIZE) as usize)
+        .collect()
+}
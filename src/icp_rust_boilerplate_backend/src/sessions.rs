@@ -0,0 +1,619 @@
+use crate::guards::only_authenticated;
+use crate::survey::{self, Survey};
+use crate::{
+    maintenance, metrics, moderation, Error, IdCell, Memory, StorablePrincipal, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::time::Duration;
+use std::{borrow::Cow, cell::RefCell};
+
+// players join with a code of this many digits
+const CODE_LEN: usize = 6;
+const MIN_WINDOW_SECS: u64 = 5;
+const MAX_WINDOW_SECS: u64 = 300;
+const MAX_NICKNAME_LEN: usize = 32;
+const MAX_PLAYERS: usize = 200;
+// what a correct answer is worth at the end of the window
+const CORRECT_POINTS: u64 = 1000;
+// added to a correct answer given right when the question opened, shrinking to 0 at the deadline
+const MAX_SPEED_BONUS: u64 = 1000;
+
+type Code = Blob<CODE_LEN>;
+// (session id, question index) followed by the player
+type AnswerKey = ((u64, u32), StorablePrincipal);
+type PlayerKey = (u64, StorablePrincipal);
+
+#[derive(candid::CandidType, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum SessionPhase {
+    // players join, no question is shown yet
+    Lobby,
+    // the current question takes answers until its window ends or the host moves on
+    Answering,
+    // the current question no longer takes answers
+    Reviewing,
+    // the countdown of the current question stands still until the host resumes
+    Paused,
+    Finished,
+}
+
+// a live game over the questions of a survey, played one question at a time
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Session {
+    id: u64,
+    survey_id: u64,
+    host: Principal,
+    // None once the session finished, the code is free for another session then
+    code: Option<String>,
+    phase: SessionPhase,
+    // the index of the question being played, None in the lobby
+    current_question: Option<u32>,
+    // how long every question takes answers
+    answer_window_ns: u64,
+    question_opened_at: Option<u64>,
+    // answers to the current question are rejected from then on, a pause pushes it back
+    question_deadline: Option<u64>,
+    // what was left of the answer window when the host paused
+    paused_remaining_ns: Option<u64>,
+    created_at: u64,
+    finished_at: Option<u64>,
+}
+
+impl Storable for Session {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Session {
+    const MAX_SIZE: u32 = 512;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct SessionPlayer {
+    principal: Principal,
+    nickname: String,
+    score: u64,
+    correct_answers: u32,
+    joined_at: u64,
+    // kicked players can't answer or join again and are left off the scoreboard
+    kicked_at: Option<u64>,
+}
+
+impl Storable for SessionPlayer {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for SessionPlayer {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct ScoreboardEntry {
+    // players with the same score share a rank
+    rank: u32,
+    principal: Principal,
+    nickname: String,
+    score: u64,
+    correct_answers: u32,
+}
+
+// the session as players see it, with the current question but never its correct option
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct SessionView {
+    session: Session,
+    title: String,
+    question_count: u32,
+    question: Option<String>,
+    options: Vec<String>,
+    players: u64,
+}
+
+thread_local! {
+    static SESSIONS: RefCell<StableBTreeMap<u64, Session, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(85)))
+    ));
+
+    static SESSION_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(86))), 0)
+            .expect("Cannot create the session counter")
+    );
+
+    // the codes of the sessions that haven't finished yet
+    static SESSION_CODES: RefCell<StableBTreeMap<Code, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(87)))
+    ));
+
+    // by (session id, player)
+    static SESSION_PLAYERS: RefCell<StableBTreeMap<PlayerKey, SessionPlayer, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(88)))
+    ));
+
+    // the option index each player picked and the time they picked it at
+    static SESSION_ANSWERS: RefCell<StableBTreeMap<AnswerKey, (u32, u64), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(89)))
+    ));
+}
+
+fn to_code(code: &str) -> Option<Code> {
+    Code::try_from(code.as_bytes()).ok()
+}
+
+fn load_session(session_id: u64) -> Result<Session, Error> {
+    SESSIONS
+        .with(|sessions| sessions.borrow().get(&session_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a session with id={} not found", session_id),
+        })
+}
+
+fn save(session: &Session) {
+    SESSIONS.with(|sessions| sessions.borrow_mut().insert(session.id, session.clone()));
+}
+
+fn hosted_session(session_id: u64) -> Result<Session, Error> {
+    let session = load_session(session_id)?;
+    if session.host != caller() {
+        return Err(Error::Unauthorized {
+            msg: format!("only the host can run the session with id={}", session_id),
+        });
+    }
+    Ok(session)
+}
+
+fn session_survey(session: &Session) -> Survey {
+    // surveys are never deleted, so the survey of a session is always there
+    survey::get_survey(session.survey_id)
+        .unwrap_or_else(|| ic_cdk::trap("the survey of a session is missing"))
+}
+
+fn player_count(session_id: u64) -> u64 {
+    SESSION_PLAYERS.with(|players| {
+        players
+            .borrow()
+            .range(crate::id_key_range(session_id))
+            .count() as u64
+    })
+}
+
+fn view_of(session: Session) -> SessionView {
+    let survey = session_survey(&session);
+    let current = session
+        .current_question
+        .and_then(|index| survey.questions().get(index as usize));
+    SessionView {
+        title: survey.title().to_string(),
+        question_count: survey.questions().len() as u32,
+        question: current.map(|question| question.question.clone()),
+        options: current
+            .map(|question| question.options.clone())
+            .unwrap_or_default(),
+        players: player_count(session.id),
+        session,
+    }
+}
+
+// the first unused code among those the random bytes make up.
+fn free_code(random: &[u8]) -> Option<String> {
+    random
+        .chunks_exact(8)
+        .map(|chunk| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(chunk);
+            format!(
+                "{:0width$}",
+                u64::from_le_bytes(bytes) % 10u64.pow(CODE_LEN as u32),
+                width = CODE_LEN
+            )
+        })
+        .find(|code| {
+            !SESSION_CODES.with(|codes| codes.borrow().contains_key(&to_code(code).unwrap()))
+        })
+}
+
+// Timer callback, skipped when the host moved on before the deadline. A pause pushes the
+// deadline back, the timer set before the pause finds it still ahead.
+fn close_question(session_id: u64, question_index: u32) {
+    let Ok(mut session) = load_session(session_id) else {
+        return;
+    };
+    if session.phase == SessionPhase::Answering
+        && session.current_question == Some(question_index)
+        && session
+            .question_deadline
+            .is_some_and(|deadline| time() >= deadline)
+    {
+        session.phase = SessionPhase::Reviewing;
+        save(&session);
+    }
+}
+
+fn schedule_close(session: &Session) {
+    let (Some(question_index), Some(deadline)) =
+        (session.current_question, session.question_deadline)
+    else {
+        return;
+    };
+    let id = session.id;
+    let delay = deadline.saturating_sub(time());
+    ic_cdk_timers::set_timer(Duration::from_nanos(delay), move || {
+        close_question(id, question_index)
+    });
+}
+
+// timers don't survive upgrades, see `post_upgrade`.
+pub fn reschedule_windows() {
+    SESSIONS.with(|sessions| {
+        sessions
+            .borrow()
+            .iter()
+            .filter(|(_, session)| session.phase == SessionPhase::Answering)
+            .for_each(|(_, session)| schedule_close(&session));
+    });
+}
+
+// Opens a session over a survey of the caller with a fresh join code. The survey needs a correct
+// option for at least one question, the others don't score.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn open_session(survey_id: u64, answer_window_secs: u64) -> Result<Session, Error> {
+    metrics::count_call("open_session");
+    maintenance::ensure_available("open_session")?;
+    let survey = survey::get_survey(survey_id).ok_or_else(|| Error::NotFound {
+        msg: format!("a survey with id={} not found", survey_id),
+    })?;
+    if survey.author() != caller() {
+        return Err(Error::Unauthorized {
+            msg: format!("only the author can host the survey with id={}", survey_id),
+        });
+    }
+    if !(0..survey.questions().len() as u32)
+        .any(|index| survey::correct_option_of(survey_id, index).is_some())
+    {
+        return Err(Error::ValidationFailed {
+            field: "survey_id".to_string(),
+            msg: "none of the questions of the survey has a correct option".to_string(),
+        });
+    }
+    if !(MIN_WINDOW_SECS..=MAX_WINDOW_SECS).contains(&answer_window_secs) {
+        return Err(Error::ValidationFailed {
+            field: "answer_window_secs".to_string(),
+            msg: format!(
+                "every question takes answers for {} to {} seconds",
+                MIN_WINDOW_SECS, MAX_WINDOW_SECS
+            ),
+        });
+    }
+
+    let (random,) = raw_rand().await.map_err(|(code, msg)| Error::CallFailed {
+        msg: format!("raw_rand failed ({:?}): {}", code, msg),
+    })?;
+    let code = free_code(&random).ok_or_else(|| Error::CallFailed {
+        msg: "no free session code was drawn, try again".to_string(),
+    })?;
+    let id = SESSION_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment session counter".to_string(),
+        })?;
+    let session = Session {
+        id,
+        survey_id,
+        host: caller(),
+        code: Some(code.clone()),
+        phase: SessionPhase::Lobby,
+        current_question: None,
+        answer_window_ns: answer_window_secs * 1_000_000_000,
+        question_opened_at: None,
+        question_deadline: None,
+        paused_remaining_ns: None,
+        created_at: time(),
+        finished_at: None,
+    };
+    save(&session);
+    SESSION_CODES.with(|codes| codes.borrow_mut().insert(to_code(&code).unwrap(), id));
+    Ok(session)
+}
+
+// joining again only changes the nickname, the score stays.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn join_session(code: String, nickname: String) -> Result<SessionView, Error> {
+    metrics::count_call("join_session");
+    maintenance::ensure_available("join_session")?;
+    let session_id = to_code(&code)
+        .and_then(|code| SESSION_CODES.with(|codes| codes.borrow().get(&code)))
+        .ok_or_else(|| Error::NotFound {
+            msg: "no running session has this code".to_string(),
+        })?;
+    let session = load_session(session_id)?;
+    let nickname = nickname.trim().to_string();
+    if nickname.is_empty() || nickname.len() > MAX_NICKNAME_LEN {
+        return Err(Error::ValidationFailed {
+            field: "nickname".to_string(),
+            msg: format!("the nickname must be 1 to {} bytes", MAX_NICKNAME_LEN),
+        });
+    }
+    moderation::ensure_clean("nickname", &nickname)?;
+    let key = (session_id, StorablePrincipal(caller()));
+    let player = match SESSION_PLAYERS.with(|players| players.borrow().get(&key)) {
+        Some(player) if player.kicked_at.is_some() => {
+            return Err(Error::Unauthorized {
+                msg: format!("you were removed from the session with id={}", session_id),
+            })
+        }
+        Some(player) => SessionPlayer { nickname, ..player },
+        None if player_count(session_id) >= MAX_PLAYERS as u64 => {
+            return Err(Error::StorageFull {
+                msg: format!("a session takes at most {} players", MAX_PLAYERS),
+            })
+        }
+        None => SessionPlayer {
+            principal: caller(),
+            nickname,
+            score: 0,
+            correct_answers: 0,
+            joined_at: time(),
+            kicked_at: None,
+        },
+    };
+    SESSION_PLAYERS.with(|players| players.borrow_mut().insert(key, player));
+    Ok(view_of(session))
+}
+
+// the session with the question being played.
+#[ic_cdk::query]
+fn get_session(session_id: u64) -> Result<SessionView, Error> {
+    load_session(session_id).map(view_of)
+}
+
+// Moves the session on. A question taking answers stops taking them, otherwise the next question
+// opens, or the session finishes after the last one.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn advance_session(session_id: u64) -> Result<SessionView, Error> {
+    metrics::count_call("advance_session");
+    maintenance::ensure_available("advance_session")?;
+    let mut session = running_session(session_id)?;
+    match session.phase {
+        SessionPhase::Answering | SessionPhase::Paused => {
+            session.phase = SessionPhase::Reviewing;
+            session.paused_remaining_ns = None;
+        }
+        _ => open_next_question(&mut session),
+    }
+    save(&session);
+    Ok(view_of(session))
+}
+
+// the session of the caller, as long as it hasn't finished.
+fn running_session(session_id: u64) -> Result<Session, Error> {
+    let session = hosted_session(session_id)?;
+    if session.phase == SessionPhase::Finished {
+        return Err(Error::WrongPhase {
+            msg: format!("the session with id={} is finished", session_id),
+        });
+    }
+    Ok(session)
+}
+
+fn open_next_question(session: &mut Session) {
+    let question_count = session_survey(session).questions().len() as u32;
+    let next = session.current_question.map_or(0, |index| index + 1);
+    if next < question_count {
+        session.phase = SessionPhase::Answering;
+        session.current_question = Some(next);
+        session.question_opened_at = Some(time());
+        session.question_deadline = Some(time().saturating_add(session.answer_window_ns));
+        session.paused_remaining_ns = None;
+        schedule_close(session);
+    } else {
+        finish(session);
+    }
+}
+
+// stops the countdown of the question taking answers, nobody can answer until it resumes.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn pause_session(session_id: u64) -> Result<SessionView, Error> {
+    metrics::count_call("pause_session");
+    maintenance::ensure_available("pause_session")?;
+    let mut session = running_session(session_id)?;
+    let Some(deadline) = session
+        .question_deadline
+        .filter(|_| session.phase == SessionPhase::Answering)
+    else {
+        return Err(Error::WrongPhase {
+            msg: format!("the session with id={} has no question running", session_id),
+        });
+    };
+    session.paused_remaining_ns = Some(deadline.saturating_sub(time()));
+    session.phase = SessionPhase::Paused;
+    save(&session);
+    Ok(view_of(session))
+}
+
+// the question takes answers again for what was left of its window.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn resume_session(session_id: u64) -> Result<SessionView, Error> {
+    metrics::count_call("resume_session");
+    maintenance::ensure_available("resume_session")?;
+    let mut session = running_session(session_id)?;
+    let Some(remaining) = session
+        .paused_remaining_ns
+        .filter(|_| session.phase == SessionPhase::Paused)
+    else {
+        return Err(Error::WrongPhase {
+            msg: format!("the session with id={} isn't paused", session_id),
+        });
+    };
+    session.question_deadline = Some(time().saturating_add(remaining));
+    session.paused_remaining_ns = None;
+    session.phase = SessionPhase::Answering;
+    schedule_close(&session);
+    save(&session);
+    Ok(view_of(session))
+}
+
+// opens the next question right away, the answers given to the skipped one still count.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn skip_question(session_id: u64) -> Result<SessionView, Error> {
+    metrics::count_call("skip_question");
+    maintenance::ensure_available("skip_question")?;
+    let mut session = running_session(session_id)?;
+    open_next_question(&mut session);
+    save(&session);
+    Ok(view_of(session))
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+fn end_session(session_id: u64) -> Result<SessionView, Error> {
+    metrics::count_call("end_session");
+    maintenance::ensure_available("end_session")?;
+    let mut session = running_session(session_id)?;
+    finish(&mut session);
+    save(&session);
+    Ok(view_of(session))
+}
+
+// the player keeps their answers but can't answer or join again.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn kick_player(session_id: u64, principal: Principal) -> Result<(), Error> {
+    metrics::count_call("kick_player");
+    maintenance::ensure_available("kick_player")?;
+    running_session(session_id)?;
+    let key = (session_id, StorablePrincipal(principal));
+    let mut player = SESSION_PLAYERS
+        .with(|players| players.borrow().get(&key))
+        .filter(|player| player.kicked_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "{} isn't playing in the session with id={}",
+                principal, session_id
+            ),
+        })?;
+    player.kicked_at = Some(time());
+    SESSION_PLAYERS.with(|players| players.borrow_mut().insert(key, player));
+    Ok(())
+}
+
+// the players by score, the highest first, the earlier to join first among equals.
+#[ic_cdk::query]
+fn get_session_scoreboard(session_id: u64) -> Result<Vec<ScoreboardEntry>, Error> {
+    load_session(session_id)?;
+    let mut players: Vec<SessionPlayer> = SESSION_PLAYERS.with(|players| {
+        players
+            .borrow()
+            .range(crate::id_key_range(session_id))
+            .map(|(_, player)| player)
+            .filter(|player| player.kicked_at.is_none())
+            .collect()
+    });
+    players.sort_by_key(|player| (std::cmp::Reverse(player.score), player.joined_at));
+    let mut entries: Vec<ScoreboardEntry> = Vec::with_capacity(players.len());
+    for (position, player) in players.into_iter().enumerate() {
+        let rank = match entries.last() {
+            Some(previous) if previous.score == player.score => previous.rank,
+            _ => position as u32 + 1,
+        };
+        entries.push(ScoreboardEntry {
+            rank,
+            principal: player.principal,
+            nickname: player.nickname,
+            score: player.score,
+            correct_answers: player.correct_answers,
+        });
+    }
+    Ok(entries)
+}
+
+fn finish(session: &mut Session) {
+    if let Some(code) = session.code.take().as_deref().and_then(to_code) {
+        SESSION_CODES.with(|codes| codes.borrow_mut().remove(&code));
+    }
+    session.phase = SessionPhase::Finished;
+    session.finished_at = Some(time());
+}
+
+// the points of a correct answer given with this much of the window left.
+fn points_for(remaining_ns: u64, window_ns: u64) -> u64 {
+    let bonus =
+        MAX_SPEED_BONUS as u128 * remaining_ns.min(window_ns) as u128 / window_ns.max(1) as u128;
+    CORRECT_POINTS + bonus as u64
+}
+
+// A player answers the current question once before its deadline, a correct answer scores right
+// away and the sooner it comes the more it is worth.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn submit_session_answer(session_id: u64, option: String) -> Result<(), Error> {
+    metrics::count_call("submit_session_answer");
+    maintenance::ensure_available("submit_session_answer")?;
+    let session = load_session(session_id)?;
+    let player_key = (session_id, StorablePrincipal(caller()));
+    let mut player = SESSION_PLAYERS
+        .with(|players| players.borrow().get(&player_key))
+        .filter(|player| player.kicked_at.is_none())
+        .ok_or_else(|| Error::Unauthorized {
+            msg: format!("you haven't joined the session with id={}", session_id),
+        })?;
+    let (question_index, deadline) = match (
+        session.phase,
+        session.current_question,
+        session.question_deadline,
+    ) {
+        (SessionPhase::Answering, Some(index), Some(deadline)) => (index, deadline),
+        _ => {
+            return Err(Error::WrongPhase {
+                msg: format!("the session with id={} takes no answers now", session_id),
+            })
+        }
+    };
+    // the timer closing the question may not have run yet
+    let now = time();
+    if now >= deadline {
+        return Err(Error::WrongPhase {
+            msg: format!("the time for question {} is up", question_index),
+        });
+    }
+    let survey = session_survey(&session);
+    let option_index = survey.questions()[question_index as usize]
+        .options
+        .iter()
+        .position(|candidate| *candidate == option)
+        .ok_or_else(|| Error::ValidationFailed {
+            field: "option".to_string(),
+            msg: format!("The option '{}' is not found for this question.", option),
+        })? as u32;
+    let answer_key = ((session_id, question_index), StorablePrincipal(caller()));
+    if SESSION_ANSWERS.with(|answers| answers.borrow().contains_key(&answer_key)) {
+        return Err(Error::AlreadyVoted {
+            msg: format!("you have already answered question {}", question_index),
+        });
+    }
+    SESSION_ANSWERS.with(|answers| answers.borrow_mut().insert(answer_key, (option_index, now)));
+    if survey::correct_option_of(session.survey_id, question_index) == Some(option_index) {
+        player.score += points_for(deadline - now, session.answer_window_ns);
+        player.correct_answers += 1;
+        SESSION_PLAYERS.with(|players| players.borrow_mut().insert(player_key, player));
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use crate::guards::only_admin;
+use crate::ratelimit::{self, RateLimits};
+use crate::{
+    governance, maintenance, metrics, Error, Memory, MAX_OPTIONS, MAX_QUESTION_LEN, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{Cell, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// a default duration shorter than this would close quizzes before anybody saw them
+const MIN_DEFAULT_DURATION_NS: u64 = 60 * 60 * 1_000_000_000;
+
+// the parts of the canister admins can turn off
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize)]
+pub struct Features {
+    comments: bool,
+    anonymous_votes: bool,
+    reactions: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum Feature {
+    Comments,
+    AnonymousVotes,
+    Reactions,
+}
+
+// The global settings, the limits can be tightened below the ones a quiz has room for but not
+// loosened past them. The rate limits are kept by `ratelimit`, these are stored here.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct StoredSettings {
+    max_options: u32,
+    max_question_len: u32,
+    // how long a quiz created without an end time runs for, None lets it run until it is closed
+    default_duration_ns: Option<u64>,
+    features: Features,
+}
+
+impl Default for StoredSettings {
+    fn default() -> Self {
+        StoredSettings {
+            max_options: MAX_OPTIONS as u32,
+            max_question_len: MAX_QUESTION_LEN as u32,
+            default_duration_ns: None,
+            features: Features {
+                comments: true,
+                anonymous_votes: true,
+                reactions: true,
+            },
+        }
+    }
+}
+
+impl Storable for StoredSettings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    max_options: u32,
+    max_question_len: u32,
+    default_duration_ns: Option<u64>,
+    rate_limits: RateLimits,
+    features: Features,
+}
+
+thread_local! {
+    static SETTINGS: RefCell<Cell<StoredSettings, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(132))),
+            StoredSettings::default(),
+        )
+        .expect("Cannot create the settings cell")
+    );
+}
+
+fn stored() -> StoredSettings {
+    SETTINGS.with(|settings| settings.borrow().get().clone())
+}
+
+pub fn max_options() -> usize {
+    stored().max_options as usize
+}
+
+pub fn max_question_len() -> usize {
+    stored().max_question_len as usize
+}
+
+pub fn default_duration_ns() -> Option<u64> {
+    stored().default_duration_ns
+}
+
+pub fn is_enabled(feature: Feature) -> bool {
+    let features = stored().features;
+    match feature {
+        Feature::Comments => features.comments,
+        Feature::AnonymousVotes => features.anonymous_votes,
+        Feature::Reactions => features.reactions,
+    }
+}
+
+pub fn ensure_enabled(feature: Feature) -> Result<(), Error> {
+    if is_enabled(feature) {
+        return Ok(());
+    }
+    let name = match feature {
+        Feature::Comments => "comments",
+        Feature::AnonymousVotes => "anonymous votes",
+        Feature::Reactions => "reactions",
+    };
+    Err(Error::Unauthorized {
+        msg: format!("{} are turned off on this canister", name),
+    })
+}
+
+fn settings() -> Settings {
+    let stored = stored();
+    Settings {
+        max_options: stored.max_options,
+        max_question_len: stored.max_question_len,
+        default_duration_ns: stored.default_duration_ns,
+        rate_limits: ratelimit::rate_limits(),
+        features: stored.features,
+    }
+}
+
+// quizzes stored already keep what they were created with, the new limits apply to new ones and
+// to edits
+pub fn apply_settings(settings: Settings) -> Result<Settings, Error> {
+    let (field, msg) = if !(2..=MAX_OPTIONS as u32).contains(&settings.max_options) {
+        (
+            "max_options",
+            format!("a quiz can have between 2 and {} options", MAX_OPTIONS),
+        )
+    } else if !(1..=MAX_QUESTION_LEN as u32).contains(&settings.max_question_len) {
+        (
+            "max_question_len",
+            format!("a question can be at most {} bytes long", MAX_QUESTION_LEN),
+        )
+    } else if settings
+        .default_duration_ns
+        .is_some_and(|duration| duration < MIN_DEFAULT_DURATION_NS)
+    {
+        (
+            "default_duration_ns",
+            "a quiz has to run for at least an hour by default".to_string(),
+        )
+    } else {
+        ratelimit::apply_rate_limits(settings.rate_limits.clone())?;
+        SETTINGS
+            .with(|cell| {
+                cell.borrow_mut().set(StoredSettings {
+                    max_options: settings.max_options,
+                    max_question_len: settings.max_question_len,
+                    default_duration_ns: settings.default_duration_ns,
+                    features: settings.features,
+                })
+            })
+            .map_err(|_| Error::StorageFull {
+                msg: "cannot store the settings".to_string(),
+            })?;
+        return Ok(settings);
+    };
+    Err(Error::ValidationFailed {
+        field: field.to_string(),
+        msg,
+    })
+}
+
+#[ic_cdk::query]
+fn get_settings() -> Settings {
+    settings()
+}
+
+// Replaces every global setting at once, start from `get_settings`.
+#[ic_cdk::update(guard = "only_admin")]
+fn update_settings(settings: Settings) -> Result<Settings, Error> {
+    metrics::count_call("update_settings");
+    maintenance::ensure_available("update_settings")?;
+    governance::ensure_ungoverned("settings")?;
+    apply_settings(settings)
+}
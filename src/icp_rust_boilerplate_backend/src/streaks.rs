@@ -0,0 +1,200 @@
+use crate::guards::only_admin;
+use crate::{
+    maintenance, metrics, Error, Memory, StorablePrincipal, MAX_PAGE_SIZE, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, Cell, StableBTreeMap, Storable};
+use std::cmp::Reverse;
+use std::{borrow::Cow, cell::RefCell};
+
+const MINUTE_NS: u64 = 60 * 1_000_000_000;
+const DAY_MINUTES: i64 = 24 * 60;
+// the time zones in use range from utc-12 to utc+14
+const MIN_OFFSET_MINUTES: i32 = -12 * 60;
+const MAX_OFFSET_MINUTES: i32 = 14 * 60;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+pub struct Streak {
+    // consecutive days with a vote, ending today or yesterday, 0 once a day was missed
+    current: u32,
+    longest: u32,
+    // the last day the principal voted on, counted in days since the unix epoch
+    last_day: Option<u64>,
+}
+
+impl Storable for Streak {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Streak {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// where a day starts, as minutes east of utc
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+pub struct StreakSettings {
+    utc_offset_minutes: i32,
+}
+
+impl Storable for StreakSettings {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct StreakEntry {
+    principal: Principal,
+    longest: u32,
+    current: u32,
+    // principals with the same longest streak share a rank
+    rank: u64,
+}
+
+thread_local! {
+    static STREAKS: RefCell<StableBTreeMap<StorablePrincipal, Streak, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(45)))
+    ));
+
+    // the longest streaks ordered best first, the current ones go stale without a vote
+    static STREAK_RANKING: RefCell<StableBTreeMap<(Reverse<u32>, StorablePrincipal), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(46)))
+    ));
+
+    static SETTINGS: RefCell<Cell<StreakSettings, Memory>> = RefCell::new(
+        Cell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(47))),
+            StreakSettings::default(),
+        )
+        .expect("Cannot create the streak settings cell")
+    );
+}
+
+fn today() -> u64 {
+    let offset = SETTINGS.with(|settings| settings.borrow().get().utc_offset_minutes) as i64;
+    let minutes = (time() / MINUTE_NS) as i64 + offset;
+    minutes.div_euclid(DAY_MINUTES) as u64
+}
+
+// the stored streak as of today, it only changes on a vote
+fn as_of_today(mut streak: Streak) -> Streak {
+    if streak
+        .last_day
+        .is_some_and(|last_day| last_day + 1 < today())
+    {
+        streak.current = 0;
+    }
+    streak
+}
+
+fn streak_of(principal: Principal) -> Streak {
+    STREAKS
+        .with(|streaks| streaks.borrow().get(&StorablePrincipal(principal)))
+        .map(as_of_today)
+        .unwrap_or_default()
+}
+
+// counts today for the voter, every way of voting calls this once.
+pub fn record_participation(voter: Principal) {
+    if voter == Principal::anonymous() {
+        return;
+    }
+    let key = StorablePrincipal(voter);
+    let day = today();
+    let previous = STREAKS.with(|streaks| streaks.borrow().get(&key));
+    let mut streak = previous.clone().unwrap_or_default();
+    match streak.last_day {
+        Some(last_day) if last_day >= day => return,
+        Some(last_day) if last_day + 1 == day => streak.current += 1,
+        _ => streak.current = 1,
+    }
+    streak.last_day = Some(day);
+    streak.longest = streak.longest.max(streak.current);
+    STREAKS.with(|streaks| streaks.borrow_mut().insert(key, streak.clone()));
+
+    let previous_longest = previous.map(|previous| previous.longest);
+    if previous_longest != Some(streak.longest) {
+        STREAK_RANKING.with(|ranking| {
+            let mut ranking = ranking.borrow_mut();
+            if let Some(previous_longest) = previous_longest {
+                ranking.remove(&(Reverse(previous_longest), key));
+            }
+            ranking.insert((Reverse(streak.longest), key), ());
+        });
+    }
+}
+
+pub fn longest_streak(principal: Principal) -> u32 {
+    streak_of(principal).longest
+}
+
+#[ic_cdk::query]
+fn get_my_streak() -> Streak {
+    streak_of(caller())
+}
+
+#[ic_cdk::query]
+fn get_streak_leaderboard(limit: u64) -> Vec<StreakEntry> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STREAK_RANKING.with(|ranking| {
+        let mut entries: Vec<StreakEntry> = Vec::with_capacity(limit);
+        for (position, ((Reverse(longest), principal), _)) in
+            ranking.borrow().iter().take(limit).enumerate()
+        {
+            let rank = match entries.last() {
+                Some(previous) if previous.longest == longest => previous.rank,
+                _ => position as u64 + 1,
+            };
+            entries.push(StreakEntry {
+                principal: principal.0,
+                longest,
+                current: streak_of(principal.0).current,
+                rank,
+            });
+        }
+        entries
+    })
+}
+
+#[ic_cdk::query]
+fn get_streak_settings() -> StreakSettings {
+    SETTINGS.with(|settings| settings.borrow().get().clone())
+}
+
+// Moves the day boundary to midnight of another time zone. The day the change happens on may be
+// counted twice or skipped, the streaks are kept as they are.
+#[ic_cdk::update(guard = "only_admin")]
+fn set_streak_settings(settings: StreakSettings) -> Result<StreakSettings, Error> {
+    metrics::count_call("set_streak_settings");
+    maintenance::ensure_available("set_streak_settings")?;
+    if !(MIN_OFFSET_MINUTES..=MAX_OFFSET_MINUTES).contains(&settings.utc_offset_minutes) {
+        return Err(Error::ValidationFailed {
+            field: "utc_offset_minutes".to_string(),
+            msg: format!(
+                "the offset must be between {} and {} minutes",
+                MIN_OFFSET_MINUTES, MAX_OFFSET_MINUTES
+            ),
+        });
+    }
+    SETTINGS
+        .with(|cell| cell.borrow_mut().set(settings.clone()))
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot store the streak settings".to_string(),
+        })?;
+    Ok(settings)
+}
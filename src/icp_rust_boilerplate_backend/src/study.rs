@@ -0,0 +1,185 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, maintenance, metrics, Error, Memory, Quiz, StorablePrincipal, MAX_PAGE_SIZE,
+    MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const DAY_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const MAX_GRADE: u8 = 5;
+// grades below this one start the repetitions over
+const PASSING_GRADE: u8 = 3;
+const INITIAL_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+// the grades a vote on a scored quiz counts as
+const CORRECT_ANSWER_GRADE: u8 = 4;
+const WRONG_ANSWER_GRADE: u8 = 1;
+
+// (principal, due time) followed by the quiz id
+type DueKey = ((StorablePrincipal, u64), u64);
+
+// the SM-2 state of a scored quiz a principal answered
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Card {
+    // reviews in a row graded at least PASSING_GRADE
+    repetitions: u32,
+    interval_days: u64,
+    ease: f64,
+    due_at: u64,
+    reviewed_at: u64,
+}
+
+impl Storable for Card {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Card {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct StudyCard {
+    quiz: Quiz,
+    repetitions: u32,
+    interval_days: u64,
+    due_at: u64,
+}
+
+thread_local! {
+    // by (principal, quiz id)
+    static CARDS: RefCell<StableBTreeMap<(StorablePrincipal, u64), Card, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(93)))
+    ));
+
+    // the same cards ordered by the time they are due at
+    static DUE_CARDS: RefCell<StableBTreeMap<DueKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(94)))
+    ));
+}
+
+// SM-2: a passing grade spaces the next review further out, a failing one brings it back tomorrow.
+// The ease drops with every grade below the top one.
+fn reviewed(card: Option<Card>, grade: u8) -> Card {
+    let mut card = card.unwrap_or(Card {
+        repetitions: 0,
+        interval_days: 0,
+        ease: INITIAL_EASE,
+        due_at: 0,
+        reviewed_at: 0,
+    });
+    if grade >= PASSING_GRADE {
+        card.interval_days = match card.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (card.interval_days as f64 * card.ease).round() as u64,
+        };
+        card.repetitions += 1;
+    } else {
+        card.repetitions = 0;
+        card.interval_days = 1;
+    }
+    let miss = (MAX_GRADE - grade) as f64;
+    card.ease = (card.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+    card.reviewed_at = time();
+    card.due_at = time().saturating_add(card.interval_days.saturating_mul(DAY_NS));
+    card
+}
+
+fn save_review(principal: Principal, quiz_id: u64, grade: u8) -> Card {
+    let key = (StorablePrincipal(principal), quiz_id);
+    let previous = CARDS.with(|cards| cards.borrow().get(&key));
+    if let Some(previous) = &previous {
+        DUE_CARDS.with(|due| {
+            due.borrow_mut()
+                .remove(&((key.0, previous.due_at), quiz_id))
+        });
+    }
+    let card = reviewed(previous, grade);
+    CARDS.with(|cards| cards.borrow_mut().insert(key, card.clone()));
+    DUE_CARDS.with(|due| due.borrow_mut().insert(((key.0, card.due_at), quiz_id), ()));
+    card
+}
+
+// the first scored vote on a quiz puts it into the study deck of the voter, graded by the answer.
+pub fn add_card(quiz_id: u64, principal: Principal, correct: bool) {
+    let key = (StorablePrincipal(principal), quiz_id);
+    if CARDS.with(|cards| cards.borrow().contains_key(&key)) {
+        return;
+    }
+    save_review(
+        principal,
+        quiz_id,
+        if correct {
+            CORRECT_ANSWER_GRADE
+        } else {
+            WRONG_ANSWER_GRADE
+        },
+    );
+}
+
+// the quizzes of the caller due for a review, the longest overdue first. Removed quizzes are
+// left out.
+#[ic_cdk::query]
+fn get_due_reviews(limit: u64) -> Vec<StudyCard> {
+    let principal = StorablePrincipal(caller());
+    let due: Vec<(u64, u64)> = DUE_CARDS.with(|due| {
+        due.borrow()
+            .range(((principal, 0), 0)..=((principal, time()), u64::MAX))
+            .map(|(((_, due_at), quiz_id), _)| (quiz_id, due_at))
+            .collect()
+    });
+    due.into_iter()
+        .filter_map(|(quiz_id, _)| {
+            let quiz = _get_quiz(&quiz_id).filter(|quiz| quiz.deleted_at.is_none())?;
+            let card = CARDS.with(|cards| cards.borrow().get(&(principal, quiz_id)))?;
+            Some(StudyCard {
+                quiz,
+                repetitions: card.repetitions,
+                interval_days: card.interval_days,
+                due_at: card.due_at,
+            })
+        })
+        .take(limit.min(MAX_PAGE_SIZE) as usize)
+        .collect()
+}
+
+// Grades how well the caller remembered the answer, from 0 for a blackout to 5 for a perfect
+// recall. Reviews ahead of the due time count too.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn submit_review(question_id: u64, grade: u8) -> Result<StudyCard, Error> {
+    metrics::count_call("submit_review");
+    maintenance::ensure_available("submit_review")?;
+    if grade > MAX_GRADE {
+        return Err(Error::ValidationFailed {
+            field: "grade".to_string(),
+            msg: format!("the grade must be between 0 and {}", MAX_GRADE),
+        });
+    }
+    let key = (StorablePrincipal(caller()), question_id);
+    let quiz = _get_quiz(&question_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .filter(|_| CARDS.with(|cards| cards.borrow().contains_key(&key)))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("the quiz with id={} isn't in your study deck", question_id),
+        })?;
+    let card = save_review(caller(), question_id, grade);
+    Ok(StudyCard {
+        quiz,
+        repetitions: card.repetitions,
+        interval_days: card.interval_days,
+        due_at: card.due_at,
+    })
+}
@@ -0,0 +1,360 @@
+use crate::guards::only_authenticated;
+use crate::{
+    maintenance, metrics, validate_question, Error, IdCell, Memory, StorablePrincipal,
+    MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+// upper bound for the number of questions in a single survey
+pub const MAX_SURVEY_QUESTIONS: usize = 20;
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+pub struct SurveyQuestion {
+    pub question: String,
+    pub options: Vec<String>,
+    // tallies in the same order as the options
+    tallies: Vec<u64>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Survey {
+    id: u64,
+    title: String,
+    questions: Vec<SurveyQuestion>,
+    author: Principal,
+    created_at: u64,
+    updated_at: Option<u64>,
+}
+
+impl Survey {
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn author(&self) -> Principal {
+        self.author
+    }
+
+    pub fn questions(&self) -> &[SurveyQuestion] {
+        &self.questions
+    }
+}
+
+impl Storable for Survey {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Survey {
+    // a survey holds several quiz-sized questions
+    const MAX_SIZE: u32 = 8192;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// the questions a principal has answered in a survey, so each question is answered once
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct SurveyResponse {
+    // (question index, option index) pairs
+    answers: Vec<(u32, u32)>,
+    updated_at: u64,
+}
+
+impl Storable for SurveyResponse {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for SurveyResponse {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// the index of the correct option of every question, kept apart so answering never returns it
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct SurveyAnswerKey {
+    correct_options: Vec<Option<u32>>,
+}
+
+impl Storable for SurveyAnswerKey {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for SurveyAnswerKey {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static SURVEY_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))), 0)
+            .expect("Cannot create a survey counter")
+    );
+
+    static SURVEYS: RefCell<StableBTreeMap<u64, Survey, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+    ));
+
+    static SURVEY_RESPONSES: RefCell<StableBTreeMap<(u64, StorablePrincipal), SurveyResponse, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+    ));
+
+    // only surveys with at least one correct option have an answer key
+    static SURVEY_ANSWER_KEYS: RefCell<StableBTreeMap<u64, SurveyAnswerKey, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(84)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct SurveyQuestionPayload {
+    pub question: String,
+    pub options: Vec<String>,
+    // lets live sessions score the question, it is never returned to respondents
+    pub correct_option: Option<String>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct SurveyPayload {
+    pub title: String,
+    pub questions: Vec<SurveyQuestionPayload>,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct QuestionResults {
+    question: String,
+    tallies: Vec<(String, u64)>,
+    total: u64,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct SurveyResults {
+    survey_id: u64,
+    title: String,
+    questions: Vec<QuestionResults>,
+    respondents: u64,
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+fn create_survey(payload: SurveyPayload) -> Result<Survey, Error> {
+    metrics::count_call("create_survey");
+    maintenance::ensure_available("create_survey")?;
+    insert_survey(payload)
+}
+
+pub fn get_survey(survey_id: u64) -> Option<Survey> {
+    SURVEYS.with(|surveys| surveys.borrow().get(&survey_id))
+}
+
+// the index of the correct option of the question, None when the survey doesn't say.
+pub fn correct_option_of(survey_id: u64, question_index: u32) -> Option<u32> {
+    SURVEY_ANSWER_KEYS
+        .with(|keys| keys.borrow().get(&survey_id))
+        .and_then(|key| {
+            key.correct_options
+                .get(question_index as usize)
+                .copied()
+                .flatten()
+        })
+}
+
+// the index of the correct option among the options, it has to be one of them.
+pub fn validate_correct_option(
+    options: &[String],
+    correct_option: &Option<String>,
+) -> Result<Option<u32>, Error> {
+    let Some(correct_option) = correct_option else {
+        return Ok(None);
+    };
+    options
+        .iter()
+        .position(|option| option == correct_option)
+        .map(|index| Some(index as u32))
+        .ok_or_else(|| Error::ValidationFailed {
+            field: "correct_option".to_string(),
+            msg: format!(
+                "the correct option '{}' is not one of the options",
+                correct_option
+            ),
+        })
+}
+
+// checks and stores a new survey of the caller.
+pub fn insert_survey(payload: SurveyPayload) -> Result<Survey, Error> {
+    if payload.title.trim().is_empty() {
+        return Err(Error::ValidationFailed {
+            field: "title".to_string(),
+            msg: "the title must not be empty".to_string(),
+        });
+    }
+    if payload.questions.is_empty() || payload.questions.len() > MAX_SURVEY_QUESTIONS {
+        return Err(Error::ValidationFailed {
+            field: "questions".to_string(),
+            msg: format!(
+                "a survey needs between 1 and {} questions",
+                MAX_SURVEY_QUESTIONS
+            ),
+        });
+    }
+    let mut correct_options = Vec::with_capacity(payload.questions.len());
+    for question in &payload.questions {
+        validate_question(&question.question, &question.options)?;
+        correct_options.push(validate_correct_option(
+            &question.options,
+            &question.correct_option,
+        )?);
+    }
+
+    let mut survey = Survey {
+        id: 0,
+        title: payload.title,
+        questions: payload
+            .questions
+            .into_iter()
+            .map(|question| SurveyQuestion {
+                tallies: vec![0; question.options.len()],
+                question: question.question,
+                options: question.options,
+            })
+            .collect(),
+        author: caller(),
+        created_at: time(),
+        updated_at: None,
+    };
+    // the stable map traps on values above MAX_SIZE, so oversized surveys are rejected up front
+    if survey.to_bytes().len() > Survey::MAX_SIZE as usize {
+        return Err(Error::TooLarge {
+            msg: format!(
+                "a survey must not exceed {} bytes once encoded",
+                Survey::MAX_SIZE
+            ),
+        });
+    }
+
+    survey.id = SURVEY_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment survey id counter".to_string(),
+        })?;
+    SURVEYS.with(|surveys| surveys.borrow_mut().insert(survey.id, survey.clone()));
+    if correct_options.iter().any(Option::is_some) {
+        SURVEY_ANSWER_KEYS.with(|keys| {
+            keys.borrow_mut()
+                .insert(survey.id, SurveyAnswerKey { correct_options })
+        });
+    }
+    Ok(survey)
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+fn answer_survey_question(
+    survey_id: u64,
+    question_index: u32,
+    option: String,
+) -> Result<Survey, Error> {
+    metrics::count_call("answer_survey_question");
+    maintenance::ensure_available("answer_survey_question")?;
+    let mut survey = SURVEYS
+        .with(|surveys| surveys.borrow().get(&survey_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a survey with id={} not found", survey_id),
+        })?;
+
+    let question = survey
+        .questions
+        .get_mut(question_index as usize)
+        .ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "the survey with id={} has no question {}",
+                survey_id, question_index
+            ),
+        })?;
+    let option_index = question
+        .options
+        .iter()
+        .position(|candidate| *candidate == option)
+        .ok_or_else(|| Error::ValidationFailed {
+            field: "option".to_string(),
+            msg: format!("The option '{}' is not found for this question.", option),
+        })?;
+
+    let response_key = (survey_id, StorablePrincipal(caller()));
+    let mut response = SURVEY_RESPONSES
+        .with(|responses| responses.borrow().get(&response_key))
+        .unwrap_or_default();
+    if response
+        .answers
+        .iter()
+        .any(|(answered, _)| *answered == question_index)
+    {
+        return Err(Error::AlreadyVoted {
+            msg: format!(
+                "you have already answered question {} of the survey with id={}",
+                question_index, survey_id
+            ),
+        });
+    }
+
+    question.tallies[option_index] += 1;
+    survey.updated_at = Some(time());
+    SURVEYS.with(|surveys| surveys.borrow_mut().insert(survey_id, survey.clone()));
+
+    response.answers.push((question_index, option_index as u32));
+    response.updated_at = time();
+    SURVEY_RESPONSES.with(|responses| responses.borrow_mut().insert(response_key, response));
+    Ok(survey)
+}
+
+#[ic_cdk::query]
+fn get_survey_results(survey_id: u64) -> Result<SurveyResults, Error> {
+    let survey = SURVEYS
+        .with(|surveys| surveys.borrow().get(&survey_id))
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a survey with id={} not found", survey_id),
+        })?;
+    let respondents = SURVEY_RESPONSES.with(|responses| {
+        responses
+            .borrow()
+            .range(crate::id_key_range(survey_id))
+            .count() as u64
+    });
+
+    Ok(SurveyResults {
+        survey_id,
+        title: survey.title,
+        questions: survey
+            .questions
+            .into_iter()
+            .map(|question| QuestionResults {
+                total: question.tallies.iter().sum(),
+                tallies: question.options.into_iter().zip(question.tallies).collect(),
+                question: question.question,
+            })
+            .collect(),
+        respondents,
+    })
+}
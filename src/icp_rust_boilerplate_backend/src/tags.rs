@@ -0,0 +1,150 @@
+use crate::{_get_quiz, allowlist, Error, Memory, Quiz, QuizPage, MAX_PAGE_SIZE, MEMORY_MANAGER};
+use ic_cdk::api::caller;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+const MAX_TAGS_PER_QUIZ: usize = 5;
+const MAX_TAG_LEN: usize = 32;
+const MAX_CATEGORY_LEN: usize = 32;
+
+type Tag = Blob<MAX_TAG_LEN>;
+
+thread_local! {
+    static TAG_INDEX: RefCell<StableBTreeMap<(Tag, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12)))
+    ));
+
+    // number of quizzes per tag, kept next to the index so listing tags doesn't walk it
+    static TAG_COUNTS: RefCell<StableBTreeMap<Tag, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct TagCount {
+    tag: String,
+    quizzes: u64,
+}
+
+// tags are stored lowercase so "Rust" and "rust" end up in the same listing.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+pub fn validate_tags(tags: &[String]) -> Result<(), Error> {
+    if tags.len() > MAX_TAGS_PER_QUIZ {
+        return Err(Error::ValidationFailed {
+            field: "tags".to_string(),
+            msg: format!("a quiz can have at most {} tags", MAX_TAGS_PER_QUIZ),
+        });
+    }
+    for tag in tags {
+        if tag.is_empty()
+            || tag.len() > MAX_TAG_LEN
+            || !tag.chars().all(|c| c.is_alphanumeric() || c == '-')
+        {
+            return Err(Error::ValidationFailed {
+                field: "tags".to_string(),
+                msg: format!(
+                    "the tag '{}' must be 1 to {} letters, digits or dashes",
+                    tag, MAX_TAG_LEN
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_category(category: &str) -> Result<(), Error> {
+    if category.trim().is_empty() || category.len() > MAX_CATEGORY_LEN {
+        return Err(Error::ValidationFailed {
+            field: "category".to_string(),
+            msg: format!("the category must be 1 to {} bytes long", MAX_CATEGORY_LEN),
+        });
+    }
+    Ok(())
+}
+
+fn to_key(tag: &str) -> Option<Tag> {
+    Tag::try_from(tag.as_bytes()).ok()
+}
+
+pub fn index_quiz(quiz: &Quiz) {
+    for tag in quiz.tags.iter().flatten().filter_map(|tag| to_key(tag)) {
+        let added = TAG_INDEX.with(|index| index.borrow_mut().insert((tag, quiz.id), ()));
+        if added.is_none() {
+            TAG_COUNTS.with(|counts| {
+                let mut counts = counts.borrow_mut();
+                let count = counts.get(&tag).unwrap_or_default();
+                counts.insert(tag, count + 1);
+            });
+        }
+    }
+}
+
+pub fn unindex_quiz(quiz: &Quiz) {
+    for tag in quiz.tags.iter().flatten().filter_map(|tag| to_key(tag)) {
+        let removed = TAG_INDEX.with(|index| index.borrow_mut().remove(&(tag, quiz.id)));
+        if removed.is_some() {
+            TAG_COUNTS.with(|counts| {
+                let mut counts = counts.borrow_mut();
+                match counts.get(&tag).unwrap_or_default() {
+                    0 | 1 => counts.remove(&tag),
+                    count => counts.insert(tag, count - 1),
+                };
+            });
+        }
+    }
+}
+
+pub fn reindex_quiz(previous: &Quiz, current: &Quiz) {
+    unindex_quiz(previous);
+    index_quiz(current);
+}
+
+#[ic_cdk::query]
+fn get_quizzes_by_tag(tag: String, offset: u64, limit: u64) -> QuizPage {
+    let tag = match to_key(&tag.trim().to_lowercase()) {
+        Some(tag) => tag,
+        None => return QuizPage::default(),
+    };
+    let quizzes: Vec<Quiz> = TAG_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((tag, 0)..=(tag, u64::MAX))
+            .filter_map(|((_, quiz_id), _)| _get_quiz(&quiz_id))
+            .filter(|quiz| allowlist::can_view(quiz, caller()))
+            .skip(offset as usize)
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .collect()
+    });
+    QuizPage {
+        quizzes,
+        total: TAG_COUNTS.with(|counts| counts.borrow().get(&tag).unwrap_or_default()),
+    }
+}
+
+#[ic_cdk::query]
+fn list_tags() -> Vec<TagCount> {
+    TAG_COUNTS.with(|counts| {
+        counts
+            .borrow()
+            .iter()
+            .map(|(tag, quizzes)| TagCount {
+                tag: String::from_utf8_lossy(tag.as_slice()).into_owned(),
+                quizzes,
+            })
+            .collect()
+    })
+}
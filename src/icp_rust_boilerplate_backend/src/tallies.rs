@@ -0,0 +1,118 @@
+use crate::{certification, Memory, Quiz, MEMORY_MANAGER};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// position of the option in `Quiz::options`, updating a quiz resets its tallies
+type OptionKey = u32;
+type TallyMap<V> = RefCell<StableBTreeMap<(u64, OptionKey), V, Memory>>;
+
+thread_local! {
+    // votes per option, a vote only rewrites its own small entry instead of the whole quiz
+    static TALLIES: TallyMap<u64> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31)))
+    ));
+
+    // sum of the voters' token balances per option, for token-weighted quizzes
+    static WEIGHTED_TALLIES: TallyMap<u128> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)))
+    ));
+}
+
+fn option_key(quiz: &Quiz, option: &str) -> Option<OptionKey> {
+    quiz.options
+        .iter()
+        .position(|candidate| candidate == option)
+        .map(|position| position as OptionKey)
+}
+
+pub fn add(quiz: &Quiz, option: &str, count: u64) {
+    if let Some(option_key) = option_key(quiz, option) {
+        TALLIES.with(|tallies| {
+            let mut tallies = tallies.borrow_mut();
+            let key = (quiz.id, option_key);
+            let current = tallies.get(&key).unwrap_or_default();
+            tallies.insert(key, current.saturating_add(count));
+        });
+        certification::certify(quiz.id);
+    }
+}
+
+pub fn add_weight(quiz: &Quiz, option: &str, weight: u128) {
+    if let Some(option_key) = option_key(quiz, option) {
+        WEIGHTED_TALLIES.with(|tallies| {
+            let mut tallies = tallies.borrow_mut();
+            let key = (quiz.id, option_key);
+            let current = tallies.get(&key).unwrap_or_default();
+            tallies.insert(key, current.saturating_add(weight));
+        });
+        certification::certify(quiz.id);
+    }
+}
+
+// overwrites every tally of the quiz, options missing from the maps end up at zero.
+pub fn replace(quiz: &Quiz, answers: &HashMap<String, u64>, weighted: &HashMap<String, u128>) {
+    remove(quiz.id);
+    for (option, count) in answers.iter().filter(|(_, count)| **count > 0) {
+        add(quiz, option, *count);
+    }
+    for (option, weight) in weighted.iter().filter(|(_, weight)| **weight > 0) {
+        add_weight(quiz, option, *weight);
+    }
+}
+
+// the callers certify the quiz once it is gone or stored again.
+pub fn remove(quiz_id: u64) {
+    remove_range(&TALLIES, quiz_id);
+    remove_range(&WEIGHTED_TALLIES, quiz_id);
+}
+
+fn remove_range<V: BoundedStorable>(
+    map: &'static std::thread::LocalKey<TallyMap<V>>,
+    quiz_id: u64,
+) {
+    map.with(|map| {
+        let mut map = map.borrow_mut();
+        let keys: Vec<(u64, OptionKey)> = map
+            .range((quiz_id, 0)..=(quiz_id, OptionKey::MAX))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            map.remove(&key);
+        }
+    });
+}
+
+// fills in the answers map (and the weighted one) that stored quizzes are kept without.
+pub fn attach(mut quiz: Quiz) -> Quiz {
+    let counts: Vec<u64> = TALLIES.with(|tallies| {
+        let tallies = tallies.borrow();
+        (0..quiz.options.len() as OptionKey)
+            .map(|option_key| tallies.get(&(quiz.id, option_key)).unwrap_or_default())
+            .collect()
+    });
+    quiz.answers = quiz
+        .options
+        .iter()
+        .zip(counts)
+        .map(|(option, count)| (option.clone(), count.min(u32::MAX as u64) as u32))
+        .collect();
+    quiz.weighted_answers = quiz.is_weighted().then(|| {
+        WEIGHTED_TALLIES.with(|tallies| {
+            let tallies = tallies.borrow();
+            quiz.options
+                .iter()
+                .enumerate()
+                .filter_map(|(position, option)| {
+                    tallies
+                        .get(&(quiz.id, position as OptionKey))
+                        .map(|weight| (option.clone(), weight))
+                })
+                .collect()
+        })
+    });
+    quiz
+}
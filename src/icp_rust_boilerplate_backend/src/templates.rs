@@ -0,0 +1,359 @@
+use crate::guards::only_authenticated;
+use crate::numeric::{self, NumericRange};
+use crate::organizations::{self, OrgRole};
+use crate::scoring::ScoringPolicy;
+use crate::{
+    _get_quiz, create_quiz, ensure_editor, maintenance, metrics, moderation, scoring, Error,
+    IdCell, Memory, Quiz, QuizPayload, StorablePrincipal, Visibility, VotingMode, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MIN_NAME_LEN: usize = 3;
+const MAX_NAME_LEN: usize = 64;
+// per principal and per organization
+const MAX_TEMPLATES: usize = 50;
+
+// the settings of a quiz without its votes and with its schedule turned into durations
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct Template {
+    id: u64,
+    name: String,
+    created_by: Principal,
+    // shared with the members of the organization, personal to its creator when None
+    organization: Option<u64>,
+    question: String,
+    options: Vec<String>,
+    // the question and the options are Markdown
+    rendered: Option<bool>,
+    // anyone who can use the template can read them, like the editors of the quiz could
+    correct_options: Option<Vec<String>>,
+    scoring_policy: Option<ScoringPolicy>,
+    wrong_answer_penalty: Option<f64>,
+    numeric_range: Option<NumericRange>,
+    correct_number: Option<f64>,
+    tags: Option<Vec<String>>,
+    category: Option<String>,
+    voting_mode: Option<VotingMode>,
+    weight_token: Option<Principal>,
+    credits_per_voter: Option<u32>,
+    max_selections: Option<u32>,
+    visibility: Option<Visibility>,
+    allow_anonymous: Option<bool>,
+    vote_cooldown_ns: Option<u64>,
+    // how long a quiz created from the template runs, it has no end when None
+    duration_ns: Option<u64>,
+    // how long the reveal phase of a commit-reveal quiz lasts after the end
+    reveal_duration_ns: Option<u64>,
+    created_at: u64,
+}
+
+impl Storable for Template {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Template {
+    // the quiz and its answer key, which holds at most every option
+    const MAX_SIZE: u32 = 2 * Quiz::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// what a quiz created from a template does differently, everything left out comes from it
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+pub struct TemplateOverrides {
+    question: Option<String>,
+    // replacing the options drops the correct options of the template
+    options: Option<Vec<String>>,
+    correct_options: Option<Vec<String>>,
+    tags: Option<Vec<String>>,
+    category: Option<String>,
+    starts_at: Option<u64>,
+    // used instead of the duration of the template
+    ends_at: Option<u64>,
+    visibility: Option<Visibility>,
+    draft: Option<bool>,
+    idempotency_key: Option<String>,
+}
+
+thread_local! {
+    static TEMPLATES: RefCell<StableBTreeMap<u64, Template, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(76)))
+    ));
+
+    static TEMPLATE_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(77))), 0)
+            .expect("Cannot create the template counter")
+    );
+
+    // (creator, template id) of the personal templates
+    static TEMPLATES_BY_PRINCIPAL: RefCell<StableBTreeMap<(StorablePrincipal, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(78)))
+    ));
+
+    // (organization, template id) of the shared templates
+    static TEMPLATES_BY_ORG: RefCell<StableBTreeMap<(u64, u64), (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(79)))
+    ));
+}
+
+fn validate_name(name: &str) -> Result<(), Error> {
+    let len = name.trim().len();
+    if !(MIN_NAME_LEN..=MAX_NAME_LEN).contains(&len) {
+        return Err(Error::ValidationFailed {
+            field: "name".to_string(),
+            msg: format!(
+                "the name must be {} to {} bytes",
+                MIN_NAME_LEN, MAX_NAME_LEN
+            ),
+        });
+    }
+    moderation::ensure_clean("name", name)
+}
+
+fn template_ids(organization: Option<u64>, principal: Principal) -> Vec<u64> {
+    match organization {
+        Some(org_id) => TEMPLATES_BY_ORG.with(|index| {
+            index
+                .borrow()
+                .range((org_id, 0)..=(org_id, u64::MAX))
+                .map(|((_, id), _)| id)
+                .collect()
+        }),
+        None => TEMPLATES_BY_PRINCIPAL.with(|index| {
+            let principal = StorablePrincipal(principal);
+            index
+                .borrow()
+                .range((principal, 0)..=(principal, u64::MAX))
+                .map(|((_, id), _)| id)
+                .collect()
+        }),
+    }
+}
+
+// the creator uses their personal templates, every member those of the organization.
+fn usable_template(id: u64) -> Result<Template, Error> {
+    let template = TEMPLATES
+        .with(|templates| templates.borrow().get(&id))
+        .filter(|template| match template.organization {
+            Some(org_id) => organizations::role_in(org_id, caller()).is_some(),
+            None => template.created_by == caller(),
+        })
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a template with id={} not found", id),
+        })?;
+    Ok(template)
+}
+
+fn template_of(quiz: &Quiz, name: String, organization: Option<u64>, id: u64) -> Template {
+    let duration_ns = quiz
+        .ends_at
+        .map(|ends_at| ends_at.saturating_sub(quiz.starts_at.unwrap_or(quiz.created_at)));
+    let reveal_duration_ns = quiz
+        .ends_at
+        .zip(quiz.reveal_ends_at)
+        .map(|(ends_at, reveal_ends_at)| reveal_ends_at.saturating_sub(ends_at));
+    Template {
+        id,
+        name: name.trim().to_string(),
+        created_by: caller(),
+        organization,
+        question: quiz.question.clone(),
+        options: quiz.options.clone(),
+        rendered: quiz.rendered,
+        correct_options: Some(scoring::correct_options_of(quiz.id))
+            .filter(|options| !options.is_empty()),
+        scoring_policy: scoring::scoring_policy_of(quiz.id),
+        wrong_answer_penalty: scoring::wrong_answer_penalty_of(quiz.id),
+        numeric_range: numeric::numeric_range_of(quiz.id),
+        correct_number: numeric::correct_number_of(quiz.id),
+        tags: quiz.tags.clone(),
+        category: quiz.category.clone(),
+        voting_mode: quiz.voting_mode.clone(),
+        weight_token: quiz.weight_token,
+        credits_per_voter: quiz.credits_per_voter,
+        max_selections: quiz.max_selections,
+        visibility: quiz.visibility.clone(),
+        allow_anonymous: quiz.allow_anonymous,
+        vote_cooldown_ns: quiz.vote_cooldown_ns,
+        duration_ns,
+        reveal_duration_ns,
+        created_at: time(),
+    }
+}
+
+// Saves the settings of a quiz the caller can edit as a template, shared with an organization of
+// the caller or kept to themselves.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn save_as_template(
+    quiz_id: u64,
+    name: String,
+    organization: Option<u64>,
+) -> Result<Template, Error> {
+    metrics::count_call("save_as_template");
+    maintenance::ensure_available("save_as_template")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_editor(&quiz)?;
+    validate_name(&name)?;
+    if let Some(org_id) = organization {
+        organizations::ensure_member(org_id)?;
+    }
+    if template_ids(organization, caller()).len() >= MAX_TEMPLATES {
+        return Err(Error::StorageFull {
+            msg: format!("at most {} templates can be kept", MAX_TEMPLATES),
+        });
+    }
+    // weighted options come on top of the answer key, so a template may not fit
+    let mut template = template_of(&quiz, name, organization, 0);
+    if template.to_bytes().len() > Template::MAX_SIZE as usize {
+        return Err(Error::TooLarge {
+            msg: format!(
+                "a template must not exceed {} bytes once encoded",
+                Template::MAX_SIZE
+            ),
+        });
+    }
+    let id = TEMPLATE_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .map_err(|_| Error::StorageFull {
+            msg: "cannot increment template counter".to_string(),
+        })?;
+    template.id = id;
+    TEMPLATES.with(|templates| templates.borrow_mut().insert(id, template.clone()));
+    match organization {
+        Some(org_id) => {
+            TEMPLATES_BY_ORG.with(|index| index.borrow_mut().insert((org_id, id), ()));
+        }
+        None => {
+            TEMPLATES_BY_PRINCIPAL.with(|index| {
+                index
+                    .borrow_mut()
+                    .insert((StorablePrincipal(caller()), id), ())
+            });
+        }
+    }
+    Ok(template)
+}
+
+// the templates of the organization, or the personal ones of the caller, the oldest first.
+#[ic_cdk::query]
+fn list_templates(organization: Option<u64>) -> Result<Vec<Template>, Error> {
+    if let Some(org_id) = organization {
+        organizations::ensure_member(org_id)?;
+    }
+    Ok(TEMPLATES.with(|templates| {
+        let templates = templates.borrow();
+        template_ids(organization, caller())
+            .into_iter()
+            .filter_map(|id| templates.get(&id))
+            .collect()
+    }))
+}
+
+// the creator removes their template, as do the admins of the organization it is shared with.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn delete_template(id: u64) -> Result<(), Error> {
+    metrics::count_call("delete_template");
+    maintenance::ensure_available("delete_template")?;
+    let template = usable_template(id)?;
+    let org_admin = template
+        .organization
+        .and_then(|org_id| organizations::role_in(org_id, caller()))
+        .is_some_and(|role| role >= OrgRole::Admin);
+    if template.created_by != caller() && !org_admin {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only its creator or an admin can delete the template with id={}",
+                id
+            ),
+        });
+    }
+    TEMPLATES.with(|templates| templates.borrow_mut().remove(&id));
+    match template.organization {
+        Some(org_id) => {
+            TEMPLATES_BY_ORG.with(|index| index.borrow_mut().remove(&(org_id, id)));
+        }
+        None => {
+            TEMPLATES_BY_PRINCIPAL.with(|index| {
+                index
+                    .borrow_mut()
+                    .remove(&(StorablePrincipal(template.created_by), id))
+            });
+        }
+    }
+    Ok(())
+}
+
+// Creates a quiz from the template with the caller as its author, it is checked like every other
+// new quiz. A shared template creates a quiz of its organization.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn create_from_template(template_id: u64, overrides: TemplateOverrides) -> Result<Quiz, Error> {
+    metrics::count_call("create_from_template");
+    maintenance::ensure_available("create_from_template")?;
+    let template = usable_template(template_id)?;
+    let start = overrides.starts_at.unwrap_or_else(time);
+    let ends_at = overrides.ends_at.or_else(|| {
+        template
+            .duration_ns
+            .map(|duration| start.saturating_add(duration.max(1)))
+    });
+    // the weights of a scoring policy name the options of the template
+    let (correct_options, scoring_policy) = match &overrides.options {
+        Some(_) => (overrides.correct_options, None),
+        None => (
+            overrides.correct_options.or(template.correct_options),
+            template.scoring_policy,
+        ),
+    };
+    let wrong_answer_penalty = template
+        .wrong_answer_penalty
+        .filter(|_| correct_options.is_some());
+    create_quiz(QuizPayload {
+        question: overrides.question.unwrap_or(template.question),
+        options: overrides.options.unwrap_or(template.options),
+        starts_at: overrides.starts_at,
+        ends_at,
+        voting_mode: template.voting_mode,
+        correct_options,
+        correct_options_hash: None,
+        scoring_policy,
+        wrong_answer_penalty,
+        numeric_range: template.numeric_range,
+        correct_number: template.correct_number,
+        explanations: None,
+        rendered: template.rendered,
+        option_images: None,
+        tags: overrides.tags.or(template.tags),
+        category: overrides.category.or(template.category),
+        weight_token: template.weight_token,
+        credits_per_voter: template.credits_per_voter,
+        max_selections: template.max_selections,
+        reveal_ends_at: ends_at
+            .zip(template.reveal_duration_ns)
+            .map(|(ends_at, duration)| ends_at.saturating_add(duration)),
+        visibility: overrides.visibility.or(template.visibility),
+        allow_anonymous: template.allow_anonymous,
+        vote_cooldown_ns: template.vote_cooldown_ns,
+        draft: overrides.draft,
+        idempotency_key: overrides.idempotency_key,
+        organization: template.organization,
+    })
+}
@@ -0,0 +1,80 @@
+use crate::roles::{self, Role};
+use crate::{_get_quiz, Error, Memory, MEMORY_MANAGER};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+
+const HOUR_NS: u64 = 60 * 60 * 1_000_000_000;
+// three months of hours, a longer range has to be fetched in several calls
+const MAX_BUCKETS: usize = 90 * 24;
+
+thread_local! {
+    // votes by (quiz, start of the hour they were cast in)
+    static VOTE_BUCKETS: RefCell<StableBTreeMap<(u64, u64), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(40)))
+    ));
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct VoteBucket {
+    hour_start: u64,
+    votes: u64,
+}
+
+// counts a vote, or a ballot, in the hour it was cast. Every way of voting calls this once.
+pub fn record_vote(quiz_id: u64) {
+    let hour_start = time() - time() % HOUR_NS;
+    VOTE_BUCKETS.with(|buckets| {
+        let mut buckets = buckets.borrow_mut();
+        let votes = buckets.get(&(quiz_id, hour_start)).unwrap_or_default();
+        buckets.insert((quiz_id, hour_start), votes + 1);
+    });
+}
+
+// the timeline goes together with the votes, see `remove_votes`.
+pub fn remove_timeline(quiz_id: u64) {
+    VOTE_BUCKETS.with(|buckets| {
+        let mut buckets = buckets.borrow_mut();
+        let hours: Vec<u64> = buckets
+            .range((quiz_id, 0)..=(quiz_id, u64::MAX))
+            .map(|((_, hour_start), _)| hour_start)
+            .collect();
+        for hour_start in hours {
+            buckets.remove(&(quiz_id, hour_start));
+        }
+    });
+}
+
+// The hours between `from` and `to` (both in ns) in which votes were cast, oldest first. Hours
+// without votes are left out.
+#[ic_cdk::query]
+fn get_vote_timeline(quiz_id: u64, from: u64, to: u64) -> Result<Vec<VoteBucket>, Error> {
+    let author = _get_quiz(&quiz_id).and_then(|quiz| quiz.author);
+    if author != Some(caller()) && !roles::has_role(caller(), Role::Admin) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only the author or an admin can read the timeline of the quiz with id={}",
+                quiz_id
+            ),
+        });
+    }
+    if from > to {
+        return Err(Error::ValidationFailed {
+            field: "from".to_string(),
+            msg: "the range must not end before it starts".to_string(),
+        });
+    }
+
+    // the hour `from` falls into is included
+    let first_hour = from - from % HOUR_NS;
+    Ok(VOTE_BUCKETS.with(|buckets| {
+        buckets
+            .borrow()
+            .range((quiz_id, first_hour)..=(quiz_id, to))
+            .take(MAX_BUCKETS)
+            .map(|((_, hour_start), votes)| VoteBucket { hour_start, votes })
+            .collect()
+    }))
+}
@@ -0,0 +1,157 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, ensure_author, icrc, maintenance, metrics, Error, Memory, Quiz, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode, Principal};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::collections::HashMap;
+use std::{borrow::Cow, cell::RefCell};
+
+// a balance is looked up again after 5 minutes, a vote in between goes by the old one
+const CACHE_TTL_NS: u64 = 5 * 60 * 1_000_000_000;
+// stale balances are dropped once the cache grows past this
+const MAX_CACHED_BALANCES: usize = 10_000;
+
+// voting takes at least `min_balance` of the token in the default account of the voter
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct TokenGate {
+    ledger: Principal,
+    min_balance: u128,
+}
+
+impl Storable for TokenGate {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for TokenGate {
+    const MAX_SIZE: u32 = 128;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static TOKEN_GATES: RefCell<StableBTreeMap<u64, TokenGate, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(120)))
+    ));
+
+    // balances by (ledger, owner) with the time they were looked up at, lost on upgrades
+    static BALANCES: RefCell<HashMap<(Principal, Principal), (u128, u64)>> =
+        RefCell::new(HashMap::new());
+}
+
+fn gate_of(quiz_id: u64) -> Option<TokenGate> {
+    TOKEN_GATES.with(|gates| gates.borrow().get(&quiz_id))
+}
+
+pub fn remove_token_gate(quiz_id: u64) {
+    TOKEN_GATES.with(|gates| gates.borrow_mut().remove(&quiz_id));
+}
+
+fn cached_balance(ledger: Principal, owner: Principal) -> Option<u128> {
+    BALANCES
+        .with(|balances| balances.borrow().get(&(ledger, owner)).copied())
+        .filter(|(_, fetched_at)| time() < fetched_at.saturating_add(CACHE_TTL_NS))
+        .map(|(balance, _)| balance)
+}
+
+// Looks up the balance of the voter for the gate of the quiz unless a recent one is cached.
+pub async fn refresh(quiz_id: u64, voter: Principal) -> Result<(), Error> {
+    let Some(gate) = gate_of(quiz_id) else {
+        return Ok(());
+    };
+    if voter == Principal::anonymous() || cached_balance(gate.ledger, voter).is_some() {
+        return Ok(());
+    }
+    let balance = icrc::balance_of(gate.ledger, voter).await?;
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        if balances.len() >= MAX_CACHED_BALANCES {
+            balances.retain(|_, (_, fetched_at)| time() < fetched_at.saturating_add(CACHE_TTL_NS));
+        }
+        balances.insert((gate.ledger, voter), (balance, time()));
+    });
+    Ok(())
+}
+
+// Every way of voting goes through here, see `ensure_open`. Only `answer_quiz` looks the balance
+// up itself, the others take the one `check_token_gate` cached.
+pub fn ensure_holder(quiz: &Quiz) -> Result<(), Error> {
+    let Some(gate) = gate_of(quiz.id) else {
+        return Ok(());
+    };
+    match cached_balance(gate.ledger, caller()) {
+        Some(balance) if balance >= gate.min_balance => Ok(()),
+        Some(balance) => Err(Error::NotEligible {
+            msg: format!(
+                "voting on the quiz with id={} takes at least {} of the token of {}, you hold {}",
+                quiz.id, gate.min_balance, gate.ledger, balance
+            ),
+        }),
+        None => Err(Error::NotEligible {
+            msg: format!(
+                "voting on the quiz with id={} takes at least {} of the token of {}, see \
+                 check_token_gate",
+                quiz.id, gate.min_balance, gate.ledger
+            ),
+        }),
+    }
+}
+
+// None opens the quiz to every voter again.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn set_token_gate(quiz_id: u64, gate: Option<TokenGate>) -> Result<(), Error> {
+    metrics::count_call("set_token_gate");
+    maintenance::ensure_available("set_token_gate")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    ensure_author(&quiz)?;
+    let msg = match gate {
+        Some(gate) if gate.ledger == Principal::anonymous() => {
+            "the gate needs the principal of the ledger of its token"
+        }
+        Some(gate) if gate.min_balance == 0 => "a gate takes more than nothing, None removes it",
+        Some(gate) => {
+            TOKEN_GATES.with(|gates| gates.borrow_mut().insert(quiz_id, gate));
+            return Ok(());
+        }
+        None => {
+            remove_token_gate(quiz_id);
+            return Ok(());
+        }
+    };
+    Err(Error::ValidationFailed {
+        field: "gate".to_string(),
+        msg: msg.to_string(),
+    })
+}
+
+#[ic_cdk::query]
+fn get_token_gate(quiz_id: u64) -> Option<TokenGate> {
+    gate_of(quiz_id)
+}
+
+// Looks up the balance of the caller for the gate of the quiz, so the ways of voting that don't
+// call the ledger themselves let them in for a while. Returns whether the caller may vote.
+#[ic_cdk::update(guard = "only_authenticated")]
+async fn check_token_gate(quiz_id: u64) -> Result<bool, Error> {
+    metrics::count_call("check_token_gate");
+    maintenance::ensure_available("check_token_gate")?;
+    let quiz = _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })?;
+    refresh(quiz_id, caller()).await?;
+    Ok(ensure_holder(&quiz).is_ok())
+}
@@ -0,0 +1,230 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, allowlist, ensure_editor, maintenance, metrics, sanitizer, validate_question,
+    validate_question_text, Error, Memory, Quiz, MEMORY_MANAGER,
+};
+use candid::{Decode, Encode};
+use ic_cdk::api::time;
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::storable::Blob;
+use ic_stable_structures::{BoundedStorable, StableBTreeMap, Storable};
+use std::{borrow::Cow, cell::RefCell};
+
+const MAX_LOCALE_LEN: usize = 16;
+const MAX_TRANSLATIONS: usize = 20;
+
+type Locale = Blob<MAX_LOCALE_LEN>;
+
+// the question and the options in another language, the options in the order of the quiz
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+pub struct TranslationPayload {
+    question: String,
+    options: Vec<String>,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Translation {
+    question: String,
+    options: Vec<String>,
+    updated_at: u64,
+}
+
+impl Storable for Translation {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<'_, [u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Translation {
+    // a question and at most 20 options, as long as the ones of the quiz may be
+    const MAX_SIZE: u32 = 16 * 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// A quiz as shown in one language. The options keep the order of the quiz, votes still name the
+// original labels or go through `answer_quiz_option`.
+#[derive(candid::CandidType, Serialize, Deserialize)]
+pub struct LocalizedQuiz {
+    quiz: Quiz,
+    // None when the quiz is shown in its default language
+    locale: Option<String>,
+    question: String,
+    options: Vec<String>,
+    available_locales: Vec<String>,
+}
+
+thread_local! {
+    static TRANSLATIONS: RefCell<StableBTreeMap<(u64, Locale), Translation, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(101)))
+    ));
+}
+
+// Locales are language tags like "de" or "pt-BR", kept lowercase since tags aren't case
+// sensitive.
+fn normalize_locale(locale: &str) -> Result<String, Error> {
+    let locale = locale.trim().to_ascii_lowercase();
+    let language = locale.split('-').next().unwrap_or_default();
+    if locale.len() > MAX_LOCALE_LEN
+        || !(2..=3).contains(&language.len())
+        || !language.chars().all(|c| c.is_ascii_lowercase())
+        || locale
+            .split('-')
+            .any(|subtag| subtag.is_empty() || !subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        return Err(Error::ValidationFailed {
+            field: "locale".to_string(),
+            msg: format!(
+                "the locale '{}' must be a language tag like 'de' or 'pt-BR' of at most {} bytes",
+                locale, MAX_LOCALE_LEN
+            ),
+        });
+    }
+    Ok(locale)
+}
+
+fn locale_key(locale: &str) -> Locale {
+    Locale::try_from(locale.as_bytes()).unwrap()
+}
+
+fn key_range(quiz_id: u64) -> std::ops::Range<(u64, Locale)> {
+    (quiz_id, Locale::default())..(quiz_id + 1, Locale::default())
+}
+
+fn locales_of(quiz_id: u64) -> Vec<String> {
+    TRANSLATIONS.with(|translations| {
+        translations
+            .borrow()
+            .range(key_range(quiz_id))
+            .map(|((_, locale), _)| String::from_utf8_lossy(locale.as_slice()).into_owned())
+            .collect()
+    })
+}
+
+// helper method to forget the translations of a quiz, they'd no longer match once its question
+// or options change.
+pub fn remove_translations(quiz_id: u64) {
+    TRANSLATIONS.with(|translations| {
+        let mut translations = translations.borrow_mut();
+        let keys: Vec<(u64, Locale)> = translations
+            .range(key_range(quiz_id))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            translations.remove(&key);
+        }
+    });
+}
+
+fn live_quiz(quiz_id: u64) -> Result<Quiz, Error> {
+    _get_quiz(&quiz_id)
+        .filter(|quiz| quiz.deleted_at.is_none())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a quiz with id={} not found", quiz_id),
+        })
+}
+
+// Adds or replaces the translation of a quiz into a locale. It translates every option of the
+// quiz and is held to the same rules as the original text.
+#[ic_cdk::update(guard = "only_authenticated")]
+fn add_translation(quiz_id: u64, locale: String, payload: TranslationPayload) -> Result<(), Error> {
+    metrics::count_call("add_translation");
+    maintenance::ensure_available("add_translation")?;
+    let quiz = live_quiz(quiz_id)?;
+    ensure_editor(&quiz)?;
+    let locale = normalize_locale(&locale)?;
+    let key = (quiz_id, locale_key(&locale));
+    let exists = TRANSLATIONS.with(|translations| translations.borrow().contains_key(&key));
+    if !exists && locales_of(quiz_id).len() >= MAX_TRANSLATIONS {
+        return Err(Error::StorageFull {
+            msg: format!("a quiz can have at most {} translations", MAX_TRANSLATIONS),
+        });
+    }
+
+    let clean = |text: &str| match quiz.rendered.unwrap_or_default() {
+        true => sanitizer::sanitize_markdown(text),
+        false => text.to_string(),
+    };
+    let question = clean(&payload.question);
+    let options: Vec<String> = payload.options.iter().map(|option| clean(option)).collect();
+    if options.len() != quiz.options.len() {
+        return Err(Error::ValidationFailed {
+            field: "options".to_string(),
+            msg: format!(
+                "the quiz has {} options, every one of them needs a translation",
+                quiz.options.len()
+            ),
+        });
+    }
+    if options.is_empty() {
+        validate_question_text(&question)?;
+    } else {
+        validate_question(&question, &options)?;
+    }
+
+    TRANSLATIONS.with(|translations| {
+        translations.borrow_mut().insert(
+            key,
+            Translation {
+                question,
+                options,
+                updated_at: time(),
+            },
+        )
+    });
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+fn remove_translation(quiz_id: u64, locale: String) -> Result<(), Error> {
+    metrics::count_call("remove_translation");
+    maintenance::ensure_available("remove_translation")?;
+    let quiz = live_quiz(quiz_id)?;
+    ensure_editor(&quiz)?;
+    let locale = normalize_locale(&locale)?;
+    TRANSLATIONS
+        .with(|translations| {
+            translations
+                .borrow_mut()
+                .remove(&(quiz_id, locale_key(&locale)))
+        })
+        .map(|_| ())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!(
+                "the quiz with id={} has no translation into '{}'",
+                quiz_id, locale
+            ),
+        })
+}
+
+// The quiz in the given locale. A regional locale like "pt-br" falls back to its language "pt",
+// and that to the default language the quiz was written in.
+#[ic_cdk::query]
+fn get_quiz_localized(id: u64, locale: String) -> Result<LocalizedQuiz, Error> {
+    let quiz = _get_quiz(&id).ok_or_else(|| Error::NotFound {
+        msg: format!("a quiz with id={} not found", id),
+    })?;
+    allowlist::ensure_can_view(&quiz)?;
+    let locale = normalize_locale(&locale)?;
+    let language = locale.split('-').next().unwrap_or_default().to_string();
+    let translation = [locale, language].into_iter().find_map(|candidate| {
+        TRANSLATIONS
+            .with(|translations| translations.borrow().get(&(id, locale_key(&candidate))))
+            .map(|translation| (candidate, translation))
+    });
+    let (locale, question, options) = match translation {
+        Some((locale, translation)) => (Some(locale), translation.question, translation.options),
+        None => (None, quiz.question.clone(), quiz.options.clone()),
+    };
+    Ok(LocalizedQuiz {
+        available_locales: locales_of(id),
+        quiz,
+        locale,
+        question,
+        options,
+    })
+}
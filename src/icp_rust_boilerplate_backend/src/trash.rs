@@ -0,0 +1,106 @@
+use crate::guards::only_authenticated;
+use crate::{
+    _get_quiz, audit, do_insert, index_listings, maintenance, metrics, remove_quiz, roles,
+    unindex_listings, AuditAction, Error, Memory, Quiz, Role, MEMORY_MANAGER,
+};
+use candid::Encode;
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use std::time::Duration;
+
+// deleted quizzes can be restored for 30 days, afterwards they are purged for good
+const RETENTION: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+thread_local! {
+    // ids of the soft deleted quizzes with the time they were deleted at
+    static TRASH: RefCell<StableBTreeMap<u64, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22)))
+    ));
+}
+
+// takes the quiz out of every listing, votes and allowlist stay so a restore brings them back.
+pub fn soft_delete(quiz: &mut Quiz) {
+    let deleted_at = time();
+    quiz.deleted_at = Some(deleted_at);
+    do_insert(quiz);
+    unindex_listings(quiz);
+    TRASH.with(|trash| trash.borrow_mut().insert(quiz.id, deleted_at));
+    schedule_purge(quiz.id, deleted_at);
+}
+
+pub fn schedule_purge(id: u64, deleted_at: u64) {
+    let delay = Duration::from_nanos((deleted_at + RETENTION).saturating_sub(time()));
+    ic_cdk_timers::set_timer(delay, move || purge(id));
+}
+
+// timers don't survive upgrades, see `post_upgrade`.
+pub fn reschedule_purges() {
+    TRASH.with(|trash| {
+        for (id, deleted_at) in trash.borrow().iter() {
+            schedule_purge(id, deleted_at);
+        }
+    });
+}
+
+// timer callback, skipped when the quiz was restored (and maybe deleted again) in between.
+fn purge(id: u64) {
+    let expired = TRASH
+        .with(|trash| trash.borrow().get(&id))
+        .is_some_and(|deleted_at| time() >= deleted_at + RETENTION);
+    if !expired {
+        return;
+    }
+    if let Some(quiz) = _get_quiz(&id) {
+        remove_quiz(&quiz);
+    }
+    TRASH.with(|trash| trash.borrow_mut().remove(&id));
+    audit::record_by(AuditAction::Purge, id, ic_cdk::id(), &Encode!(&id).unwrap());
+}
+
+fn ensure_can_restore(quiz: &Quiz) -> Result<(), Error> {
+    if quiz.author != Some(caller()) && !roles::has_role(caller(), Role::Admin) {
+        return Err(Error::Unauthorized {
+            msg: format!(
+                "only the author or an admin can restore the quiz with id={}",
+                quiz.id
+            ),
+        });
+    }
+    Ok(())
+}
+
+#[ic_cdk::update(guard = "only_authenticated")]
+fn restore_quiz(id: u64) -> Result<Quiz, Error> {
+    metrics::count_call("restore_quiz");
+    maintenance::ensure_available("restore_quiz")?;
+    let mut quiz = _get_quiz(&id)
+        .filter(|quiz| quiz.deleted_at.is_some())
+        .ok_or_else(|| Error::NotFound {
+            msg: format!("a deleted quiz with id={} not found", id),
+        })?;
+    ensure_can_restore(&quiz)?;
+
+    quiz.deleted_at = None;
+    do_insert(&quiz);
+    index_listings(&quiz);
+    TRASH.with(|trash| trash.borrow_mut().remove(&id));
+    audit::record(AuditAction::Restore, id, &Encode!(&id).unwrap());
+    Ok(quiz)
+}
+
+// admins see every deleted quiz, everybody else only their own.
+#[ic_cdk::query]
+fn list_deleted_quizzes() -> Vec<Quiz> {
+    let is_admin = roles::has_role(caller(), Role::Admin);
+    TRASH.with(|trash| {
+        trash
+            .borrow()
+            .iter()
+            .filter_map(|(id, _)| _get_quiz(&id))
+            .filter(|quiz| is_admin || quiz.author == Some(caller()))
+            .collect()
+    })
+}
@@ -0,0 +1,78 @@
+use crate::{_get_quiz, allowlist, Memory, Quiz, MAX_PAGE_SIZE, MEMORY_MANAGER};
+use ic_cdk::api::{caller, time};
+use ic_stable_structures::memory_manager::MemoryId;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+
+// a vote counts half as much after this long
+const HALF_LIFE_NS: u64 = 6 * 60 * 60 * 1_000_000_000;
+
+// The score of a quiz is the sum of 2^(t / HALF_LIFE_NS) over the times t of its votes. Decaying
+// every score by the same factor keeps their order, so instead of decaying the old votes a new
+// one weighs more. The scores are kept as log2 of that sum, the sum itself would overflow.
+// A non-negative f64 orders like its bits, which is what the index is sorted by.
+type ScoreBits = u64;
+// (score, id) reversed so that iterating yields the hottest quiz first
+type TrendingKey = (Reverse<ScoreBits>, Reverse<u64>);
+
+thread_local! {
+    static SCORES: RefCell<StableBTreeMap<u64, ScoreBits, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(41)))
+    ));
+
+    static TRENDING: RefCell<StableBTreeMap<TrendingKey, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(42)))
+    ));
+}
+
+// log2(2^a + 2^b) without leaving the log domain
+fn log2_add(a: f64, b: f64) -> f64 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    high + (1.0 + (low - high).exp2()).log2()
+}
+
+// counts a vote in the score of its quiz, every way of voting calls this once.
+pub fn record_vote(quiz_id: u64) {
+    let vote = time() as f64 / HALF_LIFE_NS as f64;
+    let previous = SCORES.with(|scores| scores.borrow().get(&quiz_id));
+    let score = match previous {
+        Some(bits) => log2_add(f64::from_bits(bits), vote),
+        None => vote,
+    };
+    SCORES.with(|scores| scores.borrow_mut().insert(quiz_id, score.to_bits()));
+    TRENDING.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(bits) = previous {
+            index.remove(&(Reverse(bits), Reverse(quiz_id)));
+        }
+        index.insert((Reverse(score.to_bits()), Reverse(quiz_id)), ());
+    });
+}
+
+// the score goes together with the votes, see `remove_votes`.
+pub fn remove_score(quiz_id: u64) {
+    if let Some(bits) = SCORES.with(|scores| scores.borrow_mut().remove(&quiz_id)) {
+        TRENDING.with(|index| {
+            index
+                .borrow_mut()
+                .remove(&(Reverse(bits), Reverse(quiz_id)))
+        });
+    }
+}
+
+// The open quizzes with the most recent votes first, a quiz nobody voted on recently sinks.
+#[ic_cdk::query]
+fn get_trending(limit: u64) -> Vec<Quiz> {
+    TRENDING.with(|index| {
+        index
+            .borrow()
+            .iter()
+            .filter_map(|((_, Reverse(quiz_id)), _)| _get_quiz(&quiz_id))
+            .filter(|quiz| !quiz.is_closed() && allowlist::can_view(quiz, caller()))
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .collect()
+    })
+}